@@ -0,0 +1,46 @@
+//! Embed git and build time information into release builds, see `Version::current`.
+//!
+//! Debug builds skip this because the dirty status and time change all the time
+//! and each change would force rebuilding the crate.
+
+use std::{process::Command, time::SystemTime};
+
+fn main() {
+    // Without this, cargo would rerun the script whenever any file in the package changes.
+    // That's desirable in release builds so the dirty status stays up to date.
+    if std::env::var("PROFILE").as_deref() != Ok("release") {
+        println!("cargo:rerun-if-changed=build.rs");
+        return;
+    }
+    // The version describes the whole game, not just this crate, so also watch the game's sources.
+    println!("cargo:rerun-if-changed=.");
+    println!("cargo:rerun-if-changed=../src");
+
+    if let Some(hash) = git(&["rev-parse", "--short", "HEAD"]) {
+        println!("cargo:rustc-env=RUSTCYCLES_GIT_HASH={hash}");
+    }
+    if let Some(status) = git(&["status", "--porcelain", "--untracked-files=no"]) {
+        println!("cargo:rustc-env=RUSTCYCLES_GIT_DIRTY={}", !status.is_empty());
+    }
+    // Format: <tag>-<commits>-g<hash>, fails if there are no tags.
+    if let Some(describe) = git(&["describe", "--tags", "--long"]) {
+        if let Some(commits) = describe.rsplitn(3, '-').nth(1) {
+            println!("cargo:rustc-env=RUSTCYCLES_GIT_COMMITS={commits}");
+        }
+    }
+
+    let secs = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    println!("cargo:rustc-env=RUSTCYCLES_BUILD_TIME={secs}");
+}
+
+/// Run git and return its trimmed output, None if it's not available or fails.
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    Some(stdout.trim().to_owned())
+}
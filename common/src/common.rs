@@ -0,0 +1,1654 @@
+//! Data and code shared between the client and server. Most gamelogic goes here.
+
+pub mod collisions;
+pub mod demo;
+pub mod entities;
+pub mod layers;
+pub mod maps;
+pub mod master;
+pub mod messages;
+pub mod net;
+pub mod replication;
+pub mod savestate;
+pub mod trace;
+
+use std::hash::{Hash, Hasher};
+
+use fyrox::{
+    resource::model::ModelResourceExtension,
+    scene::{
+        collider::{GeometrySource, InteractionGroups},
+        mesh::Mesh,
+        node::NodeTrait,
+        pivot::PivotBuilder,
+        SceneContainer,
+    },
+};
+use fyrox_core::{algebra::Quaternion, instant::Instant, math::aabb::AxisAlignedBoundingBox};
+use fyrox_resource::{manager::ResourceManager, Resource};
+
+use crate::{
+    common::{
+        collisions::{ColliderEntity, CollisionEvent},
+        entities::{
+            Cycle, Pickup, PickupKind, Player, PlayerState, Projectiles, TrailPoint, Trigger,
+            TriggerKind,
+        },
+    },
+    prelude::*,
+};
+
+/// The state of the game - all data needed to run the gamelogic.
+///
+/// Gamelogic must be deterministic - the same state and inputs
+/// must always produce the same next state, see `d_determinism_check`.
+/// This means it has to use `rng` for randomness, only game time (not real time)
+/// and must not depend on the iteration order of hash maps.
+#[derive(Clone)]
+pub struct GameState {
+    pub gs_type: GameStateType,
+
+    /// Name of the loaded map, see `maps::MAPS`.
+    pub map_name: String,
+
+    /// Currently this is not synced between client and server,
+    /// it's just a debugging aid (e.g. run something on odd/even frames).
+    /// The server sends its value in `Update` so desyncs can be reported.
+    pub frame_num: usize,
+
+    /// This gamelogic frame's time in seconds.
+    ///
+    /// This does *not* have to run at the same speed as real world time,
+    /// see `d_speed` and `d_pause`.
+    /// LATER using f32 for time might lead to instability if a match is left running for a day or so
+    pub game_time: f32,
+
+    /// The previous gamelogic frame's time in seconds.
+    pub game_time_prev: f32,
+
+    /// Game time when the current match started, used for `g_timelimit`.
+    pub match_start: f32,
+
+    /// Warmup, countdown, playing or intermission.
+    pub match_state: MatchState,
+
+    /// Length of one gamelogic frame in seconds.
+    ///
+    /// Set from `sv_tickrate` when the game state is created,
+    /// the client then uses the server's value from init.
+    /// Use `set_dt` to change it so physics stays in sync.
+    pub dt: f32,
+
+    /// The RNG for all gamelogic
+    ///
+    /// TODO Should this even be here? Cl and sv will always desync
+    /// because cl shouldn't run logic for all players (invisible should be culled).
+    /// Even now they desync because they can shoot for a different number of frames
+    /// (e.g. i think if input arrives a frame late to the server).
+    pub rng: Xoshiro256PlusPlus,
+
+    /// Inclusive range [-1.0, 1.0].
+    /// Creating it once and saving it here might be faster than using gen_range according to docs.
+    pub range_uniform11: Uniform<f64>,
+
+    cycle_model: Resource<Model>,
+
+    /// Entities inside any of these die, see `FrameCtx::is_out_of_bounds`.
+    ///
+    /// Loaded from meshes in the map whose name starts with `kill_volume`.
+    pub kill_volumes: Vec<AxisAlignedBoundingBox>,
+
+    /// Jump pads, speed pads, etc. loaded from the map, see `TriggerKind::from_node_name`.
+    pub triggers: Vec<Trigger>,
+
+    /// Maps colliders back to the entities which own them,
+    /// see `FrameCtx::register_collider`.
+    pub collider_entities: FxHashMap<Handle<Node>, ColliderEntity>,
+
+    /// Pairs of colliders which were touching after the last tick's physics.
+    ///
+    /// The smaller handle is first.
+    pub contacts_prev: FxHashSet<(Handle<Node>, Handle<Node>)>,
+
+    /// Contacts which started this tick, see `FrameCtx::sys_collisions`.
+    pub collision_events: Vec<CollisionEvent>,
+    /// Where projectiles hit something this tick, for effects like camera shake.
+    pub impacts: Vec<Vec3>,
+
+    pub scene_handle: Handle<Scene>,
+
+    /// The next ID `alloc_net_id` hands out.
+    ///
+    /// Only the server allocates IDs, clients get them from messages.
+    /// Carried over to the next map so IDs are never reused.
+    pub next_net_id: u32,
+    pub player_ids: NetIds<Player>,
+    pub cycle_ids: NetIds<Cycle>,
+
+    pub players: Pool<Player>,
+    pub cycles: Pool<Cycle>,
+    pub projectiles: Projectiles,
+    pub pickups: Pool<Pickup>,
+}
+
+/// Maps network IDs of one kind of entity to their handles, see `NetId`.
+///
+/// Entities store their own ID so there's no map for the opposite direction.
+///
+/// Use `FrameCtx::spawn_player`, `FrameCtx::free_player` etc.
+/// instead of modifying the pools directly to keep it up to date.
+#[derive(Debug, Clone)]
+pub struct NetIds<T> {
+    handles: FxHashMap<NetId, Handle<T>>,
+}
+
+// Derive would require `T: Default`.
+impl<T> Default for NetIds<T> {
+    fn default() -> Self {
+        Self {
+            handles: FxHashMap::default(),
+        }
+    }
+}
+
+impl<T> NetIds<T> {
+    pub fn insert(&mut self, net_id: NetId, handle: Handle<T>) {
+        let prev = self.handles.insert(net_id, handle);
+        soft_assert!(prev.is_none(), "network ID {} is already used", net_id);
+    }
+
+    pub fn remove(&mut self, net_id: NetId) {
+        let prev = self.handles.remove(&net_id);
+        soft_assert!(prev.is_some(), "network ID {} is not used", net_id);
+    }
+
+    /// The handle of the entity with this ID, None if it doesn't exist (anymore).
+    pub fn get(&self, net_id: NetId) -> Option<Handle<T>> {
+        self.handles.get(&net_id).copied()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GameStateType {
+    Server,
+    Client,
+    Shared,
+}
+
+/// Phases of a match, the server moves between them and tells clients.
+///
+/// (Lobby or Warmup) -> Countdown -> Playing -> (Overtime) -> Intermission,
+/// then the next map starts in the lobby or warmup again.
+/// The countdown goes back if players leave or stop being ready.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum MatchState {
+    /// Waiting for `g_players_min` players, see `g_warmup`.
+    ///
+    /// Everyone plays immediately, ammo is unlimited and scores are reset when the match starts.
+    Warmup,
+    /// Waiting for players to get ready, see `g_lobby`.
+    ///
+    /// Works like warmup but players choose when the match starts.
+    Lobby,
+    /// Enough players are here, the match starts at this game time.
+    Countdown { start: f32 },
+    /// The match is running since `GameState::match_start`.
+    Playing,
+    /// The time limit was reached with tied scores at this game time.
+    ///
+    /// Nobody respawns and the playable area shrinks until the tie is broken,
+    /// see `GameState::overtime_radius`.
+    /// The server's `g_overtime_radius` and `g_overtime_shrink_duration` are included
+    /// so clients show the same area as the server uses.
+    Overtime {
+        start: f32,
+        radius: f32,
+        duration: f32,
+    },
+    /// The match ended at this game time, nobody can move and scores are shown.
+    Intermission { end: f32 },
+}
+
+impl MatchState {
+    /// Before the match starts, scores don't count.
+    pub fn is_warmup(self) -> bool {
+        matches!(self, Self::Warmup | Self::Lobby | Self::Countdown { .. })
+    }
+
+    /// Scores and stats count.
+    pub fn is_running(self) -> bool {
+        matches!(self, Self::Playing | Self::Overtime { .. })
+    }
+
+    pub fn is_over(self) -> bool {
+        matches!(self, Self::Intermission { .. })
+    }
+}
+
+/// Model of the player's vehicle, same for all players.
+pub const CYCLE_MODEL_PATH: &str = "data/rustcycle/rustcycle.fbx";
+
+/// All data necessary to run a frame of shared gamelogic in one convenient package.
+///
+/// It could be all passed as separate arguments to the functions that need it,
+/// but this is more ergonomic.
+///
+/// This struct and {Client,Server}FrameCtx are meant to have impl blocks throughout the codebase.
+///
+/// Note there is no access to wallclock time here, all gamelogic must depend only on game time.
+pub struct FrameCtx<'a> {
+    pub cvars: &'a Cvars,
+    // We have two choices here:
+    // 1) Borrow the whole engine
+    // 2) Borrow only the scene
+    // Option 1 lets us access things like the UI and resource manager
+    // but it's also easy to do bad stuff like calling update() twice per frame
+    // and we have to borrow the scene in every method.
+    // Option 2 lets us have separate client and server scenes
+    // and easily pass the one we want to update.
+    // So currently we only borrow the scene and {Client,Server}Process deal with the engine.
+    // We could also borrow the UI and the resource manager here too if needed.
+    pub scene: &'a mut Scene,
+    pub gs: &'a mut GameState,
+}
+
+/// The game state before a tick, see `FrameCtx::determinism_snapshot`.
+pub struct DeterminismSnapshot {
+    scene: Scene,
+    gs: GameState,
+}
+
+impl GameState {
+    /// Load the map and create an empty game state.
+    ///
+    /// Panics if we don't have the map, check with `maps::path` first.
+    pub async fn new(
+        cvars: &Cvars,
+        resource_manager: &ResourceManager,
+        scenes: &mut SceneContainer,
+        gs_type: GameStateType,
+        map_name: &str,
+    ) -> Self {
+        let map_path = maps::path(map_name).unwrap_or_else(|| panic!("unknown map {map_name}"));
+
+        let mut scene = Scene::new();
+
+        resource_manager
+            .request::<Model>(map_path)
+            .await
+            .unwrap()
+            .instantiate(&mut scene);
+
+        // Bounding boxes and colliders built from meshes need global transforms.
+        scene.graph.update_hierarchical_data();
+
+        if cvars.g_physics_pole_colliders {
+            let start = Instant::now();
+            let count = split_pole_colliders(&mut scene);
+            let ms = start.elapsed().as_secs_f32() * 1000.0;
+            dbg_logf!("split {} poles into their own colliders in {:.2} ms", count, ms);
+        }
+        let mut kill_volumes = Vec::new();
+        let mut triggers = Vec::new();
+        for node in scene.graph.linear_iter_mut() {
+            let Some(mesh) = node.cast::<Mesh>() else {
+                continue;
+            };
+            let bounds = mesh.world_bounding_box();
+            if node.name().starts_with("kill_volume") {
+                kill_volumes.push(bounds);
+                node.set_visibility(false);
+            } else if let Some(kind) = TriggerKind::from_node_name(node.name()) {
+                // Unlike kill volumes, these stay visible so players know where they are.
+                triggers.push(Trigger { kind, bounds });
+            }
+        }
+
+        let mut pickups = Pool::new();
+        for (node_handle, node) in scene.graph.pair_iter() {
+            if let Some(kind) = PickupKind::from_node_name(node.name()) {
+                pickups.spawn(Pickup {
+                    kind,
+                    node_handle,
+                    pos: node.global_position(),
+                    time_taken: None,
+                });
+            }
+        }
+
+        let cycle_model = resource_manager.request::<Model>(CYCLE_MODEL_PATH).await.unwrap();
+
+        if cvars.sv_tickrate == 0 {
+            dbg_logf!("sv_tickrate 0 is invalid, using 1");
+        }
+        let dt = 1.0 / cvars.sv_tickrate.max(1) as f32;
+        scene.graph.physics.integration_parameters.dt = Some(dt);
+
+        let scene_handle = scenes.add(scene);
+
+        Self {
+            gs_type,
+            map_name: map_name.to_owned(),
+            game_time: 0.0,
+            // We wanna avoid having to specialcase divisions by zero in the first frame.
+            // It would usually be 0.0 / 0.0 anyway so now it's 0.0 / -1.0.
+            game_time_prev: -1.0,
+            match_start: 0.0,
+            match_state: if cvars.g_lobby {
+                MatchState::Lobby
+            } else if cvars.g_warmup {
+                MatchState::Warmup
+            } else {
+                MatchState::Playing
+            },
+            dt,
+            frame_num: 0,
+            rng: Xoshiro256PlusPlus::seed_from_u64(cvars.d_seed),
+            range_uniform11: Uniform::new_inclusive(-1.0, 1.0),
+            cycle_model,
+            kill_volumes,
+            triggers,
+            collider_entities: FxHashMap::default(),
+            contacts_prev: FxHashSet::default(),
+            collision_events: Vec::new(),
+            impacts: Vec::new(),
+            scene_handle,
+            next_net_id: 0,
+            player_ids: NetIds::default(),
+            cycle_ids: NetIds::default(),
+            players: Pool::new(),
+            cycles: Pool::new(),
+            projectiles: Projectiles::new(),
+            pickups,
+        }
+    }
+
+    /// Horizontal distance from the center of the map beyond which cycles die,
+    /// None when not in overtime.
+    ///
+    /// Shrinks from `g_overtime_radius` to zero over `g_overtime_shrink_duration`.
+    pub fn overtime_radius(&self) -> Option<f32> {
+        let MatchState::Overtime {
+            start,
+            radius,
+            duration,
+        } = self.match_state
+        else {
+            return None;
+        };
+        // Zero (or a negative value) means it's gone immediately rather than dividing by zero.
+        if duration <= 0.0 {
+            return Some(0.0);
+        }
+        let progress = (self.game_time - start) / duration;
+        Some(radius * (1.0 - progress.clamp(0.0, 1.0)))
+    }
+
+    /// A new network ID for an entity spawned by the server.
+    pub fn alloc_net_id(&mut self) -> NetId {
+        let net_id = NetId(self.next_net_id);
+        self.next_net_id += 1;
+        net_id
+    }
+}
+
+/// Move pole meshes out of the map's trimesh colliders, each gets its own convex collider.
+///
+/// Returns how many poles were found.
+fn split_pole_colliders(scene: &mut Scene) -> usize {
+    let poles: Vec<_> = scene
+        .graph
+        .pair_iter()
+        .filter(|(_, node)| node.name().starts_with("pole") && node.cast::<Mesh>().is_some())
+        .map(|(node_handle, _)| node_handle)
+        .collect();
+    if poles.is_empty() {
+        return 0;
+    }
+
+    // The poles should collide with the same things as the rest of the map.
+    let mut groups = InteractionGroups::default();
+    let mut to_remove = Vec::new();
+    for (collider_handle, node) in scene.graph.pair_iter_mut() {
+        let Some(collider) = node.cast_mut::<Collider>() else {
+            continue;
+        };
+        let ColliderShape::Trimesh(trimesh) = collider.shape() else {
+            continue;
+        };
+        if !trimesh.sources.iter().any(|source| poles.contains(&source.0)) {
+            continue;
+        }
+        let sources: Vec<_> = trimesh
+            .sources
+            .iter()
+            .filter(|source| !poles.contains(&source.0))
+            .cloned()
+            .collect();
+        groups = collider.collision_groups();
+        if sources.is_empty() {
+            to_remove.push(collider_handle);
+        } else {
+            collider.set_shape(ColliderShape::trimesh(sources));
+        }
+    }
+    for collider_handle in to_remove {
+        scene.graph.remove_node(collider_handle);
+    }
+
+    for &pole in &poles {
+        let collider_handle = ColliderBuilder::new(BaseBuilder::new())
+            .with_shape(ColliderShape::polyhedron(GeometrySource(pole)))
+            .with_collision_groups(groups)
+            .build(&mut scene.graph);
+        RigidBodyBuilder::new(BaseBuilder::new().with_children(&[collider_handle]))
+            .with_body_type(RigidBodyType::Static)
+            .build(&mut scene.graph);
+    }
+
+    poles.len()
+}
+
+impl FrameCtx<'_> {
+    /// Change the length of gamelogic frames, including physics.
+    ///
+    /// Ignores values which are not finite and positive, e.g. from a misbehaving server.
+    pub fn set_dt(&mut self, dt: f32) {
+        if !(dt.is_finite() && dt > 0.0) {
+            dbg_logf!("Ignoring invalid dt {}", dt);
+            return;
+        }
+        self.gs.dt = dt;
+        self.scene.graph.physics.integration_parameters.dt = Some(dt);
+    }
+
+    /// End warmup and reset scores.
+    ///
+    /// The server also respawns everyone, clients get the new cycles replicated.
+    pub fn start_match(&mut self) {
+        for player in &mut self.gs.players {
+            player.kills = 0;
+            player.deaths = 0;
+        }
+        self.gs.match_start = self.gs.game_time;
+        self.gs.match_state = MatchState::Playing;
+    }
+
+    /// Hash of the whole gamelogic state, used to compare two runs of the same game
+    /// in one process, e.g. demo replays and `d_determinism_check`.
+    ///
+    /// Includes everything in `net_checksum` plus state each side simulates on its own
+    /// so it's not comparable between a client and a server.
+    pub fn checksum(&self) -> u64 {
+        let mut hasher = fxhash::FxHasher64::default();
+        self.hash_replicated(&mut hasher);
+
+        for cycle in self.gs.cycles.iter() {
+            cycle.ammo.hash(&mut hasher);
+        }
+
+        let projs = &self.gs.projectiles;
+        for (_, slot) in projs.pair_iter() {
+            projs.positions[slot].map(f32::to_bits).hash(&mut hasher);
+            projs.velocities[slot].map(f32::to_bits).hash(&mut hasher);
+        }
+
+        for pickup in &self.gs.pickups {
+            pickup.time_taken.map(f32::to_bits).hash(&mut hasher);
+        }
+
+        // Xoshiro doesn't implement Hash, its next output depends on the whole state.
+        self.gs.rng.clone().next_u64().hash(&mut hasher);
+
+        hasher.finish()
+    }
+
+    /// Hash of the server-authoritative state which should be identical on the client and server
+    /// right after the client applies the `Update` of the frame the server computed it in.
+    ///
+    /// Only covers what `Update` overwrites on the client.
+    /// Projectiles, pickup timers and the RNG run on the client's own clock
+    /// so they can legitimately differ in the middle of a frame.
+    pub fn net_checksum(&self) -> u64 {
+        let mut hasher = fxhash::FxHasher64::default();
+        self.hash_replicated(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Players and cycles are hashed in the order of their network IDs
+    /// because their pool layout differs between the client and server.
+    /// Cycle physics is hashed in the quantized form sent in `Update`
+    /// so the client gets the same result after applying an update.
+    fn hash_replicated(&self, hasher: &mut impl Hasher) {
+        let mut players: Vec<_> = self.gs.players.iter().collect();
+        players.sort_by_key(|player| player.net_id);
+        for player in players {
+            player.net_id.hash(hasher);
+            (player.state == PlayerState::Playing).hash(hasher);
+        }
+
+        let mut cycles: Vec<_> = self.gs.cycles.iter().collect();
+        cycles.sort_by_key(|cycle| cycle.net_id);
+        for cycle in cycles {
+            let body = self.scene.graph[cycle.body_handle].as_rigid_body();
+            cycle.net_id.hash(hasher);
+            QuantizedPos::encode(**body.local_transform().position()).hash(hasher);
+            QuantizedRot::encode(**body.local_transform().rotation()).hash(hasher);
+            QuantizedVel::encode(body.lin_vel()).hash(hasher);
+            cycle.boost_energy.to_bits().hash(hasher);
+            cycle.health.to_bits().hash(hasher);
+        }
+    }
+
+    /// Copy the game state so the tick about to run can be replayed
+    /// by `check_determinism`, see `d_determinism_check`.
+    ///
+    /// Call after inputs for the tick have been applied.
+    pub fn determinism_snapshot(&self) -> DeterminismSnapshot {
+        let (scene, _) = self.scene.clone_one_to_one();
+        DeterminismSnapshot {
+            scene,
+            gs: self.gs.clone(),
+        }
+    }
+
+    /// Replay the tick which just ran on the snapshot taken before it
+    /// and report if the result differs from the real one.
+    ///
+    /// Call right after `tick_after_physics` of shared gamelogic,
+    /// before server or client logic changes the state further.
+    ///
+    /// The copy starts with fresh physics caches so a difference can also mean
+    /// the result depends on state which isn't part of the scene graph.
+    pub fn check_determinism(&self, snapshot: DeterminismSnapshot, dt: f32) {
+        let DeterminismSnapshot { mut scene, mut gs } = snapshot;
+        let mut ctx = FrameCtx {
+            cvars: self.cvars,
+            scene: &mut scene,
+            gs: &mut gs,
+        };
+        ctx.set_dt(dt);
+        ctx.tick_before_physics(dt);
+        scene.update(Vector2::new(1.0, 1.0), dt, Default::default());
+        let mut ctx = FrameCtx {
+            cvars: self.cvars,
+            scene: &mut scene,
+            gs: &mut gs,
+        };
+        ctx.tick_after_physics();
+        soft_assert_eq!(
+            ctx.checksum(),
+            self.checksum(),
+            "determinism check failed at frame {}: replay differs from the real tick",
+            self.gs.frame_num
+        );
+    }
+
+    pub fn tick_before_physics(&mut self, dt: f32) {
+        self.scene.graph.physics.integration_parameters.max_ccd_substeps =
+            self.cvars.g_physics_max_ccd_substeps;
+        for cycle in &self.gs.cycles {
+            let body = self.scene.graph[cycle.body_handle].as_rigid_body_mut();
+            if body.is_ccd_enabled() != self.cvars.g_physics_ccd {
+                body.enable_ccd(self.cvars.g_physics_ccd);
+            }
+        }
+
+        self.sys_triggers();
+
+        for cycle in &mut self.gs.cycles {
+            let player = &self.gs.players[cycle.player_handle];
+
+            let playing = player.state == PlayerState::Playing && !self.gs.match_state.is_over();
+            let input = player.input;
+
+            // The wheels can only push or brake while touching the ground (or a wall).
+            let body = self.scene.graph[cycle.body_handle].as_rigid_body();
+            let pos = **body.local_transform().position();
+            let rot = **body.local_transform().rotation();
+            let speed = body.lin_vel().norm();
+            let trace_opts = TraceOptions::filter(CollisionLayer::MAP_ONLY);
+            let ground_ray = -cycle.up * self.cvars.g_wheel_ground_distance;
+            let ground = trace_line(self.cvars, self.scene, pos, ground_ray, trace_opts)
+                .first()
+                .map(|hit| hit.normal);
+            cycle.grounded = ground.is_some();
+
+            // Wall riding - treat the wall as ground as long as the cycle is fast enough.
+            if cycle.up != UP {
+                match ground {
+                    Some(normal) if speed >= self.cvars.g_wall_ride_min_speed => {
+                        // Follow curved walls, stop wall riding if they curve into the floor.
+                        if normal.y > 1.0 - self.cvars.g_wall_ride_max_normal_y {
+                            cycle.up = UP;
+                        } else {
+                            cycle.up = normal;
+                        }
+                    }
+                    _ => {
+                        cycle.up = UP;
+                        cycle.grounded = false;
+                    }
+                }
+            } else if !cycle.grounded && speed >= self.cvars.g_wall_ride_min_speed {
+                for side in [LEFT, RIGHT] {
+                    let side_ray = rot * side * self.cvars.g_wall_ride_distance;
+                    let hits = trace_line(self.cvars, self.scene, pos, side_ray, trace_opts);
+                    if let Some(hit) = hits.first() {
+                        if hit.normal.y.abs() <= self.cvars.g_wall_ride_max_normal_y {
+                            cycle.up = hit.normal;
+                            cycle.grounded = true;
+                            break;
+                        }
+                    }
+                }
+            }
+
+            // Boost drains energy while held, otherwise it regenerates.
+            let boosting = playing && input.boost && cycle.boost_energy > 0.0;
+            if boosting {
+                cycle.boost_energy -= self.cvars.g_boost_drain * dt;
+                cycle.boost_energy = cycle.boost_energy.max(0.0);
+            } else {
+                cycle.boost_energy += self.cvars.g_boost_regen * dt;
+                cycle.boost_energy = cycle.boost_energy.min(self.cvars.g_boost_energy_max);
+            }
+            let (mut accel_factor, mut max_speed_factor) = if boosting {
+                (self.cvars.g_boost_acceleration_factor, self.cvars.g_boost_max_speed_factor)
+            } else {
+                (1.0, 1.0)
+            };
+            if cycle.speed_boost_until > self.gs.game_time {
+                accel_factor *= self.cvars.g_pickup_speed_factor;
+                max_speed_factor *= self.cvars.g_pickup_speed_factor;
+            }
+
+            let body = self.scene.graph[cycle.body_handle].as_rigid_body_mut();
+            // Move in the direction the cycle is facing, not where the player is looking.
+            // On walls, that's projected onto the wall.
+            // If the player is facing into the wall, there's no good direction so fall back
+            // to world up which makes the cycle accelerate upwards along the wall.
+            let heading_dir = rot * FORWARD;
+            let forward = (heading_dir - cycle.up * heading_dir.dot(&cycle.up))
+                .try_normalize(0.01)
+                .unwrap_or(UP);
+            let left = cycle.up.cross(&forward);
+
+            // Split velocity into longitudinal (along the wheel) and lateral (sideways)
+            // and handle each separately. The rest is vertical and left to gravity.
+            let lin_vel = body.lin_vel();
+            let mut speed_forward = lin_vel.dot(&forward);
+            let mut speed_left = lin_vel.dot(&left);
+            let mut vertical = lin_vel - forward * speed_forward - left * speed_left;
+
+            if cycle.up != UP {
+                // Pull the cycle towards the wall, otherwise it would drift off.
+                vertical -= cycle.up * self.cvars.g_wall_ride_stick * dt;
+            }
+
+            // In the air, the player only has limited control and nothing slows the cycle down.
+            let control = if cycle.grounded {
+                1.0
+            } else {
+                self.cvars.g_air_control
+            };
+            let accel = self.cvars.g_wheel_acceleration * accel_factor * control * dt;
+            let brake = self.cvars.g_wheel_brake * control * dt;
+            // Faster cycles (e.g. after boosting) are not slowed down, just can't accelerate.
+            let max_speed = self.cvars.g_wheel_max_speed * max_speed_factor;
+            if playing && input.forward {
+                if speed_forward < 0.0 {
+                    speed_forward += brake;
+                } else if speed_forward < max_speed {
+                    speed_forward = (speed_forward + accel).min(max_speed);
+                }
+            } else if playing && input.backward {
+                if speed_forward > 0.0 {
+                    speed_forward -= brake;
+                } else if speed_forward > -max_speed {
+                    speed_forward = (speed_forward - accel).max(-max_speed);
+                }
+            } else if cycle.grounded {
+                let friction = self.cvars.g_wheel_rolling_friction * dt;
+                speed_forward = approach_zero(speed_forward, friction);
+            }
+            if cycle.grounded {
+                // Up to a point, the tire grips and cancels sideways movement,
+                // beyond that it slides.
+                speed_left = approach_zero(speed_left, self.cvars.g_wheel_grip * dt);
+            }
+
+            // The ground check still succeeds for a couple frames after jumping,
+            // the cooldown prevents jumping again during them.
+            if playing
+                && input.up
+                && cycle.grounded
+                && cycle.time_last_jumped + self.cvars.g_jump_cooldown < self.gs.game_time
+            {
+                // On walls, this jumps away from the wall.
+                vertical += cycle.up * self.cvars.g_jump_impulse;
+                cycle.time_last_jumped = self.gs.game_time;
+            }
+
+            body.set_lin_vel(vertical + forward * speed_forward + left * speed_left);
+            cycle.vel_prev = body.lin_vel();
+
+            // Turn towards the look direction using angular velocity
+            // so physics can stop the cycle from turning into geometry.
+            // Setting the rotation directly would let it clip into walls.
+            let turn = angle_diff(heading(rot), input.yaw).0;
+            let max_turn = self.cvars.g_cycle_turn_rate * dt;
+            let turn = turn.clamp(-max_turn, max_turn);
+            body.set_ang_vel(UP * (turn / dt).to_radians());
+
+            if playing
+                && input.fire1
+                && cycle.ammo > 0
+                && cycle.time_last_fired + self.cvars.g_projectile_refire < self.gs.game_time
+            {
+                let dir = input.look_rotation() * FORWARD;
+                let forward = dir * self.cvars.g_projectile_speed;
+                let rand = Vec3::new(
+                    self.gs.rng.sample(StandardNormal),
+                    self.gs.rng.sample(StandardNormal),
+                    self.gs.rng.sample(StandardNormal),
+                );
+                dbg_logd!(rand); // To showcase desyncs between cl and sv
+                let spread = rand * self.cvars.g_projectile_spread;
+
+                let pos = **body.local_transform().position();
+                let time = self.gs.game_time;
+                let _ = self.gs.projectiles.spawn(cycle.player_handle, pos, forward + spread, time);
+
+                cycle.time_last_fired = self.gs.game_time;
+                if !self.gs.match_state.is_warmup() {
+                    cycle.ammo -= 1;
+                }
+            }
+        }
+
+        // LATER Split into functions
+        self.gs.impacts.clear();
+        let projs = &mut self.gs.projectiles;
+        let rays = projs.positions.iter().zip(&projs.velocities);
+        let rays = rays.map(|(&pos, &vel)| (Point3::from(pos), vel * dt));
+        trace_lines(self.cvars, self.scene, rays, TraceOptions::default(), &mut projs.traces);
+
+        // Slot order differs between machines (e.g. after `load_sync`),
+        // pool order doesn't so freeing in it keeps the pool's free list and later handles the same.
+        let mut to_free = Vec::new();
+        for slot in 0..self.gs.projectiles.len() {
+            let projs = &self.gs.projectiles;
+            let handle = projs.handles[slot];
+            let pos = projs.positions[slot];
+            if projs.times_fired[slot] + self.cvars.g_projectile_lifetime < self.gs.game_time
+                || is_out_of_bounds(self.cvars, self.gs, pos)
+            {
+                to_free.push(handle);
+                continue;
+            }
+
+            // The shooter might have died or disconnected since firing.
+            let shooter_collider_handle = self
+                .gs
+                .players
+                .try_borrow(projs.player_handles[slot])
+                .and_then(|player| player.cycle_handle)
+                .map(|cycle_handle| self.gs.cycles[cycle_handle].collider_handle);
+            // LATER Let the player shoot himself - enable self collision after the projectile clears the player's hitbox.
+            let hit = projs
+                .traces
+                .hits(slot)
+                .iter()
+                .find(|hit| Some(hit.collider) != shooter_collider_handle);
+            if let Some(hit) = hit {
+                dbg_cross!(hit.position.coords, 0.5);
+                self.gs.impacts.push(hit.position.coords);
+                to_free.push(handle);
+                continue;
+            }
+
+            let step = projs.velocities[slot] * dt;
+            let step_norm = step.normalize();
+            dbg_arrow!(pos - step_norm, step_norm, 0.0);
+
+            self.gs.projectiles.positions[slot] += step;
+        }
+        to_free.sort_unstable_by_key(|handle| handle.index());
+        for handle in to_free {
+            self.gs.projectiles.free(handle);
+        }
+
+        dbg_textf!("Projectiles: {}", self.gs.projectiles.len());
+
+        // Testing
+        for player in &self.gs.players {
+            let look_rot = player.input.look_rotation();
+            dbg_rot!(v!(0 7 0), look_rot);
+            dbg_arrow!(v!(0 5 0), look_rot * FORWARD, 0.5);
+
+            let yaw_rot = player.input.yaw_rotation();
+            dbg_arrow!(v!(0 3 0), yaw_rot * FORWARD, 0.5);
+        }
+    }
+
+    /// Apply jump pads and speed pads to cycles which just entered them.
+    ///
+    /// This runs on both the client and server so prediction matches.
+    fn sys_triggers(&mut self) {
+        for cycle in &mut self.gs.cycles {
+            let body = self.scene.graph[cycle.body_handle].as_rigid_body_mut();
+            let pos = **body.local_transform().position();
+            let inside = self
+                .gs
+                .triggers
+                .iter()
+                .position(|trigger| trigger.bounds.is_contains_point(pos));
+            let entered = inside.is_some() && inside != cycle.trigger_prev;
+            cycle.trigger_prev = inside;
+            if !entered {
+                continue;
+            }
+
+            let trigger = &self.gs.triggers[inside.unwrap()];
+            let mut vel = body.lin_vel();
+            match trigger.kind {
+                TriggerKind::JumpPad { impulse } => {
+                    let impulse = impulse.unwrap_or(self.cvars.g_jump_pad_impulse);
+                    // Cancel downward velocity so falling onto the pad doesn't weaken it.
+                    vel.y = vel.y.max(0.0) + impulse;
+                    // The cycle might still be touching the ground
+                    // and the driving model shouldn't think it's wall riding.
+                    cycle.up = UP;
+                }
+                TriggerKind::SpeedPad { factor } => {
+                    let factor = factor.unwrap_or(self.cvars.g_speed_pad_factor);
+                    vel.x *= factor;
+                    vel.z *= factor;
+                }
+            }
+            body.set_lin_vel(vel);
+        }
+    }
+
+    pub fn tick_after_physics(&mut self) {
+        self.sys_collisions();
+        self.sys_trails();
+    }
+
+    /// Extend trails behind moving cycles and drop points which expired.
+    ///
+    /// Clients get trails from the server, see `Cycle::trail`.
+    fn sys_trails(&mut self) {
+        if self.gs.gs_type == GameStateType::Client {
+            return;
+        }
+
+        let time = self.gs.game_time;
+        for cycle in &mut self.gs.cycles {
+            cycle.trail_removed = 0;
+            cycle.trail_added = 0;
+            let pos = **self.scene.graph[cycle.body_handle].local_transform().position();
+            while cycle
+                .trail
+                .front()
+                .is_some_and(|point| time - point.time > self.cvars.g_trail_duration)
+            {
+                cycle.trail.pop_front();
+                cycle.trail_removed += 1;
+            }
+            let moved = match cycle.trail.back() {
+                Some(last) => (pos - last.pos).norm() >= self.cvars.g_trail_segment_length,
+                None => true,
+            };
+            if moved {
+                cycle.trail.push_back(TrailPoint {
+                    pos,
+                    up: cycle.up,
+                    time,
+                });
+                cycle.trail_added += 1;
+            }
+        }
+    }
+
+    /// Give the pickup's effect to the cycle and start its respawn timer.
+    pub fn apply_pickup(&mut self, pickup_handle: Handle<Pickup>, cycle_handle: Handle<Cycle>) {
+        let pickup = &mut self.gs.pickups[pickup_handle];
+        pickup.time_taken = Some(self.gs.game_time);
+
+        let cycle = &mut self.gs.cycles[cycle_handle];
+        match pickup.kind {
+            PickupKind::Health => {
+                cycle.health =
+                    (cycle.health + self.cvars.g_pickup_health).min(self.cvars.g_cycle_health);
+            }
+            PickupKind::Ammo => {
+                cycle.ammo = (cycle.ammo + self.cvars.g_pickup_ammo).min(self.cvars.g_ammo_max);
+            }
+            PickupKind::Speed => {
+                cycle.speed_boost_until = self.gs.game_time + self.cvars.g_pickup_speed_duration;
+            }
+            PickupKind::Damage => {
+                cycle.damage_boost_until = self.gs.game_time + self.cvars.g_pickup_damage_duration;
+            }
+        }
+    }
+
+    /// Whether anything at `pos` should die because it fell off the arena
+    /// or entered a kill volume.
+    pub fn is_out_of_bounds(&self, pos: Vec3) -> bool {
+        is_out_of_bounds(self.cvars, self.gs, pos)
+    }
+
+    pub fn spawn_player(&mut self, player: Player) -> Handle<Player> {
+        let net_id = player.net_id;
+        let player_handle = self.gs.players.spawn(player);
+        self.gs.player_ids.insert(net_id, player_handle);
+        player_handle
+    }
+
+    pub fn free_player(&mut self, player_handle: Handle<Player>) {
+        if let Some(handle) = self.gs.players[player_handle].cycle_handle {
+            self.despawn_cycle(handle);
+        }
+        let player = self.gs.players.free(player_handle);
+        self.gs.player_ids.remove(player.net_id);
+    }
+
+    /// Remove the cycle and its nodes, the player stays.
+    pub fn despawn_cycle(&mut self, cycle_handle: Handle<Cycle>) {
+        let cycle = self.gs.cycles.free(cycle_handle);
+        self.gs.cycle_ids.remove(cycle.net_id);
+        self.unregister_collider(cycle.collider_handle);
+        self.scene.graph.remove_node(cycle.body_handle);
+        self.gs.players[cycle.player_handle].cycle_handle = None;
+    }
+
+    /// Create a cycle for the player.
+    ///
+    /// The server passes None to allocate a new network ID,
+    /// clients use the one it sent.
+    pub fn spawn_cycle(
+        &mut self,
+        player_handle: Handle<Player>,
+        net_id: Option<NetId>,
+    ) -> Handle<Cycle> {
+        let net_id = net_id.unwrap_or_else(|| self.gs.alloc_net_id());
+        let node_handle = self.gs.cycle_model.instantiate(self.scene);
+        let visual_handle = PivotBuilder::new(BaseBuilder::new().with_children(&[node_handle]))
+            .build(&mut self.scene.graph);
+        let collider_handle = ColliderBuilder::new(BaseBuilder::new())
+            // Size manually copied from the result of rusty-editor's Fit Collider
+            // LATER Remove rustcycle.rgs?
+            .with_shape(ColliderShape::cuboid(0.125, 0.271, 0.271))
+            .with_collision_groups(CollisionLayer::Cycle.groups())
+            .build(&mut self.scene.graph);
+        // Slightly randomize spawn pos just to use the RNG
+        let left = 3.0 * self.gs.rng.sample(self.gs.range_uniform11);
+        dbg_logd!(left);
+        let pos = v!(left, 5, 0);
+        let body_handle = RigidBodyBuilder::new(
+            BaseBuilder::new()
+                .with_local_transform(TransformBuilder::new().with_local_position(pos).build())
+                .with_children(&[visual_handle, collider_handle]),
+        )
+        .with_ccd_enabled(self.cvars.g_physics_ccd)
+        // Cycles only turn around the up axis, see `tick_before_physics`.
+        .with_x_rotation_locked(true)
+        .with_z_rotation_locked(true)
+        .with_can_sleep(false)
+        .build(&mut self.scene.graph);
+
+        let cycle = Cycle {
+            net_id,
+            player_handle,
+            body_handle,
+            collider_handle,
+            visual_handle,
+            pos_prev: pos,
+            rot_prev: UnitQuaternion::identity(),
+            vel_prev: Vec3::zeros(),
+            health: self.cvars.g_cycle_health,
+            time_spawned: self.gs.game_time,
+            time_last_fired: 0.0,
+            grounded: false,
+            boost_energy: self.cvars.g_boost_energy_max,
+            time_last_jumped: 0.0,
+            ammo: self.cvars.g_ammo_start,
+            speed_boost_until: 0.0,
+            damage_boost_until: 0.0,
+            trigger_prev: None,
+            up: UP,
+            trail: VecDeque::new(),
+            trail_removed: 0,
+            trail_added: 0,
+        };
+        let cycle_handle = self.gs.cycles.spawn(cycle);
+        self.gs.cycle_ids.insert(net_id, cycle_handle);
+
+        self.gs.players[player_handle].cycle_handle = Some(cycle_handle);
+        self.register_collider(collider_handle, ColliderEntity::Cycle(cycle_handle));
+
+        cycle_handle
+    }
+
+    /// Snapshot of the whole game state to send to clients or save to disk.
+    pub fn full_sync(&self) -> FullSync {
+        let players = self
+            .gs
+            .players
+            .iter()
+            .map(|player| SyncPlayer {
+                player_id: player.net_id,
+                name: player.name.clone(),
+                state: match player.state {
+                    PlayerState::Observing => SyncPlayerState::Observing,
+                    PlayerState::Spectating { spectatee_handle } => {
+                        // The spectatee might have left.
+                        match self.gs.players.try_borrow(spectatee_handle) {
+                            Some(spectatee) => SyncPlayerState::Spectating {
+                                spectatee_id: spectatee.net_id,
+                            },
+                            None => SyncPlayerState::Observing,
+                        }
+                    }
+                    PlayerState::Playing => SyncPlayerState::Playing,
+                },
+                input: player.input,
+                kills: player.kills,
+                deaths: player.deaths,
+                time_died: player.time_died,
+            })
+            .collect();
+
+        let cycles = self
+            .gs
+            .cycles
+            .iter()
+            .map(|cycle| {
+                let body = self.scene.graph[cycle.body_handle].as_rigid_body();
+                SyncCycle {
+                    cycle_id: cycle.net_id,
+                    player_id: self.gs.players[cycle.player_handle].net_id,
+                    translation: (**body.local_transform().position()).into(),
+                    rotation: body.local_transform().rotation().coords.into(),
+                    velocity: body.lin_vel().into(),
+                    vel_prev: cycle.vel_prev.into(),
+                    health: cycle.health,
+                    time_spawned: cycle.time_spawned,
+                    time_last_fired: cycle.time_last_fired,
+                    boost_energy: cycle.boost_energy,
+                    time_last_jumped: cycle.time_last_jumped,
+                    ammo: cycle.ammo,
+                    speed_boost_until: cycle.speed_boost_until,
+                    damage_boost_until: cycle.damage_boost_until,
+                    trigger_prev: cycle.trigger_prev.map(|index| index as u32),
+                    up: cycle.up.into(),
+                    trail: cycle.trail.iter().map(|&point| point.into()).collect(),
+                }
+            })
+            .collect();
+
+        let projs = &self.gs.projectiles;
+        let projectiles = projs
+            .pair_iter()
+            .map(|(proj_handle, slot)| SyncProjectile {
+                projectile_index: proj_handle.index(),
+                player_id: self
+                    .gs
+                    .players
+                    .try_borrow(projs.player_handles[slot])
+                    .map(|player| player.net_id),
+                pos: projs.positions[slot].into(),
+                vel: projs.velocities[slot].into(),
+                time_fired: projs.times_fired[slot],
+            })
+            .collect();
+
+        let pickups = self
+            .gs
+            .pickups
+            .pair_iter()
+            .map(|(pickup_handle, pickup)| SyncPickup {
+                pickup_index: pickup_handle.index(),
+                time_taken: pickup.time_taken,
+            })
+            .collect();
+
+        FullSync {
+            frame_num: self.gs.frame_num as u64,
+            next_net_id: self.gs.next_net_id,
+            game_time: self.gs.game_time,
+            game_time_prev: self.gs.game_time_prev,
+            match_start: self.gs.match_start,
+            match_state: self.gs.match_state,
+            rng: self.gs.rng.clone(),
+            players,
+            cycles,
+            projectiles,
+            pickups,
+        }
+    }
+
+    /// Throw away all entities and recreate them from the snapshot.
+    ///
+    /// Network IDs are preserved but handles are not
+    /// so any handles kept outside `GameState` have to be looked up again.
+    ///
+    /// Time and frame number are left alone, the client keeps its own clock.
+    pub fn apply_full_sync(&mut self, sync: FullSync) {
+        let player_handles: Vec<_> = self.gs.players.pair_iter().map(|(h, _)| h).collect();
+        for player_handle in player_handles {
+            self.free_player(player_handle);
+        }
+        self.gs.projectiles.clear();
+
+        let mut player_handles = Vec::new();
+        for sp in &sync.players {
+            let mut player = Player::new(sp.player_id, None);
+            player.name = sp.name.clone();
+            player.input = sp.input;
+            player.kills = sp.kills;
+            player.deaths = sp.deaths;
+            player.time_died = sp.time_died;
+            player_handles.push(self.spawn_player(player));
+        }
+        // Separate loop because the spectatee might not have existed yet.
+        for (sp, player_handle) in sync.players.iter().zip(player_handles) {
+            let state = match sp.state {
+                SyncPlayerState::Observing => PlayerState::Observing,
+                SyncPlayerState::Spectating { spectatee_id } => {
+                    match self.gs.player_ids.get(spectatee_id) {
+                        Some(spectatee_handle) => PlayerState::Spectating { spectatee_handle },
+                        None => PlayerState::Observing,
+                    }
+                }
+                SyncPlayerState::Playing => PlayerState::Playing,
+            };
+            self.gs.players[player_handle].state = state;
+        }
+
+        for sc in sync.cycles {
+            let Some(player_handle) = self.gs.player_ids.get(sc.player_id) else {
+                dbg_logf!("cycle {} belongs to unknown player {}", sc.cycle_id, sc.player_id);
+                continue;
+            };
+            let cycle_handle = self.spawn_cycle(player_handle, Some(sc.cycle_id));
+            let cycle = &mut self.gs.cycles[cycle_handle];
+            let pos = Vec3::from(sc.translation);
+            let rot = UnitQuaternion::new_unchecked(Quaternion::from(Vector4::from(sc.rotation)));
+            cycle.pos_prev = pos;
+            cycle.rot_prev = rot;
+            cycle.vel_prev = sc.vel_prev.into();
+            cycle.health = sc.health;
+            cycle.time_spawned = sc.time_spawned;
+            cycle.time_last_fired = sc.time_last_fired;
+            cycle.boost_energy = sc.boost_energy;
+            cycle.time_last_jumped = sc.time_last_jumped;
+            cycle.ammo = sc.ammo;
+            cycle.speed_boost_until = sc.speed_boost_until;
+            cycle.damage_boost_until = sc.damage_boost_until;
+            cycle.trigger_prev = sc.trigger_prev.map(|index| index as usize);
+            cycle.up = sc.up.into();
+            cycle.trail = sc.trail.into_iter().map(TrailPoint::from).collect();
+            let body = self.scene.graph[cycle.body_handle].as_rigid_body_mut();
+            body.local_transform_mut().set_position(pos);
+            body.local_transform_mut().set_rotation(rot);
+            body.set_lin_vel(sc.velocity.into());
+        }
+
+        for sp in sync.projectiles {
+            let player_handle =
+                sp.player_id.and_then(|id| self.gs.player_ids.get(id)).unwrap_or_default();
+            let (pos, vel) = (sp.pos.into(), sp.vel.into());
+            self.gs
+                .projectiles
+                .spawn_at(sp.projectile_index, player_handle, pos, vel, sp.time_fired)
+                .unwrap();
+        }
+
+        // Pickups come from the map so they already exist.
+        for sp in sync.pickups {
+            self.gs.pickups.at_mut(sp.pickup_index).unwrap().time_taken = sp.time_taken;
+        }
+
+        self.gs.next_net_id = sync.next_net_id;
+        self.gs.match_start = sync.match_start;
+        self.gs.match_state = sync.match_state;
+
+        // Last because spawning cycles uses the RNG.
+        self.gs.rng = sync.rng;
+    }
+
+    /// Draw arrows in a different orientation every frame.
+    ///
+    /// This helps:
+    /// - Notice issues with framerate
+    /// - Notice tearing (but a solid object would make it even easier to see)
+    /// - Make sure debug draws and prints issued on one frame happen on the same frame.
+    ///   The intended usecase is to take a screenshot and compare
+    ///   the direction of the arrow to the direction as text.
+    ///
+    /// The rotation is clockwise when looking in the forward direction.
+    pub fn debug_engine_updates(&self, pos: Vec3) {
+        if !self.cvars.d_draw || !self.cvars.d_draw_frame_timings {
+            return;
+        }
+
+        let step = (self.gs.frame_num % self.cvars.d_draw_frame_timings_steps) as f32;
+        let angle = 2.0 * PI / self.cvars.d_draw_frame_timings_steps as f32 * step;
+        let rot = UnitQuaternion::from_axis_angle(&FORWARD_AXIS, angle);
+        let dir = rot * UP;
+        dbg_arrow!(pos, dir);
+        if self.cvars.d_draw_frame_timings_text {
+            dbg_textd!(self.gs.frame_num, pos, angle.to_degrees());
+        }
+    }
+}
+
+/// Player input in a form convenient for gamelogic.
+///
+/// This is not sent over the network directly, see `NetInput` for the compact wire format.
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Input {
+    /// LATER This should probably never be networked, since cl and sv have different time.
+    /// LATER Default impl - should not be 0 if it's not the first match
+    ///       in the same process - remove this entirely?
+    pub real_time: f32,
+
+    /// LATER specify whether this is the current or prev or next frame,
+    /// it might get messy depending on when input vs timekeeping is done
+    /// and when it's sent.
+    pub game_time: f32,
+
+    /// Counterclockwise: 0 is directly forward, negative is left, positive right.
+    /// Normalized to `[0, 360)` so it doesn't lose precision over time.
+    ///
+    /// Nalgebra rotations follow the right hand rule,
+    /// thumb points in +Y (up), the curl of fingers shows direction.
+    ///
+    /// Some things like shooting need the angle at the exact time
+    /// so we send yaw and pitch with each input, not just once per frame.
+    pub yaw: Deg,
+    pub yaw_speed: Deg,
+    pub pitch: Deg,
+    pub pitch_speed: Deg,
+
+    pub fire1: bool,
+    pub fire2: bool,
+    pub marker1: bool,
+    pub marker2: bool,
+    pub zoom: bool,
+    pub forward: bool,
+    pub backward: bool,
+    pub left: bool,
+    pub right: bool,
+    /// Jump when playing, move up when observing.
+    pub up: bool,
+    pub down: bool,
+    pub prev_weapon: bool,
+    pub next_weapon: bool,
+    pub reload: bool,
+    pub flag: bool,
+    pub grenade: bool,
+    pub kill: bool,
+    pub map: bool,
+    pub score: bool,
+    pub chat: bool,
+    pub pause: bool,
+    pub screenshot: bool,
+    pub boost: bool,
+    // ^ when adding fields, also add them to other impls and functions below
+}
+
+/// Number of bool fields in `Input`.
+///
+/// Must fit into the bitflags in `NetInput`.
+pub const INPUT_BUTTONS_COUNT: usize = 23;
+static_assert!(INPUT_BUTTONS_COUNT <= u32::BITS as usize);
+
+// LATER ClientInput? - zoom, map, chat, score, pause, screenshot, console, ...
+// These don't need to be networked
+
+impl Input {
+    pub fn release_all_keys(&mut self) {
+        self.fire1 = false;
+        self.fire2 = false;
+        self.marker1 = false;
+        self.marker2 = false;
+        self.zoom = false;
+        self.forward = false;
+        self.backward = false;
+        self.left = false;
+        self.right = false;
+        self.up = false;
+        self.down = false;
+        self.prev_weapon = false;
+        self.next_weapon = false;
+        self.reload = false;
+        self.flag = false;
+        self.grenade = false;
+        self.kill = false;
+        self.map = false;
+        self.score = false;
+        self.chat = false;
+        self.pause = false;
+        self.screenshot = false;
+        self.boost = false;
+    }
+
+    /// The state of all the buttons in a fixed order.
+    ///
+    /// The order is part of the network protocol (see `NetInput`),
+    /// add new buttons to the end.
+    pub fn buttons(&self) -> [bool; INPUT_BUTTONS_COUNT] {
+        [
+            self.fire1,
+            self.fire2,
+            self.marker1,
+            self.marker2,
+            self.zoom,
+            self.forward,
+            self.backward,
+            self.left,
+            self.right,
+            self.up,
+            self.down,
+            self.prev_weapon,
+            self.next_weapon,
+            self.reload,
+            self.flag,
+            self.grenade,
+            self.kill,
+            self.map,
+            self.score,
+            self.chat,
+            self.pause,
+            self.screenshot,
+            self.boost,
+        ]
+    }
+
+    /// Mutable references to all the buttons in the same order as `buttons()`.
+    pub fn buttons_mut(&mut self) -> [&mut bool; INPUT_BUTTONS_COUNT] {
+        [
+            &mut self.fire1,
+            &mut self.fire2,
+            &mut self.marker1,
+            &mut self.marker2,
+            &mut self.zoom,
+            &mut self.forward,
+            &mut self.backward,
+            &mut self.left,
+            &mut self.right,
+            &mut self.up,
+            &mut self.down,
+            &mut self.prev_weapon,
+            &mut self.next_weapon,
+            &mut self.reload,
+            &mut self.flag,
+            &mut self.grenade,
+            &mut self.kill,
+            &mut self.map,
+            &mut self.score,
+            &mut self.chat,
+            &mut self.pause,
+            &mut self.screenshot,
+            &mut self.boost,
+        ]
+    }
+
+    /// Pack all buttons into bitflags, bit N is the Nth button from `buttons()`.
+    pub fn buttons_to_bits(&self) -> u32 {
+        let mut bits = 0;
+        for (i, pressed) in self.buttons().into_iter().enumerate() {
+            bits |= u32::from(pressed) << i;
+        }
+        bits
+    }
+
+    /// Unpack bitflags created by `buttons_to_bits`. Unknown bits are ignored.
+    pub fn set_buttons_from_bits(&mut self, bits: u32) {
+        for (i, pressed) in self.buttons_mut().into_iter().enumerate() {
+            *pressed = bits & (1 << i) != 0;
+        }
+    }
+
+    pub fn look_rotation(&self) -> UnitQuaternion<f32> {
+        let yaw = self.yaw_rotation();
+
+        let pitch_axis = yaw * LEFT_AXIS;
+        let pitch = UnitQuaternion::from_axis_angle(&pitch_axis, self.pitch.to_radians());
+
+        pitch * yaw
+    }
+
+    pub fn yaw_rotation(&self) -> UnitQuaternion<f32> {
+        UnitQuaternion::from_axis_angle(&UP_AXIS, self.yaw.to_radians())
+    }
+}
+
+impl Debug for Input {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        // Only output the pressed buttons so it's more readable.
+        write!(
+            f,
+            "Input {{ time {} yaw {} {}/s pitch {} {}/s ",
+            self.game_time, self.yaw, self.yaw_speed, self.pitch, self.pitch_speed,
+        )?;
+        if self.fire1 {
+            write!(f, "fire1 ")?;
+        }
+        if self.fire2 {
+            write!(f, "fire2 ")?;
+        }
+        if self.marker1 {
+            write!(f, "marker1 ")?;
+        }
+        if self.marker2 {
+            write!(f, "marker2 ")?;
+        }
+        if self.zoom {
+            write!(f, "zoom ")?;
+        }
+        if self.forward {
+            write!(f, "forward ")?;
+        }
+        if self.backward {
+            write!(f, "backward ")?;
+        }
+        if self.left {
+            write!(f, "left ")?;
+        }
+        if self.right {
+            write!(f, "right ")?;
+        }
+        if self.up {
+            write!(f, "up ")?;
+        }
+        if self.down {
+            write!(f, "down ")?;
+        }
+        if self.prev_weapon {
+            write!(f, "prev_weapon ")?;
+        }
+        if self.next_weapon {
+            write!(f, "next_weapon ")?;
+        }
+        if self.reload {
+            write!(f, "reload ")?;
+        }
+        if self.flag {
+            write!(f, "flag ")?;
+        }
+        if self.grenade {
+            write!(f, "grenade ")?;
+        }
+        if self.kill {
+            write!(f, "kill ")?;
+        }
+        if self.map {
+            write!(f, "map ")?;
+        }
+        if self.score {
+            write!(f, "score ")?;
+        }
+        if self.chat {
+            write!(f, "chat ")?;
+        }
+        if self.pause {
+            write!(f, "pause ")?;
+        }
+        if self.screenshot {
+            write!(f, "screenshot ")?;
+        }
+        if self.boost {
+            write!(f, "boost ")?;
+        }
+        write!(f, "}}")?;
+        Ok(())
+    }
+}
+
+// Why not use https://crates.io/crates/angle?
+// - Not worth adding a dep for such a simple thing
+// - It shows signs of lack of attention to detail
+//   (bad readme, missing doc comments, inconsistent formatting)
+//   on the surface so it probably contains deeper issues as well.
+// - It tries to be smart and implements questionable ops such as comparisons.
+//
+// I can't find any alternatives.
+//
+// This reasoning might change if this struct gets larger but it'll probably mean
+// only taking inspiration and bits of code from the angle crate, not adding it as a dep.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+pub struct Deg(pub f32);
+
+impl Deg {
+    pub fn to_radians(self) -> f32 {
+        self.0.to_radians()
+    }
+
+    /// The same angle in the range `[0, 360)`.
+    pub fn normalized(self) -> Self {
+        let deg = self.0.rem_euclid(360.0);
+        // Tiny negative numbers round up to exactly 360.
+        if deg >= 360.0 {
+            Self(0.0)
+        } else {
+            Self(deg)
+        }
+    }
+
+    /// The same angle in the range `[-180, 180)`.
+    pub fn normalized_signed(self) -> Self {
+        let deg = (self.0 + 180.0).rem_euclid(360.0) - 180.0;
+        if deg >= 180.0 {
+            Self(-180.0)
+        } else {
+            Self(deg)
+        }
+    }
+}
+
+/// Free function version of `FrameCtx::is_out_of_bounds`
+/// so it can be used while other parts of `GameState` are borrowed.
+fn is_out_of_bounds(cvars: &Cvars, gs: &GameState, pos: Vec3) -> bool {
+    pos.y < cvars.g_kill_z || gs.kill_volumes.iter().any(|volume| volume.is_contains_point(pos))
+}
+
+/// Move `value` towards zero by at most `max_delta` without overshooting.
+pub fn approach_zero(value: f32, max_delta: f32) -> f32 {
+    if value > 0.0 {
+        (value - max_delta).max(0.0)
+    } else {
+        (value + max_delta).min(0.0)
+    }
+}
+
+/// The yaw of a rotation around the up axis, the inverse of `Input::yaw_rotation`.
+pub fn heading(rot: UnitQuaternion<f32>) -> Deg {
+    let forward = rot * FORWARD;
+    Deg(forward.left().atan2(forward.forward()).to_degrees()).normalized()
+}
+
+/// The shortest signed rotation from `from` to `to`, in the range `[-180, 180)`.
+///
+/// Positive means `to` is in the direction of increasing angles from `from`.
+pub fn angle_diff(from: Deg, to: Deg) -> Deg {
+    Deg(to.0 - from.0).normalized_signed()
+}
+
+/// Interpolate between two angles along the shortest path.
+///
+/// The result is normalized to `[0, 360)`.
+pub fn lerp_angle(from: Deg, to: Deg, t: f32) -> Deg {
+    Deg(from.0 + angle_diff(from, to).0 * t).normalized()
+}
+
+impl Display for Deg {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}°", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deg_normalized() {
+        assert_eq!(Deg(0.0).normalized().0, 0.0);
+        assert_eq!(Deg(360.0).normalized().0, 0.0);
+        assert_eq!(Deg(370.0).normalized().0, 10.0);
+        assert_eq!(Deg(-90.0).normalized().0, 270.0);
+        assert_eq!(Deg(-720.0).normalized().0, 0.0);
+        assert_eq!(Deg(-1e-6).normalized().0, 0.0);
+
+        assert_eq!(Deg(180.0).normalized_signed().0, -180.0);
+        assert_eq!(Deg(190.0).normalized_signed().0, -170.0);
+        assert_eq!(Deg(-190.0).normalized_signed().0, 170.0);
+        assert_eq!(Deg(359.0).normalized_signed().0, -1.0);
+    }
+
+    #[test]
+    fn approach_zero_doesnt_overshoot() {
+        assert_eq!(approach_zero(5.0, 2.0), 3.0);
+        assert_eq!(approach_zero(-5.0, 2.0), -3.0);
+        assert_eq!(approach_zero(1.0, 2.0), 0.0);
+        assert_eq!(approach_zero(-1.0, 2.0), 0.0);
+        assert_eq!(approach_zero(0.0, 2.0), 0.0);
+    }
+
+    #[test]
+    fn heading_inverts_yaw_rotation() {
+        for yaw in [0.0, 45.0, 90.0, 135.0, 180.0, 270.0, 359.0] {
+            let input = Input {
+                yaw: Deg(yaw),
+                ..Default::default()
+            };
+            let diff = angle_diff(heading(input.yaw_rotation()), input.yaw).0;
+            assert!(diff.abs() < 1e-3, "yaw {yaw} diff {diff}");
+        }
+    }
+
+    #[test]
+    fn angle_diff_shortest() {
+        assert_eq!(angle_diff(Deg(10.0), Deg(20.0)).0, 10.0);
+        assert_eq!(angle_diff(Deg(20.0), Deg(10.0)).0, -10.0);
+        assert_eq!(angle_diff(Deg(350.0), Deg(10.0)).0, 20.0);
+        assert_eq!(angle_diff(Deg(10.0), Deg(350.0)).0, -20.0);
+        assert_eq!(angle_diff(Deg(-10.0), Deg(710.0)).0, 0.0);
+    }
+
+    #[test]
+    fn lerp_angle_wraps() {
+        assert_eq!(lerp_angle(Deg(10.0), Deg(30.0), 0.5).0, 20.0);
+        assert_eq!(lerp_angle(Deg(350.0), Deg(10.0), 0.5).0, 0.0);
+        assert_eq!(lerp_angle(Deg(350.0), Deg(20.0), 0.5).0, 5.0);
+        assert_eq!(lerp_angle(Deg(20.0), Deg(350.0), 0.25).0, 12.5);
+        assert_eq!(lerp_angle(Deg(90.0), Deg(180.0), 0.0).0, 90.0);
+        assert_eq!(lerp_angle(Deg(90.0), Deg(180.0), 1.0).0, 180.0);
+    }
+}
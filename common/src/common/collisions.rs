@@ -0,0 +1,110 @@
+//! Physics contacts turned into events gamelogic can react to.
+//!
+//! Fyrox only lets us query the current contacts of a collider
+//! so we remember which pairs were touching last tick
+//! and only report those which started touching since.
+
+use crate::{common::entities::Cycle, prelude::*};
+
+/// The entity a collider belongs to, see `GameState::collider_entities`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColliderEntity {
+    Cycle(Handle<Cycle>),
+}
+
+/// Two colliders started touching this tick.
+///
+/// Normals point away from the first entity.
+#[derive(Debug, Clone, Copy)]
+pub enum CollisionEvent {
+    CycleCycle {
+        cycle1: Handle<Cycle>,
+        cycle2: Handle<Cycle>,
+        normal: Vec3,
+    },
+    /// A cycle hit a collider which is not an entity, e.g. part of the map.
+    CycleWorld {
+        cycle: Handle<Cycle>,
+        collider: Handle<Node>,
+        normal: Vec3,
+    },
+}
+
+impl FrameCtx<'_> {
+    /// Remember which entity owns the collider so contacts can be mapped back to it.
+    pub fn register_collider(&mut self, collider_handle: Handle<Node>, entity: ColliderEntity) {
+        self.gs.collider_entities.insert(collider_handle, entity);
+    }
+
+    pub fn unregister_collider(&mut self, collider_handle: Handle<Node>) {
+        self.gs.collider_entities.remove(&collider_handle);
+    }
+
+    /// Replace last tick's `CollisionEvent`s with contacts which started during this tick's physics.
+    ///
+    /// Must run after the engine updates physics.
+    pub fn sys_collisions(&mut self) {
+        self.gs.collision_events.clear();
+
+        // The map's iteration order depends on its history, e.g. it differs after loading a savestate.
+        // Sort it so events are always in the same order and gamelogic stays deterministic.
+        let mut colliders: Vec<_> =
+            self.gs.collider_entities.iter().map(|(&h, &e)| (h, e)).collect();
+        colliders.sort_by_key(|&(collider_handle, _)| collider_handle);
+
+        let mut contacts = FxHashSet::default();
+        for (collider_handle, entity) in colliders {
+            let collider = self.scene.graph[collider_handle].as_collider();
+            for pair in collider.contacts(&self.scene.graph.physics) {
+                if !pair.has_any_active_contact {
+                    continue;
+                }
+
+                // Make the entity first so the normal points away from it.
+                let (other, normal_sign) = if pair.collider1 == collider_handle {
+                    (pair.collider2, 1.0)
+                } else {
+                    (pair.collider1, -1.0)
+                };
+
+                // Contacts between two entities show up for both,
+                // only report them once.
+                let key = if collider_handle < other {
+                    (collider_handle, other)
+                } else {
+                    (other, collider_handle)
+                };
+                if !contacts.insert(key) || self.gs.contacts_prev.contains(&key) {
+                    continue;
+                }
+
+                let Some(manifold) = pair.manifolds.iter().find(|m| !m.points.is_empty()) else {
+                    continue;
+                };
+                let normal = manifold.normal * normal_sign;
+
+                let ColliderEntity::Cycle(cycle) = entity;
+                let event = match self.gs.collider_entities.get(&other) {
+                    Some(&ColliderEntity::Cycle(cycle2)) => CollisionEvent::CycleCycle {
+                        cycle1: cycle,
+                        cycle2,
+                        normal,
+                    },
+                    None => CollisionEvent::CycleWorld {
+                        cycle,
+                        collider: other,
+                        normal,
+                    },
+                };
+                self.gs.collision_events.push(event);
+            }
+        }
+        self.gs.contacts_prev = contacts;
+
+        if self.cvars.d_log_collisions {
+            for event in &self.gs.collision_events {
+                dbg_logd!(event);
+            }
+        }
+    }
+}
@@ -0,0 +1,118 @@
+//! Demos - recordings of everything the server received and sent, see `sv_record_demos`.
+//!
+//! A demo is a sequence of bincode-encoded `DemoEntry`s, starting with `DemoEntry::Header`.
+//! There's no framing, entries are simply read one after another until the end of the file.
+//! The header starts with `DEMO_FORMAT` so the reader can check it before decoding anything else.
+//!
+//! What clients sent is enough to re-simulate the match, see `server::replay`,
+//! what the server sent shows what players saw.
+//!
+//! `rustcycles replay` watches them in the client, see `client::demo_player`.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, ErrorKind, Read},
+    path::Path,
+};
+
+use crate::prelude::*;
+
+/// Increment when the encoding of `DemoEntry` or anything in it changes.
+///
+/// Old demos then fail to load with a clear error instead of garbage or a confusing decode error.
+pub const DEMO_FORMAT: u32 = 2;
+
+/// The message types are owned when reading, the recorder writes references
+/// so messages don't have to be cloned, see `DemoEntryRef`.
+#[derive(Debug, Deserialize, Serialize)]
+pub enum DemoEntry<S = ServerMessage, C = ClientMessage> {
+    /// The first entry in every demo.
+    Header(DemoHeader),
+    /// Everything until the next frame happened during this server frame.
+    Frame { frame_num: usize, game_time: f32 },
+    /// A client connected and got this player.
+    Connect { player_id: NetId },
+    /// The client was disconnected, no matter which side closed the connection.
+    Disconnect { player_id: NetId },
+    /// A message from a client, including its inputs.
+    Received { from: NetId, msg: C },
+    /// A message the server sent to everyone (None) or to one player.
+    Message { to: Option<NetId>, msg: S },
+    /// `FrameCtx::checksum` at the end of the frame.
+    Checksum(u64),
+}
+
+pub type DemoEntryRef<'a> = DemoEntry<&'a ServerMessage, &'a ClientMessage>;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DemoHeader {
+    /// `DEMO_FORMAT` when recording, has to stay the first field of the first variant.
+    pub format: u32,
+    pub version: Version,
+    pub map: String,
+    /// Name and value of every gameplay (`g_`) cvar when recording,
+    /// re-simulating with different ones would give a different outcome.
+    pub gameplay_cvars: Vec<(String, String)>,
+    /// When recording started as `YYYY-MM-DD hh:mm:ss` in UTC.
+    pub recorded_at: String,
+    /// The whole game state when recording started
+    /// so the demo doesn't depend on which messages clients got before.
+    pub sync: FullSync,
+}
+
+/// Read all entries of the demo at `path`.
+pub fn load(path: &Path) -> io::Result<Vec<DemoEntry>> {
+    read(BufReader::new(File::open(path)?))
+}
+
+pub fn read(mut reader: impl Read) -> io::Result<Vec<DemoEntry>> {
+    // The variant index of `DemoEntry::Header` and `DemoHeader::format`,
+    // their encoding doesn't depend on the format.
+    let mut prefix = [0; 8];
+    reader.read_exact(&mut prefix)?;
+    let (variant, format): (u32, u32) =
+        bincode::deserialize(&prefix).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+    if variant != 0 {
+        return Err(io::Error::new(ErrorKind::InvalidData, "demo doesn't start with a header"));
+    }
+    if format != DEMO_FORMAT {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("demo format is {format}, this version can only read {DEMO_FORMAT}"),
+        ));
+    }
+    let mut reader = prefix.as_slice().chain(reader);
+
+    let mut entries = Vec::new();
+    loop {
+        match bincode::deserialize_from(&mut reader) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => match *e {
+                // The server might have crashed in the middle of writing the last entry.
+                bincode::ErrorKind::Io(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+                e => return Err(io::Error::new(ErrorKind::InvalidData, e)),
+            },
+        }
+    }
+    if !matches!(entries.first(), Some(DemoEntry::Header(_))) {
+        return Err(io::Error::new(ErrorKind::InvalidData, "demo doesn't start with a header"));
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_other_formats() {
+        let data = bincode::serialize(&(0u32, DEMO_FORMAT + 1)).unwrap();
+        let err = read(data.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(err.to_string().contains("demo format"), "{err}");
+
+        let data = bincode::serialize(&(1u32, DEMO_FORMAT)).unwrap();
+        let err = read(data.as_slice()).unwrap_err();
+        assert!(err.to_string().contains("header"), "{err}");
+    }
+}
@@ -0,0 +1,453 @@
+//! The E and C in ECS
+//!
+//! We're using the ECS design pattern (decoupling behavior from data),
+//! just without the ECS data structure (we use generational arenas / pools instead to retain static typing).
+//! Most game data goes here - entities are structs, components are fields.
+//!
+//! Some entities have pure member functions.
+//! This is not a violation of the ECS pattern,
+//! because they don't modify game state - they're not behavior.
+
+use fyrox_core::math::aabb::AxisAlignedBoundingBox;
+
+use crate::{common::Input, prelude::*};
+
+/// A client connected to a server. Can be observing, spectating or playing.
+#[derive(Debug, Clone)]
+pub struct Player {
+    pub net_id: NetId,
+    pub name: String,
+    pub state: PlayerState,
+    pub input: Input,
+    /// None while observing or dead.
+    pub cycle_handle: Option<Handle<Cycle>>,
+    pub kills: u32,
+    pub deaths: u32,
+    /// Game time of the last death, used for respawning.
+    pub time_died: f32,
+}
+
+impl Player {
+    pub fn new(net_id: NetId, cycle_handle: Option<Handle<Cycle>>) -> Self {
+        Self {
+            net_id,
+            name: "unnamed".to_owned(), // TODO
+            state: PlayerState::Observing,
+            input: Input::default(),
+            cycle_handle,
+            kills: 0,
+            deaths: 0,
+            time_died: 0.0,
+        }
+    }
+}
+//
+/// How the player is participating in the game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayerState {
+    /// The player is a freely floating camera observing the game.
+    Observing,
+    /// The player is watching another player's POV - LATER
+    #[allow(dead_code)]
+    Spectating { spectatee_handle: Handle<Player> },
+    /// The player is playing
+    Playing,
+}
+
+#[derive(Debug, Clone)]
+pub struct Cycle {
+    pub net_id: NetId,
+    pub player_handle: Handle<Player>,
+    pub body_handle: Handle<Node>,
+    pub collider_handle: Handle<Node>,
+    /// Parent of the model, moved by render interpolation independently of the body.
+    pub visual_handle: Handle<Node>,
+    /// Body position at the end of the previous tick, used for render interpolation.
+    pub pos_prev: Vec3,
+    /// Body rotation at the end of the previous tick, used for render interpolation.
+    pub rot_prev: UnitQuaternion<f32>,
+    /// Velocity right before physics ran this tick, used for collision damage.
+    pub vel_prev: Vec3,
+    pub health: f32,
+    pub time_spawned: f32,
+    pub time_last_fired: f32,
+    /// Whether the wheel touched the ground at the start of the last tick.
+    pub grounded: bool,
+    /// Drained by boosting, regenerates over time. Between 0 and `g_boost_energy_max`.
+    pub boost_energy: f32,
+    pub time_last_jumped: f32,
+    /// Firing uses one per projectile.
+    pub ammo: u32,
+    /// Game time when the speed powerup runs out.
+    pub speed_boost_until: f32,
+    /// Game time when the damage powerup runs out.
+    pub damage_boost_until: f32,
+    /// Index into `GameState::triggers` of the trigger the cycle was inside last tick.
+    ///
+    /// Triggers only fire when entering them, not every tick while inside.
+    pub trigger_prev: Option<usize>,
+    /// Normal of the surface the cycle drives on.
+    ///
+    /// `UP` normally, the wall's normal while wall riding.
+    ///
+    /// LATER Tilt the model to match, rotations around other axes are locked for the body.
+    pub up: Vec3,
+    /// Recent positions, oldest first, the wall behind the cycle connects them.
+    ///
+    /// Built by the server from the cycle's position,
+    /// see `g_trail_duration` and `g_trail_segment_length`.
+    /// Clients get it with `ServerMessage::Trail` instead of building their own
+    /// so they don't end up with gaps while the cycle isn't replicated to them.
+    pub trail: VecDeque<TrailPoint>,
+    /// How many points were removed from the start of `trail` this tick.
+    pub trail_removed: u32,
+    /// How many points were added to the end of `trail` this tick.
+    pub trail_added: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TrailPoint {
+    pub pos: Vec3,
+    /// The cycle's `up` at the time so walls stand upright while wall riding.
+    pub up: Vec3,
+    pub time: f32,
+}
+
+impl From<TrailPoint> for SyncTrailPoint {
+    fn from(point: TrailPoint) -> Self {
+        Self {
+            pos: point.pos.into(),
+            up: point.up.into(),
+            time: point.time,
+        }
+    }
+}
+
+impl From<SyncTrailPoint> for TrailPoint {
+    fn from(point: SyncTrailPoint) -> Self {
+        Self {
+            pos: point.pos.into(),
+            up: point.up.into(),
+            time: point.time,
+        }
+    }
+}
+
+/// A projectile's entry in the pool of `Projectiles`, the data is in its arrays.
+#[derive(Debug, Clone)]
+pub struct Projectile {
+    /// Index into the arrays of `Projectiles`.
+    pub slot: usize,
+}
+
+/// All projectiles, stored as parallel arrays so simulating them is one tight loop.
+///
+/// The pool only gives projectiles stable handles (e.g. for trails)
+/// and indices which are the same on the client and server.
+/// The arrays are kept dense by moving the last projectile into the hole on removal
+/// so their order is unrelated to the pool's.
+#[derive(Debug, Clone)]
+pub struct Projectiles {
+    pool: Pool<Projectile>,
+    /// The pool handle of the projectile in each slot.
+    pub handles: Vec<Handle<Projectile>>,
+    pub player_handles: Vec<Handle<Player>>,
+    pub positions: Vec<Vec3>,
+    pub velocities: Vec<Vec3>,
+    pub times_fired: Vec<f32>,
+    /// Results of tracing each projectile's movement this frame, indexed by slot.
+    ///
+    /// Kept between frames to reuse its memory.
+    pub traces: TraceBatch,
+}
+
+impl Projectiles {
+    pub fn new() -> Self {
+        Self {
+            pool: Pool::new(),
+            handles: Vec::new(),
+            player_handles: Vec::new(),
+            positions: Vec::new(),
+            velocities: Vec::new(),
+            times_fired: Vec::new(),
+            traces: TraceBatch::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.handles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.handles.is_empty()
+    }
+
+    pub fn spawn(
+        &mut self,
+        player_handle: Handle<Player>,
+        pos: Vec3,
+        vel: Vec3,
+        time_fired: f32,
+    ) -> Handle<Projectile> {
+        let slot = self.len();
+        let handle = self.pool.spawn(Projectile { slot });
+        self.push(handle, player_handle, pos, vel, time_fired);
+        handle
+    }
+
+    /// Like `spawn` but at the given pool index so the client and server have the same layout.
+    ///
+    /// Returns None if the index is already taken.
+    pub fn spawn_at(
+        &mut self,
+        index: u32,
+        player_handle: Handle<Player>,
+        pos: Vec3,
+        vel: Vec3,
+        time_fired: f32,
+    ) -> Option<Handle<Projectile>> {
+        let slot = self.len();
+        let handle = self.pool.spawn_at(index, Projectile { slot }).ok()?;
+        self.push(handle, player_handle, pos, vel, time_fired);
+        Some(handle)
+    }
+
+    fn push(
+        &mut self,
+        handle: Handle<Projectile>,
+        player_handle: Handle<Player>,
+        pos: Vec3,
+        vel: Vec3,
+        time_fired: f32,
+    ) {
+        self.handles.push(handle);
+        self.player_handles.push(player_handle);
+        self.positions.push(pos);
+        self.velocities.push(vel);
+        self.times_fired.push(time_fired);
+    }
+
+    /// Remove the projectile, the last one moves into its slot.
+    ///
+    /// When removing while iterating over slots, go from the last one
+    /// so the moved projectile has already been visited.
+    pub fn free(&mut self, handle: Handle<Projectile>) {
+        let slot = self.pool.free(handle).slot;
+        self.handles.swap_remove(slot);
+        self.player_handles.swap_remove(slot);
+        self.positions.swap_remove(slot);
+        self.velocities.swap_remove(slot);
+        self.times_fired.swap_remove(slot);
+        if let Some(&moved) = self.handles.get(slot) {
+            self.pool[moved].slot = slot;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.pool.clear();
+        self.handles.clear();
+        self.player_handles.clear();
+        self.positions.clear();
+        self.velocities.clear();
+        self.times_fired.clear();
+    }
+
+    /// The handle and slot of each projectile in the order of the pool.
+    ///
+    /// Use this instead of iterating over slots when the order has to be the same
+    /// on the client and server, e.g. for checksums.
+    pub fn pair_iter(&self) -> impl Iterator<Item = (Handle<Projectile>, usize)> + '_ {
+        self.pool.pair_iter().map(|(handle, proj)| (handle, proj.slot))
+    }
+}
+
+impl Default for Projectiles {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An item placed in the map which cycles pick up by driving through it.
+///
+/// Created from marker nodes when loading the map so the client and server
+/// end up with the same pickups at the same indices.
+#[derive(Debug, Clone)]
+pub struct Pickup {
+    pub kind: PickupKind,
+    /// The marker node, also used as the model.
+    pub node_handle: Handle<Node>,
+    pub pos: Vec3,
+    /// None if it has never been picked up.
+    pub time_taken: Option<f32>,
+}
+
+impl Pickup {
+    /// Whether it's currently in the map or waiting to respawn.
+    pub fn is_available(&self, cvars: &Cvars, game_time: f32) -> bool {
+        match self.time_taken {
+            Some(time_taken) => time_taken + cvars.g_pickup_respawn <= game_time,
+            None => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PickupKind {
+    Health,
+    Ammo,
+    /// Temporarily increases acceleration and max speed.
+    Speed,
+    /// Temporarily increases damage dealt.
+    Damage,
+}
+
+impl PickupKind {
+    /// The kind of pickup a map node represents, if any.
+    ///
+    /// Markers are named `pickup_<kind>`, anything after that is ignored
+    /// so editors can number duplicates.
+    pub fn from_node_name(name: &str) -> Option<Self> {
+        let kind = name.strip_prefix("pickup_")?;
+        if kind.starts_with("health") {
+            Some(Self::Health)
+        } else if kind.starts_with("ammo") {
+            Some(Self::Ammo)
+        } else if kind.starts_with("speed") {
+            Some(Self::Speed)
+        } else if kind.starts_with("damage") {
+            Some(Self::Damage)
+        } else {
+            None
+        }
+    }
+}
+
+/// A volume in the map which affects cycles passing through it.
+#[derive(Debug, Clone)]
+pub struct Trigger {
+    pub kind: TriggerKind,
+    pub bounds: AxisAlignedBoundingBox,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TriggerKind {
+    /// Launch the cycle upwards, the impulse is in m/s.
+    JumpPad { impulse: Option<f32> },
+    /// Multiply the cycle's horizontal velocity.
+    SpeedPad { factor: Option<f32> },
+}
+
+impl TriggerKind {
+    /// The kind of trigger a map node represents, if any.
+    ///
+    /// Markers are named `jump_pad` or `speed_pad`,
+    /// optionally followed by `_<value>` to override the default from cvars
+    /// (e.g. `jump_pad_20`). Editors number duplicates by appending a `.` and 3 digits,
+    /// that suffix is ignored when the name is invalid with it.
+    /// This means `speed_pad_1.125` is a factor of 1.125
+    /// while `jump_pad_20.001` is an impulse of 20.001, which is close enough.
+    pub fn from_node_name(name: &str) -> Option<Self> {
+        Self::from_name_exact(name).or_else(|| {
+            let (base, suffix) = name.rsplit_once('.')?;
+            if suffix.len() == 3 && suffix.bytes().all(|b| b.is_ascii_digit()) {
+                Self::from_name_exact(base)
+            } else {
+                None
+            }
+        })
+    }
+
+    fn from_name_exact(name: &str) -> Option<Self> {
+        if let Some(rest) = name.strip_prefix("jump_pad") {
+            Some(Self::JumpPad {
+                impulse: parse_trigger_value(rest)?,
+            })
+        } else if let Some(rest) = name.strip_prefix("speed_pad") {
+            Some(Self::SpeedPad {
+                factor: parse_trigger_value(rest)?,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// Parse the optional `_<value>` part of a trigger's name.
+///
+/// The outer None means the name is invalid.
+fn parse_trigger_value(rest: &str) -> Option<Option<f32>> {
+    if rest.is_empty() {
+        return Some(None);
+    }
+    let value = rest.strip_prefix('_')?.parse().ok()?;
+    Some(Some(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trigger_kind_from_node_name() {
+        assert_eq!(
+            TriggerKind::from_node_name("jump_pad"),
+            Some(TriggerKind::JumpPad { impulse: None })
+        );
+        assert_eq!(
+            TriggerKind::from_node_name("jump_pad_20"),
+            Some(TriggerKind::JumpPad {
+                impulse: Some(20.0)
+            })
+        );
+        assert_eq!(
+            TriggerKind::from_node_name("speed_pad_1.5"),
+            Some(TriggerKind::SpeedPad { factor: Some(1.5) })
+        );
+        assert_eq!(TriggerKind::from_node_name("jump_pads"), None);
+        assert_eq!(TriggerKind::from_node_name("speed_pad_fast"), None);
+        assert_eq!(TriggerKind::from_node_name("pole"), None);
+    }
+
+    #[test]
+    fn trigger_kind_duplicate_suffix() {
+        assert_eq!(
+            TriggerKind::from_node_name("jump_pad.001"),
+            Some(TriggerKind::JumpPad { impulse: None })
+        );
+        assert_eq!(
+            TriggerKind::from_node_name("speed_pad_1.5.002"),
+            Some(TriggerKind::SpeedPad { factor: Some(1.5) })
+        );
+        // A value with 3 decimals is not a suffix.
+        assert_eq!(
+            TriggerKind::from_node_name("speed_pad_1.125"),
+            Some(TriggerKind::SpeedPad {
+                factor: Some(1.125)
+            })
+        );
+        assert_eq!(TriggerKind::from_node_name("jump_pad.01"), None);
+        assert_eq!(TriggerKind::from_node_name("pole.001"), None);
+    }
+
+    #[test]
+    fn projectiles_free() {
+        let mut projs = Projectiles::new();
+        let player = Handle::NONE;
+        let a = projs.spawn(player, v!(1 0 0), Vec3::zeros(), 0.0);
+        let b = projs.spawn(player, v!(2 0 0), Vec3::zeros(), 0.0);
+        let c = projs.spawn(player, v!(3 0 0), Vec3::zeros(), 0.0);
+
+        projs.free(a);
+        assert_eq!(projs.len(), 2);
+        // The last projectile moved into the hole and the pool knows about it.
+        assert_eq!(projs.handles, [c, b]);
+        let pairs: Vec<_> = projs.pair_iter().collect();
+        assert_eq!(pairs, [(b, 1), (c, 0)]);
+        assert_eq!(projs.positions, [v!(3 0 0), v!(2 0 0)]);
+
+        projs.free(b);
+        projs.free(c);
+        assert!(projs.is_empty());
+        assert_eq!(projs.pair_iter().count(), 0);
+    }
+}
@@ -0,0 +1,62 @@
+//! Collision layers - which kinds of objects collide with or are hit by traces of which others.
+//!
+//! Each layer is one bit of Fyrox's `InteractionGroups`.
+//! The map keeps Fyrox's default of being in all layers (and colliding with all of them)
+//! so it doesn't need to know about layers added later.
+//! Everything else is only in its own layer so it can be filtered out
+//! by clearing its bit, e.g. traces which should only hit the map use `CollisionLayer::MAP_ONLY`.
+
+use fyrox::scene::collider::{BitMask, InteractionGroups};
+
+use crate::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumCount)]
+pub enum CollisionLayer {
+    Cycle,
+    Trail,
+    Pickup,
+    Trigger,
+    Projectile,
+}
+
+static_assert!(CollisionLayer::COUNT <= u32::BITS as usize);
+
+impl CollisionLayer {
+    /// Every layer including ones which aren't in the enum, the default for map colliders.
+    pub const ALL: BitMask = BitMask(u32::MAX);
+
+    /// All the layers used by entities.
+    pub const ENTITIES: BitMask = BitMask((1 << Self::COUNT) - 1);
+
+    /// Filter for hitting only the map, e.g. for line of sight or placing the camera.
+    pub const MAP_ONLY: BitMask = BitMask(!Self::ENTITIES.0);
+
+    pub const fn mask(self) -> BitMask {
+        BitMask(1 << self as u32)
+    }
+
+    /// A collider in this layer which collides with everything.
+    pub fn groups(self) -> InteractionGroups {
+        InteractionGroups::new(self.mask(), Self::ALL)
+    }
+}
+
+impl From<CollisionLayer> for BitMask {
+    fn from(layer: CollisionLayer) -> Self {
+        layer.mask()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_masks() {
+        assert_eq!(CollisionLayer::Cycle.mask(), BitMask(1));
+        assert_eq!(CollisionLayer::Projectile.mask(), BitMask(1 << 4));
+        assert_eq!(CollisionLayer::ENTITIES, BitMask(0b11111));
+        assert_eq!(CollisionLayer::MAP_ONLY.0 & CollisionLayer::Trail.mask().0, 0);
+        assert_eq!(CollisionLayer::MAP_ONLY.0 | CollisionLayer::ENTITIES.0, u32::MAX);
+    }
+}
@@ -0,0 +1,81 @@
+//! The maps which can be played and where to load them from.
+//!
+//! The client and server both need the map's scene.
+//! Maps in `MAPS` ship with the game, clients download other maps
+//! from the server into `DOWNLOADS_DIR`.
+
+use std::{hash::Hasher, path::PathBuf};
+
+pub struct MapInfo {
+    /// Used in `g_map` and sent to clients.
+    pub name: &'static str,
+    pub path: &'static str,
+    /// Directory with the sky's `front.png`, `back.png`, `left.png`,
+    /// `right.png`, `top.png` and `bottom.png`.
+    pub skybox: &'static str,
+}
+
+pub const MAPS: &[MapInfo] = &[MapInfo {
+    name: "arena",
+    path: "data/arena/arena.rgs",
+    skybox: "data/skybox",
+}];
+
+/// Used for maps which don't have their own, e.g. downloaded ones.
+pub const DEFAULT_SKYBOX: &str = "data/skybox";
+
+/// Names of the textures in a skybox directory, without the `.png` extension.
+pub const SKYBOX_SIDES: [&str; 6] = ["front", "back", "left", "right", "top", "bottom"];
+
+/// Where maps downloaded from servers are stored.
+///
+/// LATER Also download the textures and models the map uses.
+pub const DOWNLOADS_DIR: &str = "data/downloads";
+
+pub fn find(name: &str) -> Option<&'static MapInfo> {
+    MAPS.iter().find(|map| map.name == name)
+}
+
+pub fn skybox(name: &str) -> &'static str {
+    find(name).map_or(DEFAULT_SKYBOX, |map| map.skybox)
+}
+
+/// Where to load the map from, None if we don't have it.
+pub fn path(name: &str) -> Option<PathBuf> {
+    if let Some(map) = find(name) {
+        return Some(map.path.into());
+    }
+    download_path(name).filter(|path| path.exists())
+}
+
+/// Where to store the map when downloading it.
+///
+/// None if the name could escape `DOWNLOADS_DIR` - it comes from the server.
+pub fn download_path(name: &str) -> Option<PathBuf> {
+    let valid =
+        !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    valid.then(|| PathBuf::from(DOWNLOADS_DIR).join(format!("{name}.rgs")))
+}
+
+/// Checksum of a map file to detect corrupted downloads.
+///
+/// Not cryptographically secure, we trust the server anyway.
+pub fn hash(data: &[u8]) -> u64 {
+    let mut hasher = fxhash::FxHasher64::default();
+    hasher.write(data);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn download_path_rejects_traversal() {
+        assert!(download_path("arena_2-final").is_some());
+        assert!(download_path("").is_none());
+        assert!(download_path("../arena").is_none());
+        assert!(download_path("maps/arena").is_none());
+        assert!(download_path("arena.rgs").is_none());
+    }
+}
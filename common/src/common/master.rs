@@ -0,0 +1,232 @@
+//! Protocol for talking to the master server which keeps a list of public game servers.
+//!
+//! Everything is sent over UDP, one bincode-encoded message per datagram.
+//! Servers periodically announce themselves, the master drops servers
+//! which haven't announced in a while.
+//! Clients query the master and get back the list of servers.
+//!
+//! The announcing side lives in `server::master`,
+//! the querying side is `ServerListQuery` below.
+//!
+//! Clients can also ask game servers directly using `InfoRequest`
+//! (sent to the game port over UDP) to get up to date info and measure ping.
+//! Broadcasting it is used to discover servers on the local network.
+//! Requests are padded so responses are never larger,
+//! otherwise servers could be used to amplify DoS attacks with spoofed source addresses.
+//!
+//! LATER The master server itself.
+//! LATER Split the server list into multiple datagrams if it gets too long.
+
+use std::{
+    io::{self, ErrorKind},
+    net::{SocketAddr, UdpSocket},
+};
+
+use crate::{common::messages::Version, prelude::*};
+
+/// Max length of a datagram we're willing to receive.
+pub const MAX_DATAGRAM_LEN: usize = 65507;
+
+/// Min length of an `InfoRequest`, servers don't send responses longer than the request.
+///
+/// Fits into one packet on all common networks.
+pub const INFO_REQUEST_LEN: usize = 1200;
+
+/// Messages sent to the master server.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum MasterRequest {
+    /// Sent by game servers to be added to the list or refresh their entry.
+    Announce(ServerInfo),
+    /// Sent by game servers when shutting down.
+    ///
+    /// The IP is taken from the datagram's source address.
+    Remove { port: u16 },
+    /// Sent by game clients to get the list of servers.
+    Query,
+}
+
+/// Messages sent by the master server.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum MasterResponse {
+    ServerList(Vec<ServerEntry>),
+}
+
+/// What a game server tells the master about itself.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ServerInfo {
+    pub name: String,
+    pub map: String,
+    pub players: u32,
+    /// The port game clients should connect to.
+    ///
+    /// The IP is taken from the datagram's source address
+    /// because the server often doesn't know its public IP.
+    pub port: u16,
+    pub version: Version,
+}
+
+/// One server in the list returned by the master.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ServerEntry {
+    /// The address to connect to.
+    pub addr: SocketAddr,
+    pub info: ServerInfo,
+}
+
+/// Sent by game clients directly to game servers.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct InfoRequest {
+    /// Client's real time, echoed back so the client can compute ping.
+    pub time: f32,
+    /// Zeros to make the request `INFO_REQUEST_LEN` bytes long.
+    pub padding: Vec<u8>,
+}
+
+impl InfoRequest {
+    pub fn new(time: f32) -> Self {
+        let mut request = Self {
+            time,
+            padding: Vec::new(),
+        };
+        let len = bincode::serialized_size(&request).unwrap() as usize;
+        request.padding = vec![0; INFO_REQUEST_LEN.saturating_sub(len)];
+        request
+    }
+}
+
+/// Sent by game servers in response to `InfoRequest`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct InfoResponse {
+    pub time: f32,
+    pub info: ServerInfo,
+}
+
+/// Bind a nonblocking UDP socket of the same family as `remote` on an arbitrary port.
+pub fn udp_socket_for(remote: SocketAddr) -> io::Result<UdpSocket> {
+    let local = if remote.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket)
+}
+
+/// A nonblocking request for the server list.
+///
+/// Call `poll` every frame until it returns the list.
+/// UDP is unreliable so the caller should give up (or retry) after a timeout.
+pub struct ServerListQuery {
+    socket: UdpSocket,
+    master_addr: SocketAddr,
+}
+
+impl ServerListQuery {
+    /// Send a query to the master at `master_addr`.
+    pub fn new(master_addr: SocketAddr) -> io::Result<Self> {
+        let socket = udp_socket_for(master_addr)?;
+        let bytes = bincode::serialize(&MasterRequest::Query).unwrap();
+        socket.send_to(&bytes, master_addr)?;
+        Ok(Self {
+            socket,
+            master_addr,
+        })
+    }
+
+    /// Check whether the master has responded.
+    ///
+    /// Datagrams from other addresses and malformed responses are ignored.
+    pub fn poll(&mut self) -> io::Result<Option<Vec<ServerEntry>>> {
+        let mut buf = vec![0; MAX_DATAGRAM_LEN];
+        loop {
+            let (len, src) = match self.socket.recv_from(&mut buf) {
+                Ok(res) => res,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(None),
+                Err(e) => return Err(e),
+            };
+            if src != self.master_addr {
+                dbg_logf!("ignoring datagram from {src}, expected master {}", self.master_addr);
+                continue;
+            }
+            match bincode::deserialize(&buf[..len]) {
+                Ok(MasterResponse::ServerList(servers)) => return Ok(Some(servers)),
+                Err(e) => dbg_logf!("malformed response from master {src}: {e}"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::*;
+
+    fn info() -> ServerInfo {
+        ServerInfo {
+            name: "test server".to_owned(),
+            map: "arena".to_owned(),
+            players: 3,
+            port: 26000,
+            version: Version::current(),
+        }
+    }
+
+    #[test]
+    fn master_request_round_trip() {
+        let bytes = bincode::serialize(&MasterRequest::Announce(info())).unwrap();
+        let MasterRequest::Announce(decoded) = bincode::deserialize(&bytes).unwrap() else {
+            panic!("wrong variant");
+        };
+        assert_eq!(decoded.name, "test server");
+        assert_eq!(decoded.map, "arena");
+        assert_eq!(decoded.players, 3);
+        assert_eq!(decoded.port, 26000);
+        assert_eq!(decoded.version.game, "RustCycles");
+    }
+
+    #[test]
+    fn info_request_padding() {
+        let bytes = bincode::serialize(&InfoRequest::new(1.5)).unwrap();
+        assert_eq!(bytes.len(), INFO_REQUEST_LEN);
+
+        // The response to a request must fit into the same number of bytes.
+        let response = InfoResponse {
+            time: 1.5,
+            info: info(),
+        };
+        assert!(bincode::serialized_size(&response).unwrap() as usize <= INFO_REQUEST_LEN);
+    }
+
+    #[test]
+    fn server_list_query() {
+        let master = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let master_addr = master.local_addr().unwrap();
+
+        let mut query = ServerListQuery::new(master_addr).unwrap();
+
+        let mut buf = vec![0; MAX_DATAGRAM_LEN];
+        let (len, client) = master.recv_from(&mut buf).unwrap();
+        let request = bincode::deserialize(&buf[..len]).unwrap();
+        assert!(matches!(request, MasterRequest::Query));
+
+        let entry = ServerEntry {
+            addr: "127.0.0.1:26000".parse().unwrap(),
+            info: info(),
+        };
+        let response = MasterResponse::ServerList(vec![entry]);
+        master.send_to(&bincode::serialize(&response).unwrap(), client).unwrap();
+
+        for _ in 0..1000 {
+            if let Some(servers) = query.poll().unwrap() {
+                assert_eq!(servers.len(), 1);
+                assert_eq!(servers[0].addr.port(), 26000);
+                assert_eq!(servers[0].info.name, "test server");
+                return;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        panic!("no response");
+    }
+}
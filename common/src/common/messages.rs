@@ -0,0 +1,1176 @@
+//! Messages sent between the client and server, usually over the network.
+//!
+//! Server messages are also recorded in demos, see `common::demo`.
+//! LATER Demo playback.
+
+use std::time::{Duration, SystemTime};
+
+use fyrox_core::algebra::Quaternion;
+
+use crate::{
+    common::{Deg, Input},
+    debug::{
+        details::{DebugShape, WorldText},
+        logger::utc_timestamp,
+    },
+    prelude::*,
+};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub enum ClientMessage {
+    /// The first message on every remote connection, sent by the connection itself.
+    ///
+    /// Over TCP, it also carries the client's Noise handshake message
+    /// if it wants the rest of the connection encrypted, see `net::encryption`.
+    /// It's after the version so it doesn't change the format older versions parse.
+    Version(Version, Option<Vec<u8>>),
+    /// Sent every client frame.
+    ///
+    /// `seq` increases by one with each input so the server can tell if it missed any.
+    Input {
+        seq: u32,
+        input: NetInput,
+    },
+    Chat(String), // LATER Allow sending this
+    Join,
+    Observe,
+    /// Ask the server to send `ServerMessage::FullSync`, for example after detecting a desync.
+    RequestFullSync,
+    /// Start a vote, the caller automatically votes yes.
+    CallVote(VoteKind),
+    /// Vote yes (true) or no (false) in the current vote.
+    Vote(bool),
+    /// We don't have the server's map, ask it to send it in `ServerMessage::MapChunk`s.
+    RequestMap(String),
+    /// Set the player's name, sent after `Version` when connecting.
+    Name(String),
+    /// Identifies the player across sessions so stats can be saved, see `cl_guid`.
+    ///
+    /// It's a secret, the server only stores and shows its hash.
+    ///
+    /// Sent after `Name` when connecting.
+    Guid(String),
+    /// Ask for a player's saved stats, the server replies with `ServerMessage::Stats`.
+    ///
+    /// The player is a network ID or a name.
+    RequestStats(String),
+    /// The player is (or is no longer) ready to start the match, see `g_lobby`.
+    Ready(bool),
+    /// Pick a team in the lobby, None to play without one.
+    Team(Option<Team>),
+}
+
+/// A side players can pick in the lobby.
+///
+/// LATER Team game modes - for now teams are only shown in the lobby.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Team {
+    Red,
+    Blue,
+}
+
+impl Display for Team {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Red => write!(f, "Red"),
+            Self::Blue => write!(f, "Blue"),
+        }
+    }
+}
+
+/// What a vote is about.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum VoteKind {
+    /// Change to the map and start a new match.
+    Map(String),
+    /// Disconnect the player.
+    Kick { player_id: NetId },
+}
+
+impl VoteKind {
+    /// Parse the arguments of `callvote`, for example `map arena` or `kick 3`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut parts = s.split_whitespace();
+        let kind = match (parts.next(), parts.next()) {
+            (Some("map"), Some(map)) => Self::Map(map.to_owned()),
+            (Some("kick"), Some(id)) => {
+                let id = id.parse().map_err(|_| format!("invalid player ID {id:?}"))?;
+                Self::Kick {
+                    player_id: NetId(id),
+                }
+            }
+            _ => return Err(format!("invalid vote {s:?}, expected map <name> or kick <player>")),
+        };
+        if parts.next().is_some() {
+            return Err(format!("too many arguments in vote {s:?}"));
+        }
+        Ok(kind)
+    }
+}
+
+impl Display for VoteKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Map(map) => write!(f, "map {map}"),
+            Self::Kick { player_id } => write!(f, "kick player {player_id}"),
+        }
+    }
+}
+
+/// Description of the client or server version to determine compatibility.
+///
+/// This struct must remain stable across all versions
+/// so old versions can parse the message from new versions.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Version {
+    /// The name of the game, for example "RecWars" or "RustCycles".
+    /// Since they use very similar protocols, this is used to make sure
+    /// we're not accidentally connecting to the wrong game's server
+    /// and therefore save headaches debugging. Yes, this happened.
+    pub game: String,
+
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+
+    /// Pre-release identifier sometimes used in SemVer.
+    /// For example "alpha.1" or "rc.0".
+    pub pre: Option<String>,
+
+    /// Number of commits since the last tag.
+    pub commits: Option<u32>,
+
+    /// Git commit hash.
+    pub hash: Option<String>,
+
+    /// Whether the working directory was dirty when building.
+    pub dirty: Option<bool>,
+
+    /// Any extra unstructured information.
+    pub extra: Option<String>,
+}
+
+impl Version {
+    /// The version of this build.
+    pub fn current() -> Self {
+        let pre = env!("CARGO_PKG_VERSION_PRE");
+        Self {
+            game: "RustCycles".to_owned(),
+            major: env!("CARGO_PKG_VERSION_MAJOR").parse().unwrap(),
+            minor: env!("CARGO_PKG_VERSION_MINOR").parse().unwrap(),
+            patch: env!("CARGO_PKG_VERSION_PATCH").parse().unwrap(),
+            pre: if pre.is_empty() {
+                None
+            } else {
+                Some(pre.to_owned())
+            },
+            // These are only set in release builds by build.rs.
+            commits: option_env!("RUSTCYCLES_GIT_COMMITS").and_then(|s| s.parse().ok()),
+            hash: option_env!("RUSTCYCLES_GIT_HASH").map(str::to_owned),
+            dirty: option_env!("RUSTCYCLES_GIT_DIRTY").and_then(|s| s.parse().ok()),
+            extra: option_env!("RUSTCYCLES_BUILD_TIME").and_then(|s| s.parse().ok()).map(|secs| {
+                let time = SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+                format!("built {} UTC", utc_timestamp(time))
+            }),
+        }
+    }
+}
+
+impl Display for Version {
+    /// For example `RustCycles 0.1.0-alpha.1+5.deadbeef.dirty (built 2024-01-01 12:00:00 UTC)`.
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}.{}.{}", self.game, self.major, self.minor, self.patch)?;
+        if let Some(pre) = &self.pre {
+            write!(f, "-{pre}")?;
+        }
+        // SemVer build metadata.
+        let build: Vec<String> = [
+            self.commits.map(|commits| commits.to_string()),
+            self.hash.clone(),
+            (self.dirty == Some(true)).then(|| "dirty".to_owned()),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        if !build.is_empty() {
+            write!(f, "+{}", build.join("."))?;
+        }
+        if let Some(extra) = &self.extra {
+            write!(f, " ({extra})")?;
+        }
+        Ok(())
+    }
+}
+
+/// Identifies an entity in messages, the same on the server and all clients.
+///
+/// Pool indices can't be used for this because slots get reused
+/// so a late message could refer to a different entity than intended.
+/// IDs are allocated by the server and never reused, see `GameState::alloc_net_id`.
+/// Each side maps them to its own handles, see `NetIds`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+pub struct NetId(pub u32);
+
+impl Display for NetId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// LATER Since messages get serialized immediately, consider using slices instead of Vecs to avoid allocations.
+
+/// Message sent from server to client
+#[derive(Debug, Deserialize, Serialize)]
+pub enum ServerMessage {
+    // Like the client's, carries the response to its Noise handshake.
+    Version(Version, Option<Vec<u8>>),
+    /// Initial game state that is sent to a new player upon connecting.
+    ///
+    /// This is intentionally separate from `Spawn` messages
+    /// because eventually those might trigger additional effects
+    /// such as info messages, sounds, particles, etc.
+    Init(Init),
+    /// The server switched to a different map.
+    ///
+    /// The client should throw away its game state and wait for a new `Init`.
+    ChangeMap {
+        map: String,
+    },
+    /// Create a new entity, for example a player who connected or a cycle when respawning.
+    Spawn(Spawn),
+    /// Remove the entity and everything that belongs to it,
+    /// for example a player who disconnected along with his cycle.
+    Despawn {
+        kind: EntityKind,
+        net_id: NetId,
+    },
+    /// Points removed from the start and added to the end of the cycle's trail,
+    /// see `Cycle::trail`.
+    ///
+    /// Sent reliably because each point is only sent once.
+    /// After a cycle's `Spawn`, its whole trail follows with `removed` 0.
+    Trail {
+        cycle_id: NetId,
+        removed: u32,
+        added: Vec<SyncTrailPoint>,
+    },
+    /// This player is now observing.
+    Observe {
+        player_id: NetId,
+    },
+    /// This player is now spectating.
+    Spectate {
+        player_id: NetId,
+        spectatee_id: NetId,
+    },
+    /// This player is now playing.
+    Join {
+        player_id: NetId,
+    },
+    /// The cycle picked up the pickup, see `FrameCtx::apply_pickup`.
+    PickupTaken {
+        pickup_index: u32,
+        cycle_id: NetId,
+    },
+    /// The player died, the cycle is despawned separately.
+    Death {
+        player_id: NetId,
+        /// None for suicides and accidents.
+        killer_id: Option<NetId>,
+    },
+    /// The cycle took damage from something at `source`, used for hit feedback.
+    ///
+    /// Health itself is synced in `Update`.
+    Damage {
+        cycle_id: NetId,
+        damage: f32,
+        source: [f32; 3],
+    },
+    /// The player damaged someone, only sent to the attacker for hit confirmation.
+    DamageDealt {
+        attacker: NetId,
+        victim: NetId,
+        amount: f32,
+        /// Where the victim's cycle was.
+        pos: [f32; 3],
+    },
+    /// The match moved to the next phase, e.g. it ended and clients should show scores.
+    MatchState(MatchState),
+    /// The current vote changed, None when there's no vote in progress.
+    VoteStatus(Option<VoteStatus>),
+    /// Everyone in the lobby, sent whenever it changes, see `g_lobby`.
+    ///
+    /// Empty once the match starts.
+    Lobby(Vec<LobbyPlayer>),
+    /// Reply to `ClientMessage::RequestStats`, None if the player has no saved stats.
+    Stats {
+        query: String,
+        stats: Option<PlayerStats>,
+    },
+    /// Part of a map file requested by `ClientMessage::RequestMap`.
+    MapChunk(MapChunk),
+    /// Update the translations, rotations, velocities, etc. of everything.
+    Update(Update),
+    /// Replace the client's whole game state with the server's.
+    ///
+    /// Sent after init and whenever the client asks for it.
+    FullSync(FullSync),
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct VoteStatus {
+    pub kind: VoteKind,
+    pub yes: u32,
+    pub no: u32,
+    /// Game time when the vote fails if it doesn't have a majority by then.
+    pub time_end: f32,
+}
+
+/// One row of the lobby's player list.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LobbyPlayer {
+    pub player_id: NetId,
+    pub name: String,
+    pub team: Option<Team>,
+    pub ready: bool,
+}
+
+/// A player's totals over all saved matches, see `sv_stats_db`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct PlayerStats {
+    /// The name the player used most recently.
+    pub name: String,
+    /// Matches played until the end.
+    pub matches: u32,
+    pub kills: u32,
+    pub deaths: u32,
+    pub damage: f32,
+    /// Meters driven.
+    pub distance: f32,
+    /// Seconds spent playing (not observing or spectating).
+    pub time_played: f32,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct MapChunk {
+    pub map: String,
+    /// Where in the file this chunk starts, chunks are sent in order.
+    pub offset: u64,
+    /// Length of the whole file in bytes.
+    pub total_len: u64,
+    /// `maps::hash` of the whole file.
+    pub hash: u64,
+    pub data: Vec<u8>,
+}
+
+impl Debug for MapChunk {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        // Don't print the data, it's long and useless.
+        f.debug_struct("MapChunk")
+            .field("map", &self.map)
+            .field("offset", &self.offset)
+            .field("total_len", &self.total_len)
+            .field("hash", &self.hash)
+            .field("data_len", &self.data.len())
+            .finish()
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Init {
+    /// See `maps::MAPS`.
+    pub map: String,
+    /// Length of the server's gamelogic frames in seconds.
+    pub dt: f32,
+    pub local_player_id: NetId,
+    /// See `GameState::match_start`.
+    pub match_start: f32,
+    /// The client's own `g_warmup` and `g_lobby` don't matter, it has to use the server's state.
+    pub match_state: MatchState,
+    /// All existing entities, owners come before the entities that belong to them.
+    pub entities: Vec<Spawn>,
+    /// The server's message of the day, see `sv_motd`.
+    ///
+    /// Only sent in the first init after connecting, empty otherwise.
+    pub motd: String,
+}
+
+/// Types of entities the server replicates to clients
+/// using `ServerMessage::Spawn`, `ServerMessage::Despawn` and `Update::entities`.
+///
+/// Projectiles are not replicated, each side simulates them independently.
+/// Pickups come from the map so both sides already have them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum EntityKind {
+    Player,
+    Cycle,
+}
+
+/// Everything needed to create a replicated entity.
+///
+/// The rest of its state comes in `Update` or `FullSync`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Spawn {
+    pub kind: EntityKind,
+    pub net_id: NetId,
+    /// The player the entity belongs to, None for players themselves.
+    pub owner_id: Option<NetId>,
+    /// The player's name, None for other entities.
+    pub name: Option<String>,
+}
+
+/// Snapshot of the whole game state, see `FrameCtx::full_sync`.
+///
+/// Unlike `Update`, values are not quantized, the result should be the exact same state.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FullSync {
+    pub frame_num: u64,
+    /// See `GameState::next_net_id`.
+    pub next_net_id: u32,
+    pub game_time: f32,
+    pub game_time_prev: f32,
+    pub match_start: f32,
+    pub match_state: MatchState,
+    pub rng: Xoshiro256PlusPlus,
+    pub players: Vec<SyncPlayer>,
+    pub cycles: Vec<SyncCycle>,
+    pub projectiles: Vec<SyncProjectile>,
+    pub pickups: Vec<SyncPickup>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SyncPlayer {
+    pub player_id: NetId,
+    pub name: String,
+    pub state: SyncPlayerState,
+    pub input: Input,
+    pub kills: u32,
+    pub deaths: u32,
+    pub time_died: f32,
+}
+
+/// `PlayerState` with network IDs instead of handles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum SyncPlayerState {
+    Observing,
+    Spectating { spectatee_id: NetId },
+    Playing,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SyncCycle {
+    pub cycle_id: NetId,
+    pub player_id: NetId,
+    pub translation: [f32; 3],
+    /// Quaternion coords in the order `i, j, k, w`.
+    pub rotation: [f32; 4],
+    pub velocity: [f32; 3],
+    pub vel_prev: [f32; 3],
+    pub health: f32,
+    pub time_spawned: f32,
+    pub time_last_fired: f32,
+    pub boost_energy: f32,
+    pub time_last_jumped: f32,
+    pub ammo: u32,
+    pub speed_boost_until: f32,
+    pub damage_boost_until: f32,
+    pub trigger_prev: Option<u32>,
+    pub up: [f32; 3],
+    pub trail: Vec<SyncTrailPoint>,
+}
+
+/// See `TrailPoint`.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct SyncTrailPoint {
+    pub pos: [f32; 3],
+    pub up: [f32; 3],
+    pub time: f32,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SyncProjectile {
+    /// Projectiles are simulated independently on each side so they don't need network IDs,
+    /// the index only keeps the pool layouts identical.
+    pub projectile_index: u32,
+    /// None if the shooter disconnected since firing.
+    pub player_id: Option<NetId>,
+    pub pos: [f32; 3],
+    pub vel: [f32; 3],
+    pub time_fired: f32,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SyncPickup {
+    /// Pickups come from the map and are never removed
+    /// so their indices are the same on all sides.
+    pub pickup_index: u32,
+    pub time_taken: Option<f32>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Update {
+    /// The server's `GameState::frame_num` when this update was sent.
+    pub frame_num: u64,
+    /// `FrameCtx::net_checksum` at `frame_num`.
+    ///
+    /// None when the client doesn't get the whole game state,
+    /// see `sv_interest_radius` and `sv_visibility_culling`.
+    pub checksum: Option<u64>,
+    pub entities: Vec<EntityUpdate>,
+    pub debug_texts: Vec<String>,
+    pub debug_texts_world: Vec<WorldText>,
+    pub debug_shapes: Vec<DebugShape>,
+}
+
+/// The state of a replicated entity which changes every frame.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct EntityUpdate {
+    pub net_id: NetId,
+    pub state: EntityState,
+}
+
+/// See `EntityKind`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum EntityState {
+    Player(NetInput),
+    Cycle(CyclePhysics),
+}
+
+/// Compact wire format of `Input`.
+///
+/// Input is sent every frame so it's worth making it small.
+/// Bincode encodes each bool as a whole byte so we pack them into bitflags instead
+/// and quantize the angles to 16 bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct NetInput {
+    /// `Input::real_time`, not quantized.
+    pub real_time: u32,
+    /// `Input::game_time`, not quantized.
+    pub game_time: u32,
+    /// Normalized to `[0, 360)` degrees and mapped to the full range of u16.
+    pub yaw: u16,
+    pub yaw_speed: i16,
+    pub pitch: i16,
+    pub pitch_speed: i16,
+    /// See `Input::buttons_to_bits`.
+    pub buttons: u32,
+}
+
+/// Pitch is normally limited to [-90, 90] by cvars
+/// but allow more in case somebody changes them.
+const QUANT_PITCH_MAX: f32 = 180.0;
+
+/// Angular speeds in degrees per second larger than this get clamped.
+/// A fast flick can be a couple thousand.
+const QUANT_ANGLE_SPEED_MAX: f32 = 8192.0;
+
+impl From<Input> for NetInput {
+    fn from(input: Input) -> Self {
+        let yaw = input.yaw.normalized().0 / 360.0 * 65536.0;
+        Self {
+            // Times are stored as bits, not f32, so we can derive Eq.
+            real_time: input.real_time.to_bits(),
+            game_time: input.game_time.to_bits(),
+            // Cast to u32 first so 360° (after rounding) wraps around to 0.
+            yaw: yaw.round() as u32 as u16,
+            yaw_speed: quantize_signed(input.yaw_speed.0, QUANT_ANGLE_SPEED_MAX),
+            pitch: quantize_signed(input.pitch.0, QUANT_PITCH_MAX),
+            pitch_speed: quantize_signed(input.pitch_speed.0, QUANT_ANGLE_SPEED_MAX),
+            buttons: input.buttons_to_bits(),
+        }
+    }
+}
+
+impl From<NetInput> for Input {
+    fn from(net: NetInput) -> Self {
+        let mut input = Input {
+            real_time: f32::from_bits(net.real_time),
+            game_time: f32::from_bits(net.game_time),
+            yaw: Deg(net.yaw as f32 / 65536.0 * 360.0),
+            yaw_speed: Deg(dequantize_signed(net.yaw_speed, QUANT_ANGLE_SPEED_MAX)),
+            pitch: Deg(dequantize_signed(net.pitch, QUANT_PITCH_MAX)),
+            pitch_speed: Deg(dequantize_signed(net.pitch_speed, QUANT_ANGLE_SPEED_MAX)),
+            ..Default::default()
+        };
+        input.set_buttons_from_bits(net.buttons);
+        input
+    }
+}
+
+/// Physics state of a cycle in a compact wire format.
+///
+/// Use `QuantizedPos::encode` etc. to create it and `decode` to get the values back.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CyclePhysics {
+    pub translation: QuantizedPos,
+    pub rotation: QuantizedRot,
+    pub velocity: QuantizedVel,
+    /// Not physics but it changes every frame too and the HUD needs it.
+    pub boost_energy: f32,
+    pub health: f32,
+}
+
+// Quantization
+//
+// Full f32 vectors and quaternions are wasteful - 12 and 16 bytes per value
+// and we send them for every cycle every frame.
+// Using 16 bits per component is precise enough for rendering
+// and for the client to not notice corrections.
+//
+// LATER Measure if the error is noticeable at high speeds, maybe use more bits for positions.
+
+/// Positions outside these bounds get clamped when quantizing.
+///
+/// LATER Get the bounds from the map.
+pub const QUANT_POS_MIN: Vec3 = v!(-256, -256, -256);
+pub const QUANT_POS_MAX: Vec3 = v!(256 256 256);
+
+/// Velocities larger than this (per component, in m/s) get clamped when quantizing.
+pub const QUANT_VEL_MAX: f32 = 256.0;
+
+/// The smallest three components of a unit quaternion
+/// are always in this range (the largest is at least 0.5).
+const QUANT_ROT_MAX: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+/// A position quantized to 16 bit fixed point relative to the arena bounds.
+///
+/// The max error per component is half of `(QUANT_POS_MAX - QUANT_POS_MIN) / u16::MAX`,
+/// currently about 4 mm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct QuantizedPos(pub [u16; 3]);
+
+impl QuantizedPos {
+    pub fn encode(pos: Vec3) -> Self {
+        Self([
+            quantize_unsigned(pos.x, QUANT_POS_MIN.x, QUANT_POS_MAX.x),
+            quantize_unsigned(pos.y, QUANT_POS_MIN.y, QUANT_POS_MAX.y),
+            quantize_unsigned(pos.z, QUANT_POS_MIN.z, QUANT_POS_MAX.z),
+        ])
+    }
+
+    pub fn decode(self) -> Vec3 {
+        Vec3::new(
+            dequantize_unsigned(self.0[0], QUANT_POS_MIN.x, QUANT_POS_MAX.x),
+            dequantize_unsigned(self.0[1], QUANT_POS_MIN.y, QUANT_POS_MAX.y),
+            dequantize_unsigned(self.0[2], QUANT_POS_MIN.z, QUANT_POS_MAX.z),
+        )
+    }
+}
+
+/// A velocity quantized to 16 bit fixed point in the range `[-QUANT_VEL_MAX, QUANT_VEL_MAX]`.
+///
+/// Unlike positions, this is symmetric around zero so zero velocity is represented exactly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct QuantizedVel(pub [i16; 3]);
+
+impl QuantizedVel {
+    pub fn encode(vel: Vec3) -> Self {
+        Self([
+            quantize_signed(vel.x, QUANT_VEL_MAX),
+            quantize_signed(vel.y, QUANT_VEL_MAX),
+            quantize_signed(vel.z, QUANT_VEL_MAX),
+        ])
+    }
+
+    pub fn decode(self) -> Vec3 {
+        Vec3::new(
+            dequantize_signed(self.0[0], QUANT_VEL_MAX),
+            dequantize_signed(self.0[1], QUANT_VEL_MAX),
+            dequantize_signed(self.0[2], QUANT_VEL_MAX),
+        )
+    }
+}
+
+/// A rotation encoded using the "smallest three" method.
+///
+/// We send the index of the component with the largest absolute value
+/// and the other three components quantized to 16 bits.
+/// The largest component is then reconstructed using the fact that the quaternion is normalized.
+/// The quaternion is negated if needed so the largest component is positive
+/// (`q` and `-q` represent the same rotation).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct QuantizedRot {
+    /// Index into `Quaternion::coords`, which is ordered `i, j, k, w`.
+    pub largest: u8,
+    pub rest: [i16; 3],
+}
+
+impl QuantizedRot {
+    pub fn encode(rot: UnitQuaternion<f32>) -> Self {
+        let coords = rot.coords;
+        let mut largest = 0;
+        for i in 1..4 {
+            if coords[i].abs() > coords[largest].abs() {
+                largest = i;
+            }
+        }
+        let sign = if coords[largest] < 0.0 { -1.0 } else { 1.0 };
+
+        let mut rest = [0; 3];
+        let mut rest_i = 0;
+        for (i, &c) in coords.iter().enumerate() {
+            if i != largest {
+                rest[rest_i] = quantize_signed(c * sign, QUANT_ROT_MAX);
+                rest_i += 1;
+            }
+        }
+
+        Self {
+            largest: largest as u8,
+            rest,
+        }
+    }
+
+    pub fn decode(self) -> UnitQuaternion<f32> {
+        // Don't trust the index, it comes from the network.
+        let largest = usize::from(self.largest).min(3);
+
+        let mut coords = Vector4::zeros();
+        let mut sum_sq = 0.0;
+        let mut rest_i = 0;
+        for i in 0..4 {
+            if i != largest {
+                let c = dequantize_signed(self.rest[rest_i], QUANT_ROT_MAX);
+                coords[i] = c;
+                sum_sq += c * c;
+                rest_i += 1;
+            }
+        }
+        coords[largest] = (1.0 - sum_sq).max(0.0).sqrt();
+
+        // Normalize again to get rid of the accumulated error.
+        UnitQuaternion::new_normalize(Quaternion::from_vector(coords))
+    }
+}
+
+fn quantize_unsigned(value: f32, min: f32, max: f32) -> u16 {
+    let t = ((value - min) / (max - min)).clamp(0.0, 1.0);
+    // NaN gets clamped to NaN and then cast to 0 which is fine.
+    (t * u16::MAX as f32).round() as u16
+}
+
+fn dequantize_unsigned(q: u16, min: f32, max: f32) -> f32 {
+    min + q as f32 / u16::MAX as f32 * (max - min)
+}
+
+fn quantize_signed(value: f32, max: f32) -> i16 {
+    let t = (value / max).clamp(-1.0, 1.0);
+    (t * i16::MAX as f32).round() as i16
+}
+
+fn dequantize_signed(q: i16, max: f32) -> f32 {
+    // i16::MIN is never produced by quantize_signed but could come from the network.
+    q.max(-i16::MAX) as f32 / i16::MAX as f32 * max
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{angle_diff, net, INPUT_BUTTONS_COUNT};
+
+    use super::*;
+
+    #[test]
+    fn net_input_buttons_round_trip() {
+        for i in 0..INPUT_BUTTONS_COUNT {
+            let mut input = Input::default();
+            *input.buttons_mut()[i] = true;
+
+            let net = NetInput::from(input);
+            assert_eq!(net.buttons, 1 << i);
+
+            let decoded = Input::from(net);
+            assert_eq!(decoded.buttons(), input.buttons());
+        }
+
+        let mut input = Input::default();
+        input.fire1 = true;
+        input.kill = true;
+        input.screenshot = true;
+        let decoded = Input::from(NetInput::from(input));
+        assert!(decoded.fire1);
+        assert!(decoded.kill);
+        assert!(decoded.screenshot);
+        assert_eq!(decoded.buttons().iter().filter(|&&b| b).count(), 3);
+
+        // Unknown bits (e.g. from a newer version) are ignored.
+        let mut net = NetInput::from(Input::default());
+        net.buttons = u32::MAX;
+        let decoded = Input::from(net);
+        assert!(decoded.buttons().iter().all(|&b| b));
+        assert_eq!(NetInput::from(decoded).buttons, (1 << INPUT_BUTTONS_COUNT) - 1);
+    }
+
+    #[test]
+    fn net_input_angles_round_trip() {
+        let yaw_err = 360.0 / 65536.0 / 2.0 + 1e-3;
+        let pitch_err = QUANT_PITCH_MAX / i16::MAX as f32 / 2.0 + 1e-4;
+        let speed_err = QUANT_ANGLE_SPEED_MAX / i16::MAX as f32 / 2.0 + 1e-3;
+
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(42);
+        for _ in 0..1000 {
+            let input = Input {
+                real_time: rng.gen_range(0.0..10_000.0),
+                game_time: rng.gen_range(0.0..10_000.0),
+                yaw: Deg(rng.gen_range(-1000.0..1000.0)),
+                yaw_speed: Deg(rng.gen_range(-5000.0..5000.0)),
+                pitch: Deg(rng.gen_range(-90.0..90.0)),
+                pitch_speed: Deg(rng.gen_range(-5000.0..5000.0)),
+                ..Default::default()
+            };
+            let decoded = Input::from(NetInput::from(input));
+
+            assert_eq!(decoded.real_time, input.real_time);
+            assert_eq!(decoded.game_time, input.game_time);
+
+            // Yaw gets normalized so compare the difference modulo 360.
+            let diff = angle_diff(input.yaw, decoded.yaw).0.abs();
+            assert!(diff <= yaw_err, "yaw {} decoded {} diff {}", input.yaw, decoded.yaw, diff);
+
+            let diff = (decoded.pitch.0 - input.pitch.0).abs();
+            assert!(diff <= pitch_err, "pitch {} decoded {}", input.pitch, decoded.pitch);
+            let diff = (decoded.yaw_speed.0 - input.yaw_speed.0).abs();
+            assert!(diff <= speed_err);
+            let diff = (decoded.pitch_speed.0 - input.pitch_speed.0).abs();
+            assert!(diff <= speed_err);
+        }
+
+        // Yaw just below 360 must wrap around to 0, not overflow.
+        let input = Input {
+            yaw: Deg(359.9999),
+            ..Default::default()
+        };
+        assert_eq!(NetInput::from(input).yaw, 0);
+    }
+
+    #[test]
+    fn net_input_size() {
+        let input = Input {
+            fire1: true,
+            ..Default::default()
+        };
+        let old = bincode::serialize(&input).unwrap();
+        let new = bincode::serialize(&NetInput::from(input)).unwrap();
+        assert_eq!(old.len(), 6 * 4 + INPUT_BUTTONS_COUNT);
+        assert_eq!(new.len(), 4 + 4 + 2 + 2 + 2 + 2 + 4);
+    }
+
+    #[test]
+    fn quantized_pos_round_trip() {
+        let max_err = (QUANT_POS_MAX - QUANT_POS_MIN).max() / u16::MAX as f32 / 2.0 + 1e-4;
+
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(42);
+        let mut positions = vec![
+            Vec3::zeros(),
+            QUANT_POS_MIN,
+            QUANT_POS_MAX,
+            v!(0.001 -0.001 12.345),
+        ];
+        for _ in 0..1000 {
+            positions.push(Vec3::new(
+                rng.gen_range(QUANT_POS_MIN.x..QUANT_POS_MAX.x),
+                rng.gen_range(QUANT_POS_MIN.y..QUANT_POS_MAX.y),
+                rng.gen_range(QUANT_POS_MIN.z..QUANT_POS_MAX.z),
+            ));
+        }
+
+        for pos in positions {
+            let decoded = QuantizedPos::encode(pos).decode();
+            let err = (decoded - pos).abs().max();
+            assert!(err <= max_err, "pos {pos:?} decoded {decoded:?} err {err}");
+        }
+    }
+
+    #[test]
+    fn quantized_pos_clamps() {
+        let decoded = QuantizedPos::encode(v!(-1000 0 1000)).decode();
+        assert_eq!(decoded.x, QUANT_POS_MIN.x);
+        assert_eq!(decoded.z, QUANT_POS_MAX.z);
+    }
+
+    #[test]
+    fn quantized_vel_round_trip() {
+        let max_err = QUANT_VEL_MAX / i16::MAX as f32 / 2.0 + 1e-4;
+
+        // Zero must survive exactly, otherwise stationary cycles would drift on the client.
+        assert_eq!(QuantizedVel::encode(Vec3::zeros()).decode(), Vec3::zeros());
+
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(42);
+        for _ in 0..1000 {
+            let vel = Vec3::new(
+                rng.gen_range(-QUANT_VEL_MAX..QUANT_VEL_MAX),
+                rng.gen_range(-QUANT_VEL_MAX..QUANT_VEL_MAX),
+                rng.gen_range(-QUANT_VEL_MAX..QUANT_VEL_MAX),
+            );
+            let decoded = QuantizedVel::encode(vel).decode();
+            let err = (decoded - vel).abs().max();
+            assert!(err <= max_err, "vel {vel:?} decoded {decoded:?} err {err}");
+        }
+
+        let decoded = QuantizedVel::encode(v!(1000 -1000 0)).decode();
+        assert_eq!(decoded, v!(QUANT_VEL_MAX, -QUANT_VEL_MAX, 0));
+    }
+
+    #[test]
+    fn quantized_rot_round_trip() {
+        // Less than 0.01 degrees.
+        let max_err = 0.01_f32.to_radians();
+
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(42);
+        let mut rots = vec![
+            UnitQuaternion::identity(),
+            UnitQuaternion::from_axis_angle(&UP_AXIS, PI),
+            UnitQuaternion::from_axis_angle(&UP_AXIS, -PI / 2.0),
+            UnitQuaternion::from_axis_angle(&LEFT_AXIS, PI / 4.0),
+            // Negative w - must get flipped.
+            UnitQuaternion::new_unchecked(Quaternion::new(-1.0, 0.0, 0.0, 0.0)),
+        ];
+        for _ in 0..1000 {
+            rots.push(UnitQuaternion::from_ypr(
+                rng.gen_range(-PI..PI),
+                rng.gen_range(-PI..PI),
+                rng.gen_range(-PI..PI),
+            ));
+        }
+
+        for rot in rots {
+            let decoded = QuantizedRot::encode(rot).decode();
+            let err = rot.angle_to(&decoded);
+            assert!(err <= max_err, "rot {rot:?} decoded {decoded:?} err {err}");
+        }
+    }
+
+    #[test]
+    fn quantized_rot_garbage() {
+        // Data from the network can be anything, it must not produce NaNs or panic.
+        let q = QuantizedRot {
+            largest: 255,
+            rest: [i16::MIN, i16::MAX, i16::MAX],
+        };
+        let decoded = q.decode();
+        assert!(decoded.coords.iter().all(|c| c.is_finite()));
+    }
+
+    #[test]
+    fn handshake_version_format() {
+        // Chech this one message always has the same binary format
+        // between different versions of the game.
+
+        // Note that if we need to break compatibility
+        // (e.g. by not using bincode),
+        // there are still options left open.
+        // The first 4 bytes (length) will never by less than 4
+        // and the first message should be short anyway,
+        // so we can always use a very low or high number
+        // to indicate a different format.
+
+        let v1 = Version {
+            game: "RecWars".to_owned(),
+            major: 0x45,
+            minor: 0x1a4,
+            patch: 0x539,
+            pre: None,
+            commits: None,
+            hash: None,
+            dirty: None,
+            extra: None,
+        };
+        let v2 = Version {
+            game: "RustCycles".to_owned(),
+            major: 0x55555555,
+            minor: 0xaaaaaaaa,
+            patch: 0xffffffff,
+            pre: Some("alpha.1".to_owned()),
+            commits: Some(0x29a),
+            hash: Some("deadbeef".to_owned()),
+            dirty: Some(true),
+            extra: Some(
+                "MOTD: I learned a lot from my mistakes so i decided to make more mistakes to learn more.".to_owned(),
+            ),
+        };
+        let msg1 = ClientMessage::Version(v1, None);
+        let msg2 = ClientMessage::Version(v2, Some(vec![0x42]));
+        let serialized1 = net::serialize(msg1);
+        let serialized2 = net::serialize(msg2);
+
+        for b in &serialized1.bytes {
+            print!("{:02x} ", b);
+        }
+        println!();
+
+        for b in &serialized2.bytes {
+            print!("{:02x} ", b);
+        }
+        println!();
+
+        assert_eq!(
+            serialized1.bytes,
+            [
+                0x29, 0x00, 0x00, 0x00, // total len
+                0x00, 0x00, 0x00, 0x00, // message variant
+                0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // name len
+                0x52, 0x65, 0x63, 0x57, 0x61, 0x72, 0x73, // "RecWars"
+                0x45, 0x00, 0x00, 0x00, // major
+                0xa4, 0x01, 0x00, 0x00, // minor
+                0x39, 0x05, 0x00, 0x00, // patch
+                0x00, // pre - None
+                0x00, // commits - None
+                0x00, // hash - None
+                0x00, // dirty - None
+                0x00, // extra - None
+                // Everything after the version is ignored by older versions.
+                0x00, // handshake - None
+            ]
+        );
+        assert_eq!(
+            serialized2.bytes,
+            [
+                0xb9, 0x00, 0x00, 0x00, // total len
+                0x00, 0x00, 0x00, 0x00, // message variant
+                0x0a, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // name len
+                0x52, 0x75, 0x73, 0x74, 0x43, 0x79, 0x63, 0x6c, 0x65, 0x73, // "RustCycles"
+                0x55, 0x55, 0x55, 0x55, // major
+                0xaa, 0xaa, 0xaa, 0xaa, // minor
+                0xff, 0xff, 0xff, 0xff, // patch
+                0x01, // pre - Some
+                0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // pre len
+                0x61, 0x6c, 0x70, 0x68, 0x61, 0x2e, 0x31, // "alpha.1"
+                0x01, // commits - Some
+                0x9a, 0x02, 0x00, 0x00, // commits
+                0x01, // hash - Some
+                0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // hash len
+                0x64, 0x65, 0x61, 0x64, 0x62, 0x65, 0x65, 0x66, // "deadbeef"
+                0x01, // dirty - Some
+                0x01, // dirty
+                0x01, // extra - Some
+                0x58, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // extra len
+                0x4d, 0x4f, 0x54, 0x44, 0x3a, 0x20, 0x49, 0x20, 0x6c, 0x65, 0x61, 0x72, 0x6e, 0x65,
+                0x64, 0x20, 0x61, 0x20, 0x6c, 0x6f, 0x74, 0x20, 0x66, 0x72, 0x6f, 0x6d, 0x20, 0x6d,
+                0x79, 0x20, 0x6d, 0x69, 0x73, 0x74, 0x61, 0x6b, 0x65, 0x73, 0x20, 0x73, 0x6f, 0x20,
+                0x69, 0x20, 0x64, 0x65, 0x63, 0x69, 0x64, 0x65, 0x64, 0x20, 0x74, 0x6f, 0x20, 0x6d,
+                0x61, 0x6b, 0x65, 0x20, 0x6d, 0x6f, 0x72, 0x65, 0x20, 0x6d, 0x69, 0x73, 0x74, 0x61,
+                0x6b, 0x65, 0x73, 0x20, 0x74, 0x6f, 0x20, 0x6c, 0x65, 0x61, 0x72, 0x6e, 0x20, 0x6d,
+                0x6f, 0x72, 0x65, 0x2e, 0x01, // handshake - Some
+                0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // handshake len
+                0x42, // handshake
+            ]
+        )
+    }
+
+    #[test]
+    fn version_display() {
+        let mut version = Version {
+            game: "RustCycles".to_owned(),
+            major: 0,
+            minor: 1,
+            patch: 2,
+            pre: None,
+            commits: None,
+            hash: None,
+            dirty: None,
+            extra: None,
+        };
+        assert_eq!(version.to_string(), "RustCycles 0.1.2");
+
+        version.pre = Some("alpha.1".to_owned());
+        version.commits = Some(5);
+        version.hash = Some("deadbeef".to_owned());
+        version.dirty = Some(true);
+        version.extra = Some("built 2024-01-01 12:00:00 UTC".to_owned());
+        assert_eq!(
+            version.to_string(),
+            "RustCycles 0.1.2-alpha.1+5.deadbeef.dirty (built 2024-01-01 12:00:00 UTC)"
+        );
+    }
+
+    #[test]
+    fn vote_kind_parse() {
+        assert_eq!(VoteKind::parse("map arena"), Ok(VoteKind::Map("arena".to_owned())));
+        assert_eq!(
+            VoteKind::parse(" kick  3 "),
+            Ok(VoteKind::Kick {
+                player_id: NetId(3)
+            })
+        );
+        assert!(VoteKind::parse("kick bob").is_err());
+        assert!(VoteKind::parse("map").is_err());
+        assert!(VoteKind::parse("map a b").is_err());
+        assert!(VoteKind::parse("restart").is_err());
+    }
+
+    #[test]
+    fn full_sync_round_trip() {
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(42);
+        rng.next_u64();
+        let sync = FullSync {
+            frame_num: 1234,
+            next_net_id: 4,
+            game_time: 20.5,
+            game_time_prev: 20.25,
+            match_start: 5.0,
+            match_state: MatchState::Playing,
+            rng: rng.clone(),
+            players: vec![SyncPlayer {
+                player_id: NetId(3),
+                name: "Player".to_owned(),
+                state: SyncPlayerState::Spectating {
+                    spectatee_id: NetId(1),
+                },
+                input: Input {
+                    yaw: Deg(12.345),
+                    fire1: true,
+                    ..Default::default()
+                },
+                kills: 4,
+                deaths: 2,
+                time_died: 15.0,
+            }],
+            cycles: vec![SyncCycle {
+                cycle_id: NetId(2),
+                player_id: NetId(3),
+                translation: [1.0, 2.5, -3.0],
+                rotation: [0.0, 0.6, 0.0, 0.8],
+                velocity: [0.1, 0.0, -1e-7],
+                vel_prev: [0.2, 0.0, -1e-7],
+                health: 42.5,
+                time_spawned: 16.0,
+                time_last_fired: 19.0,
+                boost_energy: 50.0,
+                time_last_jumped: 18.0,
+                ammo: 150,
+                speed_boost_until: 25.0,
+                damage_boost_until: 0.0,
+                trigger_prev: Some(1),
+                up: [0.0, 1.0, 0.0],
+                trail: vec![SyncTrailPoint {
+                    pos: [1.0, 2.0, -3.0],
+                    up: [0.0, 1.0, 0.0],
+                    time: 19.5,
+                }],
+            }],
+            projectiles: Vec::new(),
+            pickups: vec![SyncPickup {
+                pickup_index: 0,
+                time_taken: Some(10.0),
+            }],
+        };
+
+        let bytes = bincode::serialize(&sync).unwrap();
+        let mut decoded: FullSync = bincode::deserialize(&bytes).unwrap();
+
+        assert_eq!(decoded.frame_num, 1234);
+        assert_eq!(decoded.game_time_prev, 20.25);
+        assert_eq!(
+            decoded.players[0].state,
+            SyncPlayerState::Spectating {
+                spectatee_id: NetId(1)
+            }
+        );
+        assert_eq!(decoded.players[0].input.yaw.0, 12.345);
+        assert!(decoded.players[0].input.fire1);
+        // Full precision, unlike `Update`.
+        assert_eq!(decoded.cycles[0].velocity, [0.1, 0.0, -1e-7]);
+        assert_eq!(decoded.cycles[0].trail[0].time, 19.5);
+        // The RNG must continue exactly where the server's left off.
+        assert_eq!(decoded.rng.next_u64(), rng.next_u64());
+    }
+}
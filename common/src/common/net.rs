@@ -0,0 +1,1146 @@
+//! Networking listeners and connections. TCP (remote) and mpsc (local).
+//!
+//! We could use TCP locally too but WASM doesn't support it so we use mpsc.
+//! Browsers can't use TCP for remote connections either so they use WebSockets,
+//! see the `websocket` module, optionally with a WebRTC data channel
+//! for messages which don't need to arrive, see the `rtc` module.
+//!
+//! LATER Long term the plan is to switch away from TCP but honestly as long as it works, it's not a priority.
+//! The common wisdom to never use TCP for games doesn't seem to apply on modern networks.
+//! Veloren has been using TCP for years and nobody complains because nobody even notices.
+
+// This file is shared between RecWars and RustCycles
+// to keep their networking APIs the same
+// and as an experiment to see how much code is shareable
+// between significantly different multiplayer games.
+
+mod encryption;
+mod rtc;
+mod websocket;
+
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    iter, mem,
+    net::{IpAddr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::mpsc::{self, Receiver, Sender, TryRecvError},
+    thread,
+    time::Duration,
+};
+
+// Unlike std's, this one also works in the browser.
+use fyrox_core::instant::Instant;
+use serde::de::DeserializeOwned;
+
+use crate::{
+    common::net::{
+        encryption::{Encryption, Handshake},
+        websocket::WsStream,
+    },
+    debug::{self, DebugCounter},
+    prelude::*,
+};
+
+#[cfg(target_arch = "wasm32")]
+pub use websocket::WebSocketConnection;
+pub use websocket::WebSocketListener;
+
+/// A trait to abstract over local and remote listeners.
+///
+/// Note: ideally only the function would be generic over the message type
+/// but then the trait wouldn't be object safe so we have to make the whole trait genetic.
+/// The same reasning applies to `Connection<M>`.
+pub trait Listener<M>
+where
+    M: DeserializeOwned,
+{
+    fn accept_conn(&mut self) -> io::Result<Box<dyn Connection<M>>>;
+}
+
+pub struct LocalListener {
+    conn: Option<LocalConnection>,
+}
+
+impl LocalListener {
+    pub fn new(conn: LocalConnection) -> Self {
+        Self { conn: Some(conn) }
+    }
+}
+
+impl<M> Listener<M> for LocalListener
+where
+    M: DeserializeOwned,
+{
+    fn accept_conn(&mut self) -> io::Result<Box<dyn Connection<M>>> {
+        let conn = self.conn.take();
+        match conn {
+            Some(conn) => Ok(Box::new(conn)),
+            None => Err(io::Error::new(ErrorKind::WouldBlock, "dummy")),
+        }
+    }
+}
+
+// Note we use the TcpListener from std here, not a custom type,
+// no point adding an extra type.
+impl<M> Listener<M> for TcpListener
+where
+    M: DeserializeOwned,
+{
+    fn accept_conn(&mut self) -> io::Result<Box<dyn Connection<M>>> {
+        let (stream, addr) = self.accept()?;
+        let addr = canonical_addr(addr);
+
+        // LATER Measure if nodelay actually makes a difference,
+        // or better yet, replace TCP with something better.
+        // Same on the client.
+        // Also how does it interact with flushing the stram after each write?
+        stream.set_nodelay(true).unwrap();
+        stream.set_nonblocking(true).unwrap();
+
+        let conn = TcpConnection::accepted(stream, addr);
+        Ok(Box::new(conn))
+    }
+}
+
+// It might be tempting to save 2 bytes by using u16
+// but init/update in RustCycles can easily get large enough to overflow it.
+type MsgLen = u32;
+const HEADER_LEN: usize = mem::size_of::<MsgLen>();
+
+/// How much data the server is willing to queue for a client
+/// before it knows whether the connection is encrypted.
+const MAX_PENDING_LEN: usize = 16 * 1024 * 1024;
+
+/// How long the server waits for a client's version (and the WebSocket handshake before it).
+///
+/// The client already has a player so one which connects and never sends anything
+/// would otherwise keep it forever.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Max length of the `Version` messages which start a connection.
+///
+/// They're parsed before the game's limits apply.
+/// A Noise handshake message is 32 bytes, the rest is the version.
+const MAX_VERSION_LEN: usize = 4 * 1024;
+
+#[derive(Debug, Clone, Default)]
+pub struct NetworkMessage {
+    /// Serialized message prefixed by length.
+    /// The length includes the length field itself.
+    pub bytes: Vec<u8>,
+}
+
+/// A trait to abstract over local and remove connections.
+pub trait Connection<M>
+where
+    M: DeserializeOwned,
+{
+    fn send(&mut self, net_msg: &NetworkMessage) -> Result<(), io::Error>;
+
+    /// Send a message which doesn't have to arrive,
+    /// e.g. because a newer one with the same data follows soon.
+    ///
+    /// It can also arrive before messages sent earlier using `send`.
+    /// Connections without a separate unreliable channel just send it normally.
+    fn send_unreliable(&mut self, net_msg: &NetworkMessage) -> Result<(), io::Error> {
+        self.send(net_msg)
+    }
+
+    // `#[must_use]` only does something in the trait definition,
+    // no need to repeat it in the impls:
+    // https://github.com/rust-lang/rust/issues/48486
+
+    /// Read all available messages and return them.
+    ///
+    /// Also return whether the connection has been closed (doesn't matter if cleanly or reading failed).
+    /// Receiving a malformed message or one longer than `max_len` is treated as if the connection was closed,
+    /// the caller should drop the connection.
+    #[must_use]
+    fn receive(&mut self, max_len: usize) -> (Vec<M>, bool);
+
+    /// Read one message if available or return None.
+    ///
+    /// Also return whether the connection has been closed (doesn't matter if cleanly or reading failed).
+    /// Malformed messages are handled the same way as in `receive`.
+    #[must_use]
+    fn receive_one(&mut self, max_len: usize) -> (Option<M>, bool);
+
+    #[must_use]
+    fn addr(&self) -> String;
+
+    /// Whether the connection has been negotiated to send unencrypted data over the network.
+    ///
+    /// Returns false while still negotiating and for local connections
+    /// since they never leave the process.
+    #[must_use]
+    fn is_plaintext(&self) -> bool;
+}
+
+/// Send and receive serialized messages locally using mpsc.
+///
+/// It would be more efficient to avoid serialization entirely but:
+/// - It would require a bigger redesign.
+/// - We need to serialize them for demos/replays anyway.
+pub struct LocalConnection {
+    pub sender: Sender<NetworkMessage>,
+    pub receiver: Receiver<NetworkMessage>,
+}
+
+impl LocalConnection {
+    pub fn new(sender: Sender<NetworkMessage>, receiver: Receiver<NetworkMessage>) -> Self {
+        Self { sender, receiver }
+    }
+}
+
+impl<M> Connection<M> for LocalConnection
+where
+    M: DeserializeOwned,
+{
+    fn send(&mut self, net_msg: &NetworkMessage) -> Result<(), io::Error> {
+        // The channel needs its own copy.
+        count_send_copy();
+        self.sender.send(net_msg.clone()).unwrap();
+        Ok(())
+    }
+
+    fn receive(&mut self, max_len: usize) -> (Vec<M>, bool) {
+        let mut msgs = Vec::new();
+        loop {
+            let (msg, closed) = self.receive_one(max_len);
+            if let Some(msg) = msg {
+                msgs.push(msg);
+            } else {
+                // If closed is ever gonna be true,
+                // it's gonna be on the last iteraton
+                // so it doesn't matter we throw away the earlier values.
+                return (msgs, closed);
+            }
+        }
+    }
+
+    fn receive_one(&mut self, max_len: usize) -> (Option<M>, bool) {
+        let res = self.receiver.try_recv();
+        match res {
+            Ok(msg) => {
+                // The other side is in the same process so it's unlikely to be malicious
+                // but it can still be buggy.
+                let res = if msg.bytes.len() > max_len {
+                    Err(ParseError::TooLong {
+                        len: msg.bytes.len(),
+                        max_len,
+                    })
+                } else {
+                    deserialize(&msg.bytes[HEADER_LEN..])
+                };
+                match res {
+                    Ok(msg) => (Some(msg), false),
+                    Err(e) => {
+                        dbg_logf!("Failed to parse local message: {}", e);
+                        (None, true)
+                    }
+                }
+            }
+            Err(TryRecvError::Empty) => (None, false),
+            Err(TryRecvError::Disconnected) => (None, true),
+        }
+    }
+
+    fn addr(&self) -> String {
+        "local".to_owned()
+    }
+
+    fn is_plaintext(&self) -> bool {
+        false
+    }
+}
+
+pub struct TcpConnection {
+    stream: NetStream,
+    /// Bytes as they arrived from the network, possibly encrypted.
+    raw: VecDeque<u8>,
+    /// Plaintext bytes ready to be parsed into messages.
+    buffer: VecDeque<u8>,
+    state: TcpState,
+    /// Data sent by the server before the client's version arrived.
+    pending: Vec<u8>,
+    /// When the connection was accepted or established.
+    created: Instant,
+    pub addr: SocketAddr,
+}
+
+/// The byte stream under a `TcpConnection`.
+enum NetStream {
+    Tcp(TcpStream),
+    /// Server side only - browsers connect using WebSockets.
+    WebSocket(WsStream),
+}
+
+impl Read for NetStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            NetStream::Tcp(stream) => stream.read(buf),
+            NetStream::WebSocket(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for NetStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            NetStream::Tcp(stream) => stream.write(buf),
+            NetStream::WebSocket(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            NetStream::Tcp(stream) => stream.flush(),
+            NetStream::WebSocket(stream) => stream.flush(),
+        }
+    }
+}
+
+enum TcpState {
+    /// Server side only - waiting for the client's version
+    /// to find out whether it wants encryption.
+    Negotiating,
+    Plaintext,
+    Encrypted(Box<Encryption>),
+}
+
+/// Send and receive serialized messages over the network using TCP,
+/// either directly or wrapped in WebSocket frames.
+impl TcpConnection {
+    /// Create a client side connection after the version has been sent
+    /// and the encryption handshake (if any) has finished.
+    ///
+    /// `raw` is what arrived after the handshake.
+    fn new(
+        stream: TcpStream,
+        addr: SocketAddr,
+        encryption: Option<Encryption>,
+        raw: VecDeque<u8>,
+    ) -> Self {
+        let state = match encryption {
+            Some(encryption) => TcpState::Encrypted(Box::new(encryption)),
+            None => TcpState::Plaintext,
+        };
+        Self {
+            stream: NetStream::Tcp(stream),
+            raw,
+            buffer: VecDeque::new(),
+            state,
+            pending: Vec::new(),
+            created: Instant::now(),
+            addr,
+        }
+    }
+
+    /// Create a server side connection which waits for the client's version.
+    pub fn accepted(stream: TcpStream, addr: SocketAddr) -> Self {
+        Self::accepted_stream(NetStream::Tcp(stream), addr)
+    }
+
+    /// Create a server side connection from a browser.
+    ///
+    /// The client sends its version the same way as over TCP once the WebSocket handshake is done.
+    fn accepted_websocket(stream: WsStream, addr: SocketAddr) -> Self {
+        Self::accepted_stream(NetStream::WebSocket(stream), addr)
+    }
+
+    fn accepted_stream(stream: NetStream, addr: SocketAddr) -> Self {
+        Self {
+            stream,
+            raw: VecDeque::new(),
+            buffer: VecDeque::new(),
+            state: TcpState::Negotiating,
+            pending: Vec::new(),
+            created: Instant::now(),
+            addr,
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        match &mut self.state {
+            TcpState::Negotiating => {
+                if self.pending.len() + bytes.len() > MAX_PENDING_LEN {
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        "too much data queued before the client sent its version",
+                    ));
+                }
+                self.pending.extend(bytes);
+                return Ok(());
+            }
+            TcpState::Plaintext => self.stream.write_all(bytes)?,
+            TcpState::Encrypted(encryption) => {
+                let mut encrypted = Vec::new();
+                encryption.encrypt(bytes, &mut encrypted)?;
+                self.stream.write_all(&encrypted)?;
+            }
+        }
+        self.stream.flush()?; // LATER No idea if necessary or how it interacts with set_nodelay
+        Ok(())
+    }
+
+    /// Read all available bytes, decrypt them if needed and put them into `buffer`.
+    ///
+    /// Return whether the connection has been closed.
+    fn read_all(&mut self) -> bool {
+        let mut closed = read(&mut self.stream, &mut self.raw);
+
+        if let TcpState::Negotiating = self.state {
+            if let Err(e) = self.negotiate() {
+                dbg_logf!("Failed to negotiate connection with {}: {}", self.addr, e);
+                return true;
+            }
+        }
+        if matches!(self.state, TcpState::Negotiating) && self.created.elapsed() > HANDSHAKE_TIMEOUT
+        {
+            dbg_logf!("{} didn't send its version in time", self.addr);
+            return true;
+        }
+
+        match &mut self.state {
+            TcpState::Negotiating => {}
+            TcpState::Plaintext => self.buffer.extend(self.raw.drain(..)),
+            TcpState::Encrypted(encryption) => {
+                if let Err(e) = encryption.decrypt(&mut self.raw, &mut self.buffer) {
+                    dbg_logf!("Failed to decrypt data from {}: {}", self.addr, e);
+                    closed = true;
+                }
+            }
+        }
+
+        closed
+    }
+
+    /// Look at the client's version once it has arrived
+    /// and do the encryption handshake if the client started one.
+    ///
+    /// The version itself is left for the game to receive like any other message.
+    fn negotiate(&mut self) -> io::Result<()> {
+        let mut raw = self.raw.clone();
+        let msg = parse_one::<ClientMessage>(&mut raw, MAX_VERSION_LEN)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e.to_string()))?;
+        let version_len = self.raw.len() - raw.len();
+        match msg {
+            // Wait for the rest of the version.
+            None => return Ok(()),
+            Some(ClientMessage::Version(_, None)) => {
+                self.state = TcpState::Plaintext;
+            }
+            Some(ClientMessage::Version(_, Some(handshake))) => {
+                let (encryption, response) = Encryption::respond(&handshake)?;
+                // This is the last thing sent in plaintext.
+                let version = serialize(ServerMessage::Version(Version::current(), Some(response)));
+                self.stream.write_all(&version.bytes)?;
+                self.state = TcpState::Encrypted(Box::new(encryption));
+            }
+            Some(_) => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    "the first message is not the client's version",
+                ));
+            }
+        }
+        self.buffer.extend(self.raw.drain(0..version_len));
+
+        let pending = mem::take(&mut self.pending);
+        self.write(&pending)
+    }
+
+    /// Parse one message a browser sent over WebRTC.
+    ///
+    /// They're ignored until the client's version says it's not using Noise.
+    fn receive_unreliable<M>(&mut self, max_len: usize, closed: &mut bool) -> Option<M>
+    where
+        M: DeserializeOwned,
+    {
+        let NetStream::WebSocket(stream) = &self.stream else {
+            return None;
+        };
+        let bytes = stream.receive_unreliable()?;
+        if !matches!(self.state, TcpState::Plaintext) {
+            return None;
+        }
+        parse_datagram(bytes, max_len, &self.addr, closed)
+    }
+}
+
+impl<M> Connection<M> for TcpConnection
+where
+    M: DeserializeOwned,
+{
+    fn send(&mut self, net_msg: &NetworkMessage) -> Result<(), io::Error> {
+        // LATER Measure network usage.
+        // LATER Try to minimize network usage.
+        //       General purpose compression could help a bit,
+        //       but using what we know about the data should give much better results.
+
+        self.write(&net_msg.bytes)
+    }
+
+    /// Uses WebRTC if it's a browser which opened a data channel.
+    fn send_unreliable(&mut self, net_msg: &NetworkMessage) -> Result<(), io::Error> {
+        if let (TcpState::Plaintext, NetStream::WebSocket(stream)) = (&self.state, &self.stream) {
+            if stream.send_unreliable(&net_msg.bytes) {
+                return Ok(());
+            }
+        }
+        self.write(&net_msg.bytes)
+    }
+
+    /// Read all available bytes from `stream` into `buffer`,
+    /// parse messages that are complete and return them in a vector.
+    ///
+    /// Also return whether the connection has been closed (doesn't matter if cleanly or reading failed).
+    fn receive(&mut self, max_len: usize) -> (Vec<M>, bool) {
+        let mut closed = self.read_all();
+        let mut msgs: Vec<M> = iter::from_fn(|| {
+            parse_one_or_close(&mut self.buffer, max_len, &self.addr, &mut closed)
+        })
+        .collect();
+        msgs.extend(iter::from_fn(|| self.receive_unreliable(max_len, &mut closed)));
+        (msgs, closed)
+    }
+
+    /// Read all available bytes from `stream` into `buffer`,
+    /// parse a single message if there is enough data and return the message or None.
+    ///
+    /// Also return whether the connection has been closed (doesn't matter if cleanly or reading failed).
+    fn receive_one(&mut self, max_len: usize) -> (Option<M>, bool) {
+        let mut closed = self.read_all();
+        let msg = parse_one_or_close(&mut self.buffer, max_len, &self.addr, &mut closed)
+            .or_else(|| self.receive_unreliable(max_len, &mut closed));
+        (msg, closed)
+    }
+
+    fn addr(&self) -> String {
+        self.addr.to_string()
+    }
+
+    fn is_plaintext(&self) -> bool {
+        matches!(self.state, TcpState::Plaintext)
+    }
+}
+
+/// Resolve a `host:port` string to an address to connect to.
+///
+/// If the host has both IPv4 and IPv6 addresses, `prefer_ipv6` decides which to use.
+///
+/// Note that this blocks if a DNS lookup is needed.
+pub fn resolve(addr: &str, prefer_ipv6: bool) -> io::Result<SocketAddr> {
+    let addrs: Vec<_> = addr.to_socket_addrs()?.collect();
+    pick_addr(&addrs, prefer_ipv6)
+        .ok_or_else(|| io::Error::new(ErrorKind::NotFound, format!("{addr} didn't resolve")))
+}
+
+fn pick_addr(addrs: &[SocketAddr], prefer_ipv6: bool) -> Option<SocketAddr> {
+    addrs
+        .iter()
+        .find(|addr| addr.is_ipv6() == prefer_ipv6)
+        .or_else(|| addrs.first())
+        .copied()
+}
+
+/// Convert IPv4-mapped IPv6 addresses (e.g. `[::ffff:127.0.0.1]:26000`) to plain IPv4.
+///
+/// A socket listening on `[::]` is usually dual-stack
+/// and sees IPv4 clients this way which is confusing in logs.
+pub fn canonical_addr(addr: SocketAddr) -> SocketAddr {
+    match addr.ip() {
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => SocketAddr::new(IpAddr::V4(ip), addr.port()),
+            None => addr,
+        },
+        IpAddr::V4(_) => addr,
+    }
+}
+
+/// Start connecting to `addr` in a background thread.
+///
+/// Connecting is retried up to `cl_net_connect_max_attempts` times,
+/// poll the result with `PendingTcpConnection::poll`.
+pub fn tcp_connect(cvars: &Cvars, addr: SocketAddr) -> PendingTcpConnection {
+    let (tx, rx) = mpsc::channel();
+    let endpoint = debug::endpoint_name();
+    let max_attempts = cvars.cl_net_connect_max_attempts;
+    let retry_delay = Duration::from_millis(cvars.cl_net_connect_retry_delay_ms);
+    let print_every_n = cvars.cl_net_connect_retry_print_every_n;
+    // Zero means no timeout for the socket functions.
+    let timeout = Duration::from_secs_f32(cvars.cl_net_connect_timeout.max(0.001));
+    let encrypt = cvars.net_encryption;
+
+    thread::spawn(move || {
+        debug::set_endpoint(endpoint);
+        let res =
+            tcp_connect_blocking(addr, max_attempts, retry_delay, print_every_n, timeout, encrypt);
+        // The receiver is gone if the player started connecting somewhere else.
+        let _ = tx.send(res);
+    });
+
+    PendingTcpConnection { rx }
+}
+
+/// A connection being established in a background thread.
+pub struct PendingTcpConnection {
+    rx: Receiver<io::Result<TcpConnection>>,
+}
+
+impl PendingTcpConnection {
+    /// Returns None while still connecting.
+    pub fn poll(&mut self) -> Option<io::Result<TcpConnection>> {
+        match self.rx.try_recv() {
+            Ok(res) => Some(res),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                Some(Err(io::Error::new(ErrorKind::Other, "connecting thread exited unexpectedly")))
+            }
+        }
+    }
+}
+
+fn tcp_connect_blocking(
+    addr: SocketAddr,
+    max_attempts: u64,
+    retry_delay: Duration,
+    print_every_n: u64,
+    timeout: Duration,
+    encrypt: bool,
+) -> io::Result<TcpConnection> {
+    let mut connect_attempts = 0;
+    let mut stream = loop {
+        connect_attempts += 1;
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => {
+                dbg_logf!("connect attempts: {}", connect_attempts);
+                break stream;
+            }
+            Err(e) if connect_attempts >= max_attempts => {
+                dbg_logf!("giving up after {} connect attempts", connect_attempts);
+                return Err(e);
+            }
+            Err(_) => {}
+        }
+        if connect_attempts % print_every_n == 0 {
+            dbg_logf!("connect attempts: {}", connect_attempts);
+        }
+        thread::sleep(retry_delay);
+    };
+    stream.set_nodelay(true)?;
+    // A server which accepts the connection but doesn't respond would block us forever.
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    let (handshake, noise) = if encrypt {
+        let (handshake, msg) = Handshake::initiate()?;
+        (Some(handshake), Some(msg))
+    } else {
+        (None, None)
+    };
+    let version = serialize(ClientMessage::Version(Version::current(), noise));
+    stream.write_all(&version.bytes)?;
+
+    // The stream is still blocking here so we can do the handshake without a state machine.
+    let mut raw = VecDeque::new();
+    let encryption = match handshake {
+        Some(handshake) => {
+            let (version, rest) = read_version(&mut stream)?;
+            let ServerMessage::Version(_, Some(response)) = &version else {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    "the server didn't respond to the encryption handshake",
+                ));
+            };
+            let encryption = handshake.finish(response)?;
+            dbg_logf!("connection to {} is encrypted", addr);
+            // The server's game sends its version again, encrypted,
+            // so this one doesn't need to be passed on.
+            raw = rest;
+            Some(encryption)
+        }
+        None => None,
+    };
+
+    stream.set_read_timeout(None)?;
+    stream.set_write_timeout(None)?;
+    stream.set_nonblocking(true)?;
+
+    Ok(TcpConnection::new(stream, addr, encryption, raw))
+}
+
+/// Block until the server's version arrives.
+///
+/// Returns it and anything that arrived after it.
+fn read_version(stream: &mut TcpStream) -> io::Result<(ServerMessage, VecDeque<u8>)> {
+    let mut buffer = VecDeque::new();
+    loop {
+        let res = parse_one(&mut buffer, MAX_VERSION_LEN)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e.to_string()))?;
+        match res {
+            Some(msg @ ServerMessage::Version(..)) => return Ok((msg, buffer)),
+            Some(_) => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    "the first message is not the server's version",
+                ))
+            }
+            None => {}
+        }
+
+        let mut buf = [0; 1024];
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        buffer.extend(&buf[..n]);
+    }
+}
+
+/// POST the body over plain HTTP, blocks until the server responds.
+///
+/// Used for crash reports and match results.
+/// We don't want an HTTP client dependency just for this,
+/// receiving servers can sit behind a TLS terminating proxy if needed.
+pub fn http_post(url: &str, content_type: &str, body: &str) -> Result<(), String> {
+    let rest = url.strip_prefix("http://").ok_or("only http:// URLs are supported")?;
+    let (host_port, path) = match rest.find('/') {
+        Some(slash) => rest.split_at(slash),
+        None => (rest, "/"),
+    };
+    let addr = if host_port.contains(':') {
+        host_port.to_owned()
+    } else {
+        format!("{}:80", host_port)
+    };
+
+    // A dead endpoint must not stall the caller, e.g. when reporting a crash.
+    let timeout = Duration::from_secs(5);
+    let mut stream = http_connect(&addr, timeout)?;
+    stream.set_read_timeout(Some(timeout)).map_err(|e| e.to_string())?;
+    stream.set_write_timeout(Some(timeout)).map_err(|e| e.to_string())?;
+
+    let request = format!(
+        "POST {} HTTP/1.1\r\n\
+        Host: {}\r\n\
+        Content-Type: {}\r\n\
+        Content-Length: {}\r\n\
+        Connection: close\r\n\
+        \r\n\
+        {}",
+        path,
+        host_port,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(request.as_bytes()).map_err(|e| e.to_string())?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).map_err(|e| e.to_string())?;
+    let status_line = response.lines().next().unwrap_or_default();
+    let status = status_line.split(' ').nth(1).unwrap_or_default();
+    if status.starts_with('2') {
+        Ok(())
+    } else {
+        Err(format!("server responded with `{}`", status_line))
+    }
+}
+
+/// Connect to the first address `addr` resolves to which accepts within `timeout`.
+fn http_connect(addr: &str, timeout: Duration) -> Result<TcpStream, String> {
+    let mut last_err = format!("{} didn't resolve to any address", addr);
+    for socket_addr in addr.to_socket_addrs().map_err(|e| e.to_string())? {
+        match TcpStream::connect_timeout(&socket_addr, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_err = format!("failed to connect to {}: {}", socket_addr, e),
+        }
+    }
+    Err(last_err)
+}
+
+/// Counters for `d_net_stats`.
+static STATS: NetStats = NetStats {
+    serialized: DebugCounter::new(),
+    parsed: DebugCounter::new(),
+    allocations: DebugCounter::new(),
+};
+
+struct NetStats {
+    serialized: DebugCounter,
+    parsed: DebugCounter,
+    /// Buffers allocated for serializing, parsing or sending messages.
+    allocations: DebugCounter,
+}
+
+/// Count the copy of a message made for a channel, see `LocalConnection`.
+fn count_send_copy() {
+    STATS.allocations.add(1);
+}
+
+/// Show how many messages were serialized and parsed since the last call
+/// and reset the counters.
+///
+/// Call once per frame.
+pub fn stats_frame(cvars: &Cvars) {
+    let serialized = STATS.serialized.take();
+    let parsed = STATS.parsed.take();
+    let allocations = STATS.allocations.take();
+    if cvars.d_net_stats {
+        dbg_textf!(
+            "net: {} serialized, {} parsed, {} allocations",
+            serialized,
+            parsed,
+            allocations
+        );
+    }
+}
+
+/// Serialize `msg` into a new buffer.
+///
+/// Code which sends messages every frame should use `serialize_into` instead.
+pub fn serialize<M>(msg: M) -> NetworkMessage
+where
+    M: Serialize,
+{
+    let mut net_msg = NetworkMessage::default();
+    serialize_into(&msg, &mut net_msg);
+    net_msg
+}
+
+/// Serialize `msg` into `net_msg`, replacing its previous content.
+///
+/// Keep `net_msg` around between messages so its memory is reused.
+pub fn serialize_into<M>(msg: &M, net_msg: &mut NetworkMessage)
+where
+    M: Serialize,
+{
+    let buf = &mut net_msg.bytes;
+    let capacity = buf.capacity();
+    buf.clear();
+    buf.extend_from_slice(&[0; HEADER_LEN]);
+    bincode::serialize_into(&mut *buf, msg).expect("bincode failed to serialize message");
+
+    let len = MsgLen::try_from(buf.len()).unwrap_or_else(|err| {
+        panic!("bincode message length ({} bytes) overflowed its type: {:?}", buf.len(), err)
+    });
+    let len_bytes = len.to_le_bytes();
+    buf[0..HEADER_LEN].copy_from_slice(&len_bytes);
+
+    STATS.serialized.add(1);
+    STATS.allocations.add(usize::from(buf.capacity() != capacity));
+}
+
+/// Read all available bytes until the stream would block.
+fn read(stream: &mut impl Read, buffer: &mut VecDeque<u8>) -> bool {
+    // LATER Test networking thoroughly
+    //      - lossy and slow connections
+    //      - fragmented and merged packets
+    // LATER(security) Test large amounts of data
+    loop {
+        // No particular reason for the buffer size, except BufReader uses the same.
+        let mut buf = [0; 8192];
+        let res = stream.read(&mut buf);
+        match res {
+            Ok(0) => {
+                // The connection has been closed, don't get stuck in this loop.
+                // This can happen for example when the server crashes.
+                dbg_logf!("Connection closed when reading");
+                return true;
+            }
+            Ok(n) => {
+                buffer.extend(&buf[0..n]);
+            }
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                return false;
+            }
+            Err(e) => {
+                dbg_logf!("Connection closed when reading - error: {}", e);
+                return true;
+            }
+        }
+    }
+}
+
+/// Reasons why received data couldn't be parsed into a message.
+///
+/// Any of these means the peer is either buggy or malicious
+/// and we can't find the start of the next message anyway
+/// so the only thing left to do is to disconnect it.
+#[derive(Debug)]
+pub enum ParseError {
+    /// The length header is smaller than the header itself.
+    TooShort { len: usize },
+    /// The length header is larger than the allowed maximum.
+    TooLong { len: usize, max_len: usize },
+    /// The content is not a valid message.
+    Bincode(bincode::Error),
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::TooShort { len } => {
+                write!(f, "message length {} is shorter than its header", len)
+            }
+            ParseError::TooLong { len, max_len } => {
+                write!(f, "message length {} is over the limit {}", len, max_len)
+            }
+            ParseError::Bincode(e) => write!(f, "failed to deserialize message: {}", e),
+        }
+    }
+}
+
+/// Parse a message from `buffer`, log and clear the buffer if it fails.
+///
+/// This is a helper for `Connection` impls which report failure as a closed connection.
+fn parse_one_or_close<M>(
+    buffer: &mut VecDeque<u8>,
+    max_len: usize,
+    addr: &dyn Display,
+    closed: &mut bool,
+) -> Option<M>
+where
+    M: DeserializeOwned,
+{
+    match parse_one(buffer, max_len) {
+        Ok(msg) => msg,
+        Err(e) => {
+            dbg_logf!("Failed to parse message from {}: {}", addr, e);
+            buffer.clear();
+            *closed = true;
+            None
+        }
+    }
+}
+
+/// Parse a message which arrived whole, e.g. over WebRTC.
+///
+/// Like `parse_one_or_close`, failure is reported as a closed connection,
+/// this includes incomplete messages and trailing data.
+fn parse_datagram<M>(
+    bytes: Vec<u8>,
+    max_len: usize,
+    addr: &dyn Display,
+    closed: &mut bool,
+) -> Option<M>
+where
+    M: DeserializeOwned,
+{
+    let mut buffer = VecDeque::from(bytes);
+    let msg = parse_one_or_close(&mut buffer, max_len, addr, closed);
+    if *closed {
+        return None;
+    }
+    if msg.is_none() || !buffer.is_empty() {
+        dbg_logf!("Received a datagram which isn't exactly one message from {}", addr);
+        *closed = true;
+        return None;
+    }
+    msg
+}
+
+/// Parse a message from `buffer` or return None if there's not enough data.
+fn parse_one<M>(buffer: &mut VecDeque<u8>, max_len: usize) -> Result<Option<M>, ParseError>
+where
+    M: DeserializeOwned,
+{
+    if buffer.len() < HEADER_LEN {
+        return Ok(None);
+    }
+
+    let len_bytes = [buffer[0], buffer[1], buffer[2], buffer[3]];
+    let len = usize::try_from(MsgLen::from_le_bytes(len_bytes)).unwrap();
+
+    if len < HEADER_LEN {
+        return Err(ParseError::TooShort { len });
+    }
+    // Check this before waiting for the rest of the message
+    // so a peer can't make us buffer an arbitrary amount of data.
+    if len > max_len {
+        return Err(ParseError::TooLong { len, max_len });
+    }
+
+    if buffer.len() < len {
+        // Not enough bytes in buffer for a full message.
+        return Ok(None);
+    }
+
+    // Usually a no-op, when the data wraps around the end of the ring buffer,
+    // it's moved in place instead of copying the message into a new Vec.
+    let res = deserialize(&buffer.make_contiguous()[HEADER_LEN..len]);
+    buffer.drain(0..len);
+    let msg = res?;
+    STATS.parsed.add(1);
+
+    Ok(Some(msg))
+}
+
+/// Deserialize the content of a message (without the length header).
+fn deserialize<M>(bytes: &[u8]) -> Result<M, ParseError>
+where
+    M: DeserializeOwned,
+{
+    // Bincode caps how much it preallocates for collections based on their length prefix
+    // so a bogus length inside the message won't make us allocate gigabytes.
+    // The total size is limited by max_len.
+    bincode::deserialize(bytes).map_err(ParseError::Bincode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pick_addr_preference() {
+        let v4: SocketAddr = "127.0.0.1:26000".parse().unwrap();
+        let v6: SocketAddr = "[::1]:26000".parse().unwrap();
+
+        assert_eq!(pick_addr(&[v4, v6], false), Some(v4));
+        assert_eq!(pick_addr(&[v4, v6], true), Some(v6));
+        assert_eq!(pick_addr(&[v6, v4], false), Some(v4));
+        assert_eq!(pick_addr(&[v6, v4], true), Some(v6));
+
+        // Fall back to the other family.
+        assert_eq!(pick_addr(&[v4], true), Some(v4));
+        assert_eq!(pick_addr(&[v6], false), Some(v6));
+
+        assert_eq!(pick_addr(&[], false), None);
+    }
+
+    #[test]
+    fn canonical_addrs() {
+        let cases = [
+            ("[::ffff:1.2.3.4]:26000", "1.2.3.4:26000"),
+            ("1.2.3.4:26000", "1.2.3.4:26000"),
+            ("[::1]:26000", "[::1]:26000"),
+            ("[2001:db8::1]:26000", "[2001:db8::1]:26000"),
+        ];
+        for (addr, expected) in cases {
+            let addr = canonical_addr(addr.parse().unwrap());
+            assert_eq!(addr.to_string(), expected);
+        }
+    }
+
+    fn buffer_from(msgs: &[NetworkMessage]) -> VecDeque<u8> {
+        msgs.iter().flat_map(|msg| msg.bytes.iter().copied()).collect()
+    }
+
+    #[test]
+    fn parse_valid() {
+        let mut buffer = buffer_from(&[
+            serialize(ClientMessage::Join),
+            serialize(ClientMessage::Chat("hello".to_owned())),
+        ]);
+
+        let msg = parse_one::<ClientMessage>(&mut buffer, 1024).unwrap();
+        assert!(matches!(msg, Some(ClientMessage::Join)));
+        let msg = parse_one::<ClientMessage>(&mut buffer, 1024).unwrap();
+        assert!(matches!(msg, Some(ClientMessage::Chat(chat)) if chat == "hello"));
+        let msg = parse_one::<ClientMessage>(&mut buffer, 1024).unwrap();
+        assert!(msg.is_none());
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn parse_fragmented() {
+        let full = serialize(ClientMessage::Chat("hello".to_owned())).bytes;
+        let mut buffer = VecDeque::new();
+        for &b in &full[..full.len() - 1] {
+            buffer.push_back(b);
+            let msg = parse_one::<ClientMessage>(&mut buffer, 1024).unwrap();
+            assert!(msg.is_none());
+        }
+        buffer.push_back(*full.last().unwrap());
+        let msg = parse_one::<ClientMessage>(&mut buffer, 1024).unwrap();
+        assert!(matches!(msg, Some(ClientMessage::Chat(_))));
+    }
+
+    #[test]
+    fn parse_wrapped() {
+        let full = serialize(ClientMessage::Chat("hello".to_owned())).bytes;
+        // Make the message start near the end of the ring buffer's memory
+        // so it wraps around to the beginning.
+        let mut buffer = VecDeque::with_capacity(full.len() + 4);
+        let skip = buffer.capacity() - 3;
+        buffer.extend(iter::repeat(0).take(skip));
+        buffer.drain(0..skip);
+        buffer.extend(&full);
+        assert!(!buffer.as_slices().1.is_empty());
+
+        let msg = parse_one::<ClientMessage>(&mut buffer, 1024).unwrap();
+        assert!(matches!(msg, Some(ClientMessage::Chat(chat)) if chat == "hello"));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn serialize_reuses_buffer() {
+        let mut net_msg = NetworkMessage::default();
+        serialize_into(&ClientMessage::Chat("a".repeat(100)), &mut net_msg);
+        let capacity = net_msg.bytes.capacity();
+        serialize_into(&ClientMessage::Join, &mut net_msg);
+        assert_eq!(net_msg.bytes, serialize(ClientMessage::Join).bytes);
+        assert_eq!(net_msg.bytes.capacity(), capacity);
+    }
+
+    #[test]
+    fn parse_too_short() {
+        for len in 0..HEADER_LEN as MsgLen {
+            let mut buffer: VecDeque<u8> = len.to_le_bytes().into_iter().collect();
+            buffer.extend([0; 16]);
+            let res = parse_one::<ClientMessage>(&mut buffer, 1024);
+            assert!(matches!(res, Err(ParseError::TooShort { .. })));
+        }
+    }
+
+    #[test]
+    fn parse_too_long() {
+        // Should fail immediately, without waiting for the rest of the data.
+        let mut buffer: VecDeque<u8> = MsgLen::MAX.to_le_bytes().into_iter().collect();
+        let res = parse_one::<ClientMessage>(&mut buffer, 1024);
+        assert!(matches!(res, Err(ParseError::TooLong { .. })));
+
+        let mut buffer = buffer_from(&[serialize(ClientMessage::Chat("a".repeat(100)))]);
+        let res = parse_one::<ClientMessage>(&mut buffer, 64);
+        assert!(matches!(res, Err(ParseError::TooLong { .. })));
+    }
+
+    #[test]
+    fn parse_garbage() {
+        // Invalid enum variant.
+        let mut buffer: VecDeque<u8> = [8, 0, 0, 0, 0xff, 0xff, 0xff, 0xff].into_iter().collect();
+        let res = parse_one::<ClientMessage>(&mut buffer, 1024);
+        assert!(matches!(res, Err(ParseError::Bincode(_))));
+
+        // Chat with a huge length prefix but no data.
+        let mut bytes = vec![0; HEADER_LEN];
+        bytes.extend(2u32.to_le_bytes()); // variant
+        bytes.extend(u64::MAX.to_le_bytes()); // string len
+        let len = bytes.len() as MsgLen;
+        bytes[0..HEADER_LEN].copy_from_slice(&len.to_le_bytes());
+        let mut buffer: VecDeque<u8> = bytes.into_iter().collect();
+        let res = parse_one::<ClientMessage>(&mut buffer, 1024);
+        assert!(matches!(res, Err(ParseError::Bincode(_))));
+    }
+
+    #[test]
+    fn parse_datagrams() {
+        let bytes = serialize(ClientMessage::Join).bytes;
+        let mut closed = false;
+        let msg = parse_datagram::<ClientMessage>(bytes.clone(), 1024, &"test", &mut closed);
+        assert!(matches!(msg, Some(ClientMessage::Join)));
+        assert!(!closed);
+
+        // Datagrams must contain exactly one message.
+        let mut two = bytes.clone();
+        two.extend(&bytes);
+        let truncated = bytes[..bytes.len() - 1].to_vec();
+        for datagram in [two, truncated] {
+            let mut closed = false;
+            let msg = parse_datagram::<ClientMessage>(datagram, 1024, &"test", &mut closed);
+            assert!(msg.is_none());
+            assert!(closed);
+        }
+    }
+}
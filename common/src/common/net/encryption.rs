@@ -0,0 +1,195 @@
+//! Optional encryption of TCP connections using the Noise protocol framework.
+//!
+//! We use the NN pattern - neither side has a static key so this protects against
+//! passive eavesdropping but not against an active man in the middle.
+//! LATER Use XX or a pinned server key (e.g. from the master server) to authenticate servers.
+//!
+//! The handshake messages are carried in the client's and server's `Version` messages,
+//! everything after them is encrypted.
+//!
+//! Noise messages are limited to 64 KiB so the byte stream is split into chunks,
+//! each is encrypted separately and sent as a frame prefixed by its length.
+//! Game messages are then parsed from the decrypted stream exactly like from a plaintext one.
+
+use std::io::{self, ErrorKind};
+
+use snow::{Builder, HandshakeState, TransportState};
+
+use crate::prelude::*;
+
+const NOISE_PARAMS: &str = "Noise_NN_25519_ChaChaPoly_BLAKE2s";
+
+/// Max length of a Noise message including the authentication tag.
+const MAX_NOISE_MSG_LEN: usize = 65535;
+const TAG_LEN: usize = 16;
+const MAX_CHUNK_LEN: usize = MAX_NOISE_MSG_LEN - TAG_LEN;
+
+type FrameLen = u16;
+const FRAME_HEADER_LEN: usize = std::mem::size_of::<FrameLen>();
+
+/// Encryption state of a connection after a successful handshake.
+pub struct Encryption {
+    transport: TransportState,
+    /// Reused to avoid allocating for every frame.
+    scratch: Vec<u8>,
+}
+
+impl Encryption {
+    fn new(transport: TransportState) -> Self {
+        Self {
+            transport,
+            scratch: vec![0; MAX_NOISE_MSG_LEN],
+        }
+    }
+
+    /// Perform the server side of the handshake.
+    ///
+    /// Takes the handshake message from the client's `Version`
+    /// and returns the response for the server's `Version`.
+    pub fn respond(msg: &[u8]) -> io::Result<(Self, Vec<u8>)> {
+        let mut responder = builder().build_responder().map_err(noise_err)?;
+        let mut buf = vec![0; MAX_NOISE_MSG_LEN];
+
+        responder.read_message(msg, &mut buf).map_err(noise_err)?;
+        let len = responder.write_message(&[], &mut buf).map_err(noise_err)?;
+        let response = buf[..len].to_vec();
+
+        let transport = responder.into_transport_mode().map_err(noise_err)?;
+        Ok((Self::new(transport), response))
+    }
+
+    /// Encrypt `plaintext` and append the resulting frames to `out`.
+    pub fn encrypt(&mut self, plaintext: &[u8], out: &mut Vec<u8>) -> io::Result<()> {
+        for chunk in plaintext.chunks(MAX_CHUNK_LEN) {
+            let len = self.transport.write_message(chunk, &mut self.scratch).map_err(noise_err)?;
+            out.extend(frame(&self.scratch[..len]));
+        }
+        Ok(())
+    }
+
+    /// Decrypt all complete frames from `raw` and append the result to `plaintext`.
+    ///
+    /// Incomplete frames are left in `raw` until more data arrives.
+    pub fn decrypt(
+        &mut self,
+        raw: &mut VecDeque<u8>,
+        plaintext: &mut VecDeque<u8>,
+    ) -> io::Result<()> {
+        while let Some(msg) = take_frame(raw) {
+            let len = self.transport.read_message(&msg, &mut self.scratch).map_err(noise_err)?;
+            plaintext.extend(&self.scratch[..len]);
+        }
+        Ok(())
+    }
+}
+
+/// The client side of a handshake waiting for the server's response.
+pub struct Handshake {
+    initiator: HandshakeState,
+}
+
+impl Handshake {
+    /// Start the handshake, returns the message to put in the client's `Version`.
+    pub fn initiate() -> io::Result<(Self, Vec<u8>)> {
+        let mut initiator = builder().build_initiator().map_err(noise_err)?;
+        let mut buf = vec![0; MAX_NOISE_MSG_LEN];
+        let len = initiator.write_message(&[], &mut buf).map_err(noise_err)?;
+        buf.truncate(len);
+        Ok((Self { initiator }, buf))
+    }
+
+    /// Finish the handshake with the response from the server's `Version`.
+    pub fn finish(mut self, response: &[u8]) -> io::Result<Encryption> {
+        let mut buf = vec![0; MAX_NOISE_MSG_LEN];
+        self.initiator.read_message(response, &mut buf).map_err(noise_err)?;
+        let transport = self.initiator.into_transport_mode().map_err(noise_err)?;
+        Ok(Encryption::new(transport))
+    }
+}
+
+/// Remove one length-prefixed frame from the start of `buffer` and return its content.
+///
+/// Returns None if the frame is not complete yet.
+fn take_frame(buffer: &mut VecDeque<u8>) -> Option<Vec<u8>> {
+    if buffer.len() < FRAME_HEADER_LEN {
+        return None;
+    }
+    let len = usize::from(FrameLen::from_le_bytes([buffer[0], buffer[1]]));
+    if buffer.len() < FRAME_HEADER_LEN + len {
+        return None;
+    }
+    buffer.drain(0..FRAME_HEADER_LEN);
+    Some(buffer.drain(0..len).collect())
+}
+
+fn frame(msg: &[u8]) -> Vec<u8> {
+    // Noise messages are never longer than u16::MAX.
+    let len = FrameLen::try_from(msg.len()).unwrap();
+    let mut bytes = Vec::with_capacity(FRAME_HEADER_LEN + msg.len());
+    bytes.extend(len.to_le_bytes());
+    bytes.extend(msg);
+    bytes
+}
+
+fn builder() -> Builder<'static> {
+    Builder::new(NOISE_PARAMS.parse().unwrap())
+}
+
+fn noise_err(e: snow::Error) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handshake() -> (Encryption, Encryption) {
+        let (handshake, msg) = Handshake::initiate().unwrap();
+        let (server, response) = Encryption::respond(&msg).unwrap();
+        let client = handshake.finish(&response).unwrap();
+        (client, server)
+    }
+
+    #[test]
+    fn encryption_round_trip() {
+        let (mut client, mut server) = handshake();
+
+        // Larger than one chunk to test splitting.
+        let plaintext: Vec<u8> = (0..200_000).map(|i| (i % 251) as u8).collect();
+
+        let mut encrypted = Vec::new();
+        client.encrypt(&plaintext, &mut encrypted).unwrap();
+        assert_ne!(&encrypted[FRAME_HEADER_LEN..100], &plaintext[..100 - FRAME_HEADER_LEN]);
+
+        // Deliver it in pieces to test incomplete frames.
+        let mut raw = VecDeque::new();
+        let mut decrypted = VecDeque::new();
+        for piece in encrypted.chunks(1000) {
+            raw.extend(piece);
+            server.decrypt(&mut raw, &mut decrypted).unwrap();
+        }
+        assert!(raw.is_empty());
+        assert_eq!(decrypted.into_iter().collect::<Vec<_>>(), plaintext);
+
+        // And the other direction.
+        let mut encrypted = Vec::new();
+        server.encrypt(b"hello", &mut encrypted).unwrap();
+        let mut raw = encrypted.into_iter().collect();
+        let mut decrypted = VecDeque::new();
+        client.decrypt(&mut raw, &mut decrypted).unwrap();
+        assert_eq!(decrypted.into_iter().collect::<Vec<_>>(), b"hello");
+    }
+
+    #[test]
+    fn encryption_tampering() {
+        let (mut client, mut server) = handshake();
+
+        let mut encrypted = Vec::new();
+        client.encrypt(b"hello", &mut encrypted).unwrap();
+        encrypted[FRAME_HEADER_LEN] ^= 1;
+
+        let mut raw = encrypted.into_iter().collect();
+        let mut decrypted = VecDeque::new();
+        assert!(server.decrypt(&mut raw, &mut decrypted).is_err());
+    }
+}
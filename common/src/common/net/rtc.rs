@@ -0,0 +1,399 @@
+//! WebRTC data channels so browsers can send and receive messages which may be lost
+//! without waiting for retransmits like over the WebSocket.
+//!
+//! The data channel is opened in addition to the WebSocket, not instead of it.
+//! Most messages still go over the WebSocket, only those sent using
+//! `Connection::send_unreliable` use the data channel once it's open.
+//! Each data channel message is one whole `NetworkMessage`.
+//!
+//! Signaling uses text messages over the WebSocket (binary ones carry the game's byte stream):
+//! the browser sends `offer\n<SDP>`, the server responds with `answer\n<SDP>`.
+//! Both sides gather all their ICE candidates before sending the SDP
+//! so no further messages are needed.
+//!
+//! The server side uses webrtc-rs which needs an async runtime,
+//! it runs in background threads and talks to the game through channels.
+//! It's behind the `webrtc` feature so programs which don't answer browsers
+//! don't have to build the runtime.
+
+#[cfg(all(feature = "webrtc", not(target_arch = "wasm32")))]
+use std::{
+    error::Error,
+    sync::{
+        mpsc::{self, Receiver, Sender, TryRecvError},
+        Arc, Mutex, OnceLock,
+    },
+};
+
+#[cfg(target_arch = "wasm32")]
+use std::{cell::RefCell, rc::Rc};
+
+use std::io::{self, ErrorKind};
+
+#[cfg(all(feature = "webrtc", not(target_arch = "wasm32")))]
+use bytes::Bytes;
+#[cfg(all(feature = "webrtc", not(target_arch = "wasm32")))]
+use tokio::{
+    runtime::Runtime,
+    sync::mpsc::{unbounded_channel, UnboundedSender},
+};
+#[cfg(all(feature = "webrtc", not(target_arch = "wasm32")))]
+use webrtc::{
+    api::APIBuilder,
+    data_channel::{data_channel_message::DataChannelMessage, RTCDataChannel},
+    peer_connection::{
+        configuration::RTCConfiguration, sdp::session_description::RTCSessionDescription,
+        RTCPeerConnection,
+    },
+};
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::{closure::Closure, JsCast};
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen_futures::JsFuture;
+#[cfg(target_arch = "wasm32")]
+use web_sys::{
+    MessageEvent, RtcDataChannel, RtcDataChannelInit, RtcDataChannelState, RtcDataChannelType,
+    RtcPeerConnection, RtcPeerConnectionIceEvent, RtcSdpType, RtcSessionDescriptionInit,
+};
+
+#[cfg(target_arch = "wasm32")]
+use crate::{common::net::websocket::js_error, prelude::*};
+
+#[cfg(any(feature = "webrtc", target_arch = "wasm32"))]
+const OFFER_PREFIX: &str = "offer\n";
+#[cfg(any(feature = "webrtc", target_arch = "wasm32"))]
+const ANSWER_PREFIX: &str = "answer\n";
+
+/// Server side of the data channel to one browser.
+#[cfg(all(feature = "webrtc", not(target_arch = "wasm32")))]
+pub struct RtcPeer {
+    shared: Arc<Mutex<Shared>>,
+    /// The answer to send back to the browser, sent once by the negotiating task.
+    answer: Receiver<io::Result<String>>,
+    answered: bool,
+    incoming: Receiver<Vec<u8>>,
+    /// Sent by one long-lived task so messages leave in the order they were sent.
+    outgoing: UnboundedSender<Bytes>,
+}
+
+/// Filled in by the async callbacks.
+#[cfg(all(feature = "webrtc", not(target_arch = "wasm32")))]
+#[derive(Default)]
+struct Shared {
+    peer: Option<Arc<RTCPeerConnection>>,
+    /// Only set while the channel is open.
+    channel: Option<Arc<RTCDataChannel>>,
+}
+
+#[cfg(all(feature = "webrtc", not(target_arch = "wasm32")))]
+impl RtcPeer {
+    /// Start answering the browser's signaling message.
+    ///
+    /// Returns an error if it's not an offer.
+    /// The answer should be sent back once `poll_answer` returns it.
+    pub fn answer(signal: &str) -> io::Result<Self> {
+        let offer = signal
+            .strip_prefix(OFFER_PREFIX)
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "expected a WebRTC offer"))?
+            .to_owned();
+
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let (answer_sender, answer) = mpsc::channel();
+        let (incoming_sender, incoming) = mpsc::channel();
+        let (outgoing, mut outgoing_receiver) = unbounded_channel::<Bytes>();
+
+        let shared2 = Arc::clone(&shared);
+        runtime().spawn(async move {
+            let res = negotiate(offer, shared2, incoming_sender)
+                .await
+                .map(|sdp| format!("{ANSWER_PREFIX}{sdp}"))
+                .map_err(|e| io::Error::new(ErrorKind::Other, e.to_string()));
+            // The connection might have been dropped in the meantime.
+            let _ = answer_sender.send(res);
+        });
+
+        // Exits when the peer is dropped along with the sender.
+        let shared2 = Arc::clone(&shared);
+        runtime().spawn(async move {
+            while let Some(data) = outgoing_receiver.recv().await {
+                let channel = shared2.lock().unwrap().channel.clone();
+                let Some(channel) = channel else {
+                    continue;
+                };
+                if let Err(e) = channel.send(&data).await {
+                    dbg_logf!("failed to send over WebRTC: {}", e);
+                }
+            }
+        });
+
+        Ok(Self {
+            shared,
+            answer,
+            answered: false,
+            incoming,
+            outgoing,
+        })
+    }
+
+    /// Returns the answer once, None before it's ready and after it's been returned.
+    pub fn poll_answer(&mut self) -> Option<io::Result<String>> {
+        if self.answered {
+            return None;
+        }
+        match self.answer.try_recv() {
+            Ok(res) => {
+                self.answered = true;
+                Some(res)
+            }
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                self.answered = true;
+                Some(Err(io::Error::new(ErrorKind::Other, "WebRTC task exited unexpectedly")))
+            }
+        }
+    }
+
+    /// Send the message if the channel is open, return whether it was sent.
+    pub fn send(&self, bytes: &[u8]) -> bool {
+        if self.shared.lock().unwrap().channel.is_none() {
+            return false;
+        }
+        self.outgoing.send(Bytes::copy_from_slice(bytes)).is_ok()
+    }
+
+    /// Take one received message.
+    pub fn receive(&self) -> Option<Vec<u8>> {
+        self.incoming.try_recv().ok()
+    }
+}
+
+#[cfg(all(feature = "webrtc", not(target_arch = "wasm32")))]
+impl Drop for RtcPeer {
+    fn drop(&mut self) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.channel = None;
+        if let Some(peer) = shared.peer.take() {
+            runtime().spawn(async move {
+                let _ = peer.close().await;
+            });
+        }
+    }
+}
+
+/// All peers share one runtime, it's only needed once a browser offers WebRTC.
+#[cfg(all(feature = "webrtc", not(target_arch = "wasm32")))]
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("webrtc")
+            .enable_all()
+            .build()
+            .expect("failed to create WebRTC runtime")
+    })
+}
+
+/// Create the peer connection, set up callbacks for the browser's data channel
+/// and return the answer SDP with all ICE candidates.
+#[cfg(all(feature = "webrtc", not(target_arch = "wasm32")))]
+async fn negotiate(
+    offer: String,
+    shared: Arc<Mutex<Shared>>,
+    incoming: Sender<Vec<u8>>,
+) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let api = APIBuilder::new().build();
+    let peer = Arc::new(api.new_peer_connection(RTCConfiguration::default()).await?);
+    shared.lock().unwrap().peer = Some(Arc::clone(&peer));
+
+    // The browser creates the channel, we just wait for it.
+    let shared2 = Arc::clone(&shared);
+    peer.on_data_channel(Box::new(move |channel: Arc<RTCDataChannel>| {
+        let shared = Arc::clone(&shared2);
+        let incoming = incoming.clone();
+        Box::pin(async move {
+            // Weak so the channel doesn't keep itself alive through its own callback.
+            let channel2 = Arc::downgrade(&channel);
+            let shared2 = Arc::clone(&shared);
+            channel.on_open(Box::new(move || {
+                shared2.lock().unwrap().channel = channel2.upgrade();
+                Box::pin(async {})
+            }));
+            channel.on_close(Box::new(move || {
+                shared.lock().unwrap().channel = None;
+                Box::pin(async {})
+            }));
+            channel.on_message(Box::new(move |msg: DataChannelMessage| {
+                let _ = incoming.send(msg.data.to_vec());
+                Box::pin(async {})
+            }));
+        })
+    }));
+
+    peer.set_remote_description(RTCSessionDescription::offer(offer)?).await?;
+    let answer = peer.create_answer(None).await?;
+    let mut gathered = peer.gathering_complete_promise().await;
+    peer.set_local_description(answer).await?;
+    let _ = gathered.recv().await;
+    let local = peer.local_description().await.ok_or("missing local description")?;
+    Ok(local.sdp)
+}
+
+/// Browser side of the data channel.
+///
+/// Created right after the WebSocket opens, `poll_offer` then returns the offer
+/// to send to the server and the server's answer is passed to `answered`.
+#[cfg(target_arch = "wasm32")]
+pub struct RtcChannel {
+    peer: RtcPeerConnection,
+    channel: RtcDataChannel,
+    /// Set once all ICE candidates are gathered.
+    offer: Rc<RefCell<Option<io::Result<String>>>>,
+    offered: bool,
+    incoming: Rc<RefCell<VecDeque<Vec<u8>>>>,
+    // The callbacks have to live as long as the peer connection.
+    _on_message: Closure<dyn FnMut(MessageEvent)>,
+    _on_ice_candidate: Closure<dyn FnMut(RtcPeerConnectionIceEvent)>,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl RtcChannel {
+    pub fn new() -> io::Result<Self> {
+        let peer = RtcPeerConnection::new().map_err(js_error)?;
+
+        // Ordered so an old update never overwrites a newer one,
+        // no retransmits so a lost message doesn't delay the ones after it.
+        let init = RtcDataChannelInit::new();
+        init.set_ordered(true);
+        init.set_max_retransmits(0);
+        let channel = peer.create_data_channel_with_data_channel_dict("unreliable", &init);
+        channel.set_binary_type(RtcDataChannelType::Arraybuffer);
+
+        let incoming = Rc::new(RefCell::new(VecDeque::new()));
+        let incoming2 = Rc::clone(&incoming);
+        let on_message = Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+            if let Ok(data) = event.data().dyn_into::<js_sys::ArrayBuffer>() {
+                let bytes = js_sys::Uint8Array::new(&data).to_vec();
+                incoming2.borrow_mut().push_back(bytes);
+            }
+        });
+        channel.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+
+        // A null candidate means gathering is complete and the local description has all of them.
+        let offer = Rc::new(RefCell::new(None));
+        let offer2 = Rc::clone(&offer);
+        let peer2 = peer.clone();
+        let on_ice_candidate = Closure::<dyn FnMut(RtcPeerConnectionIceEvent)>::new(
+            move |event: RtcPeerConnectionIceEvent| {
+                if event.candidate().is_some() {
+                    return;
+                }
+                let res = match peer2.local_description() {
+                    Some(desc) => Ok(format!("{OFFER_PREFIX}{}", desc.sdp())),
+                    None => Err(io::Error::new(ErrorKind::Other, "missing local description")),
+                };
+                *offer2.borrow_mut() = Some(res);
+            },
+        );
+        peer.set_onicecandidate(Some(on_ice_candidate.as_ref().unchecked_ref()));
+
+        let peer2 = peer.clone();
+        let offer2 = Rc::clone(&offer);
+        wasm_bindgen_futures::spawn_local(async move {
+            let res = async {
+                let desc = JsFuture::from(peer2.create_offer()).await?;
+                JsFuture::from(peer2.set_local_description(desc.unchecked_ref())).await
+            };
+            if let Err(e) = res.await {
+                *offer2.borrow_mut() = Some(Err(js_error(e)));
+            }
+        });
+
+        Ok(Self {
+            peer,
+            channel,
+            offer,
+            offered: false,
+            incoming,
+            _on_message: on_message,
+            _on_ice_candidate: on_ice_candidate,
+        })
+    }
+
+    /// Returns the offer once, None before it's ready and after it's been returned.
+    pub fn poll_offer(&mut self) -> Option<io::Result<String>> {
+        if self.offered {
+            return None;
+        }
+        let res = self.offer.borrow_mut().take()?;
+        self.offered = true;
+        Some(res)
+    }
+
+    /// Process the server's signaling message.
+    pub fn answered(&mut self, signal: &str) -> io::Result<()> {
+        let sdp = signal
+            .strip_prefix(ANSWER_PREFIX)
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "expected a WebRTC answer"))?;
+        let desc = RtcSessionDescriptionInit::new(RtcSdpType::Answer);
+        desc.set_sdp(sdp);
+        let promise = self.peer.set_remote_description(&desc);
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Err(e) = JsFuture::from(promise).await {
+                dbg_logf!("failed to set WebRTC answer: {:?}", e);
+            }
+        });
+        Ok(())
+    }
+
+    /// Send the message if the channel is open, return whether it was sent.
+    pub fn send(&self, bytes: &[u8]) -> bool {
+        if self.channel.ready_state() != RtcDataChannelState::Open {
+            return false;
+        }
+        self.channel.send_with_u8_array(bytes).is_ok()
+    }
+
+    /// Take one received message.
+    pub fn receive(&self) -> Option<Vec<u8>> {
+        self.incoming.borrow_mut().pop_front()
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Drop for RtcChannel {
+    fn drop(&mut self) {
+        // The callbacks are freed with the channel, the browser must not call them anymore.
+        self.channel.set_onmessage(None);
+        self.peer.set_onicecandidate(None);
+        self.channel.close();
+        self.peer.close();
+    }
+}
+
+/// Used without the `webrtc` feature and in the browser.
+///
+/// A browser can't be a WebRTC server for other browsers (without a lot more signaling)
+/// so local servers in the browser never answer offers.
+#[cfg(any(not(feature = "webrtc"), target_arch = "wasm32"))]
+pub struct RtcPeer;
+
+#[cfg(any(not(feature = "webrtc"), target_arch = "wasm32"))]
+impl RtcPeer {
+    pub fn answer(_signal: &str) -> io::Result<Self> {
+        Err(io::Error::new(ErrorKind::Unsupported, "this build can't answer WebRTC offers"))
+    }
+
+    pub fn poll_answer(&mut self) -> Option<io::Result<String>> {
+        None
+    }
+
+    pub fn send(&self, _bytes: &[u8]) -> bool {
+        false
+    }
+
+    pub fn receive(&self) -> Option<Vec<u8>> {
+        None
+    }
+}
@@ -0,0 +1,543 @@
+//! WebSockets for browsers which can't open TCP connections.
+//!
+//! The server accepts them on `sv_net_listen_addr_websocket`.
+//! Each WebSocket message carries a chunk of the same byte stream as TCP
+//! (starting with the client's version) so `TcpConnection` works on top of it unchanged.
+//!
+//! The server side uses tungstenite for the handshake and framing.
+//! Text messages are only used for WebRTC signaling, see the `rtc` module.
+//! For encryption put the server behind a TLS terminating proxy and use `wss://`.
+
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    mem,
+    net::{SocketAddr, TcpListener, TcpStream},
+};
+
+#[cfg(target_arch = "wasm32")]
+use std::{cell::RefCell, rc::Rc};
+
+use serde::de::DeserializeOwned;
+use tungstenite::{
+    handshake::{
+        server::{NoCallback, ServerHandshake},
+        HandshakeError, MidHandshake,
+    },
+    protocol::WebSocketConfig,
+    Message, WebSocket,
+};
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+#[cfg(target_arch = "wasm32")]
+use web_sys::{BinaryType, CloseEvent, Event, MessageEvent, WebSocket as JsWebSocket};
+
+use crate::{
+    common::net::{self, rtc::RtcPeer, Connection, Listener, TcpConnection, MAX_PENDING_LEN},
+    prelude::*,
+};
+
+#[cfg(target_arch = "wasm32")]
+use crate::common::net::{rtc::RtcChannel, NetworkMessage};
+
+/// Accepts WebSocket connections from browsers.
+pub struct WebSocketListener {
+    listener: TcpListener,
+    /// Answer WebRTC offers from clients.
+    webrtc: bool,
+}
+
+impl WebSocketListener {
+    pub fn bind(addr: &str, webrtc: bool) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self { listener, webrtc })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+}
+
+impl<M> Listener<M> for WebSocketListener
+where
+    M: DeserializeOwned,
+{
+    fn accept_conn(&mut self) -> io::Result<Box<dyn Connection<M>>> {
+        let (stream, addr) = self.listener.accept()?;
+        let addr = net::canonical_addr(addr);
+
+        // Same as for plain TCP.
+        stream.set_nodelay(true).unwrap();
+        stream.set_nonblocking(true).unwrap();
+
+        let conn = TcpConnection::accepted_websocket(WsStream::new(stream, self.webrtc), addr);
+        Ok(Box::new(conn))
+    }
+}
+
+/// Server side of a WebSocket.
+///
+/// Converts between binary messages and a plain byte stream.
+/// Like the TCP stream under it, it's nonblocking.
+pub struct WsStream {
+    state: WsState,
+    /// Payloads of received binary messages, ready to be read.
+    payload: VecDeque<u8>,
+    /// Data written before the handshake finished.
+    pending: Vec<u8>,
+    /// Whether to answer WebRTC offers.
+    webrtc: bool,
+    /// Created when the client offers WebRTC.
+    rtc: Option<RtcPeer>,
+}
+
+enum WsState {
+    /// Nothing has been read yet.
+    Accepted(TcpStream),
+    /// The client's upgrade request hasn't fully arrived yet.
+    Handshake(MidHandshake<ServerHandshake<TcpStream, NoCallback>>),
+    Open(WebSocket<TcpStream>),
+    /// The handshake failed or the connection has been closed.
+    Closed,
+}
+
+impl WsStream {
+    fn new(stream: TcpStream, webrtc: bool) -> Self {
+        Self {
+            state: WsState::Accepted(stream),
+            payload: VecDeque::new(),
+            pending: Vec::new(),
+            webrtc,
+            rtc: None,
+        }
+    }
+
+    /// Send the message over WebRTC if the data channel is open, return whether it was sent.
+    pub(super) fn send_unreliable(&self, bytes: &[u8]) -> bool {
+        self.rtc.as_ref().is_some_and(|rtc| rtc.send(bytes))
+    }
+
+    /// Take one message received over WebRTC.
+    pub(super) fn receive_unreliable(&self) -> Option<Vec<u8>> {
+        self.rtc.as_ref().and_then(RtcPeer::receive)
+    }
+
+    /// Continue the handshake if the rest of the client's request has arrived.
+    fn handshake(&mut self) -> io::Result<()> {
+        let res = match mem::replace(&mut self.state, WsState::Closed) {
+            WsState::Accepted(stream) => tungstenite::accept_with_config(stream, Some(config())),
+            WsState::Handshake(mid) => mid.handshake(),
+            state => {
+                self.state = state;
+                return Ok(());
+            }
+        };
+        match res {
+            Ok(mut socket) => {
+                let pending = mem::take(&mut self.pending);
+                if !pending.is_empty() {
+                    send(&mut socket, Message::Binary(pending))?;
+                }
+                self.state = WsState::Open(socket);
+            }
+            Err(HandshakeError::Interrupted(mid)) => self.state = WsState::Handshake(mid),
+            Err(HandshakeError::Failure(e)) => return Err(ws_error(e)),
+        }
+        Ok(())
+    }
+
+    /// Read all available messages from the socket.
+    fn fill(&mut self) -> io::Result<()> {
+        self.handshake()?;
+        let WsState::Open(socket) = &mut self.state else {
+            return Ok(());
+        };
+
+        let mut closed = false;
+        loop {
+            let msg = match socket.read() {
+                Ok(msg) => msg,
+                Err(tungstenite::Error::Io(e)) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed) => {
+                    closed = true;
+                    break;
+                }
+                Err(e) => return Err(ws_error(e)),
+            };
+            match msg {
+                // The byte stream doesn't care where messages start and end.
+                Message::Binary(bytes) => self.payload.extend(bytes),
+                // Without an answer the browser just keeps using the WebSocket.
+                Message::Text(_) if !self.webrtc => dbg_logf!("ignoring WebRTC offer"),
+                Message::Text(text) => {
+                    if self.rtc.is_some() {
+                        return Err(io::Error::new(ErrorKind::InvalidData, "WebRTC offered twice"));
+                    }
+                    match RtcPeer::answer(&text) {
+                        Ok(rtc) => self.rtc = Some(rtc),
+                        Err(e) => dbg_logf!("failed to answer WebRTC offer: {}", e),
+                    }
+                }
+                // Tungstenite answers pings and closes by itself.
+                Message::Ping(_) | Message::Pong(_) | Message::Close(_) | Message::Frame(_) => {}
+            }
+        }
+
+        if let Some(answer) = self.rtc.as_mut().and_then(RtcPeer::poll_answer) {
+            match answer {
+                Ok(answer) => send(socket, Message::Text(answer))?,
+                // The WebSocket still works without it.
+                Err(e) => dbg_logf!("failed to answer WebRTC offer: {}", e),
+            }
+        }
+        if closed {
+            self.state = WsState::Closed;
+        }
+        Ok(())
+    }
+}
+
+impl Read for WsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.payload.is_empty() {
+            self.fill()?;
+        }
+        if self.payload.is_empty() {
+            if matches!(self.state, WsState::Closed) {
+                return Ok(0);
+            }
+            return Err(ErrorKind::WouldBlock.into());
+        }
+        let n = buf.len().min(self.payload.len());
+        for (dst, src) in buf.iter_mut().zip(self.payload.drain(0..n)) {
+            *dst = src;
+        }
+        Ok(n)
+    }
+}
+
+impl Write for WsStream {
+    /// Each write is sent as one binary message.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.state {
+            WsState::Accepted(_) | WsState::Handshake(_) => {
+                if self.pending.len() + buf.len() > MAX_PENDING_LEN {
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        "too much data queued before the WebSocket handshake finished",
+                    ));
+                }
+                self.pending.extend(buf);
+            }
+            WsState::Open(socket) => send(socket, Message::Binary(buf.to_vec()))?,
+            WsState::Closed => return Err(ErrorKind::NotConnected.into()),
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.state {
+            WsState::Open(socket) => queued(socket.flush()),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn config() -> WebSocketConfig {
+    // Tungstenite checks the length in the header before waiting for the rest
+    // so a client can't make us buffer an arbitrary amount of data.
+    WebSocketConfig {
+        max_message_size: Some(MAX_PENDING_LEN),
+        max_frame_size: Some(MAX_PENDING_LEN),
+        ..Default::default()
+    }
+}
+
+fn send(socket: &mut WebSocket<TcpStream>, msg: Message) -> io::Result<()> {
+    queued(socket.send(msg))
+}
+
+/// When the socket would block, tungstenite keeps the data and sends it on the next flush.
+fn queued(res: tungstenite::Result<()>) -> io::Result<()> {
+    match res {
+        Err(tungstenite::Error::Io(e)) if e.kind() == ErrorKind::WouldBlock => Ok(()),
+        res => res.map_err(ws_error),
+    }
+}
+
+fn ws_error(e: tungstenite::Error) -> io::Error {
+    match e {
+        tungstenite::Error::Io(e) => e,
+        e => io::Error::new(ErrorKind::InvalidData, e),
+    }
+}
+
+/// Client side connection from the browser.
+///
+/// The browser does the handshake and framing, we just get whole messages in callbacks.
+#[cfg(target_arch = "wasm32")]
+pub struct WebSocketConnection {
+    socket: JsWebSocket,
+    url: String,
+    /// Filled by the callbacks.
+    incoming: Rc<RefCell<Incoming>>,
+    /// Plaintext bytes ready to be parsed into messages.
+    buffer: VecDeque<u8>,
+    version_sent: bool,
+    /// Whether to offer WebRTC to the server once the socket is open.
+    webrtc: bool,
+    rtc: Option<RtcChannel>,
+    // The callbacks have to live as long as the socket.
+    _on_message: Closure<dyn FnMut(MessageEvent)>,
+    _on_close: Closure<dyn FnMut(CloseEvent)>,
+    _on_error: Closure<dyn FnMut(Event)>,
+}
+
+#[cfg(target_arch = "wasm32")]
+#[derive(Default)]
+struct Incoming {
+    bytes: VecDeque<u8>,
+    /// Text messages, used for WebRTC signaling.
+    signals: Vec<String>,
+    closed: bool,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl WebSocketConnection {
+    /// Start connecting to `addr`, either `host:port` or a full `ws://` or `wss://` URL.
+    ///
+    /// Poll the result with `poll_open`.
+    /// With `webrtc`, the client also tries to open a data channel for unreliable messages.
+    pub fn connect(addr: &str, webrtc: bool) -> io::Result<Self> {
+        let url = if addr.starts_with("ws://") || addr.starts_with("wss://") {
+            addr.to_owned()
+        } else {
+            format!("ws://{addr}")
+        };
+        let socket = JsWebSocket::new(&url).map_err(js_error)?;
+        socket.set_binary_type(BinaryType::Arraybuffer);
+
+        let incoming = Rc::new(RefCell::new(Incoming::default()));
+
+        let incoming2 = Rc::clone(&incoming);
+        let on_message = Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+            if let Ok(data) = event.data().dyn_into::<js_sys::ArrayBuffer>() {
+                let bytes = js_sys::Uint8Array::new(&data).to_vec();
+                incoming2.borrow_mut().bytes.extend(bytes);
+            } else if let Some(text) = event.data().as_string() {
+                incoming2.borrow_mut().signals.push(text);
+            } else {
+                dbg_logf!("WebSocket received an unknown message");
+                incoming2.borrow_mut().closed = true;
+            }
+        });
+        socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+
+        let incoming2 = Rc::clone(&incoming);
+        let on_close = Closure::<dyn FnMut(CloseEvent)>::new(move |event: CloseEvent| {
+            dbg_logf!("WebSocket closed with code {}", event.code());
+            incoming2.borrow_mut().closed = true;
+        });
+        socket.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+
+        // The browser doesn't say what went wrong for security reasons, close follows anyway.
+        let incoming2 = Rc::clone(&incoming);
+        let on_error = Closure::<dyn FnMut(Event)>::new(move |_: Event| {
+            incoming2.borrow_mut().closed = true;
+        });
+        socket.set_onerror(Some(on_error.as_ref().unchecked_ref()));
+
+        Ok(Self {
+            socket,
+            url,
+            incoming,
+            buffer: VecDeque::new(),
+            version_sent: false,
+            webrtc,
+            rtc: None,
+            _on_message: on_message,
+            _on_close: on_close,
+            _on_error: on_error,
+        })
+    }
+
+    /// Returns None while still connecting.
+    ///
+    /// Sends our version once the socket is open so it has to be called until it returns Some.
+    pub fn poll_open(&mut self) -> Option<io::Result<()>> {
+        match self.socket.ready_state() {
+            JsWebSocket::CONNECTING => None,
+            JsWebSocket::OPEN => {
+                if !self.version_sent {
+                    // The browser already encrypts `wss://` so there's no Noise on top.
+                    let version = net::serialize(ClientMessage::Version(Version::current(), None));
+                    if let Err(e) = self.socket.send_with_u8_array(&version.bytes) {
+                        return Some(Err(js_error(e)));
+                    }
+                    self.version_sent = true;
+
+                    if self.webrtc {
+                        match RtcChannel::new() {
+                            Ok(rtc) => self.rtc = Some(rtc),
+                            // The WebSocket still works without it.
+                            Err(e) => dbg_logf!("failed to create WebRTC channel: {}", e),
+                        }
+                    }
+                }
+                Some(Ok(()))
+            }
+            _ => Some(Err(io::Error::new(
+                ErrorKind::ConnectionRefused,
+                format!("failed to connect to {}", self.url),
+            ))),
+        }
+    }
+
+    /// Move received bytes into `buffer` and do WebRTC signaling,
+    /// return whether the connection has been closed.
+    fn read_all(&mut self) -> bool {
+        let mut incoming = self.incoming.borrow_mut();
+        self.buffer.extend(incoming.bytes.drain(..));
+
+        if let Some(rtc) = &mut self.rtc {
+            if let Some(offer) = rtc.poll_offer() {
+                if let Err(e) =
+                    offer.and_then(|offer| self.socket.send_with_str(&offer).map_err(js_error))
+                {
+                    dbg_logf!("failed to offer WebRTC: {}", e);
+                    self.rtc = None;
+                }
+            }
+        }
+        for signal in incoming.signals.drain(..) {
+            let res = match &mut self.rtc {
+                Some(rtc) => rtc.answered(&signal),
+                None => Err(io::Error::new(ErrorKind::InvalidData, "unexpected text message")),
+            };
+            if let Err(e) = res {
+                dbg_logf!("WebRTC signaling failed: {}", e);
+                self.rtc = None;
+            }
+        }
+
+        incoming.closed
+    }
+
+    /// Parse one message received over WebRTC.
+    fn receive_unreliable<M>(&mut self, max_len: usize, closed: &mut bool) -> Option<M>
+    where
+        M: DeserializeOwned,
+    {
+        let bytes = self.rtc.as_ref()?.receive()?;
+        net::parse_datagram(bytes, max_len, &self.url, closed)
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl<M> Connection<M> for WebSocketConnection
+where
+    M: DeserializeOwned,
+{
+    fn send(&mut self, net_msg: &NetworkMessage) -> Result<(), io::Error> {
+        self.socket.send_with_u8_array(&net_msg.bytes).map_err(js_error)
+    }
+
+    fn send_unreliable(&mut self, net_msg: &NetworkMessage) -> Result<(), io::Error> {
+        if self.rtc.as_ref().is_some_and(|rtc| rtc.send(&net_msg.bytes)) {
+            return Ok(());
+        }
+        Connection::<M>::send(self, net_msg)
+    }
+
+    fn receive(&mut self, max_len: usize) -> (Vec<M>, bool) {
+        let mut closed = self.read_all();
+        let mut msgs: Vec<M> = std::iter::from_fn(|| {
+            net::parse_one_or_close(&mut self.buffer, max_len, &self.url, &mut closed)
+        })
+        .collect();
+        msgs.extend(std::iter::from_fn(|| self.receive_unreliable(max_len, &mut closed)));
+        (msgs, closed)
+    }
+
+    fn receive_one(&mut self, max_len: usize) -> (Option<M>, bool) {
+        let mut closed = self.read_all();
+        let msg = net::parse_one_or_close(&mut self.buffer, max_len, &self.url, &mut closed)
+            .or_else(|| self.receive_unreliable(max_len, &mut closed));
+        (msg, closed)
+    }
+
+    fn addr(&self) -> String {
+        self.url.clone()
+    }
+
+    fn is_plaintext(&self) -> bool {
+        self.url.starts_with("ws://")
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Drop for WebSocketConnection {
+    fn drop(&mut self) {
+        // The callbacks are freed with the connection, the browser must not call them anymore.
+        self.socket.set_onmessage(None);
+        self.socket.set_onclose(None);
+        self.socket.set_onerror(None);
+        let _ = self.socket.close();
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(super) fn js_error(value: JsValue) -> io::Error {
+    io::Error::new(ErrorKind::Other, format!("{value:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::*;
+
+    #[test]
+    fn echo_over_websocket() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let client = thread::spawn(move || {
+            let stream = TcpStream::connect(addr).unwrap();
+            let (mut socket, _) = tungstenite::client(format!("ws://{addr}/"), stream).unwrap();
+            socket.send(Message::Binary(b"hello".to_vec())).unwrap();
+            socket.read().unwrap()
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        stream.set_nonblocking(true).unwrap();
+        let mut ws = WsStream::new(stream, false);
+        let mut received = VecDeque::new();
+        for _ in 0..1000 {
+            net::read(&mut ws, &mut received);
+            if received.len() >= 5 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(received, b"hello");
+
+        ws.write_all(b"world").unwrap();
+        ws.flush().unwrap();
+        assert_eq!(client.join().unwrap(), Message::Binary(b"world".to_vec()));
+    }
+
+    #[test]
+    fn too_much_pending() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        stream.set_nonblocking(true).unwrap();
+        let mut ws = WsStream::new(stream, false);
+
+        ws.write_all(&vec![0; MAX_PENDING_LEN]).unwrap();
+        let err = ws.write_all(&[0]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
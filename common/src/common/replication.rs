@@ -0,0 +1,118 @@
+//! Applying the entities the server replicates to a local `GameState`.
+//!
+//! Clients use this but so can anything else which reads server messages, e.g. demo parsers.
+
+use crate::{
+    common::entities::{Cycle, Player, TrailPoint},
+    prelude::*,
+};
+
+impl FrameCtx<'_> {
+    /// Apply the server's `Init` and return the local player.
+    ///
+    /// Fails if the local player doesn't exist afterwards,
+    /// the server is buggy or malicious so the client should disconnect.
+    pub fn init(&mut self, init: Init) -> Result<Handle<Player>, String> {
+        self.set_dt(init.dt);
+
+        // The player has already been spawned when running server logic.
+        if self.gs.gs_type != GameStateType::Shared {
+            for spawn in init.entities {
+                self.spawn_entity(spawn);
+            }
+        }
+
+        self.gs
+            .player_ids
+            .get(init.local_player_id)
+            .ok_or_else(|| format!("local player {} doesn't exist", init.local_player_id))
+    }
+
+    /// Create an entity replicated by the server.
+    pub fn spawn_entity(&mut self, spawn: Spawn) {
+        match spawn.kind {
+            EntityKind::Player => {
+                let mut player = Player::new(spawn.net_id, None);
+                if let Some(name) = spawn.name {
+                    player.name = name;
+                }
+                self.spawn_player(player);
+            }
+            EntityKind::Cycle => {
+                let Some(player_handle) = spawn.owner_id.and_then(|id| self.lookup_player(id))
+                else {
+                    return;
+                };
+                self.spawn_cycle(player_handle, Some(spawn.net_id));
+            }
+        }
+    }
+
+    /// Remove an entity replicated by the server.
+    pub fn despawn_entity(&mut self, kind: EntityKind, net_id: NetId) {
+        match kind {
+            EntityKind::Player => {
+                if let Some(player_handle) = self.lookup_player(net_id) {
+                    self.free_player(player_handle);
+                }
+            }
+            EntityKind::Cycle => {
+                if let Some(cycle_handle) = self.lookup_cycle(net_id) {
+                    self.despawn_cycle(cycle_handle);
+                }
+            }
+        }
+    }
+
+    /// Apply the latest state of an entity replicated by the server.
+    pub fn update_entity(&mut self, update: EntityUpdate) {
+        match update.state {
+            EntityState::Player(input) => {
+                if let Some(player_handle) = self.lookup_player(update.net_id) {
+                    self.gs.players[player_handle].input = input.into();
+                }
+            }
+            EntityState::Cycle(physics) => {
+                let Some(cycle_handle) = self.lookup_cycle(update.net_id) else {
+                    return;
+                };
+                let cycle = &mut self.gs.cycles[cycle_handle];
+                let body = self.scene.graph[cycle.body_handle].as_rigid_body_mut();
+                body.local_transform_mut().set_position(physics.translation.decode());
+                body.local_transform_mut().set_rotation(physics.rotation.decode());
+                body.set_lin_vel(physics.velocity.decode());
+                cycle.boost_energy = physics.boost_energy;
+                cycle.health = physics.health;
+            }
+        }
+    }
+
+    /// Apply changes to a cycle's trail, see `ServerMessage::Trail`.
+    pub fn update_trail(&mut self, cycle_id: NetId, removed: u32, added: Vec<SyncTrailPoint>) {
+        let Some(cycle_handle) = self.lookup_cycle(cycle_id) else {
+            return;
+        };
+        let trail = &mut self.gs.cycles[cycle_handle].trail;
+        let removed = (removed as usize).min(trail.len());
+        trail.drain(..removed);
+        trail.extend(added.into_iter().map(TrailPoint::from));
+    }
+
+    /// Look up a player the server sent, logs if we don't know it.
+    pub fn lookup_player(&self, player_id: NetId) -> Option<Handle<Player>> {
+        let player_handle = self.gs.player_ids.get(player_id);
+        if player_handle.is_none() {
+            dbg_logf!("unknown player {}", player_id);
+        }
+        player_handle
+    }
+
+    /// Look up a cycle the server sent, logs if we don't know it.
+    pub fn lookup_cycle(&self, cycle_id: NetId) -> Option<Handle<Cycle>> {
+        let cycle_handle = self.gs.cycle_ids.get(cycle_id);
+        if cycle_handle.is_none() {
+            dbg_logf!("unknown cycle {}", cycle_id);
+        }
+        cycle_handle
+    }
+}
@@ -0,0 +1,92 @@
+//! Savestates - snapshots of local games saved to disk.
+//!
+//! Useful for reproducing physics bugs at a specific moment.
+//! A savestate is just a bincode-encoded `FullSync`, see `FrameCtx::full_sync`.
+//!
+//! LATER Store the version and refuse to load savestates from incompatible builds.
+
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use crate::prelude::*;
+
+/// Path of the savestate called `name` in `dir`.
+///
+/// Only letters, digits, `-` and `_` are allowed
+/// so a savestate can't end up outside the directory.
+pub fn path(dir: &str, name: &str) -> io::Result<PathBuf> {
+    let valid =
+        !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        let msg = format!("invalid savestate name {name:?}");
+        return Err(io::Error::new(ErrorKind::InvalidInput, msg));
+    }
+    Ok(Path::new(dir).join(format!("{name}.bin")))
+}
+
+pub fn save(path: &Path, sync: &FullSync) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let bytes = bincode::serialize(sync).map_err(|e| io::Error::new(ErrorKind::Other, e))?;
+    fs::write(path, bytes)
+}
+
+pub fn load(path: &Path) -> io::Result<FullSync> {
+    let bytes = fs::read(path)?;
+    bincode::deserialize(&bytes).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        assert_eq!(path("saves", "test").unwrap(), Path::new("saves/test.bin"));
+        assert_eq!(path("saves", "a-b_C9").unwrap(), Path::new("saves/a-b_C9.bin"));
+        assert!(path("saves", "").is_err());
+        assert!(path("saves", "../test").is_err());
+        assert!(path("saves", "/etc/passwd").is_err());
+        assert!(path("saves", "a b").is_err());
+    }
+
+    #[test]
+    fn save_load_round_trip() {
+        let dir = std::env::temp_dir().join(format!("rustcycles-savestate-{}", std::process::id()));
+        let path = path(dir.to_str().unwrap(), "test").unwrap();
+
+        let sync = FullSync {
+            frame_num: 42,
+            next_net_id: 1,
+            game_time: 0.7,
+            game_time_prev: 0.6,
+            match_start: 0.0,
+            match_state: MatchState::Playing,
+            rng: Xoshiro256PlusPlus::seed_from_u64(7),
+            players: Vec::new(),
+            cycles: Vec::new(),
+            projectiles: vec![SyncProjectile {
+                projectile_index: 5,
+                player_id: Some(NetId(0)),
+                pos: [1.0, 2.0, 3.0],
+                vel: [-4.0, 0.0, 0.5],
+                time_fired: 0.5,
+            }],
+            pickups: Vec::new(),
+        };
+        save(&path, &sync).unwrap();
+        let loaded = load(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(loaded.frame_num, 42);
+        assert_eq!(loaded.game_time, 0.7);
+        assert_eq!(loaded.projectiles[0].vel, [-4.0, 0.0, 0.5]);
+        assert_eq!(loaded.rng, sync.rng);
+
+        assert_eq!(load(&path).unwrap_err().kind(), ErrorKind::NotFound);
+    }
+}
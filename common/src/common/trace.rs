@@ -0,0 +1,674 @@
+//! Raycasting - a more ergonomic wrapper around Fyrox's raycasting API.
+//!
+//! Most rays hit only a couple things so results are stored inline in `TraceHits`
+//! and only allocate when there are more.
+//! Code which traces many lines every frame can use `trace_lines`
+//! with a `TraceBatch` kept between frames.
+//! See `d_trace_stats` to check how many rays still allocate.
+//!
+//! Sweeping and overlapping shapes is done by `trace_shape`, `trace_sphere` and `overlap_sphere`.
+
+use std::{cmp::Ordering, ops::Range};
+
+use fyrox::scene::{
+    collider::{BitMask, InteractionGroups},
+    graph::physics::{FeatureId, Intersection, QueryFilter, QueryResultsStorage, RayCastOptions},
+};
+use fyrox_core::algebra::Isometry3;
+use rapier3d::parry::shape::{Ball, Shape};
+use smallvec::SmallVec;
+
+use crate::{debug::DebugCounter, prelude::*};
+
+/// Hits of one ray, see `trace_line`.
+pub type TraceHits = SmallVec<[Intersection; 4]>;
+
+/// Colliders touching a shape, see `overlap_sphere`.
+pub type Overlaps = SmallVec<[Handle<Node>; 4]>;
+
+/// Counters for `d_trace_stats`.
+static STATS: TraceStats = TraceStats {
+    rays: DebugCounter::new(),
+    shapes: DebugCounter::new(),
+    batches: DebugCounter::new(),
+    allocations: DebugCounter::new(),
+};
+
+struct TraceStats {
+    rays: DebugCounter,
+    shapes: DebugCounter,
+    batches: DebugCounter,
+    allocations: DebugCounter,
+}
+
+/// Show how many rays were traced since the last call and reset the counters.
+///
+/// Call once per frame.
+pub fn stats_frame(cvars: &Cvars) {
+    let rays = STATS.rays.take();
+    let shapes = STATS.shapes.take();
+    let batches = STATS.batches.take();
+    let allocations = STATS.allocations.take();
+    if cvars.d_trace_stats {
+        dbg_textf!(
+            "traces: {} rays, {} shapes, {} batches, {} allocations",
+            rays,
+            shapes,
+            batches,
+            allocations
+        );
+    }
+}
+
+/// Results of `trace_lines`.
+///
+/// Keep it around between frames, tracing reuses its buffers.
+#[derive(Debug, Clone, Default)]
+pub struct TraceBatch {
+    /// Hits of all the lines one after another.
+    hits: Vec<Intersection>,
+    /// Which part of `hits` belongs to each line.
+    ranges: Vec<Range<usize>>,
+}
+
+impl TraceBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of lines traced.
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Hits of the `index`th line, same as what `trace_line` would return.
+    pub fn hits(&self, index: usize) -> &[Intersection] {
+        &self.hits[self.ranges[index].clone()]
+    }
+
+    /// Hits of each line in the order they were traced.
+    pub fn iter(&self) -> impl Iterator<Item = &[Intersection]> {
+        self.ranges.iter().map(|range| &self.hits[range.clone()])
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TraceOptions {
+    /// This is passed to `InteractionGroups::new`, see `CollisionLayer`.
+    ///
+    /// All by default.
+    pub memberships: BitMask,
+    /// This is passed to `InteractionGroups::new`, see `CollisionLayer`.
+    ///
+    /// All by default.
+    pub filter: BitMask,
+    /// Sort the results by distance from the ray origin.
+    ///
+    /// Enabled by default.
+    pub sort: bool,
+    /// When the raycast hits something, return a position slightly before the hit
+    /// to avoid accidentally getting to the other side due to float imprecision.
+    ///
+    /// By default this is `None` and tracing uses `g_physics_nudge`.
+    /// If this is set to `Some(value)`, it overrides `g_physics_nudge`.
+    pub nudge: Option<f32>,
+    /// Include a dummy Intersection at the end with the position where the raycast ended
+    /// so that the result is always non-empty.
+    ///
+    /// This can simplify some code that wants to trace limited distance or until it hits something.
+    pub end: bool,
+}
+
+impl Default for TraceOptions {
+    fn default() -> Self {
+        Self {
+            memberships: CollisionLayer::ALL,
+            filter: CollisionLayer::ALL,
+            sort: true,
+            nudge: None,
+            end: false,
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl TraceOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn memberships(memberships: BitMask) -> Self {
+        Self {
+            memberships,
+            ..Default::default()
+        }
+    }
+
+    pub fn filter(filter: BitMask) -> Self {
+        Self {
+            filter,
+            ..Default::default()
+        }
+    }
+
+    pub fn sort(sort: bool) -> Self {
+        Self {
+            sort,
+            ..Default::default()
+        }
+    }
+
+    pub fn nudge(nudge: Option<f32>) -> Self {
+        Self {
+            nudge,
+            ..Default::default()
+        }
+    }
+
+    pub fn end(end: bool) -> Self {
+        Self {
+            end,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_memberships(mut self, memberships: BitMask) -> Self {
+        self.memberships = memberships;
+        self
+    }
+
+    pub fn with_filter(mut self, filter: BitMask) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn with_sort(mut self, sort: bool) -> Self {
+        self.sort = sort;
+        self
+    }
+
+    pub fn with_nudge(mut self, nudge: Option<f32>) -> Self {
+        self.nudge = nudge;
+        self
+    }
+
+    pub fn with_end(mut self, end: bool) -> Self {
+        self.end = end;
+        self
+    }
+}
+
+impl FrameCtx<'_> {
+    pub fn trace_line<P>(
+        &self,
+        ray_origin: P,
+        ray_direction: Vec3,
+        options: TraceOptions,
+    ) -> TraceHits
+    where
+        P: Into<Point3>,
+    {
+        let ray_origin = ray_origin.into();
+        trace_line_inner(self.cvars, self.scene, ray_origin, ray_direction, options)
+    }
+
+    /// Trace all the `(origin, direction)` lines, see `trace_lines`.
+    pub fn trace_lines<I>(&self, rays: I, options: TraceOptions, batch: &mut TraceBatch)
+    where
+        I: IntoIterator<Item = (Point3, Vec3)>,
+    {
+        trace_lines(self.cvars, self.scene, rays, options, batch);
+    }
+}
+
+impl FrameCtx<'_> {
+    /// Sweep a sphere along `direction`, see `trace_shape`.
+    ///
+    /// Returns how far the center can move before the sphere hits something.
+    pub fn trace_sphere<P>(
+        &self,
+        origin: P,
+        direction: Vec3,
+        radius: f32,
+        options: TraceOptions,
+    ) -> f32
+    where
+        P: Into<Point3>,
+    {
+        trace_sphere(self.cvars, self.scene, origin, direction, radius, options)
+    }
+
+    /// Sweep `shape` along `direction`, see the standalone `trace_shape`.
+    pub fn trace_shape<P>(
+        &self,
+        shape: &dyn Shape,
+        origin: P,
+        direction: Vec3,
+        options: TraceOptions,
+    ) -> Option<Intersection>
+    where
+        P: Into<Point3>,
+    {
+        let origin = origin.into();
+        trace_shape_inner(self.cvars, self.scene, shape, origin, direction, options)
+    }
+
+    /// Colliders touching the sphere, see the standalone `overlap_sphere`.
+    pub fn overlap_sphere<P>(&self, center: P, radius: f32, options: TraceOptions) -> Overlaps
+    where
+        P: Into<Point3>,
+    {
+        overlap_sphere(self.scene, center, radius, options)
+    }
+}
+
+/// Standalone function for cases where it's impossible to borrow `FrameCtx` as a whole
+/// but `cvars` and `scene` are available separately.
+pub fn trace_line<P>(
+    cvars: &Cvars,
+    scene: &Scene,
+    ray_origin: P,
+    ray_direction: Vec3,
+    options: TraceOptions,
+) -> TraceHits
+where
+    P: Into<Point3>,
+{
+    let ray_origin = ray_origin.into();
+    trace_line_inner(cvars, scene, ray_origin, ray_direction, options)
+}
+
+/// Sweep a sphere along `direction`.
+///
+/// Returns how far the center can move before the sphere hits something.
+pub fn trace_sphere<P>(
+    cvars: &Cvars,
+    scene: &Scene,
+    origin: P,
+    direction: Vec3,
+    radius: f32,
+    options: TraceOptions,
+) -> f32
+where
+    P: Into<Point3>,
+{
+    let origin = origin.into();
+    let Some(dir) = direction.try_normalize(f32::EPSILON) else {
+        return 0.0;
+    };
+    let ball = Ball::new(radius);
+    match trace_shape_inner(cvars, scene, &ball, origin, direction, options) {
+        Some(hit) if hit.collider.is_some() => (hit.position - origin).dot(&dir).max(0.0),
+        _ => direction.norm(),
+    }
+}
+
+/// Sweep `shape` (without rotation) from `origin` along `direction`
+/// and return where it first hits something.
+///
+/// Like `trace_line` except there's at most one hit so `TraceOptions::sort` is ignored.
+/// The hit's `position` is where the shape's center stops (nudged back the same way),
+/// `normal` is the surface normal of what it hit and `toi` is the distance travelled.
+/// If the shape already overlaps something at `origin`, it hits immediately.
+pub fn trace_shape<P>(
+    cvars: &Cvars,
+    scene: &Scene,
+    shape: &dyn Shape,
+    origin: P,
+    direction: Vec3,
+    options: TraceOptions,
+) -> Option<Intersection>
+where
+    P: Into<Point3>,
+{
+    let origin = origin.into();
+    trace_shape_inner(cvars, scene, shape, origin, direction, options)
+}
+
+fn trace_shape_inner(
+    cvars: &Cvars,
+    scene: &Scene,
+    shape: &dyn Shape,
+    origin: Point3,
+    direction: Vec3,
+    options: TraceOptions,
+) -> Option<Intersection> {
+    STATS.shapes.add(1);
+    let max_len = direction.norm();
+    let end = options.end.then(|| Intersection {
+        collider: Handle::NONE,
+        normal: Vec3::zeros(),
+        position: origin + direction,
+        feature: FeatureId::Unknown,
+        toi: max_len,
+    });
+    let Some(dir) = direction.try_normalize(f32::EPSILON) else {
+        return end;
+    };
+
+    let shape_pos = Isometry3::translation(origin.x, origin.y, origin.z);
+    let filter = QueryFilter {
+        groups: Some(InteractionGroups::new(options.memberships, options.filter)),
+        ..Default::default()
+    };
+    // With a unit velocity, time of impact is the distance.
+    let Some((collider, toi)) = scene.graph.physics.cast_shape(
+        &scene.graph,
+        shape,
+        &shape_pos,
+        &dir,
+        max_len,
+        true,
+        filter,
+    ) else {
+        return end;
+    };
+
+    let nudge = options.nudge.unwrap_or(cvars.g_physics_nudge);
+    // Starting inside something doesn't move the shape back out.
+    let dist = (toi.toi - nudge).max(0.0);
+    Some(Intersection {
+        collider,
+        // The cast shape isn't rotated so its local space normal is also in world space.
+        // It points from the shape to the collider.
+        normal: -*toi.normal1,
+        position: origin + dir * dist,
+        feature: FeatureId::Unknown,
+        toi: toi.toi,
+    })
+}
+
+/// Colliders touching the sphere, e.g. for splash damage.
+///
+/// Only `memberships` and `filter` of `options` are used.
+pub fn overlap_sphere<P>(scene: &Scene, center: P, radius: f32, options: TraceOptions) -> Overlaps
+where
+    P: Into<Point3>,
+{
+    let center = center.into();
+    let ball = Ball::new(radius);
+    let shape_pos = Isometry3::translation(center.x, center.y, center.z);
+    let groups = InteractionGroups::new(options.memberships, options.filter);
+
+    // Fyrox only exposes shape casts which return the first hit.
+    // Casting with zero length finds something the sphere already overlaps,
+    // then it's excluded and we cast again until nothing is left.
+    // LATER(perf) Query all intersections at once if Fyrox exposes it.
+    let mut overlaps = Overlaps::new();
+    loop {
+        STATS.shapes.add(1);
+        let predicate = |collider: Handle<Node>, _: &Collider| !overlaps.contains(&collider);
+        let filter = QueryFilter {
+            groups: Some(groups),
+            predicate: Some(&predicate),
+            ..Default::default()
+        };
+        // Any direction works, the shape doesn't move.
+        let hit =
+            scene
+                .graph
+                .physics
+                .cast_shape(&scene.graph, &ball, &shape_pos, &UP, 0.0, true, filter);
+        let Some((collider, _)) = hit else {
+            break;
+        };
+        overlaps.push(collider);
+    }
+    STATS.allocations.add(usize::from(overlaps.spilled()));
+    overlaps
+}
+
+/// Trace all the `(origin, direction)` lines and store their hits in `batch`.
+///
+/// Replaces whatever was in `batch` before but reuses its memory
+/// so tracing the same number of lines every frame doesn't allocate.
+pub fn trace_lines<I>(
+    cvars: &Cvars,
+    scene: &Scene,
+    rays: I,
+    options: TraceOptions,
+    batch: &mut TraceBatch,
+) where
+    I: IntoIterator<Item = (Point3, Vec3)>,
+{
+    let capacity = (batch.hits.capacity(), batch.ranges.capacity());
+    batch.hits.clear();
+    batch.ranges.clear();
+
+    // Spills at most once, then the memory is reused by the following lines.
+    let mut hits = TraceHits::new();
+    for (ray_origin, ray_direction) in rays {
+        trace_line_into(cvars, scene, ray_origin, ray_direction, options, &mut hits);
+        let start = batch.hits.len();
+        batch.hits.extend(hits.drain(..));
+        batch.ranges.push(start..batch.hits.len());
+    }
+
+    STATS.batches.add(1);
+    STATS.allocations.add(usize::from(hits.spilled()));
+    STATS.allocations.add(usize::from(batch.hits.capacity() != capacity.0));
+    STATS.allocations.add(usize::from(batch.ranges.capacity() != capacity.1));
+}
+
+/// Separate non-generic inner fn to avoid monomorphising everything
+/// because of the generic ray_origin.
+fn trace_line_inner(
+    cvars: &Cvars,
+    scene: &Scene,
+    ray_origin: Point3,
+    ray_direction: Vec3,
+    options: TraceOptions,
+) -> TraceHits {
+    let mut hits = TraceHits::new();
+    trace_line_into(cvars, scene, ray_origin, ray_direction, options, &mut hits);
+    STATS.allocations.add(usize::from(hits.spilled()));
+    hits
+}
+
+/// Replace the contents of `hits` with what the line hit.
+///
+/// Doesn't count allocations, callers which reuse `hits` know when it spilled.
+fn trace_line_into(
+    cvars: &Cvars,
+    scene: &Scene,
+    ray_origin: Point3,
+    ray_direction: Vec3,
+    options: TraceOptions,
+    hits: &mut TraceHits,
+) {
+    STATS.rays.add(1);
+    let max_len = ray_direction.norm();
+
+    // Fyrox clears the storage before casting.
+    scene.graph.physics.cast_ray(
+        RayCastOptions {
+            ray_origin,
+            ray_direction,
+            max_len,
+            groups: InteractionGroups::new(options.memberships, options.filter),
+            sort_results: options.sort,
+        },
+        &mut HitStorage(hits),
+    );
+
+    let nudge = options.nudge.unwrap_or(cvars.g_physics_nudge);
+    for hit in hits.iter_mut() {
+        hit.position -= ray_direction.normalize() * nudge;
+    }
+
+    if options.end {
+        hits.push(Intersection {
+            collider: Handle::NONE,
+            normal: Vec3::zeros(),
+            position: ray_origin + ray_direction,
+            feature: FeatureId::Unknown,
+            toi: max_len,
+        });
+    }
+}
+
+/// Fyrox only supports `Vec` and `ArrayVec` which discards hits when full.
+struct HitStorage<'a>(&'a mut TraceHits);
+
+impl QueryResultsStorage for HitStorage<'_> {
+    fn push(&mut self, intersection: Intersection) -> bool {
+        self.0.push(intersection);
+        true
+    }
+
+    fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    fn sort_intersections_by<C>(&mut self, cmp: C)
+    where
+        C: FnMut(&Intersection, &Intersection) -> Ordering,
+    {
+        self.0.sort_by(cmp);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A map wall 4.5 m in front of the origin along X
+    /// and a cycle-sized box 4.5 m along Z.
+    fn test_scene() -> (Scene, Handle<Node>, Handle<Node>) {
+        let mut scene = Scene::new();
+        let wall = collider(
+            &mut scene,
+            v!(5 0 0),
+            v!(0.5 5 5),
+            InteractionGroups::new(CollisionLayer::ALL, CollisionLayer::ALL),
+        );
+        let cycle =
+            collider(&mut scene, v!(0 0 5), v!(0.5 0.5 0.5), CollisionLayer::Cycle.groups());
+        // Creates the physics objects and updates the query pipeline.
+        scene.graph.update(Vector2::new(1.0, 1.0), 1.0 / 60.0, Default::default());
+        (scene, wall, cycle)
+    }
+
+    fn collider(
+        scene: &mut Scene,
+        pos: Vec3,
+        half_extents: Vec3,
+        groups: InteractionGroups,
+    ) -> Handle<Node> {
+        let collider = ColliderBuilder::new(BaseBuilder::new())
+            .with_shape(ColliderShape::cuboid(half_extents.x, half_extents.y, half_extents.z))
+            .with_collision_groups(groups)
+            .build(&mut scene.graph);
+        RigidBodyBuilder::new(
+            BaseBuilder::new()
+                .with_local_transform(TransformBuilder::new().with_local_position(pos).build())
+                .with_children(&[collider]),
+        )
+        .with_body_type(RigidBodyType::Static)
+        .build(&mut scene.graph);
+        collider
+    }
+
+    fn exact() -> TraceOptions {
+        TraceOptions::nudge(Some(0.0))
+    }
+
+    #[test]
+    fn test_trace_line() {
+        let cvars = Cvars::default();
+        let (scene, wall, _) = test_scene();
+
+        let hits = trace_line(&cvars, &scene, Point3::origin(), v!(10 0 0), exact());
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].collider, wall);
+        assert!((hits[0].position.x - 4.5).abs() < 0.001, "{:?}", hits[0].position);
+
+        let hits =
+            trace_line(&cvars, &scene, Point3::origin(), v!(-10 0 0), exact().with_end(true));
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].collider.is_none());
+        assert_eq!(hits[0].position, Point3::new(-10.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_trace_lines() {
+        let cvars = Cvars::default();
+        let (scene, wall, cycle) = test_scene();
+        let rays = [
+            (Point3::origin(), v!(10 0 0)),
+            (Point3::origin(), v!(-10 0 0)),
+            (Point3::origin(), v!(0 0 10)),
+        ];
+
+        let mut batch = TraceBatch::new();
+        trace_lines(&cvars, &scene, rays, exact(), &mut batch);
+        assert_eq!(batch.len(), 3);
+        assert_eq!(batch.hits(0).len(), 1);
+        assert_eq!(batch.hits(0)[0].collider, wall);
+        assert!(batch.hits(1).is_empty());
+        assert_eq!(batch.hits(2).len(), 1);
+        assert_eq!(batch.hits(2)[0].collider, cycle);
+        for (i, hits) in batch.iter().enumerate() {
+            let single = trace_line(&cvars, &scene, rays[i].0, rays[i].1, exact());
+            assert_eq!(hits.len(), single.len());
+        }
+
+        // Tracing the same lines again replaces the results without allocating.
+        let capacity = (batch.hits.capacity(), batch.ranges.capacity());
+        let options = exact().with_filter(CollisionLayer::MAP_ONLY);
+        trace_lines(&cvars, &scene, rays, options, &mut batch);
+        assert_eq!(batch.len(), 3);
+        assert_eq!(batch.hits(0).len(), 1);
+        assert!(batch.hits(2).is_empty());
+        assert_eq!((batch.hits.capacity(), batch.ranges.capacity()), capacity);
+
+        trace_lines(&cvars, &scene, std::iter::empty(), exact(), &mut batch);
+        assert!(batch.is_empty());
+    }
+
+    #[test]
+    fn test_trace_shape() {
+        let cvars = Cvars::default();
+        let (scene, wall, _) = test_scene();
+        let ball = Ball::new(0.5);
+
+        let hit =
+            trace_shape(&cvars, &scene, &ball, Point3::origin(), v!(10 0 0), exact()).unwrap();
+        assert_eq!(hit.collider, wall);
+        assert!((hit.toi - 4.0).abs() < 0.001, "{}", hit.toi);
+        assert!((hit.position.x - 4.0).abs() < 0.001, "{:?}", hit.position);
+        assert!((hit.normal - v!(-1 0 0)).norm() < 0.001, "{:?}", hit.normal);
+
+        let miss = trace_shape(&cvars, &scene, &ball, Point3::origin(), v!(-10 0 0), exact());
+        assert!(miss.is_none());
+
+        let dist = trace_sphere(&cvars, &scene, Point3::origin(), v!(10 0 0), 0.5, exact());
+        assert!((dist - 4.0).abs() < 0.001, "{dist}");
+        let dist = trace_sphere(&cvars, &scene, Point3::origin(), v!(-10 0 0), 0.5, exact());
+        assert_eq!(dist, 10.0);
+    }
+
+    #[test]
+    fn test_overlap_sphere() {
+        let (scene, wall, cycle) = test_scene();
+        // 2 m from both the wall and the box.
+        let center = Point3::new(2.5, 0.0, 4.5);
+
+        let mut overlaps = overlap_sphere(&scene, center, 2.2, TraceOptions::default());
+        overlaps.sort_by_key(|handle| handle.index());
+        let mut expected = [wall, cycle];
+        expected.sort_by_key(|handle| handle.index());
+        assert_eq!(overlaps.as_slice(), expected);
+
+        let options = TraceOptions::filter(CollisionLayer::MAP_ONLY);
+        let overlaps = overlap_sphere(&scene, center, 2.2, options);
+        assert_eq!(overlaps.as_slice(), [wall]);
+
+        let overlaps = overlap_sphere(&scene, center, 1.0, TraceOptions::default());
+        assert!(overlaps.is_empty());
+    }
+}
@@ -0,0 +1,1148 @@
+//! Console variables - configuration options for anything and everything.
+
+use std::{num::ParseFloatError, sync::OnceLock};
+
+use cvars::cvars;
+
+use crate::prelude::*;
+
+// Normally we use pub everywhere for when the project is eventually
+// split into crates but here we have to use pub:
+// https://github.com/martin-t/cvars/issues/13
+// Plus then cvars will likely be pub anyway.
+cvars! {
+    #![cvars(sorted)]
+
+    //! Console variables - configuration options for anything and everything.
+    //!
+    //! Prefix meanings:
+    //! cl_     client
+    //! d_      debugging
+    //! g_      gameplay (some of it runs only on the server but this can change with better clientside prediction)
+    //! hud_    heads-up display
+    //! net_    networking settings shared by the client and server
+    //! r_      rendering
+    //! snd_    sound
+    //! sv_     server administration + performance (not gameplay even if it only runs on the server)
+    //! sys_    low level / "engine"
+
+    /// Port to broadcast to when looking for servers on the local network.
+    cl_browser_lan_port: u16 = 26000,
+    /// How long to wait for the master server's response in seconds.
+    cl_browser_timeout: f32 = 3.0,
+
+    /// How high above the cycle's center the first person camera is.
+    cl_camera_1st_person_up: f32 = 0.3,
+    // LATER move back depending on speed?
+    cl_camera_3rd_person_back: f32 = 2.0,
+    /// Radius of the sphere traced to keep the third person camera out of walls.
+    cl_camera_3rd_person_radius: f32 = 0.2,
+    cl_camera_3rd_person_up: f32 = 1.0,
+    /// How long in seconds the third person camera takes to move back out after being pushed in by a wall.
+    ///
+    /// Walls push it in immediately. This and the other `_lag` cvars are time constants
+    /// of exponential smoothing, 0 disables smoothing.
+    cl_camera_arm_lag: f32 = 0.2,
+    /// Vertical field of view in degrees.
+    ///
+    /// LATER What do other games use? Horiz/vert, what values?
+    cl_camera_fov: f32 = 75.0,
+    /// How many degrees the FOV widens at `g_wheel_max_speed`, scales linearly with speed.
+    cl_camera_fov_kick: f32 = 10.0,
+    cl_camera_initial_position: CVec3 = v!(0 5 -15).into(),
+    /// `first` or `third` person. In first person, the local cycle only casts a shadow.
+    cl_camera_mode: CameraMode = CameraMode::ThirdPerson,
+    /// How long in seconds the third person camera takes to follow the cycle.
+    cl_camera_position_lag: f32 = 0.05,
+    /// How long in seconds the camera takes to turn after the mouse.
+    ///
+    /// Only visual, aiming always uses the exact direction.
+    cl_camera_rotation_lag: f32 = 0.0,
+    cl_camera_speed: f32 = 10.0,
+    /// How much the camera rolls while wall riding, 1.0 means the wall is the floor.
+    cl_camera_wall_ride_tilt: f32 = 0.5,
+    cl_camera_z_far: f32 = 2048.0,
+    cl_camera_z_near: f32 = 0.001,
+
+    /// Server to connect to in the format `host:port`.
+    ///
+    /// In the browser this is the server's `sv_net_listen_addr_websocket`,
+    /// optionally as a full `ws://` or `wss://` URL.
+    /// Changing it at runtime (e.g. in the console) leaves the current game and connects to the new address.
+    /// LATER A proper `connect` console command once the console supports commands.
+    cl_connect_addr: String = "127.0.0.1:26000".to_owned(),
+    /// How many lines the console keeps, older ones are dropped.
+    ///
+    /// Includes everything logged by `dbg_logf` and friends, use `condump` to save it.
+    cl_console_history_max: usize = 10_000,
+
+    /// Max frames per second while the window has focus, 0 means unlimited.
+    ///
+    /// Frames are paced by sleeping so the game doesn't occupy a full CPU core.
+    /// Gamelogic catches up on missed ticks so it still runs at `sv_tickrate`.
+    cl_fps_max: f32 = 0.0,
+    /// Max frames per second while the window is in the background, 0 means unlimited.
+    cl_fps_max_unfocused: f32 = 30.0,
+    /// Applied immediately when changed, Alt+Enter toggles it.
+    cl_fullscreen: bool = true,
+    /// Change the monitor's video mode instead of covering it with a borderless window.
+    cl_fullscreen_exclusive: bool = false,
+    /// How far a stick has to be pushed to count, from 0 to 1.
+    cl_gamepad_deadzone: f32 = 0.25,
+    /// Degrees per second when the right stick is pushed all the way.
+    cl_gamepad_sensitivity: f32 = 180.0,
+    /// Identifies the player to servers across sessions so they can save stats,
+    /// see `sv_stats_db`.
+    ///
+    /// Keep it secret, anyone who knows it can add to your stats.
+    /// Servers only accept 32 to 64 alphanumeric characters.
+    ///
+    /// Loaded from or generated into `GUID_PATH` at startup when empty.
+    cl_guid: String = "".to_owned(),
+    /// Run the game without a window. Useful for CI.
+    cl_headless: bool = false,
+    /// When headless, sleep until the next tick instead of polling for events in a busy loop.
+    cl_headless_idle_sleep: bool = true,
+    /// After dying, watch from the killer's point of view until respawning.
+    cl_killcam: bool = true,
+    /// How long the killcam lasts in seconds at most.
+    cl_killcam_duration: f32 = 2.0,
+    /// Render entities between the last two ticks to avoid judder
+    /// when the framerate doesn't match the tickrate.
+    cl_interpolation: bool = true,
+    /// Download maps we don't have from the server, see `maps::DOWNLOADS_DIR`.
+    cl_map_download: bool = true,
+    /// Index of the monitor to use in fullscreen mode.
+    cl_monitor: usize = 0,
+    cl_mouse_grab_on_focus: bool = true,
+
+    /// Player name, sent to the server when connecting.
+    cl_name: String = "Player".to_owned(),
+
+    /// How many times to try connecting before giving up.
+    cl_net_connect_max_attempts: u64 = 500,
+    cl_net_connect_retry_delay_ms: u64 = 10,
+    cl_net_connect_retry_print_every_n: u64 = 100,
+    /// How long one attempt to connect may take including the encryption handshake, in seconds.
+    cl_net_connect_timeout: f32 = 5.0,
+    /// How long to wait for the server to send init data after connecting
+    /// or the next part of a map when downloading it, in seconds.
+    cl_net_init_timeout: f32 = 10.0,
+    /// Max length of a message from the server in bytes, larger messages cause a disconnect.
+    ///
+    /// This is large because updates contain all debug shapes and texts.
+    cl_net_max_message_len: usize = 64 * 1024 * 1024,
+    /// Ask the server for the full game state when the client detects a desync.
+    ///
+    /// Off by default until the checksum has proven stable enough
+    /// that false positives don't cause needless full syncs.
+    cl_net_resync: bool = false,
+    /// In the browser, also open a WebRTC data channel to the server
+    /// for messages which don't need to arrive (e.g. inputs and updates).
+    ///
+    /// The WebSocket is still used for everything else and as a fallback
+    /// if the server doesn't support WebRTC. Only read when connecting.
+    cl_net_webrtc: bool = true,
+
+    /// The observer camera flies through walls.
+    cl_observer_noclip: bool = false,
+    /// Max angle in degrees between where the observer is looking and a cycle
+    /// for right click to move the camera to it.
+    cl_observer_pick_angle: f32 = 10.0,
+    /// Observer camera speed is multiplied by this while holding shift.
+    cl_observer_speed_fast: f32 = 3.0,
+    /// Observer camera speed is multiplied by this while holding ctrl.
+    cl_observer_speed_slow: f32 = 0.25,
+    /// How far behind the cycle the observer camera ends up after moving to it, in m.
+    cl_observer_teleport_distance: f32 = 5.0,
+
+    /// How fast pickups spin, in degrees per second.
+    cl_pickup_rotation_speed: f32 = 90.0,
+
+    /// Camera shake per point of damage taken.
+    ///
+    /// Shake is a "trauma" value between 0 and 1, the camera moves by its square.
+    cl_shake_damage: f32 = 0.02,
+    /// How fast trauma wears off per second.
+    cl_shake_decay: f32 = 1.5,
+    /// How fast the camera shakes.
+    cl_shake_frequency: f32 = 15.0,
+    /// Trauma from a projectile impact right next to the camera.
+    cl_shake_impact: f32 = 0.3,
+    /// Projectile impacts further than this (in m) don't shake the camera.
+    cl_shake_impact_radius: f32 = 10.0,
+    /// Max camera rotation in degrees at full trauma.
+    cl_shake_max_angle: f32 = 3.0,
+    /// Max camera movement in m at full trauma, only when following a cycle.
+    cl_shake_max_offset: f32 = 0.1,
+
+    /// Add a second local player in local games, the screen is split horizontally.
+    ///
+    /// The keyboard and mouse control the first player, gamepads the second.
+    /// Applied when a local game starts.
+    cl_splitscreen: bool = false,
+    cl_splitscreen_name: String = "Player 2".to_owned(),
+
+    /// Show touch controls - a virtual joystick, buttons and dragging to look around.
+    cl_touch: bool = false,
+    /// Turn on `cl_touch` the first time the screen is touched.
+    cl_touch_auto: bool = true,
+    /// Radius of the fire, boost and jump buttons as a fraction of the window height.
+    cl_touch_button_radius: f32 = 0.07,
+    /// Center of the fire button from the bottom right corner as a fraction of the window height.
+    ///
+    /// The boost button is to the left of it, jump above it.
+    cl_touch_buttons_x: f32 = 0.15,
+    cl_touch_buttons_y: f32 = 0.15,
+    /// How far the joystick has to be pushed to move, as a fraction of its radius.
+    cl_touch_joystick_deadzone: f32 = 0.3,
+    /// Radius of the joystick as a fraction of the window height.
+    cl_touch_joystick_radius: f32 = 0.12,
+    /// Center of the joystick from the bottom left corner as a fraction of the window height.
+    cl_touch_joystick_x: f32 = 0.25,
+    cl_touch_joystick_y: f32 = 0.25,
+    /// Degrees per pixel when dragging to look around.
+    cl_touch_sensitivity: f32 = 0.15,
+
+    /// Applied immediately when changed by recreating the graphics context.
+    cl_vsync: bool = true,
+    /// Size of the window in windowed mode, applied immediately when changed.
+    cl_window_height: i32 = 540,
+    /// Let the OS place the window.
+    ///
+    /// Turned off when the window is moved so `cl_window_x` and `cl_window_y`
+    /// remember its position, e.g. when switching back from fullscreen.
+    cl_window_pos_auto: bool = true,
+    cl_window_width: i32 = 960,
+    cl_window_x: i32 = 0,
+    cl_window_y: i32 = 0,
+
+    cl_zoom_factor: f32 = 4.0,
+    /// How long in seconds zooming in or out takes.
+    cl_zoom_lag: f32 = 0.05,
+
+    // TODO A lot of these cvars need to be synced to server when playing locally.
+    /// Exit after this many frames and print frame statistics, 0 to run normally.
+    ///
+    /// Set by the `benchmark` command.
+    d_benchmark_frames: usize = 0,
+
+    /// Where to POST crash reports, only plain `http://` URLs are supported.
+    d_crash_report_url: String = "".to_owned(),
+    /// Send crash reports to `d_crash_report_url` automatically.
+    ///
+    /// Off by default because the reports contain logs and settings.
+    d_crash_report_upload: bool = false,
+    /// Save a crash report into `crashes/` when the game panics.
+    d_crash_reports: bool = true,
+
+    /// Set to a file name to record all debug shapes and texts drawn each frame into it.
+    ///
+    /// Frames are appended if the file already exists. Use `d_debug_replay` to view them.
+    d_debug_record: String = "".to_owned(),
+    /// Set to a file recorded by `d_debug_record` to draw its shapes and texts
+    /// in addition to the live ones.
+    d_debug_replay: String = "".to_owned(),
+    /// Change this to seek to the first recorded frame with this number or higher.
+    d_debug_replay_frame: usize = 0,
+    /// How many recorded frames to advance each tick, 0 to pause the replay.
+    d_debug_replay_speed: f32 = 1.0,
+
+    /// Every tick, replay shared gamelogic on a copy of the game state from before the tick
+    /// and report an error if the result differs from the real tick.
+    ///
+    /// Only checks the server or local game. Very slow,
+    /// debug shapes and texts drawn by gamelogic show up twice.
+    d_determinism_check: bool = false,
+
+    /// Master switch for debug output - the d_draw_* group.
+    d_draw: bool = true,
+    d_draw_arrows: bool = true,
+    d_draw_crosses: bool = true,
+    d_draw_crosses_half_len: f32 = 0.5,
+    /// Sometimes useful if you have trouble finding the crosses.
+    d_draw_crosses_line_from_origin: bool = false,
+    d_draw_frame_timings: bool = true,
+    d_draw_frame_timings_steps: usize = 4,
+    d_draw_frame_timings_text: bool = false,
+    d_draw_lines: bool = true,
+    /// This ruins perf in debug builds: https://github.com/FyroxEngine/Fyrox/issues/237
+    d_draw_physics: bool = true,
+    d_draw_rots: bool = true,
+    d_draw_rots_size: f32 = 1.0,
+    d_draw_text: bool = true,
+    d_draw_text_shadow: bool = true,
+    d_draw_text_shadow_dilation: f32 = 0.0,
+    d_draw_text_shadow_offset_x: f32 = 1.0,
+    d_draw_text_shadow_offset_y: f32 = 1.0,
+
+    d_engine_stats: bool = true,
+
+    d_events: bool = true,
+    d_events_focused: bool = false,
+    d_events_keyboard_input: bool = false,
+    d_events_mouse_input: bool = false,
+    d_events_mouse_motion: bool = false,
+    d_events_mouse_wheel: bool = false,
+    d_events_resized: bool = false,
+    d_events_touch: bool = false,
+
+    /// Run init, gamelogic, and rendering once, then exit. Useful for crude testing/benchmarking.
+    d_exit_after_one_frame: bool = false,
+    /// During init. Set this first.
+    d_exit_on_unknown_cvar: bool = true,
+
+    /// Show the average, 99th percentile and max of frame and update times.
+    ///
+    /// Use the `frame_stats_reset` console command to start measuring again.
+    d_frame_stats: bool = true,
+    /// How many of the most recent frames are included in `d_frame_stats`.
+    d_frame_stats_samples: usize = 300,
+
+    /// Plot frame time, tick time, physics time and network usage as scrolling graphs.
+    d_graphs: bool = false,
+    /// How many frames the graphs show.
+    d_graphs_samples: usize = 240,
+
+    /// Watch `data/` and reload models and textures when their files change.
+    ///
+    /// Changing the current map restarts it in local games. Not available in browsers.
+    d_hot_reload_assets: bool = false,
+
+    /// Print every `CollisionEvent` when it happens.
+    d_log_collisions: bool = false,
+    /// Print logged lines as JSON objects, one per line. Intended for servers
+    /// so logs can be processed by other tools.
+    d_log_json: bool = false,
+    /// Which log messages to print - a comma separated list of `level` or `module=level`,
+    /// e.g. `warn,server=debug`. Levels are `off`, `error`, `warn`, `info`, `debug` and `trace`.
+    ///
+    /// The logger is shared by all threads so in local games
+    /// the client and server can override each other's settings.
+    d_log_level: String = "info".to_owned(),
+
+    /// Show how many messages were serialized and parsed last frame
+    /// and how many buffers had to be allocated for them.
+    d_net_stats: bool = false,
+
+    /// Stop advancing game time. Only works in local games.
+    d_pause: bool = false,
+
+    d_physics_extra_sync: bool = false,
+
+    /// Show how long each `profile_scope` took in the previous frame.
+    d_profiler: bool = false,
+    /// Set to a file name to record `d_profiler_trace_frames` frames
+    /// in the chrome tracing format (open it in chrome://tracing or Perfetto).
+    ///
+    /// The cvar is cleared after saving.
+    d_profiler_trace: String = "".to_owned(),
+    d_profiler_trace_frames: usize = 300,
+
+    /// Directory where the `save` and `load` console commands store savestates.
+    ///
+    /// They only work in local games and a savestate must be from a game with the same players.
+    d_savestate_dir: String = "savestates".to_owned(),
+
+    /// The seed to initialize the RNG.
+    ///
+    /// This is not very helpful by itself because by the time you can change cvars in the console,
+    /// the seed has already been used. However, in the desktop version, you can set it on the command line.
+    ///
+    /// LATER If the seed is 0 at match start, the cvar is changed to the current time and that is used as seed.
+    /// This means you can look at the cvar's value later and know what seed you need to replay the same game.
+    d_seed: u64 = 0,
+
+    /// Pause the game and only advance one gamelogic frame
+    /// each time F10 is pressed or the `step` console command is entered.
+    /// Only works in local games.
+    d_singlestep: bool = false,
+
+    /// How fast game time advances relative to real time. Only works in local games.
+    ///
+    /// Useful for looking at physics and trails in slow motion.
+    d_speed: f32 = 1.0,
+
+    /// Enable extra logging useful when testing the game, for example on CI.
+    d_testing: bool = false,
+
+    /// Show how many rays were traced last frame and how many of them had to allocate.
+    d_trace_stats: bool = false,
+
+    /// Print UI messages or a subset of them.
+    d_ui_msgs: bool = false,
+    d_ui_msgs_direction_from: bool = true,
+    d_ui_msgs_direction_to: bool = false,
+    d_ui_msgs_mouse: bool = false,
+
+    /// Apply cvars from `d_watch_config_path` whenever the file changes.
+    ///
+    /// Like `tweak!` but for values which are already cvars.
+    /// Only changes are applied, use `--config` to also load the file at startup.
+    d_watch_config: bool = false,
+    /// The cvar file watched by `d_watch_config`, same format as the saved config.
+    d_watch_config_path: String = "autoexec.cfg".to_owned(),
+
+    /// A "temporary" cvar for quick testing. Normally unused but kept here
+    /// so I don't have to add a cvar each time I want a quick toggle.
+    dbg: bool = false,
+    /// Same as dbg but for floats.
+    dbgf: f32 = 0.0,
+    /// Same as dbg but for ints.
+    dbgi: i32 = 0,
+
+    /// Fraction of acceleration and braking available while airborne.
+    g_air_control: f32 = 0.2,
+
+    g_ammo_max: u32 = 400,
+    /// Ammo when spawning.
+    g_ammo_start: u32 = 200,
+
+    /// Acceleration is multiplied by this while boosting.
+    g_boost_acceleration_factor: f32 = 2.0,
+    /// Boost energy used per second.
+    g_boost_drain: f32 = 50.0,
+    g_boost_energy_max: f32 = 100.0,
+    /// Max speed is multiplied by this while boosting.
+    g_boost_max_speed_factor: f32 = 1.5,
+    /// Boost energy regenerated per second while not boosting.
+    g_boost_regen: f32 = 20.0,
+
+    g_cycle_health: f32 = 100.0,
+
+    /// The match ends when a player gets this many kills, 0 means no limit.
+    g_fraglimit: u32 = 20,
+
+    /// How long scores are shown after a match ends before the next map loads, in seconds.
+    g_intermission_duration: f32 = 10.0,
+
+    /// How fast cycles turn towards where the player is looking, in degrees per second.
+    g_cycle_turn_rate: f32 = 360.0,
+
+    /// Min time in seconds between jumps.
+    g_jump_cooldown: f32 = 0.3,
+    /// Upward velocity change in m/s when jumping.
+    g_jump_impulse: f32 = 6.0,
+    /// Upward velocity change in m/s from jump pads which don't specify their own.
+    g_jump_pad_impulse: f32 = 20.0,
+
+    /// Minimum time alive before the kill button works.
+    g_kill_cooldown: f32 = 2.0,
+    /// Cycles and projectiles below this height die, see also kill volumes in the map.
+    g_kill_z: f32 = -50.0,
+
+    /// Start each map in a lobby where players pick teams and get ready,
+    /// the countdown starts once enough of them are ready, see `g_lobby_min_ready`.
+    ///
+    /// Takes precedence over `g_warmup`.
+    g_lobby: bool = false,
+    /// How many players have to be ready to start the match, 0 means everyone.
+    g_lobby_min_ready: u32 = 0,
+
+    /// The map to play, see `maps::MAPS` for the list.
+    ///
+    /// Changing it on the server loads the new map and moves everyone to it.
+    /// In local games, the `map <name>` console command does the same.
+    g_map: String = "arena".to_owned(),
+    /// Space separated list of maps to cycle through when a match ends.
+    ///
+    /// If empty, the current map is played again.
+    g_map_rotation: String = "".to_owned(),
+
+    /// When the time limit is reached with tied scores, go into overtime:
+    /// nobody respawns and the playable area shrinks until someone gets a kill.
+    g_overtime: bool = true,
+    /// Radius in m of the playable area around the map's center when overtime starts.
+    g_overtime_radius: f32 = 100.0,
+    /// Time in seconds for the playable area to shrink to nothing in overtime.
+    ///
+    /// 0 means there's no playable area at all once overtime starts.
+    g_overtime_shrink_duration: f32 = 60.0,
+
+    /// Continuous collision detection for cycle bodies
+    /// so they don't tunnel through thin geometry at high speeds.
+    ///
+    /// Only bodies with CCD enabled pay for the substeps in `g_physics_max_ccd_substeps`,
+    /// the map's static colliders don't need it.
+    g_physics_ccd: bool = true,
+    /// Max CCD substeps per tick for bodies with CCD enabled, see `g_physics_ccd`.
+    ///
+    /// This is needed because the default 1 causes the wheel to randomly stutter/stop
+    /// when passing between poles if they use a single trimesh collider
+    /// (see `g_physics_pole_colliders`).
+    /// 2 is very noticeable, 5 is better, 10 is only noticeable at high speeds.
+    /// It never completely goes away, even with 100.
+    g_physics_max_ccd_substeps: u32 = 100,
+    /// How far to go back when doing raycasts to avoid entering objects
+    /// due to floating point errors.
+    ///
+    /// Nudging should enabled by default because it's easy to forget
+    /// and usually this is what we want for most traces anyway.
+    g_physics_nudge: f32 = 0.01,
+    /// Give each pole (mesh named `pole*`) its own convex collider
+    /// instead of leaving it in the map's shared trimesh.
+    ///
+    /// Wheels catch on the internal edges of a trimesh and convex shapes are cheaper
+    /// to collide with. Only read when loading a map, must be the same on client and server.
+    g_physics_pole_colliders: bool = true,
+
+    /// Ammo given by ammo pickups, up to `g_ammo_max`.
+    g_pickup_ammo: u32 = 100,
+    /// Damage dealt is multiplied by this while the damage powerup is active.
+    g_pickup_damage_factor: f32 = 2.0,
+    /// How long the damage powerup lasts in seconds.
+    g_pickup_damage_duration: f32 = 10.0,
+    /// Health given by health pickups, up to `g_cycle_health`.
+    g_pickup_health: f32 = 50.0,
+    /// Max distance in m from the cycle's center to a pickup's center to pick it up.
+    g_pickup_radius: f32 = 1.5,
+    /// Time in seconds before a taken pickup appears again.
+    g_pickup_respawn: f32 = 20.0,
+    /// Acceleration and max speed are multiplied by this while the speed powerup is active.
+    g_pickup_speed_factor: f32 = 1.5,
+    /// How long the speed powerup lasts in seconds.
+    g_pickup_speed_duration: f32 = 10.0,
+
+    /// If fewer human players are connected, bots will join.
+    ///
+    /// With `g_warmup`, the match starts once this many players are connected.
+    g_players_min: u32 = 4, // TODO
+
+    g_projectile_lifetime: f32 = 60.0,
+    g_projectile_refire: f32 = 0.05,
+    g_projectile_speed: f32 = 75.0,
+    g_projectile_spread: f32 = 0.2,
+
+    /// Damage per m/s of relative speed above `g_ram_threshold` when cycles collide.
+    g_ram_damage: f32 = 5.0,
+    /// Collisions with lower relative speed along the contact normal (in m/s) don't cause damage.
+    g_ram_threshold: f32 = 10.0,
+
+    /// Time in seconds between dying and spawning a new cycle.
+    g_respawn_delay: f32 = 2.0,
+
+    /// Horizontal velocity multiplier of speed pads which don't specify their own.
+    g_speed_pad_factor: f32 = 1.5,
+
+    /// The match ends after this many seconds, 0 means no limit.
+    g_timelimit: f32 = 600.0,
+
+    /// How long each point of a cycle's trail lasts in seconds.
+    g_trail_duration: f32 = 5.0,
+    /// Distance in m a cycle has to move before a new trail point is added.
+    g_trail_segment_length: f32 = 1.0,
+
+    /// Max distance in m from the cycle's center to a wall to start wall riding.
+    g_wall_ride_distance: f32 = 0.5,
+    /// Surfaces whose normal's vertical component is at most this count as walls.
+    g_wall_ride_max_normal_y: f32 = 0.3,
+    /// Cycles slower than this (in m/s) fall off the wall.
+    g_wall_ride_min_speed: f32 = 10.0,
+    /// How hard cycles are pulled towards the wall in m/s².
+    g_wall_ride_stick: f32 = 10.0,
+
+    /// Start each map in warmup until `g_players_min` players are connected.
+    ///
+    /// Off by default because local games would never leave warmup.
+    g_warmup: bool = false,
+    /// Seconds between enough players connecting and the match starting.
+    g_warmup_countdown: f32 = 10.0,
+
+    /// Forward / backward acceleration in m/s², only applies when touching the ground.
+    g_wheel_acceleration: f32 = 20.0,
+    /// Deceleration in m/s² when pressing the opposite direction of movement.
+    g_wheel_brake: f32 = 40.0,
+    /// Max sideways deceleration in m/s² - how hard the tire grips before it starts sliding.
+    g_wheel_grip: f32 = 30.0,
+    /// How far below the cycle's center to look for ground.
+    ///
+    /// Slightly more than half the collider's height.
+    g_wheel_ground_distance: f32 = 0.35,
+    /// Cycles can't accelerate beyond this speed in m/s, see also `g_boost_max_speed_factor`.
+    g_wheel_max_speed: f32 = 40.0,
+    /// Deceleration in m/s² when not accelerating or braking.
+    g_wheel_rolling_friction: f32 = 1.0,
+
+    hud_ammo: bool = true,
+    /// Show the boost energy meter.
+    hud_boost: bool = true,
+    hud_crosshair: bool = true,
+    /// Red, green and blue from 0 to 1.
+    hud_crosshair_color: CVec3 = v!(1 1 1).into(),
+    /// Distance in pixels from the center to where the lines of the cross start.
+    hud_crosshair_gap: f32 = 3.0,
+    /// Color of the crosshair after damaging someone.
+    hud_crosshair_hit_color: CVec3 = v!(1 0 0).into(),
+    /// How long the crosshair flashes after damaging someone, in seconds.
+    hud_crosshair_hit_duration: f32 = 0.2,
+    /// Length of the lines of the cross or radius of the circle in pixels.
+    hud_crosshair_size: f32 = 8.0,
+    /// Draw a circle showing how much projectiles spread, see `g_projectile_spread`.
+    hud_crosshair_spread: bool = true,
+    /// `dot`, `cross` or `circle`.
+    hud_crosshair_style: CrosshairStyle = CrosshairStyle::Cross,
+    hud_crosshair_thickness: f32 = 2.0,
+    /// How long damage indicators stay visible in seconds.
+    hud_damage_indicator_duration: f32 = 1.0,
+    /// Show which direction damage came from.
+    hud_damage_indicators: bool = true,
+    /// Show how much damage the local player dealt over the victim.
+    hud_damage_numbers: bool = true,
+    /// How long damage numbers stay on screen in seconds.
+    hud_damage_numbers_duration: f32 = 1.0,
+    /// How fast damage numbers float up in m/s.
+    hud_damage_numbers_speed: f32 = 1.0,
+    /// Show the local player's health above the ammo.
+    hud_health: bool = true,
+    /// Always show the minimap, the overview on the map key works either way.
+    hud_minimap: bool = true,
+    /// How far from the map's center the overview shows, in m.
+    hud_minimap_overview_range: f32 = 150.0,
+    /// Width and height of the overview in pixels.
+    hud_minimap_overview_size: f32 = 600.0,
+    /// How far from the camera the minimap shows, in m.
+    hud_minimap_range: f32 = 50.0,
+    /// Other players stay on the minimap for this many seconds after being seen.
+    hud_minimap_seen_duration: f32 = 3.0,
+    /// Width and height of the minimap in pixels.
+    hud_minimap_size: f32 = 200.0,
+    /// How long the server's message of the day stays on screen after connecting in seconds.
+    ///
+    /// It's always printed into the console.
+    hud_motd_duration: f32 = 10.0,
+    /// While observing, list all players and draw colored markers over their cycles.
+    hud_spectator_panel: bool = true,
+    /// Show the current vote and how to vote.
+    hud_vote: bool = true,
+
+    m_pitch_max: f32 = 90.0,
+    m_pitch_min: f32 = -90.0,
+
+    /// Mouse sensitivity.
+    m_sensitivity: f32 = 0.15,
+    /// Additional coefficient for horizontal sensitivity.
+    m_sensitivity_horizontal: f32 = 1.0,
+    /// Additional coefficient for vertical sensitivity.
+    m_sensitivity_vertical: f32 = 1.0,
+
+    /// Encrypt connections using the Noise protocol.
+    ///
+    /// On the client, this is whether to request encryption when connecting.
+    /// On the server, this is whether to require it - unencrypted clients get disconnected.
+    /// The server accepts encrypted clients either way.
+    ///
+    /// Only used for remote connections, local games are never encrypted.
+    net_encryption: bool = false,
+    /// Address of the master server which keeps the list of public servers.
+    ///
+    /// Servers announce themselves there (if `sv_master_announce` is enabled)
+    /// and clients query it for the server list.
+    net_master_addr: String = "".to_owned(),
+    /// When a hostname resolves to both IPv4 and IPv6 addresses, use IPv6.
+    net_prefer_ipv6: bool = false,
+
+    /// Bloom around bright surfaces, only used with `r_quality 3`.
+    r_bloom: bool = true,
+    /// Fast approximate antialiasing, only used with `r_quality 3`.
+    ///
+    /// LATER MSAA once Fyrox supports it.
+    r_fxaa: bool = true,
+    /// Volumetric light scattering, only used with `r_quality 3`.
+    r_light_scatter: bool = true,
+    /// Skip rendering objects hidden behind other objects, only used with `r_quality 3`.
+    r_occlusion_culling: bool = false,
+    /// Parallax mapping, only used with `r_quality 3`.
+    r_parallax: bool = true,
+    /// Graphics preset: 0 low, 1 medium, 2 high,
+    /// 3 custom - composed from the other `r_*` cvars.
+    ///
+    /// Applied immediately when changed.
+    r_quality: i32 = 0,
+    /// Render the 3D scene at this fraction of the window resolution and upscale it.
+    ///
+    /// The UI is always rendered at full resolution.
+    /// Clamped to between 0.1 and 1.0, supersampling is not supported.
+    r_render_scale: f32 = 1.0,
+    /// Lower the render scale when frames take longer than `r_render_scale_target_ms`,
+    /// it never goes above `r_render_scale` or below `r_render_scale_min`.
+    r_render_scale_dynamic: bool = false,
+    r_render_scale_min: f32 = 0.5,
+    /// Frame time the dynamic render scale tries to stay under, in ms.
+    r_render_scale_target_ms: f32 = 16.7,
+    /// Size in pixels of shadow maps, only used with `r_quality 3`.
+    ///
+    /// Clamped to 16 - 4096.
+    r_shadow_map_size: usize = 1024,
+    /// Shadows of point and spot lights, only used with `r_quality 3`.
+    r_shadows: bool = true,
+    /// Lights further than this in meters don't cast shadows, only used with `r_quality 3`.
+    r_shadows_distance: f32 = 15.0,
+    /// Filter shadow edges, only used with `r_quality 3`.
+    r_soft_shadows: bool = true,
+    /// Screen space ambient occlusion, only used with `r_quality 3`.
+    r_ssao: bool = true,
+    r_ssao_radius: f32 = 0.5,
+
+    /// How long before expiring trail walls start fading out, in seconds.
+    r_trail_wall_fade: f32 = 1.0,
+    /// Strength of the trail walls' emission, more glows brighter with `r_bloom`.
+    r_trail_wall_glow: f32 = 2.0,
+    /// How tall trail walls are in m, measured along the cycle's up direction.
+    r_trail_wall_height: f32 = 1.0,
+    /// Opacity of trail walls before they start fading, 0 to 1.
+    r_trail_wall_opacity: f32 = 0.7,
+    /// Draw walls behind cycles along their trails.
+    r_trail_walls: bool = true,
+    /// Draw short streaks behind projectiles and cycles to make them easier to follow.
+    r_trails: bool = true,
+    /// How long cycle streaks are in seconds.
+    r_trails_cycle_duration: f32 = 0.3,
+    r_trails_cycle_width: f32 = 0.4,
+    /// Strength of the streaks' emission, more glows brighter with `r_bloom`.
+    r_trails_glow: f32 = 3.0,
+    /// Opacity of streaks at the front, they get narrower towards the back.
+    r_trails_opacity: f32 = 0.5,
+    /// How long projectile streaks are in seconds.
+    r_trails_projectile_duration: f32 = 0.15,
+    r_trails_projectile_width: f32 = 0.1,
+
+    /// Play a sound when the local player damages someone.
+    snd_hitsound: bool = true,
+    snd_hitsound_volume: f32 = 0.5,
+    /// Master volume, 0 is silent, 1 is full volume.
+    ///
+    /// Applied immediately when changed.
+    snd_volume: f32 = 1.0,
+
+    /// Log suspicious client behavior such as invalid inputs or impossible speeds.
+    sv_anticheat_log: bool = true,
+    /// Horizontal speed in m/s above which cycles are flagged as suspicious.
+    ///
+    /// Boost, the speed powerup and speed pads stacked together
+    /// stay below the default.
+    sv_anticheat_speed_max: f32 = 150.0,
+
+    /// Run the dedicated server without a window, event loop or graphics.
+    ///
+    /// This is what you want when running on a VPS / as a service.
+    /// LATER Make this the default once it's been tested more.
+    sv_headless: bool = false,
+
+    /// Sleep until the next tick instead of polling for events in a busy loop.
+    ///
+    /// Without this, the server uses a full CPU core even when idle.
+    sv_idle_sleep: bool = true,
+
+    /// Yaw and pitch speeds in inputs are clamped to this many degrees per second.
+    sv_input_angle_speed_max: f32 = 3600.0,
+
+    /// Max number of inputs buffered per client, each tick uses one.
+    ///
+    /// When more arrive (e.g. after a lag spike), the oldest are dropped
+    /// so the player's input latency doesn't grow indefinitely.
+    sv_input_buffer_max: usize = 4,
+
+    /// Pitch in inputs is clamped to this many degrees up or down.
+    sv_input_pitch_max: f32 = 90.0,
+    /// Inputs whose game time differs from the server's by more than this many seconds
+    /// are dropped.
+    sv_input_time_diff_max: f32 = 5.0,
+
+    /// Only replicate cycles within this many meters of the client's cycle, 0 to send everything.
+    ///
+    /// Clients without a cycle get everything because we don't know where their camera is.
+    /// Desync detection is disabled when this is on since clients only simulate part of the game.
+    sv_interest_radius: f32 = 0.0,
+    /// Cycles stop being replicated only after getting this much further than `sv_interest_radius`
+    /// so they don't flicker in and out at the edge.
+    sv_interest_margin: f32 = 10.0,
+
+    /// Also write the log into this file, empty to disable.
+    ///
+    /// Each line is prefixed with the UTC wall clock time.
+    /// Use `d_log_level` to choose what gets logged.
+    sv_log_file: String = "".to_owned(),
+    /// When the log file grows past this many megabytes, it's renamed to `<sv_log_file>.1`
+    /// (replacing the previous one) and a new file is started. 0 to never rotate.
+    sv_log_rotate_mb: f32 = 10.0,
+
+    /// Bytes of a map sent to each downloading client per tick.
+    sv_map_download_chunk_size: usize = 16 * 1024,
+
+    /// Announce the server to `net_master_addr` so players can find it.
+    sv_master_announce: bool = false,
+    /// How often to announce the server in seconds.
+    sv_master_announce_interval: f32 = 30.0,
+
+    /// Directory where a JSON summary of each finished match is saved, empty to disable.
+    sv_match_results_dir: String = "".to_owned(),
+    /// Send match summaries to this URL as a POST request, empty to disable.
+    ///
+    /// Only plain `http://` is supported.
+    sv_match_results_url: String = "".to_owned(),
+
+    /// Message of the day shown to players when they connect, e.g. server rules.
+    ///
+    /// `\n` starts a new line. Ignored when `sv_motd_file` is set.
+    sv_motd: String = "".to_owned(),
+    /// Read the message of the day from this file instead of `sv_motd`.
+    ///
+    /// Read again for each connecting player so it can be changed without a restart.
+    sv_motd_file: String = "".to_owned(),
+
+    /// Server name shown in the server browser.
+    sv_name: String = "RustCycles server".to_owned(),
+
+    /// Address to listen on for game clients in the format `ip:port`.
+    ///
+    /// The default accepts connections from other machines over IPv4,
+    /// use `[::]:26000` for IPv6 (on most systems this also accepts IPv4)
+    /// or `127.0.0.1:26000` to only accept connections from this machine.
+    /// The server also answers server browser requests including LAN discovery on this address.
+    /// Only read at startup.
+    sv_net_listen_addr: String = "0.0.0.0:26000".to_owned(),
+    /// Address to listen on for browser clients which connect using WebSockets, empty to disable.
+    ///
+    /// Same format as `sv_net_listen_addr` but it has to be a different port.
+    /// WebSocket clients are never encrypted by the game itself,
+    /// use a TLS terminating proxy and `wss://` instead.
+    /// Only read at startup.
+    sv_net_listen_addr_websocket: String = "".to_owned(),
+    /// Max length of a message from a client in bytes, larger messages cause a disconnect.
+    sv_net_max_message_len: usize = 64 * 1024,
+    /// Answer WebRTC offers from browser clients connected using WebSockets, see `cl_net_webrtc`.
+    ///
+    /// The data channel uses random UDP ports which have to be reachable by clients.
+    /// Needs the `webrtc` feature which is on by default.
+    /// Only read at startup.
+    sv_net_webrtc: bool = true,
+
+    /// Record everything the server sends and every input it uses into a demo file per map,
+    /// see `sv_record_demos_dir`.
+    ///
+    /// Files are named by date and map.
+    sv_record_demos: bool = false,
+    /// Directory where `sv_record_demos` saves demos.
+    sv_record_demos_dir: String = "demos".to_owned(),
+
+    /// SQLite file where player stats are saved, empty to disable.
+    ///
+    /// Stats are keyed by a hash of the `cl_guid` clients send when connecting.
+    /// Players can query them with the `stats` console command.
+    sv_stats_db: String = "".to_owned(),
+
+    /// Gamelogic and physics frames per second.
+    ///
+    /// Read when a match starts, clients use the server's value.
+    /// 0 is treated as 1.
+    sv_tickrate: u32 = 60,
+
+    /// Withhold updates of cycles the client's cycle can't see to limit what wallhacks can show.
+    ///
+    /// Occluded cycles stay spawned on the client but it stops receiving their position
+    /// and their player's input so it can only extrapolate from the last known state.
+    /// Like `sv_interest_radius`, this disables desync detection.
+    sv_visibility_culling: bool = false,
+    /// Height above the cycle's center from which visibility is checked.
+    sv_visibility_eye_height: f32 = 1.0,
+    /// Keep sending updates for this many seconds after a cycle was last visible.
+    ///
+    /// Latency means the client can see further along than the server
+    /// so it's better to reveal cycles a bit early than have them pop in late.
+    sv_visibility_grace: f32 = 0.5,
+    /// Move the eye along the cycle's velocity by this many seconds
+    /// to account for where the client will be when the update arrives.
+    sv_visibility_lookahead: f32 = 0.1,
+
+    /// How long players have to vote in seconds before a vote fails.
+    sv_vote_duration: f32 = 30.0,
+    /// Allow players to call votes.
+    sv_votes: bool = true,
+}
+
+/// Vec3 with support for cvars. Should be converted to Vec3 before use in gamecode.
+#[derive(Debug, Clone, Copy)]
+pub struct CVec3 {
+    x: f32,
+    y: f32,
+    z: f32,
+}
+
+impl CVec3 {
+    fn new(x: f32, y: f32, z: f32) -> Self {
+        Self { x, y, z }
+    }
+}
+
+impl FromStr for CVec3 {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_cvec3(s).map_err(|e| {
+            if let Some(e) = e {
+                format!("Expected format `'x y z'`, got `{}`: {}", s, e)
+            } else {
+                format!("Expected format `'x y z'`, got `{}`", s)
+            }
+        })
+    }
+}
+
+fn parse_cvec3(mut s: &str) -> Result<CVec3, Option<ParseFloatError>> {
+    if s.starts_with('\'') && s.ends_with('\'') {
+        s = &s[1..s.len() - 1];
+    }
+    let mut parts = s.split(' ');
+    let x = parts.next().ok_or(None)?.parse().map_err(Some)?;
+    let y = parts.next().ok_or(None)?.parse().map_err(Some)?;
+    let z = parts.next().ok_or(None)?.parse().map_err(Some)?;
+    if parts.next().is_some() {
+        return Err(None);
+    }
+    Ok(CVec3::new(x, y, z))
+}
+
+impl Display for CVec3 {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "'{:?} {:?} {:?}'", self.x, self.y, self.z)
+    }
+}
+
+impl From<CVec3> for Vec3 {
+    fn from(v: CVec3) -> Self {
+        Vec3::new(v.x, v.y, v.z)
+    }
+}
+
+impl From<Vec3> for CVec3 {
+    fn from(v: Vec3) -> Self {
+        CVec3::new(v.x, v.y, v.z)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CameraMode {
+    /// The camera is at the cycle's head and the local cycle is hidden.
+    FirstPerson,
+    ThirdPerson,
+}
+
+impl FromStr for CameraMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "first" | "1" => Ok(Self::FirstPerson),
+            "third" | "3" => Ok(Self::ThirdPerson),
+            _ => Err(format!("Expected `first` or `third`, got `{}`", s)),
+        }
+    }
+}
+
+impl Display for CameraMode {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::FirstPerson => write!(f, "first"),
+            Self::ThirdPerson => write!(f, "third"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrosshairStyle {
+    Dot,
+    Cross,
+    Circle,
+}
+
+impl FromStr for CrosshairStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dot" => Ok(Self::Dot),
+            "cross" => Ok(Self::Cross),
+            "circle" => Ok(Self::Circle),
+            _ => Err(format!("Expected `dot`, `cross` or `circle`, got `{}`", s)),
+        }
+    }
+}
+
+impl Display for CrosshairStyle {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Dot => write!(f, "dot"),
+            Self::Cross => write!(f, "cross"),
+            Self::Circle => write!(f, "circle"),
+        }
+    }
+}
+
+/// Where the settings menu saves cvars.
+pub const CONFIG_PATH: &str = "config.cfg";
+
+/// The settings menu's tab a cvar is shown in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingsTab {
+    Video,
+    Audio,
+    Mouse,
+    Binds,
+}
+
+impl SettingsTab {
+    pub const ALL: [SettingsTab; 4] = [Self::Video, Self::Audio, Self::Mouse, Self::Binds];
+}
+
+/// What kind of widget the settings menu uses for a cvar.
+#[derive(Debug, Clone, Copy)]
+pub enum SettingKind {
+    Bool,
+    Range {
+        min: f32,
+        max: f32,
+        step: f32,
+    },
+    /// Pairs of cvar value and label.
+    Enum(&'static [(&'static str, &'static str)]),
+}
+
+/// A cvar shown in the settings menu.
+#[derive(Debug, Clone, Copy)]
+pub struct Setting {
+    pub tab: SettingsTab,
+    pub cvar: &'static str,
+    pub label: &'static str,
+    pub kind: SettingKind,
+}
+
+const fn setting(
+    tab: SettingsTab,
+    cvar: &'static str,
+    label: &'static str,
+    kind: SettingKind,
+) -> Setting {
+    Setting {
+        tab,
+        cvar,
+        label,
+        kind,
+    }
+}
+
+const fn range(min: f32, max: f32, step: f32) -> SettingKind {
+    SettingKind::Range { min, max, step }
+}
+
+/// Cvars players are expected to change, everything else is only in the console.
+///
+/// Only these are saved to `CONFIG_PATH`.
+#[rustfmt::skip]
+pub const SETTINGS: &[Setting] = {
+    use SettingKind::*;
+    use SettingsTab::*;
+    &[
+        setting(Video, "cl_fullscreen", "Fullscreen", Bool),
+        setting(Video, "cl_vsync", "VSync", Bool),
+        setting(Video, "cl_fps_max", "Max FPS (0 unlimited)", range(0.0, 300.0, 10.0)),
+        setting(Video, "r_quality", "Quality", Enum(&[("0", "Low"), ("1", "Medium"), ("2", "High"), ("3", "Custom")])),
+        setting(Video, "r_render_scale", "Render scale", range(0.25, 1.0, 0.05)),
+        setting(Video, "r_render_scale_dynamic", "Dynamic render scale", Bool),
+        setting(Video, "r_shadows", "Shadows (custom quality)", Bool),
+        setting(Video, "r_ssao", "Ambient occlusion (custom quality)", Bool),
+        setting(Video, "r_bloom", "Bloom (custom quality)", Bool),
+        setting(Video, "r_fxaa", "Antialiasing (custom quality)", Bool),
+        setting(Video, "cl_camera_mode", "Camera", Enum(&[("first", "First person"), ("third", "Third person")])),
+        setting(Video, "cl_camera_fov", "Field of view", range(60.0, 120.0, 1.0)),
+        setting(Audio, "snd_volume", "Volume", range(0.0, 1.0, 0.05)),
+        setting(Audio, "snd_hitsound", "Hit sound", Bool),
+        setting(Mouse, "m_sensitivity", "Sensitivity", range(0.01, 1.0, 0.01)),
+        setting(Mouse, "m_sensitivity_horizontal", "Horizontal multiplier", range(0.1, 3.0, 0.05)),
+        setting(Mouse, "m_sensitivity_vertical", "Vertical multiplier", range(0.1, 3.0, 0.05)),
+        setting(Mouse, "cl_zoom_factor", "Zoom factor", range(1.0, 10.0, 0.5)),
+        setting(Mouse, "cl_mouse_grab_on_focus", "Grab mouse on focus", Bool),
+    ]
+};
+
+impl Cvars {
+    /// Apply cvars saved by `save_config`.
+    ///
+    /// A missing file is not an error, it just means nothing was saved yet.
+    pub fn load_config(&mut self, path: &str) {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return,
+            Err(e) => {
+                dbg_logf!("WARNING failed to read config {path}: {e}");
+                return;
+            }
+        };
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, value) = line.split_once(' ').unwrap_or((line, ""));
+            if let Err(e) = self.set_str(name, value.trim()) {
+                dbg_logf!("WARNING failed to set cvar {name} to value {value} from config: {e}");
+            }
+        }
+    }
+
+    /// Save `SETTINGS` which differ from their defaults.
+    pub fn save_config(&self, path: &str) -> std::io::Result<()> {
+        let defaults = Cvars::default();
+        let mut text =
+            String::from("# Written by the settings menu, command line arguments override it.\n");
+        for setting in SETTINGS {
+            let value = self.get_string(setting.cvar).unwrap();
+            if value != defaults.get_string(setting.cvar).unwrap() {
+                text.push_str(&format!("{} {}\n", setting.cvar, value));
+            }
+        }
+        std::fs::write(path, text)
+    }
+
+    /// Names of all cvars in declaration order.
+    ///
+    /// The cvars macro doesn't list them so they're taken from the field names
+    /// in the `Debug` output of the defaults, once per process.
+    pub fn cvar_names() -> &'static [String] {
+        static NAMES: OnceLock<Vec<String>> = OnceLock::new();
+        NAMES.get_or_init(|| {
+            format!("{:#?}", Cvars::default())
+                .lines()
+                .filter(|line| line.starts_with("    ") && !line.starts_with("     "))
+                .filter_map(|line| Some(line.trim().split_once(':')?.0.to_owned()))
+                .collect()
+        })
+    }
+
+    /// Gameplay (`g_`) cvars as names and values, e.g. so demos can be re-simulated with them.
+    pub fn gameplay_cvars(&self) -> Vec<(String, String)> {
+        Cvars::cvar_names()
+            .iter()
+            .filter(|name| name.starts_with("g_"))
+            .map(|name| (name.clone(), self.get_string(name).unwrap()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_are_valid() {
+        let mut cvars = Cvars::default();
+        for setting in SETTINGS {
+            let value = cvars.get_string(setting.cvar);
+            assert!(value.is_ok(), "unknown cvar {}", setting.cvar);
+            if let SettingKind::Enum(options) = setting.kind {
+                for (value, _) in options {
+                    assert!(cvars.set_str(setting.cvar, value).is_ok(), "{} {value}", setting.cvar);
+                }
+            }
+        }
+    }
+}
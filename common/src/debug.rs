@@ -7,8 +7,10 @@
 //!   to make it easy to debug server-side issues.
 //! - Prefer `soft_assert` over `assert` in gamecode.
 //! - Use `dbg_log*` instead of `dbg` / `println`.
+//! - Use `dbg_logf_once` or `dbg_logf_every` for things that can happen every frame.
 //! - Use `dbg_text*` to print things that happen every frame on screen.
 //! - Use `dbg_line`, `dbg_arrow`, `dbg_cross`, `dbg_rot` to draw shapes in 3D space.
+//! - Use `profile_scope` to measure how long a block of code takes, see `profiler`.
 //! - If you're testing something that needs to be toggled at runtime,
 //!   consider using `cvars.dbg*`.
 //!
@@ -38,7 +40,8 @@
 //! This should, of course, only be used in gamelogic code
 //! which is not concerned with security, doesn't save to disk, etc.
 //!
-//! LATER Offer a way for servers and clients to autoreport errors.
+//! Crashes are reported by `crash`.
+//! LATER Offer a way for servers and clients to autoreport soft errors too.
 //! LATER How does sending logs from sv to cl interact with cl vs sv framerates?
 //! LATER Add usage examples
 
@@ -55,24 +58,65 @@
 
 #![allow(dead_code)]
 
+pub mod config_watch;
+pub mod crash;
 pub mod details;
-
-use std::cell::{Cell, RefCell};
+pub mod logger;
+pub mod profiler;
+
+use std::{
+    cell::{Cell, RefCell},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, MutexGuard, PoisonError, TryLockError,
+    },
+};
 
 use crate::{
     debug::details::{DebugShape, WorldText},
     prelude::*,
 };
 
-/// Print text into stdout. Uses `println!(..)`-style formatting.
+/// Print text into stdout and the console (if enabled, see `set_log_capture`).
+/// Uses `println!(..)`-style formatting.
+///
+/// Logs at the info level, see `logger` for filtering and output formats.
 #[macro_export]
 macro_rules! dbg_logf {
     () => {
         dbg_logf!("")
     };
     ($($t:tt)*) => {{
-        let msg = format!($($t)*);
-        $crate::__println!("{} {:.04} {}", $crate::debug::endpoint_name(), $crate::debug::game_time(), msg);
+        ::log::info!($($t)*);
+    }};
+}
+
+/// Same as `dbg_logf!` but only prints the first time this call site is reached.
+#[macro_export]
+macro_rules! dbg_logf_once {
+    ($($t:tt)*) => {{
+        if $crate::debug::details::call_count(file!(), line!(), column!()) == 0 {
+            dbg_logf!($($t)*);
+        }
+    }};
+}
+
+/// Same as `dbg_logf!` but only prints the first and then every `n`-th time
+/// this call site is reached, e.g. so per-frame warnings don't flood the log.
+#[macro_export]
+macro_rules! dbg_logf_every {
+    ($n:expr, $($t:tt)*) => {{
+        let count = $crate::debug::details::call_count(file!(), line!(), column!());
+        #[allow(trivial_numeric_casts)]
+        let n = ($n as usize).max(1);
+        if count % n == 0 {
+            let msg = format!($($t)*);
+            if count == 0 {
+                dbg_logf!("{}", msg);
+            } else {
+                dbg_logf!("{} (x{})", msg, count + 1);
+            }
+        }
     }};
 }
 
@@ -228,6 +272,17 @@ macro_rules! dbg_rot {
     };
 }
 
+/// Measure how long the rest of the enclosing block takes, see `profiler`.
+///
+/// Unlike the other macros, this is a statement, not an expression
+/// because it has to keep a guard alive until the end of the block.
+#[macro_export]
+macro_rules! profile_scope {
+    ($name:expr) => {
+        let _profile_scope = $crate::debug::profiler::ScopeGuard::new($name);
+    };
+}
+
 /// Same as `assert!` but at compile time.
 #[macro_export]
 macro_rules! static_assert {
@@ -249,7 +304,7 @@ macro_rules! soft_assert {
         match (&$cond) {
             cond_val => {
                 if !*cond_val {
-                    dbg_logf!("[ERROR]: soft_assert failed: {}, {}:{}:{}", format!($($arg)+), file!(), line!(), column!());
+                    $crate::__soft_error!("soft_assert failed: {}, {}:{}:{}", format!($($arg)+), file!(), line!(), column!());
                 }
             }
         }
@@ -274,7 +329,7 @@ macro_rules! soft_assert_eq {
         match (&$left, &$right) {
             (left_val, right_val) => {
                 if !(*left_val == *right_val) {
-                    dbg_logf!("[ERROR]: soft_assert_eq failed: {}, left: {:?}, right {:?}, {}:{}:{}",
+                    $crate::__soft_error!("soft_assert_eq failed: {}, left: {:?}, right {:?}, {}:{}:{}",
                         format!($($arg)+), &*left_val, &*right_val, file!(), line!(), column!()
                     )
                 }
@@ -304,7 +359,7 @@ macro_rules! soft_assert_ne {
         match (&$left, &$right) {
             (left_val, right_val) => {
                 if !(*left_val != *right_val) {
-                    dbg_logf!("[ERROR]: soft_assert_ne failed: {}, left: {:?}, right {:?}, {}:{}:{}",
+                    $crate::__soft_error!("soft_assert_ne failed: {}, left: {:?}, right {:?}, {}:{}:{}",
                         format!($($arg)+), &*left_val, &*right_val, file!(), line!(), column!()
                     )
                 }
@@ -324,18 +379,29 @@ macro_rules! soft_assert_ne {
 macro_rules! soft_unreachable {
     () => {
         {
-            dbg_logf!("[ERROR]: soft_unreachable {}:{}:{}", file!(), line!(), column!());
+            $crate::__soft_error!("soft_unreachable {}:{}:{}", file!(), line!(), column!());
             return Default::default();
         }
     };
     ($($arg:tt)+) => {
         {
-            dbg_logf!("[ERROR]: soft_unreachable: {}, {}:{}:{}", format!($($arg)+), file!(), line!(), column!());
+            $crate::__soft_error!("soft_unreachable: {}, {}:{}:{}", format!($($arg)+), file!(), line!(), column!());
             return Default::default();
         }
     };
 }
 
+/// Private helper to log and count a failed soft assert or unwrap.
+/// Not meant to be used directly.
+#[macro_export]
+macro_rules! __soft_error {
+    ($($t:tt)*) => {{
+        let msg = format!($($t)*);
+        ::log::error!("{}", msg);
+        $crate::debug::details::soft_error(msg);
+    }};
+}
+
 pub trait SoftUnwrap {
     type Inner;
 
@@ -351,8 +417,8 @@ impl<T: Default> SoftUnwrap for Option<T> {
             Some(x) => x,
             None => {
                 let loc = std::panic::Location::caller();
-                dbg_logf!(
-                    "[ERROR]: soft_unwrap failed: Option::None, {}:{}:{}",
+                crate::__soft_error!(
+                    "soft_unwrap failed: Option::None, {}:{}:{}",
                     loc.file(),
                     loc.line(),
                     loc.column()
@@ -371,8 +437,8 @@ impl<T: Default, E: Debug> SoftUnwrap for Result<T, E> {
             Ok(x) => x,
             Err(e) => {
                 let loc = std::panic::Location::caller();
-                dbg_logf!(
-                    "[ERROR]: soft_unwrap failed: Result::Err({:?}), {}:{}:{}",
+                crate::__soft_error!(
+                    "soft_unwrap failed: Result::Err({:?}), {}:{}:{}",
                     e,
                     loc.file(),
                     loc.line(),
@@ -509,7 +575,16 @@ where
 //
 //
 
-// LATER(multithreading) Make debug tools work correctly from all threads.
+// Texts and shapes are global so they can be drawn from any thread
+// and all end up on the thread which renders them.
+// Everything in `thread_local` describes the thread itself
+// or is only ever used by the main thread.
+// LATER(multithreading) Worker threads have no endpoint, game time or console capture.
+
+pub static DEBUG_TEXTS: DebugGlobal<String> = DebugGlobal::new();
+pub static DEBUG_TEXTS_WORLD: DebugGlobal<WorldText> = DebugGlobal::new();
+pub static DEBUG_SHAPES: DebugGlobal<DebugShape> = DebugGlobal::new();
+
 thread_local! {
     // The default value here should be overwritten as soon as it's decided
     // whether the thread is a client or a server. If you see it in stdout/stderr,
@@ -522,11 +597,99 @@ thread_local! {
         })
     };
 
+    static DEBUG_FRAME_NUM: Cell<usize> = const { Cell::new(0) };
     static DEBUG_GAME_TIME: Cell<fl> = const { Cell::new(-1.0) };
 
-    pub static DEBUG_TEXTS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
-    pub static DEBUG_TEXTS_WORLD: RefCell<Vec<WorldText>> =const { RefCell::new(Vec::new()) };
-    pub static DEBUG_SHAPES: RefCell<Vec<DebugShape>> = const { RefCell::new(Vec::new()) };
+    /// How many times each `dbg_logf_once` / `dbg_logf_every` call site was reached.
+    pub static DEBUG_LOG_CALLS: RefCell<FxHashMap<(&'static str, u32, u32), usize>> =
+        RefCell::new(FxHashMap::default());
+}
+
+// These are global, not thread local, because errors from any thread should be noticed.
+
+/// Failed soft asserts and unwraps since the start.
+static SOFT_ERRORS_TOTAL: AtomicUsize = AtomicUsize::new(0);
+/// Failed soft asserts and unwraps since the last `clear_expired`.
+static SOFT_ERRORS_FRAME: AtomicUsize = AtomicUsize::new(0);
+/// The value of `SOFT_ERRORS_FRAME` before the last `clear_expired`.
+static SOFT_ERRORS_LAST_FRAME: AtomicUsize = AtomicUsize::new(0);
+/// Messages of the most recent failures, oldest first.
+static SOFT_ERRORS_RECENT: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+const SOFT_ERRORS_RECENT_MAX: usize = 100;
+
+/// Logged lines waiting to be shown in the console, None when not capturing.
+///
+/// Lines logged by the networking thread or a local server also show up in the console.
+static DEBUG_LOG: Mutex<Option<Vec<String>>> = Mutex::new(None);
+
+/// Lock a mutex used by logging, without blocking while the thread is panicking.
+///
+/// The panic might have happened while this thread held the lock,
+/// waiting for it in the panic hook would deadlock so this returns None instead.
+/// Poisoning is ignored, debug tools shouldn't cause more panics.
+pub fn lock_for_log<T>(mutex: &Mutex<T>) -> Option<MutexGuard<'_, T>> {
+    if std::thread::panicking() {
+        match mutex.try_lock() {
+            Ok(guard) => Some(guard),
+            Err(TryLockError::Poisoned(e)) => Some(e.into_inner()),
+            Err(TryLockError::WouldBlock) => None,
+        }
+    } else {
+        Some(mutex.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+/// A list of debug items which can be added to from any thread.
+///
+/// The methods mimic `LocalKey<RefCell<Vec<T>>>` which these globals used to be.
+/// Don't use the debug macros inside the closures, the lock is not reentrant.
+pub struct DebugGlobal<T>(Mutex<Vec<T>>);
+
+impl<T> DebugGlobal<T> {
+    const fn new() -> Self {
+        Self(Mutex::new(Vec::new()))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<T>> {
+        // A panic while holding the lock doesn't leave the Vec in an invalid state
+        // and debug tools shouldn't cause more panics.
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn with_borrow<R>(&self, f: impl FnOnce(&Vec<T>) -> R) -> R {
+        f(&self.lock())
+    }
+
+    pub fn with_borrow_mut<R>(&self, f: impl FnOnce(&mut Vec<T>) -> R) -> R {
+        f(&mut self.lock())
+    }
+
+    pub fn take(&self) -> Vec<T> {
+        std::mem::take(&mut *self.lock())
+    }
+}
+
+/// A counter for per-frame debug stats (e.g. `d_net_stats`) which can be added to from any thread.
+///
+/// Global so work done on background threads is counted too.
+/// In local games, client and server share the counters
+/// and whichever calls `take` first shows both.
+#[derive(Debug)]
+pub struct DebugCounter(AtomicUsize);
+
+impl DebugCounter {
+    pub const fn new() -> Self {
+        Self(AtomicUsize::new(0))
+    }
+
+    pub fn add(&self, n: usize) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Return the count and reset it to 0.
+    pub fn take(&self) -> usize {
+        self.0.swap(0, Ordering::Relaxed)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -559,15 +722,65 @@ pub fn endpoint_color() -> Color {
     DEBUG_ENDPOINT.with_borrow(|endpoint| endpoint.default_color)
 }
 
-pub fn set_game_time(time: fl) {
-    DEBUG_GAME_TIME.set(time);
+/// Start or stop collecting logged lines for `take_log`.
+///
+/// Only processes with a console should enable this,
+/// otherwise nothing would ever take the lines.
+pub fn set_log_capture(enable: bool) {
+    if let Some(mut log) = lock_for_log(&DEBUG_LOG) {
+        *log = enable.then(Vec::new);
+    }
+}
+
+/// Lines logged since the last call by any thread, oldest first.
+pub fn take_log() -> Vec<String> {
+    let Some(mut log) = lock_for_log(&DEBUG_LOG) else {
+        return Vec::new();
+    };
+    log.as_mut().map(std::mem::take).unwrap_or_default()
+}
+
+/// Keep a logged line for `take_log` if capturing is enabled.
+pub fn capture_log_line(line: String) {
+    if let Some(Some(log)) = lock_for_log(&DEBUG_LOG).as_deref_mut() {
+        log.push(line);
+    }
+}
+
+/// Set the current gamelogic frame, it's included in every logged line.
+pub fn set_frame(frame_num: usize, game_time: fl) {
+    DEBUG_FRAME_NUM.set(frame_num);
+    DEBUG_GAME_TIME.set(game_time);
+}
+
+pub fn frame_num() -> usize {
+    DEBUG_FRAME_NUM.get()
 }
 
 pub fn game_time() -> fl {
     DEBUG_GAME_TIME.get()
 }
 
+/// How many soft asserts and unwraps failed in total and during the last frame.
+pub fn soft_errors() -> (usize, usize) {
+    let total = SOFT_ERRORS_TOTAL.load(Ordering::Relaxed);
+    let last_frame = SOFT_ERRORS_LAST_FRAME.load(Ordering::Relaxed);
+    (total, last_frame)
+}
+
+/// Messages of up to `count` most recent soft assert and unwrap failures, oldest first.
+pub fn soft_errors_recent(count: usize) -> Vec<String> {
+    let Some(recent) = lock_for_log(&SOFT_ERRORS_RECENT) else {
+        return Vec::new();
+    };
+    let skip = recent.len().saturating_sub(count);
+    recent.iter().skip(skip).cloned().collect()
+}
+
 pub fn clear_expired() {
+    let frame = SOFT_ERRORS_FRAME.swap(0, Ordering::Relaxed);
+    SOFT_ERRORS_LAST_FRAME.store(frame, Ordering::Relaxed);
+
     DEBUG_TEXTS.with_borrow_mut(|texts| texts.clear());
     DEBUG_TEXTS_WORLD.with_borrow_mut(|texts| texts.clear());
     DEBUG_SHAPES.with_borrow_mut(|shapes| shapes.retain(|shape| shape.time > 0.0));
@@ -602,6 +815,12 @@ mod tests {
         dbg_logd!(x);
         dbg_logd!(x, y, 7);
 
+        dbg_logf_once!("abcd");
+        dbg_logf_once!("x: {}, y: {y}, 7: {}", x, 7);
+
+        dbg_logf_every!(10, "abcd");
+        dbg_logf_every!(10, "x: {}, y: {y}, 7: {}", x, 7);
+
         dbg_textf!();
         dbg_textf!("abcd");
         dbg_textf!("x: {}, y: {y}, 7: {}", x, 7);
@@ -627,6 +846,9 @@ mod tests {
             _ => dbg_logd!(x),
             _ => dbg_logd!(x, y, 7),
 
+            _ => dbg_logf_once!("abcd"),
+            _ => dbg_logf_every!(10, "x: {}, y: {y}, 7: {}", x, 7),
+
             _ => dbg_textf!(),
             _ => dbg_textf!("abcd"),
             _ => dbg_textf!("x: {}, y: {y}, 7: {}", x, 7),
@@ -644,6 +866,15 @@ mod tests {
         assert_eq!(nothing, ());
     }
 
+    #[test]
+    fn test_call_count() {
+        let count = || crate::debug::details::call_count("test", 1, 2);
+        assert_eq!(count(), 0);
+        assert_eq!(count(), 1);
+        assert_eq!(count(), 2);
+        assert_eq!(crate::debug::details::call_count("test", 1, 3), 0);
+    }
+
     #[test]
     fn test_drawing_compiles() {
         #![allow(clippy::let_unit_value)] // We need to test that the macros eval to a ()
@@ -867,4 +1098,21 @@ mod tests {
         assert_eq!(count, 3);
         assert!(!finished);
     }
+
+    #[test]
+    fn test_draw_from_other_thread() {
+        std::thread::spawn(|| {
+            dbg_textf!("drawn from another thread");
+            dbg_line!(V1, V2);
+        })
+        .join()
+        .unwrap();
+
+        let found = crate::debug::DEBUG_TEXTS.with_borrow(|texts| {
+            texts.iter().any(|text| text.ends_with("drawn from another thread"))
+        });
+        assert!(found);
+        let found = crate::debug::DEBUG_SHAPES.with_borrow(|shapes| !shapes.is_empty());
+        assert!(found);
+    }
 }
@@ -0,0 +1,70 @@
+//! Applying a cvar file whenever it's saved, see `d_watch_config`.
+//!
+//! The file's modification time is polled, a few times a second is plenty
+//! for someone editing it by hand.
+//! Each thread has its own watcher so the client and server can watch different files.
+
+use std::{cell::RefCell, fs, time::SystemTime};
+
+// Unlike std's, this one also works in the browser.
+use fyrox_core::instant::Instant;
+
+use crate::{dbg_logf, prelude::*};
+
+/// How often to check the file, in seconds.
+const INTERVAL: f32 = 0.25;
+
+thread_local! {
+    static WATCHER: RefCell<Option<ConfigWatcher>> = const { RefCell::new(None) };
+}
+
+struct ConfigWatcher {
+    path: String,
+    /// None if the file didn't exist, it's applied once it's created.
+    modified: Option<SystemTime>,
+    last_check: Instant,
+}
+
+/// Start or stop watching according to `d_watch_config`
+/// and apply the file if it changed since the last check.
+///
+/// Cheap when nothing changed so it can be called every frame.
+pub fn update(cvars: &mut Cvars) {
+    WATCHER.with_borrow_mut(|watcher| {
+        if !cvars.d_watch_config {
+            if let Some(watcher) = watcher.take() {
+                dbg_logf!("stopped watching {}", watcher.path);
+            }
+            return;
+        }
+
+        let Some(w) = watcher.as_mut().filter(|w| w.path == cvars.d_watch_config_path) else {
+            // Only later changes are applied, the file is usually loaded on startup if needed.
+            let path = cvars.d_watch_config_path.clone();
+            dbg_logf!("watching {} for changes", path);
+            *watcher = Some(ConfigWatcher {
+                modified: modified(&path),
+                path,
+                last_check: Instant::now(),
+            });
+            return;
+        };
+
+        if w.last_check.elapsed().as_secs_f32() < INTERVAL {
+            return;
+        }
+        w.last_check = Instant::now();
+
+        let modified = modified(&w.path);
+        if modified.is_none() || modified == w.modified {
+            return;
+        }
+        w.modified = modified;
+        dbg_logf!("applying {}", w.path);
+        cvars.load_config(&w.path);
+    });
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
@@ -0,0 +1,156 @@
+//! Crash dumps written when the game panics, see `d_crash_reports`.
+//!
+//! A dump contains the panic message and backtrace, the version,
+//! the current frame, cvars which differ from their defaults
+//! and the most recent log lines.
+//! It's saved into `crashes/` and can optionally be uploaded,
+//! see `d_crash_report_upload`.
+//!
+//! Everything here runs inside the panic hook so it must not panic itself
+//! and must not wait for locks the panicking code might be holding.
+
+use std::{
+    backtrace::Backtrace,
+    fmt::Write as _,
+    fs,
+    panic::PanicInfo,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use fyrox_core::instant::Instant;
+
+use crate::{common::net, debug, prelude::*};
+
+pub const CRASH_DIR: &str = "crashes";
+
+/// How often to save cvars for the dump, they rarely change so every frame would be wasteful.
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Recent cvars of this process for the cvar diff and when they were saved.
+static CVARS: Mutex<Option<(Cvars, Instant)>> = Mutex::new(None);
+
+/// Remember the current cvars in case the game crashes.
+///
+/// Call once per frame, they're only saved once per `SNAPSHOT_INTERVAL`
+/// so changes made just before a crash might be missing from the dump.
+pub fn update(cvars: &Cvars) {
+    // Build the list now so the panic hook doesn't have to.
+    Cvars::cvar_names();
+
+    let Ok(mut snapshot) = CVARS.try_lock() else {
+        return;
+    };
+    if snapshot.as_ref().is_some_and(|(_, time)| time.elapsed() < SNAPSHOT_INTERVAL) {
+        return;
+    }
+    *snapshot = Some((cvars.clone(), Instant::now()));
+}
+
+/// Write a crash dump and upload it if enabled.
+///
+/// Called from the panic hook.
+pub fn report(panic_info: &PanicInfo) {
+    let snapshot = CVARS.try_lock().ok();
+    let cvars = snapshot.and_then(|snapshot| snapshot.as_ref().map(|(cvars, _)| cvars.clone()));
+    if cvars.as_ref().is_some_and(|cvars| !cvars.d_crash_reports) {
+        return;
+    }
+
+    let dump = format_dump(panic_info, cvars.as_ref());
+
+    let secs = debug::logger::system_time_now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let path = format!("{}/crash-{}-{}.txt", CRASH_DIR, secs, debug::endpoint_name());
+    match fs::create_dir_all(CRASH_DIR).and_then(|()| fs::write(&path, &dump)) {
+        Ok(()) => crate::__println!("crash report saved to {}", path),
+        Err(e) => crate::__println!("failed to save crash report to {}: {}", path, e),
+    }
+
+    let Some(cvars) = cvars else {
+        return;
+    };
+    if cvars.d_crash_report_url.is_empty() {
+        return;
+    }
+    if !cvars.d_crash_report_upload {
+        crate::__println!(
+            "set d_crash_report_upload to true to send crash reports to {}",
+            cvars.d_crash_report_url
+        );
+        return;
+    }
+    match net::http_post(&cvars.d_crash_report_url, "text/plain; charset=utf-8", &dump) {
+        Ok(()) => crate::__println!("crash report sent to {}", cvars.d_crash_report_url),
+        Err(e) => {
+            crate::__println!("failed to send crash report to {}: {}", cvars.d_crash_report_url, e)
+        }
+    }
+}
+
+fn format_dump(panic_info: &PanicInfo, cvars: Option<&Cvars>) -> String {
+    let mut dump = String::new();
+    writeln!(dump, "{}", Version::current()).unwrap();
+    writeln!(dump, "endpoint: {}", debug::endpoint_name()).unwrap();
+    writeln!(dump, "frame: {}", debug::frame_num()).unwrap();
+    writeln!(dump, "game time: {:.4}", debug::game_time()).unwrap();
+    let thread = std::thread::current();
+    writeln!(dump, "thread: {}", thread.name().unwrap_or("<unnamed>")).unwrap();
+    writeln!(dump).unwrap();
+
+    writeln!(dump, "{}", panic_info).unwrap();
+    writeln!(dump).unwrap();
+    writeln!(dump, "backtrace:\n{}", Backtrace::force_capture()).unwrap();
+
+    writeln!(dump, "cvars changed from defaults:").unwrap();
+    match cvars {
+        Some(cvars) => {
+            for line in cvar_diff(cvars) {
+                writeln!(dump, "    {}", line).unwrap();
+            }
+        }
+        None => writeln!(dump, "    unknown").unwrap(),
+    }
+    writeln!(dump).unwrap();
+
+    writeln!(dump, "recent log:").unwrap();
+    for line in debug::logger::recent() {
+        writeln!(dump, "{}", line).unwrap();
+    }
+    dump
+}
+
+/// Cvars which differ from their defaults as `name: value`.
+///
+/// Compares each cvar's value as the cvars API formats it
+/// so nested values don't need any special handling.
+fn cvar_diff(cvars: &Cvars) -> Vec<String> {
+    let defaults = Cvars::default();
+    Cvars::cvar_names()
+        .iter()
+        .filter_map(|name| {
+            let value = cvars.get_string(name).ok()?;
+            let default = defaults.get_string(name).ok()?;
+            (value != default).then(|| format!("{name}: {value}"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_from_defaults() {
+        let mut cvars = Cvars::default();
+        assert_eq!(cvar_diff(&cvars), Vec::<String>::new());
+
+        cvars.d_log_level = "debug".to_owned();
+        cvars.cl_window_width = 1234;
+        let diff = cvar_diff(&cvars);
+        assert_eq!(diff.len(), 2);
+        assert!(diff.contains(&"d_log_level: debug".to_owned()));
+        assert!(diff.contains(&"cl_window_width: 1234".to_owned()));
+    }
+}
@@ -7,14 +7,58 @@
 // Some items in this file could trivially be inlined into debug.rs.
 // Usually, they're here because they differ between RecWars and RustCycles.
 
+use std::sync::atomic::Ordering;
+
 use fyrox::scene::debug::Line;
 
-use crate::{debug::DEBUG_SHAPES, prelude::*};
+use crate::{
+    debug::{
+        self, DEBUG_LOG_CALLS, DEBUG_SHAPES, SOFT_ERRORS_FRAME, SOFT_ERRORS_RECENT,
+        SOFT_ERRORS_RECENT_MAX, SOFT_ERRORS_TOTAL,
+    },
+    prelude::*,
+};
 
 #[macro_export]
 macro_rules! __println {
     ($($t:tt)*) => {
-        println!($($t)*)
+        $crate::debug::details::print_line(&format!($($t)*))
+    }
+}
+
+/// Helper function, use `dbg_logf!()` instead.
+///
+/// Stdout goes nowhere in the browser so print into its console there.
+pub fn print_line(line: &str) {
+    #[cfg(target_arch = "wasm32")]
+    web_sys::console::log_1(&line.into());
+    #[cfg(not(target_arch = "wasm32"))]
+    println!("{}", line);
+}
+
+/// Helper function, use `dbg_logf_once!()` or `dbg_logf_every!()` instead.
+///
+/// Returns how many times the call site was reached before.
+pub fn call_count(file: &'static str, line: u32, column: u32) -> usize {
+    DEBUG_LOG_CALLS.with_borrow_mut(|calls| {
+        let count = calls.entry((file, line, column)).or_insert(0);
+        *count += 1;
+        *count - 1
+    })
+}
+
+/// Helper function, use one of the `soft_*!()` macros or `soft_unwrap()`.
+pub fn soft_error(msg: String) {
+    SOFT_ERRORS_TOTAL.fetch_add(1, Ordering::Relaxed);
+    SOFT_ERRORS_FRAME.fetch_add(1, Ordering::Relaxed);
+
+    let line = format!("{} {:.04} {}", debug::endpoint_name(), debug::game_time(), msg);
+    let Some(mut recent) = debug::lock_for_log(&SOFT_ERRORS_RECENT) else {
+        return;
+    };
+    recent.push_back(line);
+    while recent.len() > SOFT_ERRORS_RECENT_MAX {
+        recent.pop_front();
     }
 }
 
@@ -0,0 +1,391 @@
+//! The `log` backend behind `dbg_logf` and the soft asserts.
+//!
+//! Each record carries the endpoint name, frame number and game time
+//! of the thread which logged it.
+//! Which records are printed is decided per module, see `d_log_level`.
+//!
+//! Servers can additionally write them into a file, see `sv_log_file`.
+
+use std::{
+    fmt::Write as _,
+    fs::{self, File, OpenOptions},
+    io::Write as _,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, RwLock,
+    },
+    time::SystemTime,
+};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use crate::{debug, prelude::*};
+
+static LOGGER: Logger = Logger;
+
+static FILTER: RwLock<Filter> = RwLock::new(Filter {
+    spec: String::new(),
+    default: LevelFilter::Info,
+    modules: Vec::new(),
+});
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+static FILE: Mutex<Option<LogFile>> = Mutex::new(None);
+
+/// The last `RECENT_MAX` printed lines for crash reports.
+static RECENT: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+const RECENT_MAX: usize = 200;
+
+struct Logger;
+
+/// See `sv_log_file`.
+struct LogFile {
+    path: String,
+    file: File,
+    /// Current size of the file in bytes.
+    len: u64,
+    /// Rotate when the file would grow past this, 0 means never.
+    rotate_len: u64,
+}
+
+/// Parsed `d_log_level`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Filter {
+    /// The string this was parsed from to detect changes.
+    spec: String,
+    default: LevelFilter,
+    /// Module paths relative to the crate root (see `relative_path`) and their levels.
+    modules: Vec<(String, LevelFilter)>,
+}
+
+impl Filter {
+    /// Parse a comma separated list of `level` or `module=level` items,
+    /// e.g. `warn,server=debug,common::net=trace`.
+    fn parse(spec: &str) -> Result<Self, String> {
+        let mut filter = Filter {
+            spec: spec.to_owned(),
+            default: LevelFilter::Info,
+            modules: Vec::new(),
+        };
+        for item in spec.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            let parse_level = |level: &str| {
+                level.parse::<LevelFilter>().map_err(|_| format!("unknown log level `{level}`"))
+            };
+            match item.split_once('=') {
+                Some((module, level)) => {
+                    let module = relative_path(module.trim());
+                    filter.modules.push((module.to_owned(), parse_level(level.trim())?));
+                }
+                None => filter.default = parse_level(item)?,
+            }
+        }
+        // The most specific module wins.
+        filter.modules.sort_by_key(|(module, _)| std::cmp::Reverse(module.len()));
+        Ok(filter)
+    }
+
+    fn level(&self, target: &str) -> LevelFilter {
+        let target = relative_path(target);
+        for (module, level) in &self.modules {
+            let matches = target
+                .strip_prefix(module.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"));
+            if matches {
+                return *level;
+            }
+        }
+        self.default
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        let modules = self.modules.iter().map(|(_, level)| *level);
+        modules.fold(self.default, LevelFilter::max)
+    }
+}
+
+/// Strip the crate name from a module path.
+///
+/// The game re-exports the modules of `rustcycles_common` at its root
+/// so e.g. `common::net` matches the same code it did before the crates were split.
+fn relative_path(path: &str) -> &str {
+    ["rustcycles::", "rustcycles_common::"]
+        .iter()
+        .find_map(|krate| path.strip_prefix(krate))
+        .unwrap_or(path)
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        // Don't wait for the lock in the panic hook, log everything instead.
+        let filter = if std::thread::panicking() {
+            FILTER.try_read().ok()
+        } else {
+            FILTER.read().ok()
+        };
+        filter.map_or(true, |filter| metadata.level() <= filter.level(metadata.target()))
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let endpoint = debug::endpoint_name();
+        let frame_num = debug::frame_num();
+        let game_time = debug::game_time();
+        let msg = record.args().to_string();
+
+        let timestamp = utc_timestamp(system_time_now());
+        let json = JSON.load(Ordering::Relaxed);
+
+        let line = if json {
+            format!(
+                r#"{{"time":"{}","endpoint":"{}","frame":{},"game_time":{:.4},"level":"{}","module":"{}","msg":"{}"}}"#,
+                timestamp,
+                endpoint,
+                frame_num,
+                game_time,
+                record.level(),
+                json_escape(record.target()),
+                json_escape(&msg),
+            )
+        } else if record.level() == Level::Info {
+            format!("{endpoint} {game_time:.04} {msg}")
+        } else {
+            format!("{endpoint} {game_time:.04} [{}]: {msg}", record.level())
+        };
+        crate::__println!("{}", line);
+
+        // Logging must keep working in the panic hook so it never waits there,
+        // lines which would have to wait are only printed.
+        if let Some(mut file) = debug::lock_for_log(&FILE) {
+            if let Some(log_file) = file.as_mut() {
+                // JSON already has the time as a field.
+                let file_line = if json {
+                    format!("{line}\n")
+                } else {
+                    format!("{timestamp} {line}\n")
+                };
+                if let Err(e) = log_file.write(&file_line) {
+                    // Can't use the logger from inside itself.
+                    crate::__println!(
+                        "failed to write to log file {}, closing it: {}",
+                        log_file.path,
+                        e
+                    );
+                    *file = None;
+                }
+            }
+        }
+
+        if let Some(mut recent) = debug::lock_for_log(&RECENT) {
+            if recent.len() >= RECENT_MAX {
+                recent.pop_front();
+            }
+            recent.push_back(line.clone());
+        }
+
+        debug::capture_log_line(line);
+    }
+
+    fn flush(&self) {}
+}
+
+/// The most recently printed lines, oldest first.
+///
+/// Returns nothing if the buffer is locked, e.g. when we panicked while logging.
+pub fn recent() -> Vec<String> {
+    match RECENT.try_lock() {
+        Ok(recent) => recent.iter().cloned().collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Install the logger, must be called before anything is logged.
+pub fn init() {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(FILTER.read().unwrap().max_level());
+}
+
+/// Apply changes to `d_log_level` and `d_log_json`.
+///
+/// Cheap when nothing changed so it can be called every frame.
+pub fn update(cvars: &Cvars) {
+    JSON.store(cvars.d_log_json, Ordering::Relaxed);
+
+    if FILTER.read().unwrap().spec == cvars.d_log_level {
+        return;
+    }
+    match Filter::parse(&cvars.d_log_level) {
+        Ok(filter) => {
+            log::set_max_level(filter.max_level());
+            *FILTER.write().unwrap() = filter;
+        }
+        Err(e) => {
+            // Remember the spec so the error isn't printed every frame.
+            FILTER.write().unwrap().spec = cvars.d_log_level.clone();
+            crate::dbg_logf!("failed to parse d_log_level: {e}");
+        }
+    }
+}
+
+/// Start, stop or reconfigure writing the log into a file.
+///
+/// Cheap when nothing changed so it can be called every frame.
+pub fn update_file(path: &str, rotate_mb: f32) {
+    let rotate_len = (rotate_mb.max(0.0) * 1024.0 * 1024.0) as u64;
+
+    let mut file = FILE.lock().unwrap();
+    let path_prev = file.as_ref().map_or("", |log_file| log_file.path.as_str());
+    if path == path_prev {
+        if let Some(log_file) = file.as_mut() {
+            log_file.rotate_len = rotate_len;
+        }
+        return;
+    }
+
+    *file = None;
+    if path.is_empty() {
+        return;
+    }
+    let res = LogFile::open(path, rotate_len);
+    // Log outside the lock, the logger needs it.
+    drop(file);
+    match res {
+        Ok(log_file) => {
+            *FILE.lock().unwrap() = Some(log_file);
+            crate::dbg_logf!("logging into {}", path);
+        }
+        Err(e) => crate::dbg_logf!("failed to open log file {}: {}", path, e),
+    }
+}
+
+impl LogFile {
+    fn open(path: &str, rotate_len: u64) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let len = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_owned(),
+            file,
+            len,
+            rotate_len,
+        })
+    }
+
+    fn write(&mut self, line: &str) -> std::io::Result<()> {
+        let line_len = line.len() as u64;
+        if self.rotate_len > 0 && self.len > 0 && self.len + line_len > self.rotate_len {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.len += line_len;
+        Ok(())
+    }
+
+    /// Move the current file to `<path>.1`, replacing the previous one, and start a new one.
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.file.flush()?;
+        fs::rename(&self.path, format!("{}.1", self.path))?;
+        *self = Self::open(&self.path, self.rotate_len)?;
+        Ok(())
+    }
+}
+
+/// `SystemTime::now` which also works in the browser where std has no clock.
+pub fn system_time_now() -> SystemTime {
+    #[cfg(target_arch = "wasm32")]
+    {
+        let millis = js_sys::Date::now() as u64;
+        SystemTime::UNIX_EPOCH + std::time::Duration::from_millis(millis)
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        SystemTime::now()
+    }
+}
+
+/// Format as `YYYY-MM-DD hh:mm:ss` in UTC.
+pub fn utc_timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let days = (secs / 86400) as i64;
+    let secs_of_day = secs % 86400;
+
+    // Days to civil date, see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}
+
+fn json_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(escaped, "\\u{:04x}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_levels() {
+        let filter = Filter::parse("warn, server=debug, rustcycles::server::game=trace").unwrap();
+        assert_eq!(filter.level("rustcycles::client::game"), LevelFilter::Warn);
+        assert_eq!(filter.level("rustcycles::server"), LevelFilter::Debug);
+        assert_eq!(filter.level("rustcycles::server::process"), LevelFilter::Debug);
+        assert_eq!(filter.level("rustcycles::server::game"), LevelFilter::Trace);
+        assert_eq!(filter.level("rustcycles::serverless"), LevelFilter::Warn);
+        assert_eq!(filter.max_level(), LevelFilter::Trace);
+
+        let filter = Filter::parse("common::net=trace, rustcycles_common::debug=error").unwrap();
+        assert_eq!(filter.level("rustcycles_common::common::net::rtc"), LevelFilter::Trace);
+        assert_eq!(filter.level("rustcycles_common::common::trace"), LevelFilter::Info);
+        assert_eq!(filter.level("rustcycles_common::debug::logger"), LevelFilter::Error);
+
+        assert!(Filter::parse("loud").is_err());
+    }
+
+    #[test]
+    fn timestamps() {
+        let at =
+            |secs| utc_timestamp(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(secs));
+        assert_eq!(at(0), "1970-01-01 00:00:00");
+        assert_eq!(at(951_782_400), "2000-02-29 00:00:00");
+        assert_eq!(at(1_700_000_000), "2023-11-14 22:13:20");
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(json_escape(r#"a "b" \c"#), r#"a \"b\" \\c"#);
+        assert_eq!(json_escape("x\ny\u{1}"), "x\\ny\\u0001");
+    }
+}
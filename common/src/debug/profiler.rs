@@ -0,0 +1,292 @@
+//! A lightweight instrumentation profiler.
+//!
+//! Wrap interesting code in `profile_scope!("name")`,
+//! the durations of the scopes are collected per frame.
+//! The report of the last frame can be shown on screen (`d_profiler`)
+//! and multiple frames can be exported for chrome://tracing or Perfetto (`d_profiler_trace`).
+//!
+//! Scopes nest - a scope opened while another is open becomes its child.
+//! Each thread has its own profiler.
+
+use std::{cell::RefCell, fmt::Write as _, fs, time::Duration};
+
+// Unlike std's, this one also works in the browser.
+use fyrox_core::instant::Instant;
+
+use crate::{dbg_logf, prelude::*, soft_assert_eq};
+
+thread_local! {
+    static PROFILER: RefCell<Profiler> = RefCell::new(Profiler::new());
+}
+
+struct Profiler {
+    /// Whether scopes are being recorded.
+    enabled: bool,
+    /// Times are relative to this.
+    epoch: Instant,
+    /// Indices into `scopes` of the currently open scopes, innermost last.
+    stack: Vec<usize>,
+    /// All scopes opened this frame in the order they were opened.
+    scopes: Vec<ScopeRecord>,
+    /// Human readable summary of the previous frame.
+    report: String,
+    /// Scopes of previous frames waiting to be exported.
+    trace: Option<Trace>,
+}
+
+struct ScopeRecord {
+    name: &'static str,
+    /// Names of all enclosing scopes and this one joined by `/`.
+    path: String,
+    depth: usize,
+    start: Duration,
+    /// None while still open.
+    duration: Option<Duration>,
+}
+
+struct Trace {
+    path: String,
+    frames_left: usize,
+    events: Vec<TraceEvent>,
+}
+
+struct TraceEvent {
+    name: &'static str,
+    start: Duration,
+    duration: Duration,
+}
+
+/// Closes a scope when dropped, use `profile_scope!()` instead.
+#[must_use]
+pub struct ScopeGuard {
+    /// None if the profiler was disabled when the scope opened.
+    index: Option<usize>,
+}
+
+impl ScopeGuard {
+    /// Helper function, use `profile_scope!()` instead.
+    pub fn new(name: &'static str) -> Self {
+        let index = PROFILER.with_borrow_mut(|profiler| {
+            if !profiler.enabled {
+                return None;
+            }
+
+            let (path, depth) = match profiler.stack.last() {
+                Some(&parent) => {
+                    let parent = &profiler.scopes[parent];
+                    (format!("{}/{}", parent.path, name), parent.depth + 1)
+                }
+                None => (name.to_owned(), 0),
+            };
+            let index = profiler.scopes.len();
+            profiler.scopes.push(ScopeRecord {
+                name,
+                path,
+                depth,
+                start: profiler.epoch.elapsed(),
+                duration: None,
+            });
+            profiler.stack.push(index);
+            Some(index)
+        });
+        Self { index }
+    }
+}
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        let Some(index) = self.index else {
+            return;
+        };
+        PROFILER.with_borrow_mut(|profiler| {
+            let now = profiler.epoch.elapsed();
+            // The frame might have ended while the scope was open.
+            let Some(scope) = profiler.scopes.get_mut(index) else {
+                return;
+            };
+            scope.duration = Some(now - scope.start);
+            soft_assert_eq!(profiler.stack.last(), Some(&index));
+            profiler.stack.pop();
+        });
+    }
+}
+
+impl Profiler {
+    fn new() -> Self {
+        Self {
+            enabled: false,
+            epoch: Instant::now(),
+            stack: Vec::new(),
+            scopes: Vec::new(),
+            report: String::new(),
+            trace: None,
+        }
+    }
+
+    /// Turn the scopes of the frame that just ended into a report and trace events.
+    fn end_frame(&mut self) {
+        // Scopes which are still open span frames, their data would be incomplete.
+        // Drop them all to keep indices in open guards from pointing to the wrong scopes.
+        if !self.stack.is_empty() {
+            self.stack.clear();
+            self.scopes.clear();
+            return;
+        }
+        let scopes = std::mem::take(&mut self.scopes);
+
+        // Merge scopes with the same path, e.g. from multiple ticks in one frame,
+        // keeping the order in which they first appeared.
+        let mut merged: Vec<(&ScopeRecord, Duration, usize)> = Vec::new();
+        for scope in &scopes {
+            let duration = scope.duration.unwrap_or_default();
+            match merged.iter_mut().find(|(first, _, _)| first.path == scope.path) {
+                Some((_, total, count)) => {
+                    *total += duration;
+                    *count += 1;
+                }
+                None => merged.push((scope, duration, 1)),
+            }
+        }
+        // Children are listed after their parents but a later iteration of the parent
+        // (e.g. the second tick) can add children after other scopes.
+        // Group them under the parent by sorting by path position in the list of merged scopes.
+        let mut order: Vec<usize> = (0..merged.len()).collect();
+        let sort_key = |i: usize| {
+            let mut key = Vec::new();
+            let mut path = merged[i].0.path.as_str();
+            loop {
+                let pos = merged.iter().position(|(first, _, _)| first.path == path).unwrap();
+                key.push(pos);
+                match path.rfind('/') {
+                    Some(slash) => path = &path[..slash],
+                    None => break,
+                }
+            }
+            key.reverse();
+            key
+        };
+        order.sort_by_cached_key(|&i| sort_key(i));
+
+        self.report.clear();
+        for i in order {
+            let (scope, total, count) = merged[i];
+            let indent = "  ".repeat(scope.depth);
+            let ms = total.as_secs_f64() * 1000.0;
+            write!(self.report, "{}{} {:.3} ms", indent, scope.name, ms).unwrap();
+            if count > 1 {
+                write!(self.report, " (x{})", count).unwrap();
+            }
+            self.report.push('\n');
+        }
+
+        if let Some(trace) = &mut self.trace {
+            trace.events.extend(scopes.iter().map(|scope| TraceEvent {
+                name: scope.name,
+                start: scope.start,
+                duration: scope.duration.unwrap_or_default(),
+            }));
+            trace.frames_left = trace.frames_left.saturating_sub(1);
+        }
+    }
+}
+
+/// End the current frame and start the next one.
+///
+/// Call once per frame, before any scopes are opened.
+/// Starts and finishes exporting the trace when requested by `d_profiler_trace`.
+pub fn frame(cvars: &mut Cvars) {
+    PROFILER.with_borrow_mut(|profiler| {
+        profiler.end_frame();
+
+        if profiler.trace.as_ref().is_some_and(|trace| trace.frames_left == 0) {
+            let trace = profiler.trace.take().unwrap();
+            match fs::write(&trace.path, trace.to_json()) {
+                Ok(()) => dbg_logf!("saved profiler trace to {}", trace.path),
+                Err(e) => dbg_logf!("failed to save profiler trace to {}: {}", trace.path, e),
+            }
+            cvars.d_profiler_trace.clear();
+        }
+        if profiler.trace.is_none() && !cvars.d_profiler_trace.is_empty() {
+            dbg_logf!(
+                "recording {} frames into {}",
+                cvars.d_profiler_trace_frames,
+                cvars.d_profiler_trace
+            );
+            profiler.trace = Some(Trace {
+                path: cvars.d_profiler_trace.clone(),
+                frames_left: cvars.d_profiler_trace_frames,
+                events: Vec::new(),
+            });
+        }
+
+        profiler.enabled = cvars.d_profiler || profiler.trace.is_some();
+        if !profiler.enabled {
+            profiler.report.clear();
+        }
+    });
+}
+
+/// Durations of all scopes in the previous frame, one per line, nested scopes indented.
+pub fn report() -> String {
+    PROFILER.with_borrow(|profiler| profiler.report.clone())
+}
+
+impl Trace {
+    /// The chrome tracing format, see
+    /// https://docs.google.com/document/d/1CvAClvFfyA5R-PhYUmn5OOQtYMH4h6I0nSsKchNAySU
+    fn to_json(&self) -> String {
+        let endpoint = crate::debug::endpoint_name();
+        let mut json = String::from("{\"traceEvents\":[\n");
+        for (i, event) in self.events.iter().enumerate() {
+            if i > 0 {
+                json.push_str(",\n");
+            }
+            write!(
+                json,
+                r#"{{"name":"{}","cat":"{}","ph":"X","ts":{},"dur":{},"pid":1,"tid":1}}"#,
+                event.name,
+                endpoint,
+                event.start.as_micros(),
+                event.duration.as_micros(),
+            )
+            .unwrap();
+        }
+        json.push_str("\n]}\n");
+        json
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile_scope;
+
+    #[test]
+    fn nested_scopes() {
+        let mut cvars = Cvars::default();
+        cvars.d_profiler = true;
+        frame(&mut cvars);
+        for _ in 0..2 {
+            profile_scope!("tick");
+            {
+                profile_scope!("physics");
+            }
+            profile_scope!("gamelogic");
+        }
+        {
+            profile_scope!("render");
+        }
+        frame(&mut cvars);
+
+        let names: Vec<_> = report()
+            .lines()
+            .map(|line| line.split(" ms").next().unwrap().rsplit_once(' ').unwrap().0.to_owned())
+            .collect();
+        assert_eq!(names, ["tick", "  physics", "  gamelogic", "render"]);
+        assert!(report().lines().next().unwrap().ends_with("(x2)"));
+
+        cvars.d_profiler = false;
+        frame(&mut cvars);
+        assert_eq!(report(), "");
+    }
+}
@@ -0,0 +1,20 @@
+//! Gamelogic, network messages, cvars and debug tools shared by the client and server.
+//!
+//! This is a separate crate so other programs (e.g. a lightweight dedicated server,
+//! demo parsers or bots) can use the gamelogic without linking the client and server.
+//!
+//! The split is not complete - the gamelogic still uses Fyrox's scene graph and physics
+//! which in Fyrox 0.33 are in the same crate as the renderer and windowing so those still get linked.
+//! Nothing in this crate uses them though, the `Engine` and the game loop stepping it
+//! live in the game. Everything else from Fyrox comes from `fyrox-core` and `fyrox-resource`
+//! so only the dependency has to change once Fyrox's scene is split out.
+
+#![allow(clippy::option_map_unit_fn)] // Map is sometimes more readable.
+
+// Keep this first so the macros are available everywhere without having to import them.
+#[macro_use]
+pub mod debug;
+
+pub mod common;
+pub mod cvars;
+pub mod prelude;
@@ -10,7 +10,7 @@
 #![allow(dead_code)]
 
 // Some private imports that are intentionally *not* re-exported.
-use fyrox::{core::algebra, scene::collider::BitMask};
+use fyrox_core::algebra;
 
 // Public re-exports.
 // Make the most commonly used types available without importing manually.
@@ -27,13 +27,13 @@ pub use std::{
 // because we usually don't need HashDoS protection but do need determinism.
 pub use fxhash::{FxHashMap, FxHashSet};
 
+pub use fyrox_core::{
+    algebra::{Unit, UnitQuaternion, Vector2, Vector3, Vector4},
+    color::Color,
+    pool::{Handle, Pool},
+};
+
 pub use fyrox::{
-    core::{
-        algebra::{Unit, UnitQuaternion, Vector2, Vector3, Vector4},
-        color::Color,
-        pool::{Handle, Pool},
-    },
-    engine::Engine,
     resource::model::Model,
     scene::{
         base::{Base, BaseBuilder},
@@ -62,15 +62,17 @@ pub use strum::EnumCount;
 pub use strum_macros::{EnumCount, FromRepr};
 
 pub use crate::{
-    client::game::ClientFrameCtx,
     common::{
+        layers::CollisionLayer,
         messages::*,
-        trace::{trace_line, TraceOptions},
-        FrameCtx, GameState, GameStateType,
+        trace::{
+            overlap_sphere, trace_line, trace_lines, trace_shape, trace_sphere, Overlaps,
+            TraceBatch, TraceHits, TraceOptions,
+        },
+        FrameCtx, GameState, GameStateType, MatchState,
     },
     cvars::*,
     debug::{DbgIterator, SoftUnwrap},
-    server::game::ServerFrameCtx,
 };
 
 // Visibility of macros by example works diffrently from normal items,
@@ -310,13 +312,6 @@ pub const YELLOW: Color = Color::opaque(255, 255, 0);
 pub const MAGENTA: Color = Color::opaque(255, 0, 255);
 pub const CYAN: Color = Color::opaque(0, 255, 255);
 
-// Note: These are a bit weird because the default is all bits set
-// so most colliders have all bits but some special objects only have a subset.
-// For example, the player can have only IG_ENTITIES and then we can
-// raycast while ignoring the player by setting `filter` to !IG_ENTITIES.
-pub const IG_ENTITIES: BitMask = BitMask(1 << 0);
-pub const IG_ALL: BitMask = BitMask(u32::MAX);
-
 pub trait PoolExt<T> {
     /// Collect the handles into a `Vec`.
     ///
@@ -332,13 +327,26 @@ pub trait PoolExt<T> {
     /// When/if we have perf issues and profiling says this is the cause,
     /// then we can restructure the code to avoid it.
     /// Until then writing code faster is more important than writing faster code.
+    ///
+    /// In code which runs every frame, prefer `collect_handles_into` with a buffer
+    /// kept around between frames, e.g. `ServerFrameCtx::for_each_client`.
     fn collect_handles(&self) -> Vec<Handle<T>>;
+
+    /// Like `collect_handles` but reuses the `Vec`'s allocation.
+    ///
+    /// Anything already in `handles` is removed.
+    fn collect_handles_into(&self, handles: &mut Vec<Handle<T>>);
 }
 
 impl<T: 'static> PoolExt<T> for Pool<T> {
     fn collect_handles(&self) -> Vec<Handle<T>> {
         self.pair_iter().map(|(h, _)| h).collect()
     }
+
+    fn collect_handles_into(&self, handles: &mut Vec<Handle<T>>) {
+        handles.clear();
+        handles.extend(self.pair_iter().map(|(h, _)| h));
+    }
 }
 
 // For easly switching between f32 and f64.
@@ -367,4 +375,18 @@ mod tests {
         let b = 0.69;
         assert_eq!(v!(-s.x, 0, a + b), Vec3::new(-42.0, 0.0, 420.69));
     }
+
+    #[test]
+    fn test_collect_handles_into() {
+        let mut pool = Pool::new();
+        let a = pool.spawn(1);
+        let b = pool.spawn(2);
+        let c = pool.spawn(3);
+        pool.free(b);
+
+        let mut handles = vec![b];
+        pool.collect_handles_into(&mut handles);
+        assert_eq!(handles, pool.collect_handles());
+        assert_eq!(handles, [a, c]);
+    }
 }
@@ -1,4 +1,23 @@
 //! The client in a client-server multiplayer game architecture.
 
+pub mod browser;
+pub mod console;
+pub mod debug_replay;
+pub mod demo_player;
+pub mod frame_stats;
 pub mod game;
+pub mod gamepad;
+pub mod graphs;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod hot_reload;
+pub mod hud;
+pub mod lobby;
+pub mod menu;
+pub mod minimap;
 pub mod process;
+pub mod render_scale;
+pub mod settings;
+pub mod shadow_only;
+pub mod touch;
+pub mod trails;
+pub mod vector_shapes;
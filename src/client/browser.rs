@@ -0,0 +1,342 @@
+//! Server browser - lists servers from the master server and the local network
+//! and lets the player connect to one of them.
+//!
+//! Servers from the master are queried directly to get up to date info and ping,
+//! servers on the local network respond to a broadcast.
+//! See `common::master` for the protocol.
+
+use std::{
+    io::{self, ErrorKind},
+    net::{SocketAddr, UdpSocket},
+};
+
+use fyrox::gui::{
+    border::BorderBuilder,
+    button::{ButtonBuilder, ButtonMessage},
+    list_view::{ListViewBuilder, ListViewMessage},
+    message::{MessageDirection, UiMessage},
+    stack_panel::StackPanelBuilder,
+    text::{TextBuilder, TextMessage},
+    widget::{WidgetBuilder, WidgetMessage},
+    HorizontalAlignment, Thickness, UiNode, UserInterface, VerticalAlignment,
+};
+
+use crate::{
+    common::{
+        master::{
+            InfoRequest, InfoResponse, ServerEntry, ServerInfo, ServerListQuery, MAX_DATAGRAM_LEN,
+        },
+        net,
+    },
+    prelude::*,
+};
+
+/// The server browser screen.
+pub struct ServerBrowser {
+    root: Handle<UiNode>,
+    status: Handle<UiNode>,
+    list: Handle<UiNode>,
+    refresh: Handle<UiNode>,
+    /// List items, in the same order as `servers`.
+    rows: Vec<Handle<UiNode>>,
+    open: bool,
+    /// Pending query to the master server.
+    query: Option<ServerListQuery>,
+    /// Real time when the last refresh started.
+    refresh_time: f32,
+    /// Used to send info requests to individual servers and the broadcast address.
+    socket_v4: Option<UdpSocket>,
+    /// Used to send info requests to servers with IPv6 addresses.
+    ///
+    /// LATER LAN discovery over IPv6 using multicast.
+    socket_v6: Option<UdpSocket>,
+    servers: Vec<BrowserEntry>,
+}
+
+struct BrowserEntry {
+    addr: SocketAddr,
+    info: ServerInfo,
+    /// Round trip time in seconds, None if the server hasn't responded (yet).
+    ping: Option<f32>,
+}
+
+impl ServerBrowser {
+    pub fn new(ui: &mut UserInterface) -> Self {
+        let ctx = &mut ui.build_ctx();
+
+        let status =
+            TextBuilder::new(WidgetBuilder::new().with_margin(Thickness::uniform(4.0))).build(ctx);
+        let list = ListViewBuilder::new(
+            WidgetBuilder::new().with_height(400.0).with_margin(Thickness::uniform(4.0)),
+        )
+        .build(ctx);
+        let refresh = ButtonBuilder::new(
+            WidgetBuilder::new()
+                .with_width(100.0)
+                .with_height(25.0)
+                .with_margin(Thickness::uniform(4.0)),
+        )
+        .with_text("Refresh")
+        .build(ctx);
+
+        let panel = StackPanelBuilder::new(
+            WidgetBuilder::new().with_child(status).with_child(list).with_child(refresh),
+        )
+        .build(ctx);
+        let root = BorderBuilder::new(
+            WidgetBuilder::new()
+                .with_width(800.0)
+                .with_horizontal_alignment(HorizontalAlignment::Center)
+                .with_vertical_alignment(VerticalAlignment::Center)
+                .with_visibility(false)
+                .with_child(panel),
+        )
+        .build(ctx);
+
+        Self {
+            root,
+            status,
+            list,
+            refresh,
+            rows: Vec::new(),
+            open: false,
+            query: None,
+            refresh_time: 0.0,
+            socket_v4: None,
+            socket_v6: None,
+            servers: Vec::new(),
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn open(&mut self, ui: &mut UserInterface, cvars: &Cvars, real_time: f32) {
+        self.open = true;
+        ui.send_message(WidgetMessage::visibility(self.root, MessageDirection::ToWidget, true));
+        self.refresh(ui, cvars, real_time);
+    }
+
+    pub fn close(&mut self, ui: &mut UserInterface) {
+        self.open = false;
+        ui.send_message(WidgetMessage::visibility(self.root, MessageDirection::ToWidget, false));
+        self.query = None;
+        self.socket_v4 = None;
+        self.socket_v6 = None;
+    }
+
+    /// Forget all servers and ask the master and local network again.
+    fn refresh(&mut self, ui: &mut UserInterface, cvars: &Cvars, real_time: f32) {
+        self.servers.clear();
+        self.update_rows(ui);
+        self.refresh_time = real_time;
+
+        self.query = None;
+        if !cvars.net_master_addr.is_empty() {
+            let res = net::resolve(&cvars.net_master_addr, cvars.net_prefer_ipv6)
+                .and_then(ServerListQuery::new);
+            match res {
+                Ok(query) => self.query = Some(query),
+                Err(e) => dbg_logf!("failed to query master {}: {e}", cvars.net_master_addr),
+            }
+        }
+
+        self.socket_v4 = match bind_socket("0.0.0.0:0") {
+            Ok(socket) => {
+                if let Err(e) = socket.set_broadcast(true) {
+                    dbg_logf!("failed to enable broadcast: {e}");
+                }
+                Some(socket)
+            }
+            Err(e) => {
+                dbg_logf!("failed to bind IPv4 server browser socket: {e}");
+                None
+            }
+        };
+        // Not an error, IPv6 might just not be available.
+        self.socket_v6 = bind_socket("[::]:0").ok();
+        let broadcast = SocketAddr::from(([255, 255, 255, 255], cvars.cl_browser_lan_port));
+        self.send_info_request(broadcast, real_time);
+
+        self.set_status(ui, "Refreshing...");
+    }
+
+    /// Receive responses from the master and individual servers.
+    pub fn update(&mut self, ui: &mut UserInterface, cvars: &Cvars, real_time: f32) {
+        if !self.open {
+            return;
+        }
+
+        let mut changed = false;
+
+        if let Some(poll) = self.query.as_mut().map(ServerListQuery::poll) {
+            match poll {
+                Ok(Some(servers)) => {
+                    self.query = None;
+                    for ServerEntry { addr, info } in servers {
+                        self.send_info_request(addr, real_time);
+                        self.insert(addr, info, None);
+                    }
+                    changed = true;
+                }
+                Ok(None) => {
+                    if real_time - self.refresh_time > cvars.cl_browser_timeout {
+                        self.query = None;
+                        self.set_status(ui, "Master server didn't respond");
+                    }
+                }
+                Err(e) => {
+                    self.query = None;
+                    dbg_logf!("failed to receive server list: {e}");
+                    self.set_status(ui, "Failed to get the server list");
+                }
+            }
+        }
+
+        let mut responses = Vec::new();
+        let mut buf = vec![0; MAX_DATAGRAM_LEN];
+        for socket in self.socket_v4.iter().chain(&self.socket_v6) {
+            loop {
+                let (len, src) = match socket.recv_from(&mut buf) {
+                    Ok(res) => res,
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(e) => {
+                        dbg_logf!("failed to receive info response: {e}");
+                        break;
+                    }
+                };
+                match bincode::deserialize::<InfoResponse>(&buf[..len]) {
+                    Ok(response) => responses.push((src, response)),
+                    Err(e) => dbg_logf!("malformed info response from {src}: {e}"),
+                }
+            }
+        }
+        for (src, response) in responses {
+            let addr = SocketAddr::new(src.ip(), response.info.port);
+            let ping = real_time - response.time;
+            self.insert(addr, response.info, Some(ping));
+            changed = true;
+        }
+
+        if changed {
+            self.update_rows(ui);
+            if self.query.is_none() {
+                let status = format!("{} servers", self.servers.len());
+                self.set_status(ui, &status);
+            }
+        }
+    }
+
+    /// Handle UI events.
+    ///
+    /// Returns the address of the server to connect to if the player chose one.
+    pub fn ui_message(
+        &mut self,
+        ui: &mut UserInterface,
+        cvars: &Cvars,
+        real_time: f32,
+        msg: &UiMessage,
+    ) -> Option<SocketAddr> {
+        if !self.open || msg.direction != MessageDirection::FromWidget {
+            return None;
+        }
+
+        if msg.destination() == self.refresh && msg.data() == Some(&ButtonMessage::Click) {
+            self.refresh(ui, cvars, real_time);
+            return None;
+        }
+
+        if let Some(WidgetMessage::DoubleClick { .. }) = msg.data() {
+            // The list wraps our items in its own widgets
+            // and the click can land on any of them so walk up to the row.
+            let mut handle = msg.destination();
+            while let Some(node) = ui.try_get_node(handle) {
+                if let Some(index) = self.rows.iter().position(|&row| row == handle) {
+                    return Some(self.servers[index].addr);
+                }
+                handle = node.parent();
+            }
+        }
+
+        None
+    }
+
+    /// Add a server or update its info.
+    ///
+    /// Servers are deduplicated by address, a new ping overwrites the old one.
+    fn insert(&mut self, addr: SocketAddr, info: ServerInfo, ping: Option<f32>) {
+        let addr = net::canonical_addr(addr);
+        if let Some(entry) = self.servers.iter_mut().find(|entry| entry.addr == addr) {
+            entry.info = info;
+            entry.ping = ping.or(entry.ping);
+        } else {
+            self.servers.push(BrowserEntry { addr, info, ping });
+        }
+    }
+
+    fn send_info_request(&self, addr: SocketAddr, real_time: f32) {
+        let socket = if addr.is_ipv4() {
+            &self.socket_v4
+        } else {
+            &self.socket_v6
+        };
+        let Some(socket) = socket else {
+            return;
+        };
+        let bytes = bincode::serialize(&InfoRequest::new(real_time)).unwrap();
+        if let Err(e) = socket.send_to(&bytes, addr) {
+            dbg_logf!("failed to send info request to {addr}: {e}");
+        }
+    }
+
+    /// Recreate the list items from `servers`, sorted by ping.
+    fn update_rows(&mut self, ui: &mut UserInterface) {
+        self.servers.sort_by(|a, b| match (a.ping, b.ping) {
+            (Some(a), Some(b)) => a.total_cmp(&b),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => std::cmp::Ordering::Equal,
+        });
+
+        // LATER Proper columns.
+        let ctx = &mut ui.build_ctx();
+        self.rows = self
+            .servers
+            .iter()
+            .map(|entry| {
+                let ping = match entry.ping {
+                    Some(ping) => format!("{:.0} ms", ping * 1000.0),
+                    None => "?".to_owned(),
+                };
+                let text = format!(
+                    "{}    {}    {} players    {}    {}",
+                    entry.info.name, entry.info.map, entry.info.players, ping, entry.addr
+                );
+                TextBuilder::new(WidgetBuilder::new().with_margin(Thickness::uniform(2.0)))
+                    .with_text(text)
+                    .build(ctx)
+            })
+            .collect();
+
+        ui.send_message(ListViewMessage::items(
+            self.list,
+            MessageDirection::ToWidget,
+            self.rows.clone(),
+        ));
+    }
+
+    fn set_status(&self, ui: &mut UserInterface, status: &str) {
+        ui.send_message(TextMessage::text(
+            self.status,
+            MessageDirection::ToWidget,
+            status.to_owned(),
+        ));
+    }
+}
+
+fn bind_socket(addr: &str) -> io::Result<UdpSocket> {
+    let socket = UdpSocket::bind(addr)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket)
+}
@@ -0,0 +1,518 @@
+//! In-game console for changing cvars at runtime.
+//!
+//! Used to live in the cvars-console-fyrox crate, it's here now because:
+//! - Fixing focus and caret handling needed access to the console's widgets
+//!   which the crate doesn't expose.
+//! - Commands like `map` and `stats` need to reach game state, the crate only knows cvars.
+//! - The crate has to be released in lockstep with both cvars and fyrox-ui,
+//!   which made updating Fyrox harder.
+//!
+//! LATER Upstream the generic parts (focus, history, scrolling) and depend on the crate again.
+
+use std::collections::VecDeque;
+
+use fyrox::gui::{
+    brush::Brush,
+    formatted_text::WrapMode,
+    grid::{Column, GridBuilder, Row},
+    message::{KeyCode, MessageDirection, UiMessage},
+    scroll_bar::{ScrollBarBuilder, ScrollBarMessage},
+    text::{TextBuilder, TextMessage},
+    text_box::{TextBoxBuilder, TextCommitMode},
+    widget::{WidgetBuilder, WidgetMessage},
+    Orientation, UiNode, UserInterface, VerticalAlignment,
+};
+
+use crate::{debug, prelude::*};
+
+const FONT_SIZE: f32 = 14.0;
+/// Height of one line of history in pixels.
+///
+/// LATER Get it from the font, this is what Fyrox's default font gives us.
+const LINE_HEIGHT: f32 = FONT_SIZE * 1.25;
+const PROMPT_HEIGHT: f32 = 22.0;
+const SCROLL_BAR_WIDTH: f32 = 12.0;
+/// Lines scrolled by one step of the mouse wheel.
+const WHEEL_LINES: f32 = 3.0;
+
+/// Commands which the console can't handle itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConsoleCommand {
+    CallVote(VoteKind),
+    FrameStatsReset,
+    /// Restore the savestate with this name, see `savestate`.
+    Load(String),
+    /// Change the map of a local game or call a vote to change it on a remote server.
+    Map(String),
+    /// Save the game state under this name, see `savestate`.
+    Save(String),
+    /// Ask the server for the player's saved stats.
+    Stats(String),
+    /// Run one gamelogic frame when `d_singlestep` is on.
+    Step,
+}
+
+/// The console - prompt, history and the UI showing them.
+pub struct Console {
+    is_open: bool,
+    /// Whether the mouse was grabbed before opening so it can be restored after closing.
+    was_mouse_grabbed: bool,
+    /// Focus the prompt once the UI has processed the messages which made it visible,
+    /// Fyrox ignores focus requests for hidden widgets.
+    focus_pending: bool,
+    /// The text being typed.
+    prompt: String,
+    /// Previously entered commands, oldest first.
+    prompt_history: Vec<String>,
+    /// Which command from `prompt_history` is in the prompt,
+    /// None when typing a new one.
+    prompt_history_index: Option<usize>,
+    /// Everything printed to the console, oldest first.
+    ///
+    /// Limited by `cl_console_history_max`.
+    history: VecDeque<HistoryLine>,
+    /// Index one past the last visible line of history.
+    history_view_end: usize,
+    /// How many lines of history fit into the console, depends on window height.
+    ///
+    /// Lines which wrap take up more space so this is just an estimate.
+    /// The history is aligned to the bottom so the newest lines are always visible.
+    visible_lines: usize,
+    layout: Handle<UiNode>,
+    history_text: Handle<UiNode>,
+    scroll_bar: Handle<UiNode>,
+    prompt_text_box: Handle<UiNode>,
+}
+
+#[derive(Debug, Clone)]
+struct HistoryLine {
+    text: String,
+    /// Entered by the player, as opposed to printed by the game.
+    is_input: bool,
+}
+
+impl Console {
+    pub fn new(ui: &mut UserInterface) -> Self {
+        let ctx = &mut ui.build_ctx();
+
+        let history_text = TextBuilder::new(WidgetBuilder::new().on_row(0).on_column(0))
+            .with_font_size(FONT_SIZE)
+            .with_vertical_text_alignment(VerticalAlignment::Bottom)
+            // Word wrap doesn't work if there's an extremely long word.
+            .with_wrap(WrapMode::Letter)
+            .build(ctx);
+
+        // Shows where in the history we are, it can also be dragged.
+        let scroll_bar = ScrollBarBuilder::new(
+            WidgetBuilder::new().with_width(SCROLL_BAR_WIDTH).on_row(0).on_column(1),
+        )
+        .with_orientation(Orientation::Vertical)
+        .with_min(0.0)
+        .with_max(0.0)
+        .with_step(1.0)
+        .build(ctx);
+
+        let prompt_text_box = TextBoxBuilder::new(
+            WidgetBuilder::new().with_height(PROMPT_HEIGHT).on_row(1).on_column(0),
+        )
+        .with_text_commit_mode(TextCommitMode::Immediate)
+        .build(ctx);
+
+        let layout = GridBuilder::new(
+            WidgetBuilder::new()
+                .with_visibility(false)
+                .with_background(Brush::Solid(Color::BLACK.with_new_alpha(220)))
+                .with_child(history_text)
+                .with_child(scroll_bar)
+                .with_child(prompt_text_box),
+        )
+        .add_row(Row::stretch())
+        .add_row(Row::strict(PROMPT_HEIGHT))
+        .add_column(Column::stretch())
+        .add_column(Column::strict(SCROLL_BAR_WIDTH))
+        .build(ctx);
+
+        Self {
+            is_open: false,
+            was_mouse_grabbed: false,
+            focus_pending: false,
+            prompt: String::new(),
+            prompt_history: Vec::new(),
+            prompt_history_index: None,
+            history: VecDeque::new(),
+            history_view_end: 0,
+            visible_lines: 1,
+            layout,
+            history_text,
+            scroll_bar,
+            prompt_text_box,
+        }
+    }
+
+    pub fn resized(&mut self, ui: &mut UserInterface, width: f32, height: f32) {
+        let console_height = height / 2.0;
+        ui.send_message(WidgetMessage::width(self.layout, MessageDirection::ToWidget, width));
+        ui.send_message(WidgetMessage::height(
+            self.layout,
+            MessageDirection::ToWidget,
+            console_height,
+        ));
+
+        let history_height = console_height - PROMPT_HEIGHT;
+        self.visible_lines = ((history_height / LINE_HEIGHT).floor() as usize).max(1);
+        self.clamp_view();
+        self.update_ui_history(ui);
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.is_open
+    }
+
+    pub fn open(&mut self, ui: &mut UserInterface, was_mouse_grabbed: bool) {
+        self.is_open = true;
+        self.was_mouse_grabbed = was_mouse_grabbed;
+        ui.send_message(WidgetMessage::visibility(self.layout, MessageDirection::ToWidget, true));
+        self.focus_pending = true;
+    }
+
+    /// Returns whether the mouse was grabbed before opening.
+    pub fn close(&mut self, ui: &mut UserInterface) -> bool {
+        self.is_open = false;
+        self.focus_pending = false;
+        ui.send_message(WidgetMessage::visibility(self.layout, MessageDirection::ToWidget, false));
+        ui.send_message(WidgetMessage::unfocus(self.prompt_text_box, MessageDirection::ToWidget));
+        self.was_mouse_grabbed
+    }
+
+    /// Once per frame, after the UI has processed messages.
+    pub fn update(&mut self, ui: &mut UserInterface, cvars: &Cvars) {
+        if self.focus_pending {
+            self.focus_pending = false;
+            self.focus_prompt(ui);
+        }
+
+        let log = debug::take_log();
+        if !log.is_empty() {
+            for line in log {
+                self.print(line);
+            }
+            self.trim_history(cvars.cl_console_history_max);
+            self.update_ui_history(ui);
+        }
+    }
+
+    /// Handle UI events.
+    ///
+    /// Returns a command entered by the player if the caller should handle it.
+    pub fn ui_message(
+        &mut self,
+        ui: &mut UserInterface,
+        cvars: &mut Cvars,
+        msg: &UiMessage,
+    ) -> Option<ConsoleCommand> {
+        if !self.is_open {
+            return None;
+        }
+
+        match msg.data() {
+            // Clicking the history would take focus from the prompt
+            // and typing would go nowhere.
+            Some(WidgetMessage::MouseDown { .. }) => {
+                if self.contains(ui, msg.destination()) {
+                    self.focus_pending = true;
+                }
+                return None;
+            }
+            Some(WidgetMessage::MouseWheel { amount, .. }) => {
+                if self.contains(ui, msg.destination()) {
+                    let lines = (amount.abs() * WHEEL_LINES).round() as usize;
+                    if *amount > 0.0 {
+                        self.history_scroll_up(lines);
+                    } else {
+                        self.history_scroll_down(lines);
+                    }
+                    self.update_ui_history(ui);
+                }
+                return None;
+            }
+            _ => {}
+        }
+
+        if msg.destination() == self.scroll_bar && msg.direction == MessageDirection::FromWidget {
+            if let Some(ScrollBarMessage::Value(value)) = msg.data() {
+                // The value is the index of the first visible line.
+                let old = self.history_view_end;
+                self.history_view_end = value.round() as usize + self.visible_lines;
+                self.clamp_view();
+                if self.history_view_end != old {
+                    self.update_ui_history(ui);
+                }
+            }
+            return None;
+        }
+
+        if msg.destination() != self.prompt_text_box {
+            return None;
+        }
+
+        if let Some(TextMessage::Text(text)) = msg.data() {
+            if msg.direction == MessageDirection::FromWidget {
+                self.prompt = text.clone();
+            }
+            return None;
+        }
+
+        let Some(WidgetMessage::KeyDown(key)) = msg.data() else {
+            return None;
+        };
+        let mut command = None;
+        let handled = match key {
+            KeyCode::ArrowUp => self.prompt_history_back(),
+            KeyCode::ArrowDown => self.prompt_history_forward(),
+            KeyCode::PageUp => self.history_scroll_up(self.visible_lines.saturating_sub(1).max(1)),
+            KeyCode::PageDown => {
+                self.history_scroll_down(self.visible_lines.saturating_sub(1).max(1))
+            }
+            KeyCode::Enter | KeyCode::NumpadEnter => {
+                command = self.enter(cvars);
+                self.trim_history(cvars.cl_console_history_max)
+            }
+            _ => false,
+        };
+        if handled {
+            self.update_ui_prompt(ui);
+            self.update_ui_history(ui);
+        }
+        command
+    }
+
+    /// Whether `handle` is the console or one of its descendants.
+    fn contains(&self, ui: &UserInterface, mut handle: Handle<UiNode>) -> bool {
+        while let Some(node) = ui.try_get_node(handle) {
+            if handle == self.layout {
+                return true;
+            }
+            handle = node.parent();
+        }
+        false
+    }
+
+    fn focus_prompt(&self, ui: &mut UserInterface) {
+        ui.send_message(WidgetMessage::focus(self.prompt_text_box, MessageDirection::ToWidget));
+    }
+
+    // The following methods return true so `ui_message` knows to update the UI.
+
+    /// Replace the prompt with an older command.
+    fn prompt_history_back(&mut self) -> bool {
+        let index = match self.prompt_history_index {
+            Some(0) => return false,
+            Some(index) => index - 1,
+            None if self.prompt_history.is_empty() => return false,
+            None => self.prompt_history.len() - 1,
+        };
+        self.prompt_history_index = Some(index);
+        self.prompt = self.prompt_history[index].clone();
+        true
+    }
+
+    /// Replace the prompt with a newer command or clear it after the newest.
+    fn prompt_history_forward(&mut self) -> bool {
+        let Some(index) = self.prompt_history_index else {
+            return false;
+        };
+        if index + 1 < self.prompt_history.len() {
+            self.prompt_history_index = Some(index + 1);
+            self.prompt = self.prompt_history[index + 1].clone();
+        } else {
+            self.prompt_history_index = None;
+            self.prompt.clear();
+        }
+        true
+    }
+
+    fn history_scroll_up(&mut self, count: usize) -> bool {
+        self.history_view_end = self.history_view_end.saturating_sub(count);
+        self.clamp_view();
+        true
+    }
+
+    fn history_scroll_down(&mut self, count: usize) -> bool {
+        self.history_view_end += count;
+        self.clamp_view();
+        true
+    }
+
+    /// Don't scroll past the point where the first or last line is at the edge.
+    fn clamp_view(&mut self) {
+        let min = self.visible_lines.min(self.history.len());
+        self.history_view_end = self.history_view_end.clamp(min, self.history.len());
+    }
+
+    /// Run the command in the prompt.
+    ///
+    /// Returns commands which have to be handled outside the console.
+    fn enter(&mut self, cvars: &mut Cvars) -> Option<ConsoleCommand> {
+        let cmd = std::mem::take(&mut self.prompt);
+        self.prompt_history_index = None;
+        self.print_line(cmd.clone(), true);
+        let cmd = cmd.trim();
+        if cmd.is_empty() {
+            return None;
+        }
+        if self.prompt_history.last().map(String::as_str) != Some(cmd) {
+            self.prompt_history.push(cmd.to_owned());
+        }
+
+        let (name, value) = match cmd.split_once(char::is_whitespace) {
+            Some((name, value)) => (name, Some(value.trim())),
+            None => (cmd, None),
+        };
+        match (name, value) {
+            ("help", None) => {
+                self.print("Available actions:");
+                self.print("    help                 Print this message");
+                self.print("    callvote map <name>  Call a vote to change the map");
+                self.print("    callvote kick <id>   Call a vote to kick a player");
+                self.print("    condump <file>       Save the console history to a file");
+                self.print("    errors [count]       Print the most recent soft assert failures");
+                self.print("    frame_stats_reset    Forget frame time statistics");
+                self.print("    load <name>          Restore a savestate, only in local games");
+                self.print(
+                    "    map <name>           Change the map, calls a vote on remote servers",
+                );
+                self.print("    save <name>          Save the game state, only in local games");
+                self.print("    stats <player>       Print a player's saved stats (ID or name)");
+                self.print("    step                 Run one frame when d_singlestep is on");
+                self.print("    <cvar name>          Print the cvar's value");
+                self.print("    <cvar name> <value>  Set the cvar's value");
+            }
+            ("callvote", None) => self.print("Usage: callvote map <name> | callvote kick <id>"),
+            ("callvote", Some(args)) => match VoteKind::parse(args) {
+                Ok(kind) => return Some(ConsoleCommand::CallVote(kind)),
+                Err(e) => self.print(e),
+            },
+            ("condump", None) => self.print("Usage: condump <file>"),
+            ("errors", count) => {
+                let count = match count.map(str::parse) {
+                    None => 10,
+                    Some(Ok(count)) => count,
+                    Some(Err(e)) => {
+                        self.print(format!("Invalid count: {e}"));
+                        return None;
+                    }
+                };
+                let (total, _) = debug::soft_errors();
+                let recent = debug::soft_errors_recent(count);
+                self.print(format!(
+                    "{} errors in total, showing the last {}:",
+                    total,
+                    recent.len()
+                ));
+                for line in recent {
+                    self.print(line);
+                }
+            }
+            ("frame_stats_reset", None) => {
+                self.print("Frame stats reset");
+                return Some(ConsoleCommand::FrameStatsReset);
+            }
+            ("load", None) => self.print("Usage: load <name>"),
+            ("load", Some(name)) => return Some(ConsoleCommand::Load(name.to_owned())),
+            ("map", None) => self.print("Usage: map <name>"),
+            ("map", Some(map)) => return Some(ConsoleCommand::Map(map.to_owned())),
+            ("save", None) => self.print("Usage: save <name>"),
+            ("save", Some(name)) => return Some(ConsoleCommand::Save(name.to_owned())),
+            ("stats", None) => self.print("Usage: stats <player>"),
+            ("stats", Some(player)) => return Some(ConsoleCommand::Stats(player.to_owned())),
+            ("step", None) => return Some(ConsoleCommand::Step),
+            ("condump", Some(path)) => match self.dump(path) {
+                Ok(()) => self.print(format!("Saved console history to {path}")),
+                Err(e) => self.print(format!("Failed to save console history to {path}: {e}")),
+            },
+            (name, None) => match cvars.get_string(name) {
+                Ok(value) => self.print(value),
+                Err(e) => self.print(e),
+            },
+            (name, Some(value)) => match cvars.set_str(name, value) {
+                // Print the new value from cvars so the player can check it was parsed correctly.
+                Ok(()) => self.print(format!("{} = {}", name, cvars.get_string(name).unwrap())),
+                Err(e) => self.print(e),
+            },
+        }
+        None
+    }
+
+    /// Write the whole history into a file, e.g. to attach it to a bug report.
+    fn dump(&self, path: &str) -> std::io::Result<()> {
+        let mut text = String::new();
+        for line in &self.history {
+            if line.is_input {
+                text.push_str("> ");
+            }
+            text.push_str(&line.text);
+            text.push('\n');
+        }
+        std::fs::write(path, text)
+    }
+
+    /// Drop the oldest lines so at most `max` remain.
+    ///
+    /// Returns true so it can be used like the methods above.
+    fn trim_history(&mut self, max: usize) -> bool {
+        let excess = self.history.len().saturating_sub(max);
+        self.history.drain(..excess);
+        self.history_view_end = self.history_view_end.saturating_sub(excess);
+        self.clamp_view();
+        true
+    }
+
+    pub fn print(&mut self, text: impl Into<String>) {
+        self.print_line(text.into(), false);
+    }
+
+    fn print_line(&mut self, text: String, is_input: bool) {
+        // Keep following new lines unless the player scrolled up.
+        let at_end = self.history_view_end == self.history.len();
+        self.history.push_back(HistoryLine { text, is_input });
+        if at_end {
+            self.history_view_end = self.history.len();
+        }
+    }
+
+    fn update_ui_prompt(&self, ui: &mut UserInterface) {
+        ui.send_message(TextMessage::text(
+            self.prompt_text_box,
+            MessageDirection::ToWidget,
+            self.prompt.clone(),
+        ));
+        // Setting the text doesn't move the caret,
+        // without this it'd end up in the middle of recalled commands.
+        // There's no message to set the caret position so pretend the player pressed End.
+        ui.send_message(WidgetMessage::key_down(
+            self.prompt_text_box,
+            MessageDirection::FromWidget,
+            KeyCode::End,
+        ));
+    }
+
+    fn update_ui_history(&self, ui: &mut UserInterface) {
+        let end = self.history_view_end;
+        let start = end.saturating_sub(self.visible_lines);
+        let mut text = String::new();
+        for line in self.history.range(start..end) {
+            if line.is_input {
+                text.push_str("> ");
+            }
+            text.push_str(&line.text);
+            text.push('\n');
+        }
+        ui.send_message(TextMessage::text(self.history_text, MessageDirection::ToWidget, text));
+
+        // The echo of these comes back as FromWidget but it matches the view so it's ignored.
+        let max = self.history.len().saturating_sub(self.visible_lines);
+        let dir = MessageDirection::ToWidget;
+        ui.send_message(ScrollBarMessage::max_value(self.scroll_bar, dir, max as f32));
+        ui.send_message(ScrollBarMessage::value(self.scroll_bar, dir, start as f32));
+    }
+}
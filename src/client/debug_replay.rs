@@ -0,0 +1,103 @@
+//! Recording debug shapes and texts into a file and replaying them later,
+//! see `d_debug_record` and `d_debug_replay`.
+//!
+//! This is meant for bugs which are hard to reproduce,
+//! especially server-side ones - connect to the server, record what it draws,
+//! then replay it as many times as needed, e.g. in a local game with `d_pause`
+//! so the scene stays static and doesn't add its own debug draws.
+
+use std::{
+    error::Error,
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+};
+
+use crate::{debug::details::DebugShape, prelude::*};
+
+/// Everything drawn in one frame.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DebugFrame {
+    pub frame_num: usize,
+    pub game_time: f32,
+    pub shapes: Vec<DebugShape>,
+    pub texts: Vec<String>,
+}
+
+pub struct DebugRecorder {
+    writer: BufWriter<File>,
+}
+
+impl DebugRecorder {
+    /// Frames are appended if the file already exists.
+    pub fn open(path: &str) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+        })
+    }
+
+    pub fn write(&mut self, frame: &DebugFrame) -> Result<(), Box<dyn Error>> {
+        bincode::serialize_into(&mut self.writer, frame)?;
+        // Flush every frame so the recording survives a crash,
+        // which is often exactly what we're trying to debug.
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+pub struct DebugReplay {
+    frames: Vec<DebugFrame>,
+    /// Index into `frames`, fractional when `d_debug_replay_speed` is not a whole number.
+    pos: f32,
+    /// The last seen value of `d_debug_replay_frame` to detect seeking.
+    seek: Option<usize>,
+}
+
+impl DebugReplay {
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        let data = fs::read(path)?;
+        let mut reader = data.as_slice();
+        let mut frames = Vec::new();
+        while !reader.is_empty() {
+            match bincode::deserialize_from(&mut reader) {
+                Ok(frame) => frames.push(frame),
+                Err(e) => match *e {
+                    // The game might have crashed in the middle of writing the last frame.
+                    bincode::ErrorKind::Io(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                        dbg_logf!("{path} ends with a truncated frame, ignoring it");
+                        break;
+                    }
+                    e => return Err(e.into()),
+                },
+            }
+        }
+        if frames.is_empty() {
+            return Err("no frames recorded".into());
+        }
+        Ok(Self {
+            frames,
+            pos: 0.0,
+            seek: None,
+        })
+    }
+
+    /// The frame to draw this tick.
+    ///
+    /// Seeks if `d_debug_replay_frame` changed, then advances by `d_debug_replay_speed`.
+    pub fn next_frame(&mut self, cvars: &Cvars) -> &DebugFrame {
+        let last = self.frames.len() - 1;
+        if self.seek != Some(cvars.d_debug_replay_frame) {
+            self.seek = Some(cvars.d_debug_replay_frame);
+            let index = self
+                .frames
+                .iter()
+                .position(|frame| frame.frame_num >= cvars.d_debug_replay_frame)
+                .unwrap_or(last);
+            self.pos = index as f32;
+        }
+
+        let index = (self.pos as usize).min(last);
+        self.pos = (self.pos + cvars.d_debug_replay_speed).clamp(0.0, last as f32);
+        &self.frames[index]
+    }
+}
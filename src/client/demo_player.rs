@@ -0,0 +1,190 @@
+//! Watching server demos in the client, see the `replay` command and `common::demo`.
+//!
+//! The demo is played as if it was a connection to the server
+//! which delivers what one player received at the time it was sent.
+//! The client then handles it the same way as during a real game.
+
+use std::io::{self, ErrorKind};
+
+use fyrox::core::instant::Instant;
+
+use crate::{
+    common::{
+        demo::DemoEntry,
+        net::{Connection, NetworkMessage},
+    },
+    prelude::*,
+};
+
+/// A fake connection which plays back the messages one player got while the demo was recorded.
+pub struct DemoConnection {
+    player_id: NetId,
+    /// Game time relative to when the player connected and the message sent at that time.
+    msgs: VecDeque<(f32, ServerMessage)>,
+    /// Real time when the client first asked for messages.
+    start: Option<Instant>,
+}
+
+impl DemoConnection {
+    /// Watch from the view of the first player who connected during the recording.
+    pub fn new(entries: Vec<DemoEntry>) -> io::Result<Self> {
+        let player_id = entries
+            .iter()
+            .find_map(|entry| match entry {
+                DemoEntry::Connect { player_id } => Some(*player_id),
+                _ => None,
+            })
+            .ok_or_else(|| {
+                io::Error::new(ErrorKind::InvalidData, "nobody connected in the demo")
+            })?;
+
+        let mut msgs = VecDeque::new();
+        let mut game_time = 0.0;
+        let mut connect_time = None;
+        // The player's first message is `Version`,
+        // broadcasts before it were sent while it wasn't a client yet.
+        let mut client = false;
+        for entry in entries {
+            match entry {
+                DemoEntry::Frame {
+                    game_time: time, ..
+                } => game_time = time,
+                DemoEntry::Connect { player_id: id } if id == player_id => {
+                    connect_time = Some(game_time);
+                }
+                DemoEntry::Disconnect { player_id: id } if id == player_id => break,
+                DemoEntry::Message { to, msg } => {
+                    let Some(connect_time) = connect_time else {
+                        continue;
+                    };
+                    if to == Some(player_id) {
+                        client = true;
+                    }
+                    if client && (to.is_none() || to == Some(player_id)) {
+                        msgs.push_back((game_time - connect_time, msg));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            player_id,
+            msgs,
+            start: None,
+        })
+    }
+
+    /// Remove and return all messages sent up to `time` after the player connected.
+    fn due(&mut self, time: f32) -> Vec<ServerMessage> {
+        let mut due = Vec::new();
+        while let Some((msg_time, _)) = self.msgs.front() {
+            if *msg_time > time {
+                break;
+            }
+            due.push(self.msgs.pop_front().unwrap().1);
+        }
+        due
+    }
+
+    fn elapsed(&mut self) -> f32 {
+        let start = *self.start.get_or_insert_with(Instant::now);
+        start.elapsed().as_secs_f32()
+    }
+}
+
+impl Connection<ServerMessage> for DemoConnection {
+    /// What the player sent is already part of the recording.
+    fn send(&mut self, _net_msg: &NetworkMessage) -> Result<(), io::Error> {
+        Ok(())
+    }
+
+    fn receive(&mut self, _max_len: usize) -> (Vec<ServerMessage>, bool) {
+        let time = self.elapsed();
+        let msgs = self.due(time);
+        // Like a server disconnecting after the last message.
+        let closed = msgs.is_empty() && self.msgs.is_empty();
+        (msgs, closed)
+    }
+
+    fn receive_one(&mut self, _max_len: usize) -> (Option<ServerMessage>, bool) {
+        let time = self.elapsed();
+        match self.msgs.front() {
+            Some((msg_time, _)) if *msg_time <= time => {
+                (self.msgs.pop_front().map(|(_, msg)| msg), false)
+            }
+            Some(_) => (None, false),
+            None => (None, true),
+        }
+    }
+
+    fn addr(&self) -> String {
+        format!("demo (player {})", self.player_id)
+    }
+
+    fn is_plaintext(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plays_one_players_messages() {
+        let p1 = NetId(1);
+        let p2 = NetId(2);
+        let observe = |player_id| ServerMessage::Observe { player_id };
+        let entries = vec![
+            DemoEntry::Frame {
+                frame_num: 0,
+                game_time: 1.0,
+            },
+            DemoEntry::Message {
+                to: None,
+                msg: observe(p2),
+            },
+            DemoEntry::Connect { player_id: p1 },
+            // Sent before p1's client existed.
+            DemoEntry::Message {
+                to: None,
+                msg: observe(p1),
+            },
+            DemoEntry::Message {
+                to: Some(p1),
+                msg: observe(p1),
+            },
+            DemoEntry::Frame {
+                frame_num: 1,
+                game_time: 1.5,
+            },
+            DemoEntry::Connect { player_id: p2 },
+            DemoEntry::Message {
+                to: Some(p2),
+                msg: observe(p2),
+            },
+            DemoEntry::Message {
+                to: None,
+                msg: observe(p2),
+            },
+            DemoEntry::Frame {
+                frame_num: 2,
+                game_time: 2.0,
+            },
+            DemoEntry::Disconnect { player_id: p1 },
+            DemoEntry::Message {
+                to: None,
+                msg: observe(p2),
+            },
+        ];
+        let mut conn = DemoConnection::new(entries).unwrap();
+        assert_eq!(conn.player_id, p1);
+        assert_eq!(conn.msgs.len(), 2);
+
+        assert_eq!(conn.due(0.0).len(), 1);
+        assert_eq!(conn.due(0.4).len(), 0);
+        assert_eq!(conn.due(0.5).len(), 1);
+        assert!(conn.msgs.is_empty());
+    }
+}
@@ -0,0 +1,109 @@
+//! Rolling statistics of how long frames take, see `d_frame_stats`.
+
+use crate::prelude::*;
+
+/// Frame and update durations of the last `d_frame_stats_samples` frames.
+#[derive(Debug, Clone, Default)]
+pub struct FrameStats {
+    /// Real time between frames.
+    frame: Durations,
+    /// Time spent in `ClientProcess::update`.
+    update: Durations,
+}
+
+/// Durations in milliseconds, oldest first.
+#[derive(Debug, Clone, Default)]
+struct Durations(VecDeque<f32>);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Summary {
+    avg: f32,
+    p99: f32,
+    max: f32,
+}
+
+impl FrameStats {
+    pub fn add(&mut self, samples_max: usize, frame_ms: f32, update_ms: f32) {
+        self.frame.add(samples_max, frame_ms);
+        self.update.add(samples_max, update_ms);
+    }
+
+    /// Forget all samples, e.g. to measure only after some change.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Human readable summary for the debug text.
+    pub fn text(&self) -> String {
+        let frame = self.frame.summary();
+        let update = self.update.summary();
+        let fps = if frame.avg > 0.0 {
+            1000.0 / frame.avg
+        } else {
+            0.0
+        };
+        format!(
+            "FPS {:.1} over {} frames\n\
+            frame  avg {:.2} ms, p99 {:.2} ms, max {:.2} ms\n\
+            update avg {:.2} ms, p99 {:.2} ms, max {:.2} ms\n",
+            fps,
+            self.frame.0.len(),
+            frame.avg,
+            frame.p99,
+            frame.max,
+            update.avg,
+            update.p99,
+            update.max,
+        )
+    }
+}
+
+impl Durations {
+    fn add(&mut self, samples_max: usize, ms: f32) {
+        self.0.push_back(ms);
+        while self.0.len() > samples_max.max(1) {
+            self.0.pop_front();
+        }
+    }
+
+    fn summary(&self) -> Summary {
+        if self.0.is_empty() {
+            return Summary {
+                avg: 0.0,
+                p99: 0.0,
+                max: 0.0,
+            };
+        }
+
+        let mut sorted: Vec<_> = self.0.iter().copied().collect();
+        sorted.sort_by(f32::total_cmp);
+        let len = sorted.len();
+        // Nearest-rank percentile.
+        let p99_index = (len * 99).div_ceil(100) - 1;
+        Summary {
+            avg: sorted.iter().sum::<f32>() / len as f32,
+            p99: sorted[p99_index],
+            max: sorted[len - 1],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary() {
+        let mut durations = Durations::default();
+        assert_eq!(durations.summary().max, 0.0);
+
+        for i in (1..=200).rev() {
+            durations.add(100, i as f32);
+        }
+        // Only the last 100 samples (100 down to 1) remain.
+        let summary = durations.summary();
+        assert_eq!(summary.avg, 50.5);
+        assert_eq!(summary.p99, 99.0);
+        assert_eq!(summary.max, 100.0);
+    }
+}
@@ -2,19 +2,33 @@
 //!
 //! Mainly receiving updates from the server and updating local state.
 
-use std::{io::ErrorKind, thread, time::Duration};
+use std::io::ErrorKind;
 
 use fyrox::{
+    core::futures::future,
+    engine::Engine,
     gui::{message::MessageDirection, text::TextMessage, UiNode, UserInterface},
     renderer::Renderer,
-    scene::camera::{CameraBuilder, Projection, SkyBoxBuilder},
+    resource::texture::Texture,
+    scene::{
+        camera::{CameraBuilder, Projection, SkyBox, SkyBoxBuilder},
+        debug::Line,
+        sound::{DataSource, SoundBufferResource, SoundBuilder, Status},
+    },
 };
 
 use crate::{
+    client::{
+        debug_replay::{DebugFrame, DebugRecorder, DebugReplay},
+        hud,
+        shadow_only::ShadowOnly,
+        trails::{Ribbons, TrailWalls},
+    },
     common::{
         entities::{Player, PlayerState},
-        net::{self, Connection},
-        Input,
+        maps,
+        net::{self, Connection, NetworkMessage},
+        Deg, Input,
     },
     debug::{
         self,
@@ -32,11 +46,141 @@ pub struct ClientGame {
     debug_text: Handle<UiNode>,
     conn: Box<dyn Connection<ServerMessage>>,
     pub camera_handle: Handle<Node>,
+    /// Camera position at the end of the previous tick, used for render interpolation.
+    camera_pos_prev: Vec3,
+    /// Camera position at the end of the current tick.
+    ///
+    /// Render interpolation moves the camera between frames
+    /// so this is used to restore it before running gamelogic.
+    camera_pos: Vec3,
+    /// Smoothed point the third person camera orbits around,
+    /// None when not following a cycle.
+    camera_pivot: Option<Vec3>,
+    /// Smoothed distance of the third person camera from the pivot.
+    camera_arm: f32,
+    /// Smoothed camera rotation at the end of the previous tick, used for render interpolation.
+    camera_rot_prev: UnitQuaternion<f32>,
+    /// Smoothed camera rotation, see `cl_camera_rotation_lag`.
+    camera_rot: Option<UnitQuaternion<f32>>,
+    /// Rotational screen shake from the last tick, applied on top of the final camera rotation.
+    shake_rot: UnitQuaternion<f32>,
+    /// Smoothed zoom factor.
+    zoom: f32,
+    /// Shift is held, the observer camera moves faster, see `cl_observer_speed_fast`.
+    pub observer_fast: bool,
+    /// Ctrl is held, the observer camera moves slower, see `cl_observer_speed_slow`.
+    pub observer_slow: bool,
+    /// The player clicked to move the observer camera to the cycle they're looking at.
+    pub observer_teleport: bool,
+    /// A number key was pressed to move the observer camera to the player
+    /// at this index in `hud::spectator_players`.
+    pub observer_jump: Option<usize>,
+    /// How much the camera shakes, between 0 and 1, see `cl_shake_*`.
+    pub trauma: f32,
+    /// Game time when the local player last damaged someone, for the hit marker.
+    pub time_hit_confirmed: Option<f32>,
+    /// Recent damage dealt by the local player, oldest first.
+    pub damage_numbers: Vec<DamageNumber>,
+    /// Generated on first use, see `snd_hitsound`.
+    hit_sound: Option<SoundBufferResource>,
+    /// Recent hits on the local cycle, oldest first.
+    pub damage_indicators: Vec<DamageIndicator>,
+    /// The first server frame whose checksum didn't match ours,
+    /// None if we're in sync.
+    desync_frame: Option<u64>,
     pub player_handle: Handle<Player>,
     pub delta_yaw: f32,
     pub delta_pitch: f32,
     pub input: Input,
     pub input_prev: Input,
+    /// Sequence number of the last input sent to the server.
+    input_seq: u32,
+    /// Buttons pressed since the last input was sent, see `latch_buttons`.
+    buttons_pressed: u32,
+    /// The server changed the map, `ClientProcess` should load it
+    /// and wait for a new init.
+    pub map_change: bool,
+    /// The vote in progress on the server, if any.
+    pub vote: Option<VoteStatus>,
+    /// We already voted in the current vote.
+    pub voted: bool,
+    /// Players in the lobby, empty when there's no lobby, see `g_lobby`.
+    pub lobby: Vec<LobbyPlayer>,
+    /// The second local player when playing splitscreen, see `cl_splitscreen`.
+    ///
+    /// It shares the scene with the first player so it leaves visuals
+    /// which aren't specific to one camera (trails, debug drawing, ...) to the first.
+    pub secondary: bool,
+    /// Watching the player who killed us, see `cl_killcam`.
+    pub killcam: Option<Killcam>,
+    /// Game time when each other cycle was last visible from the camera, for the minimap.
+    pub seen: FxHashMap<NetId, f32>,
+    /// Kept between frames so `sys_seen` doesn't allocate.
+    seen_traces: TraceBatch,
+    trail_walls: TrailWalls,
+    ribbons: Ribbons,
+    /// The local cycle in first person.
+    shadow_only: ShadowOnly,
+    /// Bytes sent and received since `ClientProcess` last reset it, see `d_graphs`.
+    pub net_bytes: usize,
+    /// Reused for serializing every message so sending doesn't allocate.
+    send_buf: NetworkMessage,
+    /// Summary of `FrameStats` from `ClientProcess` to show in the debug text.
+    pub frame_stats: String,
+    /// See `d_debug_record`.
+    debug_recorder: Option<DebugRecorder>,
+    /// The value of `d_debug_record` when we last tried to open it to detect changes.
+    debug_record_path: String,
+    /// See `d_debug_replay`.
+    debug_replay: Option<DebugReplay>,
+    /// The value of `d_debug_replay` when we last tried to load it to detect changes.
+    debug_replay_path: String,
+}
+
+/// Following the player who killed the local player.
+#[derive(Debug, Clone, Copy)]
+pub struct Killcam {
+    pub killer_handle: Handle<Player>,
+    /// Game time when the killcam ends.
+    pub time_end: f32,
+}
+
+/// Damage dealt by the local player floating over where it happened.
+#[derive(Debug, Clone, Copy)]
+pub struct DamageNumber {
+    pub pos: Vec3,
+    pub amount: f32,
+    /// Game time of the hit.
+    pub time: f32,
+}
+
+/// A short click, there are no sound files yet.
+fn hit_sound_buffer() -> SoundBufferResource {
+    const SAMPLE_RATE: usize = 44100;
+    const DURATION: f32 = 0.06;
+    const FREQUENCY: f32 = 1500.0;
+    let len = (SAMPLE_RATE as f32 * DURATION) as usize;
+    let samples = (0..len)
+        .map(|i| {
+            let t = i as f32 / SAMPLE_RATE as f32;
+            let envelope = 1.0 - t / DURATION;
+            (t * FREQUENCY * 2.0 * PI).sin() * envelope * envelope
+        })
+        .collect();
+    let data_source = DataSource::Raw {
+        sample_rate: SAMPLE_RATE,
+        channel_count: 1,
+        samples,
+    };
+    SoundBufferResource::new_generic(data_source).unwrap()
+}
+
+/// Where damage to the local cycle came from, shown on the HUD.
+#[derive(Debug, Clone, Copy)]
+pub struct DamageIndicator {
+    pub source: Vec3,
+    /// Game time of the hit.
+    pub time: f32,
 }
 
 /// All data necessary to run a frame of client-side game logic in one convenient package.
@@ -56,64 +200,36 @@ impl ClientGame {
         cvars: &Cvars,
         engine: &mut Engine,
         debug_text: Handle<UiNode>,
-        mut conn: Box<dyn Connection<ServerMessage>>,
+        conn: Box<dyn Connection<ServerMessage>>,
         gs: &mut GameState,
-    ) -> Self {
+        init: Init,
+        secondary: bool,
+    ) -> Result<Self, String> {
+        // LATER Load everything in parallel (i.e. with GameState)
+        let skybox = load_skybox(engine, maps::skybox(&gs.map_name)).await;
         let scene = &mut engine.scenes[gs.scene_handle];
 
-        // LATER Load everything in parallel (i.e. with GameState)
-        // LATER Report error if loading fails
-        let front = engine.resource_manager.request("data/skybox/front.png").await.ok();
-        let back = engine.resource_manager.request("data/skybox/back.png").await.ok();
-        let left = engine.resource_manager.request("data/skybox/left.png").await.ok();
-        let right = engine.resource_manager.request("data/skybox/right.png").await.ok();
-        let top = engine.resource_manager.request("data/skybox/top.png").await.ok();
-        let bottom = engine.resource_manager.request("data/skybox/bottom.png").await.ok();
-        let camera_handle = CameraBuilder::new(
+        let mut camera = CameraBuilder::new(
             BaseBuilder::new().with_local_transform(
                 TransformBuilder::new()
                     .with_local_position(cvars.cl_camera_initial_position.into())
                     .build(),
             ),
-        )
-        .with_skybox(
-            SkyBoxBuilder {
-                front,
-                back,
-                left,
-                right,
-                top,
-                bottom,
-            }
-            .build()
-            .unwrap(),
-        )
-        .build(&mut scene.graph);
+        );
+        if let Some(skybox) = skybox {
+            camera = camera.with_skybox(skybox);
+        }
+        let camera_handle = camera.build(&mut scene.graph);
 
         let mut ctx = FrameCtx { cvars, scene, gs };
-
-        let mut init_attempts = 0;
-        let player_handle = loop {
-            init_attempts += 1;
-            let (msg, closed) = conn.receive_one();
-            if closed {
-                panic!("connection closed before init"); // LATER Don't crash
-            }
-            if let Some(msg) = msg {
-                if let ServerMessage::Init(init) = msg {
-                    let local_player_handle = ctx.init(init);
-                    dbg_logf!("init attempts: {}", init_attempts);
-                    break local_player_handle;
-                } else {
-                    panic!("First message wasn't init"); // LATER Don't crash
-                }
+        let player_handle = match ctx.init(init) {
+            Ok(handle) => handle,
+            Err(e) => {
+                ctx.scene.graph.remove_node(camera_handle);
+                return Err(e);
             }
-            if init_attempts % 100 == 0 {
-                dbg_logf!("init attempts: {}", init_attempts);
-            }
-            thread::sleep(Duration::from_millis(10));
         };
-        dbg_logf!("local player_index is {}", player_handle.index());
+        dbg_logf!("local player ID is {}", ctx.gs.players[player_handle].net_id);
 
         if cvars.d_testing {
             let f = false;
@@ -139,25 +255,145 @@ impl ClientGame {
             {}
         }
 
-        Self {
+        let camera_pos: Vec3 = cvars.cl_camera_initial_position.into();
+
+        Ok(Self {
             debug_text,
             conn,
             camera_handle,
+            camera_pos_prev: camera_pos,
+            camera_pos,
+            camera_pivot: None,
+            camera_arm: 0.0,
+            camera_rot_prev: UnitQuaternion::identity(),
+            camera_rot: None,
+            shake_rot: UnitQuaternion::identity(),
+            zoom: 1.0,
+            observer_fast: false,
+            observer_slow: false,
+            observer_teleport: false,
+            observer_jump: None,
+            trauma: 0.0,
+            time_hit_confirmed: None,
+            damage_numbers: Vec::new(),
+            hit_sound: None,
+            damage_indicators: Vec::new(),
+            desync_frame: None,
             player_handle,
             delta_yaw: 0.0,
             delta_pitch: 0.0,
             input: Input::default(),
             input_prev: Input::default(),
-        }
+            input_seq: 0,
+            buttons_pressed: 0,
+            map_change: false,
+            vote: None,
+            lobby: Vec::new(),
+            voted: false,
+            secondary,
+            killcam: None,
+            seen: FxHashMap::default(),
+            seen_traces: TraceBatch::new(),
+            trail_walls: TrailWalls::default(),
+            ribbons: Ribbons::default(),
+            shadow_only: ShadowOnly::default(),
+            net_bytes: 0,
+            send_buf: NetworkMessage::default(),
+            frame_stats: String::new(),
+            debug_recorder: None,
+            debug_record_path: String::new(),
+            debug_replay: None,
+            debug_replay_path: String::new(),
+        })
+    }
+
+    /// Throw away the game but keep the connection to the server, e.g. to change maps.
+    pub fn into_conn(self) -> Box<dyn Connection<ServerMessage>> {
+        self.conn
+    }
+
+    /// Remember which buttons are currently pressed until the next input is sent.
+    ///
+    /// Call after changing buttons in an event handler
+    /// so a press and release between two ticks still reaches the server.
+    pub fn latch_buttons(&mut self) {
+        self.buttons_pressed |= self.input.buttons_to_bits();
     }
 
-    pub fn send_input(&mut self) {
-        self.network_send(ClientMessage::Input(self.input));
+    /// Sent exactly once per tick from `tick_begin_frame`
+    /// so the server's input buffer holds one input per client tick.
+    fn send_input(&mut self) {
+        self.input_seq = self.input_seq.wrapping_add(1);
+        let mut input = NetInput::from(self.input);
+        input.buttons |= self.buttons_pressed;
+        self.buttons_pressed = 0;
+        // Sent every frame so a lost one doesn't matter much, the server logs the gap.
+        self.network_send_unreliable(ClientMessage::Input {
+            seq: self.input_seq,
+            input,
+        });
+    }
+
+    /// Accumulate mouse, touch or gamepad movement,
+    /// it's applied to the angles once per frame.
+    pub fn look(&mut self, cvars: &Cvars, delta: (f64, f64), sens_h: f32, sens_v: f32) {
+        let zoom_factor = if self.input.zoom {
+            cvars.cl_zoom_factor
+        } else {
+            1.0
+        };
+
+        // Subtract, don't add the delta - nalgebra rotations are counterclockwise.
+        let delta_yaw = -delta.0 as f32 * sens_h / zoom_factor;
+        let delta_pitch = delta.1 as f32 * sens_v / zoom_factor;
+
+        self.delta_yaw += delta_yaw;
+        self.delta_pitch += delta_pitch;
+    }
+
+    pub fn request_full_sync(&mut self) {
+        self.network_send(ClientMessage::RequestFullSync);
+    }
+
+    pub fn call_vote(&mut self, kind: VoteKind) {
+        dbg_logf!("calling vote {}", kind);
+        self.network_send(ClientMessage::CallVote(kind));
+    }
+
+    pub fn request_stats(&mut self, player: String) {
+        self.network_send(ClientMessage::RequestStats(player));
+    }
+
+    pub fn vote(&mut self, yes: bool) {
+        self.voted = true;
+        self.network_send(ClientMessage::Vote(yes));
+    }
+
+    pub fn set_ready(&mut self, ready: bool) {
+        self.network_send(ClientMessage::Ready(ready));
+    }
+
+    pub fn set_team(&mut self, team: Option<Team>) {
+        self.network_send(ClientMessage::Team(team));
     }
 
     fn network_send(&mut self, msg: ClientMessage) {
-        let network_msg = net::serialize(msg);
-        let res = self.conn.send(&network_msg);
+        self.network_send_with(msg, true);
+    }
+
+    /// Like `network_send` but the message may be lost, see `Connection::send_unreliable`.
+    fn network_send_unreliable(&mut self, msg: ClientMessage) {
+        self.network_send_with(msg, false);
+    }
+
+    fn network_send_with(&mut self, msg: ClientMessage, reliable: bool) {
+        net::serialize_into(&msg, &mut self.send_buf);
+        self.net_bytes += self.send_buf.bytes.len();
+        let res = if reliable {
+            self.conn.send(&self.send_buf)
+        } else {
+            self.conn.send_unreliable(&self.send_buf)
+        };
         if let Err(ref e) = res {
             if e.kind() == ErrorKind::ConnectionReset {
                 dbg_logf!("Server disconnected, exitting");
@@ -168,38 +404,40 @@ impl ClientGame {
     }
 }
 
-impl FrameCtx<'_> {
-    pub fn init(&mut self, init: Init) -> Handle<Player> {
-        if self.gs.gs_type == GameStateType::Shared {
-            // The player has already been spawned when running server logic.
-            // LATER Deduplicate this line. Maybe don't even return the handle?
-            return self.gs.players.handle_from_index(init.local_player_index);
-        }
-
-        for player_index in init.player_indices {
-            let player = Player::new(None);
-            self.gs.players.spawn_at(player_index, player).unwrap();
-        }
-        let local_player_handle = self.gs.players.handle_from_index(init.local_player_index);
-
-        for PlayerCycle {
-            player_index,
-            cycle_index,
-        } in init.player_cycles
-        {
-            let player_handle = self.gs.players.handle_from_index(player_index);
-            self.spawn_cycle(player_handle, Some(cycle_index));
+/// Load the sides of the skybox in `dir` in parallel.
+///
+/// Missing sides are logged and left empty,
+/// None if the textures can't be combined into a skybox.
+async fn load_skybox(engine: &Engine, dir: &str) -> Option<SkyBox> {
+    let requests = maps::SKYBOX_SIDES.map(|name| {
+        let path = format!("{dir}/{name}.png");
+        let request = engine.resource_manager.request::<Texture>(&path);
+        async move {
+            let texture = request.await;
+            if let Err(e) = &texture {
+                dbg_logf!("failed to load skybox texture {}: {:?}", path, e);
+            }
+            texture.ok()
         }
-
-        for PlayerProjectile {
-            player_index: _,
-            projectile_index: _,
-        } in init.player_projectiles
-        {
-            todo!("init projectiles");
+    });
+    let [front, back, left, right, top, bottom]: [_; 6] =
+        future::join_all(requests).await.try_into().unwrap();
+
+    let res = SkyBoxBuilder {
+        front,
+        back,
+        left,
+        right,
+        top,
+        bottom,
+    }
+    .build();
+    match res {
+        Ok(skybox) => Some(skybox),
+        Err(e) => {
+            dbg_logf!("failed to create skybox from {}: {:?}", dir, e);
+            None
         }
-
-        local_player_handle
     }
 }
 
@@ -214,17 +452,20 @@ impl ClientFrameCtx<'_> {
 
     /// All once-per-frame networking.
     pub fn tick_begin_frame(&mut self) {
-        // LATER Always send key/mouse presses immediately
-        // but maybe rate-limit mouse movement updates
-        // in case some systems update mouse position at a very high rate.
+        self.interpolation_begin_tick();
+
         self.cg.input_prev = self.cg.input;
+        self.cg.input.game_time = self.gs.game_time;
 
-        self.cg.input.yaw.0 += self.cg.delta_yaw; // LATER Normalize to [0, 360°) or something
+        self.cg.input.yaw = Deg(self.cg.input.yaw.0 + self.cg.delta_yaw).normalized();
         self.cg.input.pitch.0 = (self.cg.input.pitch.0 + self.cg.delta_pitch)
             .clamp(self.cvars.m_pitch_min, self.cvars.m_pitch_max);
 
         let delta_time = self.gs.game_time - self.gs.game_time_prev;
-        soft_assert!(delta_time > 0.0);
+        if delta_time <= 0.0 {
+            // This can happen every frame so don't flood the log.
+            dbg_logf_every!(60, "delta_time is not positive: {}", delta_time);
+        }
         self.cg.input.yaw_speed.0 = self.cg.delta_yaw / delta_time;
         self.cg.input.pitch_speed.0 = self.cg.delta_pitch / delta_time;
 
@@ -235,89 +476,210 @@ impl ClientFrameCtx<'_> {
 
         self.scene.drawing_context.clear_lines();
 
-        let (msgs, closed) = self.cg.conn.receive();
+        // Receive one by one so we can stop at a map change
+        // and leave the messages for the new map in the connection.
+        let mut closed = false;
+        loop {
+            let (msg, msg_closed) = self.cg.conn.receive_one(self.cvars.cl_net_max_message_len);
+            closed |= msg_closed;
+            let Some(msg) = msg else {
+                break;
+            };
+            if self.cvars.d_graphs {
+                // The message has already been parsed, this is close enough
+                // and only costs anything when the graphs are visible.
+                let len = bincode::serialized_size(&msg).unwrap_or_default();
+                self.cg.net_bytes += len as usize;
+            }
 
-        if self.gs.gs_type == GameStateType::Shared {
-            // Shared mode ignores all messages that update game state
-            // since it's updated when running server logic.
-            return; // LATER Early return in the middle of a function is ugly
-        }
+            if let ServerMessage::ChangeMap { map } = &msg {
+                dbg_logf!("server changed map to {}", map);
+                self.cg.map_change = true;
+                break;
+            }
+
+            // Hit feedback is client state so even shared mode needs it.
+            if let ServerMessage::Damage {
+                cycle_id,
+                damage,
+                source,
+            } = msg
+            {
+                self.hit_feedback(cycle_id, damage, source.into());
+                continue;
+            }
+            if let ServerMessage::DamageDealt {
+                attacker,
+                victim,
+                amount,
+                pos,
+            } = msg
+            {
+                self.hit_confirmed(attacker, victim, amount, pos.into());
+                continue;
+            }
+
+            // The killcam is client state so even shared mode needs it.
+            // The message is also handled below to update game state.
+            if let ServerMessage::Death {
+                player_id,
+                killer_id: Some(killer_id),
+            } = msg
+            {
+                self.start_killcam(player_id, killer_id);
+            }
+
+            // Votes are client state so even shared mode needs them.
+            if let ServerMessage::VoteStatus(status) = msg {
+                match &status {
+                    Some(status) => {
+                        dbg_logf!("vote {}: {} yes, {} no", status.kind, status.yes, status.no);
+                    }
+                    None => self.cg.voted = false,
+                }
+                self.cg.vote = status;
+                continue;
+            }
+            if let ServerMessage::Lobby(lobby) = msg {
+                self.cg.lobby = lobby;
+                continue;
+            }
+            // Answers to the `stats` console command, local games have them too.
+            if let ServerMessage::Stats { query, stats } = msg {
+                match stats {
+                    Some(stats) => {
+                        dbg_logf!("stats of {:?}:", stats.name);
+                        dbg_logf!("    matches: {}", stats.matches);
+                        dbg_logf!("    kills: {}, deaths: {}", stats.kills, stats.deaths);
+                        dbg_logf!("    damage dealt: {:.0}", stats.damage);
+                        dbg_logf!("    distance driven: {:.1} km", stats.distance / 1000.0);
+                        dbg_logf!("    time played: {:.1} h", stats.time_played / 3600.0);
+                    }
+                    None => dbg_logf!("no stats for player {:?}", query),
+                }
+                continue;
+            }
+
+            if self.gs.gs_type == GameStateType::Shared {
+                // Shared mode ignores all messages that update game state
+                // since it's updated when running server logic.
+                continue;
+            }
 
-        for msg in msgs {
             match msg {
-                ServerMessage::Version(_) => todo!(),
+                ServerMessage::Version(version, _) => {
+                    // Normally only sent before init.
+                    dbg_logf!("server version: {}", version);
+                }
                 ServerMessage::Init(_) => {
                     // LATER Make this type safe? Init part of handshake?
                     panic!("Received unexpected init")
                 }
-                ServerMessage::AddPlayer(AddPlayer { player_index, name }) => {
-                    let player = Player::new(None);
-                    self.gs.players.spawn_at(player_index, player).unwrap();
-                    dbg_logd!("player {} added", name);
+                ServerMessage::ChangeMap { .. }
+                | ServerMessage::Damage { .. }
+                | ServerMessage::DamageDealt { .. }
+                | ServerMessage::VoteStatus(_)
+                | ServerMessage::Lobby(_)
+                | ServerMessage::Stats { .. } => unreachable!(),
+                ServerMessage::MapChunk(chunk) => {
+                    // We only request maps while connecting.
+                    dbg_logf!("unexpected map chunk {:?}", chunk);
                 }
-                ServerMessage::RemovePlayer { player_index } => {
-                    let player_handle = self.gs.players.handle_from_index(player_index);
-                    self.ctx().free_player(player_handle);
+                ServerMessage::Spawn(spawn) => {
+                    self.ctx().spawn_entity(spawn);
                 }
-                ServerMessage::Observe { player_index } => {
-                    self.gs.players.at_mut(player_index).unwrap().state = PlayerState::Observing;
-                    dbg_logf!("player {} is now observing", player_index);
+                ServerMessage::Despawn { kind, net_id } => {
+                    self.ctx().despawn_entity(kind, net_id);
+                }
+                ServerMessage::Trail {
+                    cycle_id,
+                    removed,
+                    added,
+                } => {
+                    self.ctx().update_trail(cycle_id, removed, added);
+                }
+                ServerMessage::Observe { player_id } => {
+                    let Some(player_handle) = self.ctx().lookup_player(player_id) else {
+                        continue;
+                    };
+                    self.gs.players[player_handle].state = PlayerState::Observing;
+                    dbg_logf!("player {} is now observing", player_id);
                 }
                 ServerMessage::Spectate {
-                    player_index,
-                    spectatee_index,
+                    player_id,
+                    spectatee_id,
                 } => {
-                    let spectatee_handle = self.gs.players.handle_from_index(spectatee_index);
-                    self.gs.players.at_mut(player_index).unwrap().state =
+                    let (Some(player_handle), Some(spectatee_handle)) = (
+                        self.ctx().lookup_player(player_id),
+                        self.ctx().lookup_player(spectatee_id),
+                    ) else {
+                        continue;
+                    };
+                    self.gs.players[player_handle].state =
                         PlayerState::Spectating { spectatee_handle };
-                    dbg_logf!(
-                        "player {} is now spectating player {}",
-                        player_index,
-                        spectatee_index
-                    );
+                    dbg_logf!("player {} is now spectating player {}", player_id, spectatee_id);
                 }
-                ServerMessage::Join { player_index } => {
-                    self.gs.players.at_mut(player_index).unwrap().state = PlayerState::Playing;
-                    dbg_logf!("player {} is now playing", player_index);
+                ServerMessage::Join { player_id } => {
+                    let Some(player_handle) = self.ctx().lookup_player(player_id) else {
+                        continue;
+                    };
+                    self.gs.players[player_handle].state = PlayerState::Playing;
+                    dbg_logf!("player {} is now playing", player_id);
                 }
-                ServerMessage::SpawnCycle(PlayerCycle {
-                    player_index,
-                    cycle_index,
-                }) => {
-                    let player_handle = self.gs.players.handle_from_index(player_index);
-                    self.ctx().spawn_cycle(player_handle, Some(cycle_index));
+                ServerMessage::PickupTaken {
+                    pickup_index,
+                    cycle_id,
+                } => {
+                    let pickup_handle = self.gs.pickups.handle_from_index(pickup_index);
+                    match self.gs.cycle_ids.get(cycle_id) {
+                        Some(cycle_handle) => self.ctx().apply_pickup(pickup_handle, cycle_handle),
+                        // The cycle is too far to be replicated to us, see `sv_interest_radius`.
+                        None => {
+                            self.gs.pickups[pickup_handle].time_taken = Some(self.gs.game_time);
+                        }
+                    }
                 }
-                ServerMessage::DespawnCycle { cycle_index } => {
-                    dbg_logd!(cycle_index);
-                    todo!("despawn cycle");
+                ServerMessage::Death {
+                    player_id,
+                    killer_id,
+                } => {
+                    let Some(player_handle) = self.ctx().lookup_player(player_id) else {
+                        continue;
+                    };
+                    let player = &mut self.gs.players[player_handle];
+                    player.deaths += 1;
+                    player.time_died = self.gs.game_time;
+                    if let Some(killer_id) = killer_id {
+                        // The killer might have disconnected in the meantime.
+                        if let Some(killer_handle) = self.gs.player_ids.get(killer_id) {
+                            self.gs.players[killer_handle].kills += 1;
+                        }
+                        dbg_logf!("player {} killed player {}", killer_id, player_id);
+                    } else {
+                        dbg_logf!("player {} died", player_id);
+                    }
+                }
+                ServerMessage::MatchState(state) => {
+                    if state == MatchState::Playing && self.gs.match_state.is_warmup() {
+                        self.ctx().start_match();
+                    }
+                    self.gs.match_state = state;
+                    dbg_logf!("match state: {:?}", state);
                 }
                 ServerMessage::Update(Update {
-                    player_inputs,
-                    cycle_physics,
+                    frame_num,
+                    checksum,
+                    entities,
                     debug_texts,
                     debug_texts_world,
                     debug_shapes,
                 }) => {
-                    for PlayerInput {
-                        player_index,
-                        input,
-                    } in player_inputs
-                    {
-                        self.gs.players.at_mut(player_index).unwrap().input = input;
+                    for update in entities {
+                        self.ctx().update_entity(update);
                     }
 
-                    for CyclePhysics {
-                        cycle_index,
-                        translation,
-                        rotation,
-                        velocity,
-                    } in cycle_physics
-                    {
-                        let cycle = self.gs.cycles.at_mut(cycle_index).unwrap();
-                        let body = self.scene.graph[cycle.body_handle].as_rigid_body_mut();
-                        body.local_transform_mut().set_position(translation);
-                        body.local_transform_mut().set_rotation(rotation);
-                        body.set_lin_vel(velocity);
+                    if let Some(checksum) = checksum {
+                        self.check_desync(frame_num, checksum);
                     }
 
                     DEBUG_TEXTS.with_borrow_mut(|texts| {
@@ -330,13 +692,128 @@ impl ClientFrameCtx<'_> {
                         shapes.extend(debug_shapes);
                     });
                 }
+                ServerMessage::FullSync(sync) => {
+                    dbg_logf!("full sync at server frame {}", sync.frame_num);
+                    let player_id = self.gs.players[self.cg.player_handle].net_id;
+                    self.ctx().apply_full_sync(sync);
+                    self.cg.player_handle = self.gs.player_ids.get(player_id).unwrap();
+                }
             }
         }
 
+        if self.gs.gs_type == GameStateType::Shared {
+            return; // LATER Early return in the middle of a function is ugly
+        }
+
         if closed {
             dbg_logf!("Server closed the connection, exitting"); // LATER Don't exit
             std::process::exit(0);
         }
+
+        if let Some(frame) = self.cg.desync_frame {
+            dbg_textf!("WARNING desynced from server since frame {}", frame);
+        }
+    }
+
+    /// Compare our state after applying an update with the server's.
+    ///
+    /// Called right after applying the update so both sides hash the state of `frame_num`.
+    /// Only the first differing frame is reported so the log doesn't get spammed.
+    fn check_desync(&mut self, frame_num: u64, checksum: u64) {
+        let ours = self.ctx().net_checksum();
+        match (ours == checksum, self.cg.desync_frame) {
+            (false, None) => {
+                dbg_logf!(
+                    "WARNING desynced from server at frame {}: checksum {:016x}, server {:016x}",
+                    frame_num,
+                    ours,
+                    checksum
+                );
+                self.cg.desync_frame = Some(frame_num);
+                if self.cvars.cl_net_resync {
+                    self.cg.request_full_sync();
+                }
+            }
+            (true, Some(frame)) => {
+                dbg_logf!(
+                    "back in sync with server at frame {} (desynced since {})",
+                    frame_num,
+                    frame
+                );
+                self.cg.desync_frame = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Undo render interpolation so gamelogic sees the real state
+    /// and remember the state for interpolating after this tick.
+    fn interpolation_begin_tick(&mut self) {
+        self.scene.graph[self.cg.camera_handle]
+            .local_transform_mut()
+            .set_position(self.cg.camera_pos);
+
+        for cycle in self.gs.cycles.iter_mut() {
+            let transform = self.scene.graph[cycle.body_handle].local_transform();
+            cycle.pos_prev = **transform.position();
+            cycle.rot_prev = **transform.rotation();
+
+            let visual = self.scene.graph[cycle.visual_handle].local_transform_mut();
+            visual.set_position(Vec3::zeros());
+            visual.set_rotation(UnitQuaternion::identity());
+        }
+    }
+
+    /// Render entities between their state after the previous and current tick.
+    ///
+    /// `alpha` is how far real time is between the current tick and the next one.
+    /// This renders up to one tick in the past but avoids judder
+    /// when the framerate doesn't match the tickrate.
+    pub fn interpolate(&mut self, alpha: f32) {
+        if !self.cvars.cl_interpolation {
+            return;
+        }
+
+        let camera_pos = self.cg.camera_pos_prev.lerp(&self.cg.camera_pos, alpha);
+        let camera = self.scene.graph[self.cg.camera_handle].local_transform_mut();
+        camera.set_position(camera_pos);
+        // Interpolate the smoothed rotation, not the input,
+        // so `cl_camera_rotation_lag` and the killcam still apply.
+        if let Some(camera_rot) = self.cg.camera_rot {
+            let rot = self.cg.camera_rot_prev.slerp(&camera_rot, alpha);
+            camera.set_rotation(rot * self.cg.shake_rot);
+        }
+
+        for cycle in self.gs.cycles.iter() {
+            // The body is a root node so local is the same as global.
+            let transform = self.scene.graph[cycle.body_handle].local_transform();
+            let pos = **transform.position();
+            let rot = **transform.rotation();
+            let pos_interp = cycle.pos_prev.lerp(&pos, alpha);
+            let rot_interp = cycle.rot_prev.slerp(&rot, alpha);
+
+            // The visual is a child of the body so express the difference in the body's space.
+            let visual = self.scene.graph[cycle.visual_handle].local_transform_mut();
+            visual.set_position(rot.inverse() * (pos_interp - pos));
+            visual.set_rotation(rot.inverse() * rot_interp);
+        }
+
+        // Rendering uses global transforms which are only updated during engine updates.
+        self.scene.graph.update_hierarchical_data();
+    }
+
+    /// Roll the camera while wall riding so the wall feels more like the floor.
+    fn camera_tilt(&self) -> UnitQuaternion<f32> {
+        let player = &self.gs.players[self.cg.player_handle];
+        let cycle_handle = match player.cycle_handle {
+            Some(handle) if player.state == PlayerState::Playing => handle,
+            _ => return UnitQuaternion::identity(),
+        };
+        let up = self.gs.cycles[cycle_handle].up;
+        match UnitQuaternion::rotation_between(&UP, &up) {
+            Some(rot) => rot.powf(self.cvars.cl_camera_wall_ride_tilt),
+            None => UnitQuaternion::identity(),
+        }
     }
 
     pub fn tick_before_physics(&mut self, dt: f32) {
@@ -348,14 +825,33 @@ impl ClientFrameCtx<'_> {
             self.cg.network_send(ClientMessage::Observe);
         }
 
-        let player_cycle_handle = self.gs.players[self.cg.player_handle].cycle_handle.unwrap();
-        let player_body_handle = self.gs.cycles[player_cycle_handle].body_handle;
-        let player_cycle_pos = **self.scene.graph[player_body_handle].local_transform().position();
+        // None while dead and waiting to respawn.
+        let player_cycle_handle = self.gs.players[self.cg.player_handle].cycle_handle;
+        let player_cycle_pos = player_cycle_handle.map(|cycle_handle| {
+            let body_handle = self.gs.cycles[cycle_handle].body_handle;
+            **self.scene.graph[body_handle].local_transform().position()
+        });
+        let player_cycle_speed = player_cycle_handle.map_or(0.0, |cycle_handle| {
+            let body_handle = self.gs.cycles[cycle_handle].body_handle;
+            self.scene.graph[body_handle].as_rigid_body().lin_vel().norm()
+        });
+        let first_person = self.cvars.cl_camera_mode == CameraMode::FirstPerson;
+        let killcam_view = self.killcam_view(player_cycle_handle.is_some());
 
+        let tilt = self.camera_tilt();
         let camera = &mut self.scene.graph[self.cg.camera_handle];
 
         // Camera turning
-        let cam_rot = self.cg.input.look_rotation();
+        let input_rot = match killcam_view {
+            Some((_, killer_rot)) => killer_rot,
+            None => tilt * self.cg.input.look_rotation(),
+        };
+        let cam_rot = match self.cg.camera_rot {
+            Some(rot) => rot.slerp(&input_rot, smoothing(dt, self.cvars.cl_camera_rotation_lag)),
+            None => input_rot,
+        };
+        self.cg.camera_rot_prev = self.cg.camera_rot.unwrap_or(cam_rot);
+        self.cg.camera_rot = Some(cam_rot);
         camera.local_transform_mut().set_rotation(cam_rot);
 
         dbg_rot!(v!(0 7 0), cam_rot);
@@ -363,59 +859,138 @@ impl ClientFrameCtx<'_> {
 
         // Camera movement
         let camera_pos_old = **camera.local_transform().position();
-        let trace_opts = TraceOptions::filter(!IG_ENTITIES).with_end(true);
+        let trace_opts = TraceOptions::filter(CollisionLayer::MAP_ONLY).with_end(true);
+        // Only a click in this tick counts, not one from before switching to observing.
+        let teleport = std::mem::take(&mut self.cg.observer_teleport);
+        let jump = self.cg.observer_jump.take();
         if ps == PlayerState::Observing {
             let forward = camera.forward_vec_normed();
             let left = camera.left_vec_normed();
             let up = camera.up_vec_normed();
+            let mut speed = self.cvars.cl_camera_speed;
+            if self.cg.observer_fast {
+                speed *= self.cvars.cl_observer_speed_fast;
+            }
+            if self.cg.observer_slow {
+                speed *= self.cvars.cl_observer_speed_slow;
+            }
             let mut delta = Vec3::zeros();
             if self.cg.input.forward {
-                delta += forward * dt * self.cvars.cl_camera_speed;
+                delta += forward * dt * speed;
             }
             if self.cg.input.backward {
-                delta += -forward * dt * self.cvars.cl_camera_speed;
+                delta += -forward * dt * speed;
             }
             if self.cg.input.left {
-                delta += left * dt * self.cvars.cl_camera_speed;
+                delta += left * dt * speed;
             }
             if self.cg.input.right {
-                delta += -left * dt * self.cvars.cl_camera_speed;
+                delta += -left * dt * speed;
             }
             if self.cg.input.up {
-                delta += up * dt * self.cvars.cl_camera_speed;
+                delta += up * dt * speed;
             }
             if self.cg.input.down {
-                delta += -up * dt * self.cvars.cl_camera_speed;
+                delta += -up * dt * speed;
             }
 
-            let hits = self.ctx().trace_line(camera_pos_old, delta, trace_opts);
-            let new_pos = hits[0].position.coords;
+            self.cg.camera_pivot = None;
+            let target = if teleport {
+                self.observer_pick_cycle(camera_pos_old, forward)
+            } else if let Some(index) = jump {
+                self.observer_jump_target(index)
+            } else {
+                None
+            };
+            let new_pos = if let Some(target_pos) = target {
+                // Stop a bit behind the cycle so it's in view.
+                let back = -forward * self.cvars.cl_observer_teleport_distance;
+                if self.cvars.cl_observer_noclip {
+                    target_pos + back
+                } else {
+                    self.ctx().trace_line(target_pos, back, trace_opts)[0].position.coords
+                }
+            } else if self.cvars.cl_observer_noclip {
+                camera_pos_old + delta
+            } else {
+                let hits = self.ctx().trace_line(camera_pos_old, delta, trace_opts);
+                hits[0].position.coords
+            };
             self.scene.graph[self.cg.camera_handle]
                 .local_transform_mut()
                 .set_position(new_pos);
         } else if ps == PlayerState::Playing {
-            let up = UP * self.cvars.cl_camera_3rd_person_up;
-            let back = cam_rot * BACK * self.cvars.cl_camera_3rd_person_back;
-
-            let hits = self.ctx().trace_line(player_cycle_pos, up, trace_opts);
-            let hits = self.ctx().trace_line(hits[0].position, back, trace_opts);
-            let new_pos = hits[0].position.coords;
-            self.scene.graph[self.cg.camera_handle]
-                .local_transform_mut()
-                .set_position(new_pos);
+            // While dead, the camera stays where the cycle was destroyed.
+            if let Some(player_cycle_pos) = player_cycle_pos {
+                let new_pos = if first_person {
+                    self.cg.camera_pivot = None;
+                    player_cycle_pos + UP * self.cvars.cl_camera_1st_person_up
+                } else {
+                    let up = UP * self.cvars.cl_camera_3rd_person_up;
+                    let hits = self.ctx().trace_line(player_cycle_pos, up, trace_opts);
+                    let pivot_target = hits[0].position.coords;
+                    let pivot = match self.cg.camera_pivot {
+                        Some(pivot) => pivot
+                            .lerp(&pivot_target, smoothing(dt, self.cvars.cl_camera_position_lag)),
+                        None => pivot_target,
+                    };
+                    self.cg.camera_pivot = Some(pivot);
+
+                    // Like a spring arm - walls push the camera in immediately,
+                    // then it slowly moves back out.
+                    let back = cam_rot * BACK * self.cvars.cl_camera_3rd_person_back;
+                    let radius = self.cvars.cl_camera_3rd_person_radius;
+                    let free = self.ctx().trace_sphere(pivot, back, radius, trace_opts);
+                    self.cg.camera_arm = if free < self.cg.camera_arm {
+                        free
+                    } else {
+                        let t = smoothing(dt, self.cvars.cl_camera_arm_lag);
+                        self.cg.camera_arm + (free - self.cg.camera_arm) * t
+                    };
+                    pivot + cam_rot * BACK * self.cg.camera_arm
+                };
+                self.scene.graph[self.cg.camera_handle]
+                    .local_transform_mut()
+                    .set_position(new_pos);
+            } else {
+                self.cg.camera_pivot = None;
+                self.cg.camera_arm = 0.0;
+                if let Some((killcam_pos, _)) = killcam_view {
+                    self.scene.graph[self.cg.camera_handle]
+                        .local_transform_mut()
+                        .set_position(killcam_pos);
+                }
+            }
         } else {
             unreachable!(); // LATER Spectating
         }
 
+        self.sys_camera_shake(dt, player_cycle_pos.is_some());
+
+        // The local cycle would block the view in first person but it should still cast a shadow.
+        // LATER Splitscreen - materials are per scene, not per camera.
+        if !self.cg.secondary {
+            let hidden = match player_cycle_handle {
+                Some(cycle_handle) if first_person => self.gs.cycles[cycle_handle].visual_handle,
+                _ => Handle::NONE,
+            };
+            self.cg.shadow_only.update(&mut self.scene.graph, hidden);
+        }
+
         // Camera zoom
         let camera = self.scene.graph[self.cg.camera_handle].as_camera_mut();
         if let Projection::Perspective(perspective) = camera.projection_mut() {
-            let zoom_factor = if self.cg.input.zoom {
+            let zoom_target = if self.cg.input.zoom {
                 self.cvars.cl_zoom_factor
             } else {
                 1.0
             };
-            perspective.fov = self.cvars.cl_camera_fov.to_radians() / zoom_factor;
+            let t = smoothing(dt, self.cvars.cl_zoom_lag);
+            self.cg.zoom += (zoom_target - self.cg.zoom) * t;
+            let zoom_factor = self.cg.zoom;
+            let speed_fraction = (player_cycle_speed / self.cvars.g_wheel_max_speed).min(1.0);
+            let fov = self.cvars.cl_camera_fov + self.cvars.cl_camera_fov_kick * speed_fraction;
+            perspective.fov = fov.to_radians() / zoom_factor;
             perspective.z_near = self.cvars.cl_camera_z_near;
             perspective.z_far = self.cvars.cl_camera_z_far;
         } else {
@@ -538,7 +1113,275 @@ impl ClientFrameCtx<'_> {
         dbg_arrow!(v!(50 5 7), pitch_rot2 * yaw_rot * FORWARD);
     }
 
+    /// Spin available pickups and hide taken ones.
+    fn sys_pickup_visuals(&mut self) {
+        let angle = (self.gs.game_time * self.cvars.cl_pickup_rotation_speed).to_radians();
+        let rot = UnitQuaternion::from_axis_angle(&UP_AXIS, angle);
+        for pickup in &self.gs.pickups {
+            let available = pickup.is_available(self.cvars, self.gs.game_time);
+            let node = &mut self.scene.graph[pickup.node_handle];
+            node.set_visibility(available);
+            node.local_transform_mut().set_rotation(rot);
+        }
+    }
+
+    /// Position of the cycle closest to where the observer is looking,
+    /// if any is within `cl_observer_pick_angle`.
+    fn observer_pick_cycle(&self, camera_pos: Vec3, forward: Vec3) -> Option<Vec3> {
+        let max_angle = self.cvars.cl_observer_pick_angle.to_radians();
+        self.gs
+            .cycles
+            .iter()
+            .map(|cycle| **self.scene.graph[cycle.body_handle].local_transform().position())
+            .map(|pos| (pos, (pos - camera_pos).angle(&forward)))
+            .filter(|&(_, angle)| angle <= max_angle)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(pos, _)| pos)
+    }
+
+    /// Position of the cycle of the player with the given number in the spectator panel.
+    fn observer_jump_target(&self, index: usize) -> Option<Vec3> {
+        let player_handle = *hud::spectator_players(self.gs).get(index)?;
+        let cycle_handle = self.gs.players[player_handle].cycle_handle?;
+        let body_handle = self.gs.cycles[cycle_handle].body_handle;
+        Some(**self.scene.graph[body_handle].local_transform().position())
+    }
+
+    /// Colored markers over cycles matching the rows of the spectator panel.
+    fn sys_spectator_markers(&mut self) {
+        let state = self.gs.players[self.cg.player_handle].state;
+        if !self.cvars.hud_spectator_panel || state == PlayerState::Playing {
+            return;
+        }
+        for cycle in &self.gs.cycles {
+            let color = hud::player_color(self.gs.players[cycle.player_handle].net_id);
+            let pos = self.scene.graph[cycle.body_handle].global_position() + UP * 1.5;
+            let lines = [
+                (pos, pos + UP * 0.5),
+                (pos + LEFT * 0.25 + UP * 0.25, pos - LEFT * 0.25 + UP * 0.25),
+                (pos + FORWARD * 0.25 + UP * 0.25, pos - FORWARD * 0.25 + UP * 0.25),
+            ];
+            for (begin, end) in lines {
+                self.scene.drawing_context.add_line(Line { begin, end, color });
+            }
+        }
+    }
+
+    fn sys_damage_numbers(&mut self) {
+        let duration = self.cvars.hud_damage_numbers_duration;
+        let game_time = self.gs.game_time;
+        self.cg.damage_numbers.retain(|number| number.time + duration > game_time);
+    }
+
+    /// Remember which cycles the local player can see.
+    fn sys_seen(&mut self) {
+        let camera_pos = self.cg.camera_pos;
+        let trace_opts = TraceOptions::filter(CollisionLayer::MAP_ONLY);
+        let scene = &*self.scene;
+        let rays = self.gs.cycles.iter().map(|cycle| {
+            let pos = scene.graph[cycle.body_handle].global_position();
+            (Point3::from(camera_pos), pos - camera_pos)
+        });
+        trace_lines(self.cvars, scene, rays, trace_opts, &mut self.cg.seen_traces);
+        for (cycle, hits) in self.gs.cycles.iter().zip(self.cg.seen_traces.iter()) {
+            if hits.is_empty() {
+                self.cg.seen.insert(cycle.net_id, self.gs.game_time);
+            }
+        }
+        let ids = &self.gs.cycle_ids;
+        self.cg.seen.retain(|&net_id, _| ids.get(net_id).is_some());
+    }
+
+    /// Show where the playable area ends in overtime.
+    ///
+    /// The map's height varies so the rings are drawn around the camera's height.
+    fn sys_overtime_boundary(&mut self) {
+        let Some(radius) = self.gs.overtime_radius() else {
+            return;
+        };
+        const SEGMENTS: usize = 64;
+        const POST_EVERY: usize = 4;
+        let camera_y = self.cg.camera_pos.y;
+        let point = |i: usize, y: f32| {
+            let angle = i as f32 / SEGMENTS as f32 * std::f32::consts::TAU;
+            v!(angle.cos() * radius, y, angle.sin() * radius)
+        };
+        for i in 0..SEGMENTS {
+            for y in [camera_y - 2.0, camera_y, camera_y + 2.0] {
+                self.scene.drawing_context.add_line(Line {
+                    begin: point(i, y),
+                    end: point(i + 1, y),
+                    color: RED,
+                });
+            }
+            if i % POST_EVERY == 0 {
+                self.scene.drawing_context.add_line(Line {
+                    begin: point(i, camera_y - 10.0),
+                    end: point(i, camera_y + 10.0),
+                    color: RED,
+                });
+            }
+        }
+    }
+
+    /// Watch the killer if the local player was killed by someone else.
+    fn start_killcam(&mut self, player_id: NetId, killer_id: NetId) {
+        if !self.cvars.cl_killcam
+            || player_id != self.gs.players[self.cg.player_handle].net_id
+            || killer_id == player_id
+        {
+            return;
+        }
+        let Some(killer_handle) = self.gs.player_ids.get(killer_id) else {
+            return;
+        };
+        self.cg.killcam = Some(Killcam {
+            killer_handle,
+            time_end: self.gs.game_time + self.cvars.cl_killcam_duration,
+        });
+    }
+
+    /// Camera position and rotation behind the killer's cycle, None when not in a killcam.
+    ///
+    /// Ends the killcam once it times out, the local player respawns
+    /// or the killer no longer has a cycle to watch.
+    fn killcam_view(&mut self, respawned: bool) -> Option<(Vec3, UnitQuaternion<f32>)> {
+        let killcam = self.cg.killcam?;
+        let killer = self.gs.players.try_borrow(killcam.killer_handle);
+        let killer_cycle = killer.and_then(|killer| killer.cycle_handle);
+        let (Some(killer), Some(killer_cycle), false) = (killer, killer_cycle, respawned) else {
+            self.cg.killcam = None;
+            return None;
+        };
+        if self.gs.game_time >= killcam.time_end {
+            self.cg.killcam = None;
+            return None;
+        }
+
+        let rot = killer.input.look_rotation();
+        let body_handle = self.gs.cycles[killer_cycle].body_handle;
+        let killer_pos = **self.scene.graph[body_handle].local_transform().position();
+        let trace_opts = TraceOptions::filter(CollisionLayer::MAP_ONLY).with_end(true);
+        let up = UP * self.cvars.cl_camera_3rd_person_up;
+        let hits = self.ctx().trace_line(killer_pos, up, trace_opts);
+        let pivot = hits[0].position.coords;
+        let back = rot * BACK * self.cvars.cl_camera_3rd_person_back;
+        let hits = self.ctx().trace_line(pivot, back, trace_opts);
+        Some((hits[0].position.coords, rot))
+    }
+
+    /// Shake the camera if the local cycle was hit.
+    fn hit_feedback(&mut self, cycle_id: NetId, damage: f32, source: Vec3) {
+        let local_cycle = self.gs.players[self.cg.player_handle].cycle_handle;
+        if local_cycle.is_none() || local_cycle != self.gs.cycle_ids.get(cycle_id) {
+            return;
+        }
+        self.cg.trauma = (self.cg.trauma + damage * self.cvars.cl_shake_damage).min(1.0);
+        self.cg.damage_indicators.push(DamageIndicator {
+            source,
+            time: self.gs.game_time,
+        });
+    }
+
+    /// Flash the crosshair, show how much damage the local player dealt and play the hit sound.
+    fn hit_confirmed(&mut self, attacker: NetId, victim: NetId, amount: f32, pos: Vec3) {
+        if attacker != self.gs.players[self.cg.player_handle].net_id {
+            soft_unreachable!("damage dealt by player {} sent to us", attacker);
+            return;
+        }
+        self.cg.time_hit_confirmed = Some(self.gs.game_time);
+        if self.cvars.hud_damage_numbers {
+            self.cg.damage_numbers.push(DamageNumber {
+                pos,
+                amount,
+                time: self.gs.game_time,
+            });
+        }
+        if self.cvars.snd_hitsound {
+            let buffer = self.cg.hit_sound.get_or_insert_with(hit_sound_buffer).clone();
+            SoundBuilder::new(BaseBuilder::new())
+                .with_buffer(Some(buffer))
+                .with_status(Status::Playing)
+                .with_play_once(true)
+                .with_gain(self.cvars.snd_hitsound_volume)
+                // Not positional, it's feedback for the player, not a sound in the world.
+                .with_spatial_blend_factor(0.0)
+                .build(&mut self.scene.graph);
+        }
+        dbg_logf!("hit player {} for {:.1}", victim, amount);
+    }
+
+    /// Add trauma from nearby impacts and shake the camera according to it.
+    ///
+    /// Must run after the camera is positioned for this tick.
+    /// Moving the camera is only safe when it follows a cycle,
+    /// otherwise its position is relative to the previous tick and the shake would accumulate.
+    fn sys_camera_shake(&mut self, dt: f32, shake_position: bool) {
+        let camera = &self.scene.graph[self.cg.camera_handle];
+        let camera_pos = **camera.local_transform().position();
+        for impact in &self.gs.impacts {
+            let dist = (impact - camera_pos).norm();
+            let closeness = 1.0 - dist / self.cvars.cl_shake_impact_radius;
+            if closeness > 0.0 {
+                self.cg.trauma += self.cvars.cl_shake_impact * closeness;
+            }
+        }
+        self.cg.trauma = (self.cg.trauma - self.cvars.cl_shake_decay * dt).clamp(0.0, 1.0);
+
+        let duration = self.cvars.hud_damage_indicator_duration;
+        let game_time = self.gs.game_time;
+        self.cg
+            .damage_indicators
+            .retain(|indicator| indicator.time + duration > game_time);
+
+        if self.cg.trauma == 0.0 {
+            self.cg.shake_rot = UnitQuaternion::identity();
+            return;
+        }
+
+        // Sines with unrelated frequencies look random enough
+        // and unlike an RNG they're smooth.
+        let t = self.gs.game_time * self.cvars.cl_shake_frequency;
+        let noise = |seed: f32| (t * (1.0 + 0.37 * seed) + 10.0 * seed).sin();
+        let shake = self.cg.trauma * self.cg.trauma;
+
+        let camera = &mut self.scene.graph[self.cg.camera_handle];
+        if shake_position {
+            let offset = v!(noise(1.0), noise(2.0), noise(3.0));
+            let pos = camera_pos + offset * self.cvars.cl_shake_max_offset * shake;
+            camera.local_transform_mut().set_position(pos);
+        }
+        let max_angle = self.cvars.cl_shake_max_angle.to_radians() * shake;
+        let shake_rot = UnitQuaternion::from_euler_angles(
+            noise(4.0) * max_angle,
+            noise(5.0) * max_angle,
+            noise(6.0) * max_angle,
+        );
+        // `interpolate` applies it again after it computes the rendered rotation.
+        self.cg.shake_rot = shake_rot;
+        let rot = **camera.local_transform().rotation();
+        camera.local_transform_mut().set_rotation(rot * shake_rot);
+    }
+
     pub fn tick_after_physics(&mut self, dt: f32) {
+        self.cg.camera_pos_prev = self.cg.camera_pos;
+        self.cg.camera_pos = **self.scene.graph[self.cg.camera_handle].local_transform().position();
+
+        if self.cg.secondary {
+            self.sys_seen();
+            self.sys_damage_numbers();
+            return;
+        }
+
+        self.sys_pickup_visuals();
+        self.cg.trail_walls.update(self.cvars, self.gs, self.scene);
+        let camera_pos = self.cg.camera_pos;
+        self.cg.ribbons.update(self.cvars, self.gs, self.scene, camera_pos);
+        self.sys_overtime_boundary();
+        self.sys_spectator_markers();
+        self.sys_seen();
+        self.sys_damage_numbers();
+
         if self.cvars.d_physics_extra_sync {
             self.scene.graph.update_hierarchical_data();
         }
@@ -562,6 +1405,13 @@ impl ClientFrameCtx<'_> {
             dbg_line!(body_pos, body_pos + UP, 0.0);
         }
 
+        self.debug_record();
+        self.debug_replay_load();
+        let replay_frame = match &mut self.cg.debug_replay {
+            Some(replay) => Some(replay.next_frame(self.cvars)),
+            None => None,
+        };
+
         // Deduplicate and draw debug shapes
         DEBUG_SHAPES.with_borrow_mut(|shapes| {
             // Sometimes debug shapes overlap and only the last one gets drawn.
@@ -576,6 +1426,13 @@ impl ClientFrameCtx<'_> {
                 }
                 shape.time -= dt;
             }
+            if let Some(frame) = replay_frame {
+                if self.cvars.d_draw {
+                    for shape in &frame.shapes {
+                        shape.to_lines(self.cvars, &mut lines);
+                    }
+                }
+            }
             for (_, line) in lines.0 {
                 self.scene.drawing_context.add_line(line);
             }
@@ -592,12 +1449,30 @@ impl ClientFrameCtx<'_> {
                 debug_string.push('\n');
                 debug_string.push('\n');
             }
+            if !self.cg.frame_stats.is_empty() {
+                debug_string.push_str(&self.cg.frame_stats);
+                debug_string.push('\n');
+            }
+            if self.cvars.d_profiler {
+                debug_string.push_str(&debug::profiler::report());
+                debug_string.push('\n');
+            }
             DEBUG_TEXTS.with_borrow(|texts| {
                 for text in texts.iter() {
                     debug_string.push_str(text);
                     debug_string.push('\n');
                 }
             });
+            if let Some(frame) = replay_frame {
+                debug_string.push_str(&format!(
+                    "replay frame {} time {:.04}\n",
+                    frame.frame_num, frame.game_time
+                ));
+                for text in &frame.texts {
+                    debug_string.push_str(text);
+                    debug_string.push('\n');
+                }
+            }
         }
 
         // Draw per-frame debug string.
@@ -618,3 +1493,68 @@ impl ClientFrameCtx<'_> {
         debug::clear_expired();
     }
 }
+
+impl ClientFrameCtx<'_> {
+    /// Open or close the recording when `d_debug_record` changes
+    /// and write this frame's debug shapes and texts into it.
+    fn debug_record(&mut self) {
+        if self.cvars.d_debug_record != self.cg.debug_record_path {
+            self.cg.debug_record_path = self.cvars.d_debug_record.clone();
+            self.cg.debug_recorder = None;
+            let path = &self.cg.debug_record_path;
+            if !path.is_empty() {
+                match DebugRecorder::open(path) {
+                    Ok(recorder) => {
+                        dbg_logf!("recording debug draws into {}", path);
+                        self.cg.debug_recorder = Some(recorder);
+                    }
+                    Err(e) => dbg_logf!("failed to open {} for recording: {}", path, e),
+                }
+            }
+        }
+
+        let Some(recorder) = &mut self.cg.debug_recorder else {
+            return;
+        };
+        let frame = DebugFrame {
+            frame_num: self.gs.frame_num,
+            game_time: self.gs.game_time,
+            shapes: DEBUG_SHAPES.with_borrow(|shapes| shapes.clone()),
+            texts: DEBUG_TEXTS.with_borrow(|texts| texts.clone()),
+        };
+        if let Err(e) = recorder.write(&frame) {
+            dbg_logf!("failed to record debug draws, stopping: {}", e);
+            self.cg.debug_recorder = None;
+        }
+    }
+
+    /// Load or unload the replay when `d_debug_replay` changes.
+    fn debug_replay_load(&mut self) {
+        if self.cvars.d_debug_replay == self.cg.debug_replay_path {
+            return;
+        }
+        self.cg.debug_replay_path = self.cvars.d_debug_replay.clone();
+        self.cg.debug_replay = None;
+        let path = &self.cg.debug_replay_path;
+        if path.is_empty() {
+            return;
+        }
+        match DebugReplay::load(path) {
+            Ok(replay) => {
+                dbg_logf!("replaying debug draws from {}", path);
+                self.cg.debug_replay = Some(replay);
+            }
+            Err(e) => dbg_logf!("failed to load debug draws from {}: {}", path, e),
+        }
+    }
+}
+
+/// How far to move towards a target this tick when smoothing exponentially
+/// with time constant `lag` in seconds, 0 means no smoothing.
+fn smoothing(dt: f32, lag: f32) -> f32 {
+    if lag <= 0.0 {
+        1.0
+    } else {
+        1.0 - (-dt / lag).exp()
+    }
+}
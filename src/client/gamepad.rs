@@ -0,0 +1,120 @@
+//! Gamepad input, see `cl_gamepad_*`.
+//!
+//! Gamepads set the same `Input` fields as the keyboard and mouse.
+//! When playing splitscreen, they control the second player
+//! so the first one can keep using the keyboard.
+
+use gilrs::{Axis, Button, EventType, GamepadId, Gilrs};
+
+use crate::prelude::*;
+
+pub struct Gamepads {
+    /// None if gamepads aren't supported on this platform.
+    gilrs: Option<Gilrs>,
+    /// The gamepad whose sticks are read, the one used most recently.
+    active: Option<GamepadId>,
+    /// Forward and backward according to the left stick last frame.
+    /// Cycles steer where the player looks so the stick's X axis is unused.
+    ///
+    /// Input is only changed when these change so the stick resting in the middle
+    /// doesn't override the keyboard.
+    directions: (bool, bool),
+}
+
+impl Gamepads {
+    pub fn new() -> Self {
+        let gilrs = match Gilrs::new() {
+            Ok(gilrs) => Some(gilrs),
+            Err(e) => {
+                dbg_logf!("gamepads are not available: {}", e);
+                None
+            }
+        };
+        Self {
+            gilrs,
+            active: None,
+            directions: (false, false),
+        }
+    }
+
+    /// Apply gamepad events since the last frame to `input`.
+    ///
+    /// Events are consumed even without `input` so they don't pile up.
+    /// Returns whether any input buttons changed
+    /// and how far the right stick turned the view in degrees.
+    pub fn update(
+        &mut self,
+        cvars: &Cvars,
+        mut input: Option<&mut Input>,
+        frame_time: f32,
+    ) -> (bool, Vector2<f32>) {
+        let Some(gilrs) = &mut self.gilrs else {
+            return (false, Vector2::zeros());
+        };
+
+        let mut changed = false;
+        while let Some(event) = gilrs.next_event() {
+            self.active = Some(event.id);
+            let (button, pressed) = match event.event {
+                EventType::ButtonPressed(button, _) => (button, true),
+                EventType::ButtonReleased(button, _) => (button, false),
+                EventType::Connected => {
+                    dbg_logf!("gamepad connected: {}", gilrs.gamepad(event.id).name());
+                    continue;
+                }
+                EventType::Disconnected => {
+                    dbg_logf!("gamepad disconnected: {}", gilrs.gamepad(event.id).name());
+                    self.active = None;
+                    continue;
+                }
+                _ => continue,
+            };
+            let Some(input) = input.as_deref_mut() else {
+                continue;
+            };
+            let Some(field) = button_input(button, input) else {
+                continue;
+            };
+            changed |= *field != pressed;
+            *field = pressed;
+        }
+
+        let (Some(input), Some(id)) = (input, self.active) else {
+            return (changed, Vector2::zeros());
+        };
+        let gamepad = gilrs.gamepad(id);
+        let deadzone = cvars.cl_gamepad_deadzone;
+
+        // Stick Y points up, unlike the screen's.
+        let y = gamepad.value(Axis::LeftStickY);
+        let directions = (y > deadzone, y < -deadzone);
+        if directions != self.directions {
+            self.directions = directions;
+            (input.forward, input.backward) = directions;
+            changed = true;
+        }
+
+        let stick =
+            Vector2::new(gamepad.value(Axis::RightStickX), -gamepad.value(Axis::RightStickY));
+        let look = if stick.norm() > deadzone {
+            stick * frame_time
+        } else {
+            Vector2::zeros()
+        };
+        (changed, look)
+    }
+}
+
+fn button_input(button: Button, input: &mut Input) -> Option<&mut bool> {
+    match button {
+        Button::RightTrigger2 => Some(&mut input.fire1),
+        Button::LeftTrigger2 => Some(&mut input.fire2),
+        Button::RightTrigger => Some(&mut input.boost),
+        Button::LeftTrigger => Some(&mut input.zoom),
+        Button::South => Some(&mut input.up),
+        Button::East => Some(&mut input.down),
+        Button::West => Some(&mut input.reload),
+        Button::Select => Some(&mut input.score),
+        _ => None,
+    }
+}
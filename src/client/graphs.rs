@@ -0,0 +1,188 @@
+//! Scrolling line graphs of frame timings and network usage, see `d_graphs`.
+
+use fyrox::gui::{
+    border::BorderBuilder,
+    brush::Brush,
+    message::MessageDirection,
+    stack_panel::StackPanelBuilder,
+    text::{TextBuilder, TextMessage},
+    vector_image::Primitive,
+    widget::{WidgetBuilder, WidgetMessage},
+    HorizontalAlignment, Thickness, UiNode, UserInterface, VerticalAlignment,
+};
+
+use crate::{
+    client::vector_shapes::{Placement, Shape, VectorShapes},
+    prelude::*,
+};
+
+const GRAPH_WIDTH: f32 = 300.0;
+const GRAPH_HEIGHT: f32 = 60.0;
+const MARGIN: f32 = 10.0;
+
+/// One value for each graph, measured over a single rendered frame.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GraphSample {
+    /// Real time between this frame and the previous one.
+    pub frame_ms: f32,
+    /// Time spent in gamelogic ticks (excluding physics) this frame.
+    pub tick_ms: f32,
+    /// Time spent updating the engine (mostly physics) this frame.
+    pub physics_ms: f32,
+    /// Bytes sent and received this frame.
+    pub net_bytes: usize,
+}
+
+pub struct PerfGraphs {
+    root: Handle<UiNode>,
+    graphs: [Graph; 4],
+    visible: bool,
+}
+
+struct Graph {
+    name: &'static str,
+    unit: &'static str,
+    color: Color,
+    /// Oldest first.
+    samples: VecDeque<f32>,
+    /// Holds the label and the lines.
+    container: Handle<UiNode>,
+    label: Handle<UiNode>,
+    lines: VectorShapes,
+}
+
+impl PerfGraphs {
+    pub fn new(ui: &mut UserInterface) -> Self {
+        let ctx = &mut ui.build_ctx();
+
+        let graph_defs = [
+            ("frame", "ms", Color::WHITE),
+            ("ticks", "ms", Color::GREEN),
+            ("physics", "ms", Color::opaque(100, 150, 255)),
+            ("net", "kB/s", Color::ORANGE),
+        ];
+        let graphs = graph_defs.map(|(name, unit, color)| {
+            let label = TextBuilder::new(
+                WidgetBuilder::new()
+                    .with_horizontal_alignment(HorizontalAlignment::Left)
+                    .with_vertical_alignment(VerticalAlignment::Top)
+                    .with_margin(Thickness::uniform(2.0))
+                    .with_foreground(Brush::Solid(color)),
+            )
+            .with_shadow(true)
+            .build(ctx);
+            let container = BorderBuilder::new(
+                WidgetBuilder::new()
+                    .with_width(GRAPH_WIDTH)
+                    .with_height(GRAPH_HEIGHT)
+                    .with_margin(Thickness::bottom(4.0))
+                    .with_background(Brush::Solid(Color::from_rgba(0, 0, 0, 120)))
+                    .with_hit_test_visibility(false)
+                    .with_child(label),
+            )
+            .with_stroke_thickness(Thickness::uniform(0.0))
+            .build(ctx);
+            Graph {
+                name,
+                unit,
+                color,
+                samples: VecDeque::new(),
+                container,
+                label,
+                lines: VectorShapes::default(),
+            }
+        });
+
+        let root = StackPanelBuilder::new(
+            WidgetBuilder::new()
+                .with_visibility(false)
+                .with_hit_test_visibility(false)
+                .with_children(graphs.iter().map(|graph| graph.container)),
+        )
+        .build(ctx);
+
+        Self {
+            root,
+            graphs,
+            visible: false,
+        }
+    }
+
+    pub fn resized(&self, ui: &mut UserInterface, width: f32, _height: f32) {
+        let pos = Vector2::new(width - GRAPH_WIDTH - MARGIN, MARGIN);
+        ui.send_message(WidgetMessage::desired_position(
+            self.root,
+            MessageDirection::ToWidget,
+            pos,
+        ));
+    }
+
+    /// Add the sample and redraw the graphs.
+    pub fn update(&mut self, ui: &mut UserInterface, cvars: &Cvars, sample: GraphSample) {
+        let visible = cvars.d_draw && cvars.d_graphs;
+        if visible != self.visible {
+            self.visible = visible;
+            ui.send_message(WidgetMessage::visibility(
+                self.root,
+                MessageDirection::ToWidget,
+                visible,
+            ));
+        }
+        if !visible {
+            for graph in &mut self.graphs {
+                graph.samples.clear();
+            }
+            return;
+        }
+
+        let net_kbps = if sample.frame_ms > 0.0 {
+            sample.net_bytes as f32 / 1024.0 / (sample.frame_ms / 1000.0)
+        } else {
+            0.0
+        };
+        let values = [sample.frame_ms, sample.tick_ms, sample.physics_ms, net_kbps];
+        for (graph, value) in self.graphs.iter_mut().zip(values) {
+            graph.samples.push_back(value);
+            while graph.samples.len() > cvars.d_graphs_samples.max(2) {
+                graph.samples.pop_front();
+            }
+            graph.redraw(ui, cvars.d_graphs_samples.max(2));
+        }
+    }
+}
+
+impl Graph {
+    fn redraw(&mut self, ui: &mut UserInterface, samples_max: usize) {
+        let current = self.samples.back().copied().unwrap_or_default();
+        let max = self.samples.iter().copied().fold(0.0, f32::max);
+        let text = format!("{} {:.1} {} (max {:.1})", self.name, current, self.unit, max);
+        ui.send_message(TextMessage::text(self.label, MessageDirection::ToWidget, text));
+
+        // Leave some space at the top for the label.
+        let scale = (GRAPH_HEIGHT - 16.0) / max.max(f32::EPSILON);
+        // Newest samples are on the right, the graph fills up from there.
+        let x_step = GRAPH_WIDTH / (samples_max - 1) as f32;
+        let x_start = GRAPH_WIDTH - (self.samples.len() - 1) as f32 * x_step;
+        let point = |i: usize, value: f32| {
+            Vector2::new(x_start + i as f32 * x_step, GRAPH_HEIGHT - value * scale)
+        };
+        let primitives = self
+            .samples
+            .iter()
+            .zip(self.samples.iter().skip(1))
+            .enumerate()
+            .map(|(i, (&begin, &end))| Primitive::Line {
+                begin: point(i, begin),
+                end: point(i + 1, end),
+                thickness: 1.0,
+            })
+            .collect();
+
+        let shape = Shape {
+            color: self.color,
+            primitives,
+            placement: Placement::Fill,
+        };
+        self.lines.update(ui, self.container, vec![shape]);
+    }
+}
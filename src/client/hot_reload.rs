@@ -0,0 +1,75 @@
+//! Reloading models and textures when their files change, see `d_hot_reload_assets`.
+//!
+//! Resources are reloaded in place so everything using them sees the new data.
+//! Textures change immediately, models are only used when instantiating
+//! so the new version shows up on newly spawned cycles.
+//! Maps are instantiated once when loaded so changing the current map's file restarts it.
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+};
+
+use fyrox::asset::manager::ResourceManager;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::prelude::*;
+
+/// Watches `data/` for changes to files which are already loaded.
+pub struct AssetWatcher {
+    /// Stops watching when dropped.
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    /// Events have absolute paths but resources are requested by paths relative to this.
+    root: PathBuf,
+}
+
+impl AssetWatcher {
+    pub fn new() -> notify::Result<Self> {
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        watcher.watch(Path::new("data"), RecursiveMode::Recursive)?;
+        let root = env::current_dir()
+            .and_then(|dir| dir.canonicalize())
+            .map_err(notify::Error::io)?;
+        dbg_logf!("watching data/ for changes");
+        Ok(Self {
+            _watcher: watcher,
+            events,
+            root,
+        })
+    }
+
+    /// Reload resources whose files changed since the last call.
+    ///
+    /// Returns their paths relative to the game's directory, e.g. `data/arena/arena.rgs`.
+    pub fn update(&mut self, resource_manager: &ResourceManager) -> Vec<PathBuf> {
+        // Editors often write a file in several steps, only reload it once.
+        let mut changed = FxHashSet::default();
+        while let Ok(res) = self.events.try_recv() {
+            match res {
+                Ok(event) => {
+                    if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                        changed.extend(event.paths);
+                    }
+                }
+                Err(e) => dbg_logf!("asset watcher error: {}", e),
+            }
+        }
+
+        let mut reloaded = Vec::new();
+        let mut state = resource_manager.state();
+        for path in changed {
+            let path = path.strip_prefix(&self.root).unwrap_or(&path).to_owned();
+            // Files which haven't been loaded yet will be loaded from disk when first needed.
+            let Some(resource) = state.find(&path).cloned() else {
+                continue;
+            };
+            dbg_logf!("reloading {}", path.display());
+            state.reload_resource(resource);
+            reloaded.push(path);
+        }
+        reloaded
+    }
+}
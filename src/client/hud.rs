@@ -0,0 +1,717 @@
+//! Heads-up display - in-game information drawn over the 3D scene.
+
+use fyrox::{
+    core::math::Rect,
+    gui::{
+        border::BorderBuilder,
+        brush::Brush,
+        message::MessageDirection,
+        stack_panel::StackPanelBuilder,
+        text::{TextBuilder, TextMessage},
+        vector_image::Primitive,
+        widget::{WidgetBuilder, WidgetMessage},
+        BuildContext, HorizontalAlignment, Thickness, UiNode, UserInterface, VerticalAlignment,
+    },
+    scene::camera::Camera,
+};
+
+use crate::{
+    client::{
+        game::{DamageIndicator, DamageNumber},
+        vector_shapes::{Placement, Shape, VectorShapes},
+    },
+    common::entities::{Cycle, Player, PlayerState},
+    debug,
+    prelude::*,
+};
+
+pub struct Hud {
+    /// Window-sized container so children can be aligned to the edges of the screen.
+    root: Handle<UiNode>,
+    /// One for each local player, the second is only used in splitscreen.
+    players: [PlayerHud; 2],
+    scoreboard: Handle<UiNode>,
+    vote: Handle<UiNode>,
+    /// Warmup and the countdown to the match start.
+    match_state: Handle<UiNode>,
+    motd: Handle<UiNode>,
+    /// Player overview while observing, one text per row so each can have its own color.
+    spectator_rows: Vec<Handle<UiNode>>,
+    /// Failed soft asserts and unwraps.
+    errors: Handle<UiNode>,
+    /// Top-level texts so they can be positioned anywhere on the screen.
+    damage_numbers: Vec<Handle<UiNode>>,
+    /// The crosshair and spread circle.
+    crosshair: VectorShapes,
+}
+
+/// The parts of the HUD about one local player, drawn over their viewport.
+struct PlayerHud {
+    /// Viewport-sized container so children can be aligned to the edges of the player's view.
+    root: Handle<UiNode>,
+    boost: Handle<UiNode>,
+    health: Handle<UiNode>,
+    ammo: Handle<UiNode>,
+    killcam: Handle<UiNode>,
+    /// Damage indicators in front of, behind, left and right of the crosshair.
+    damage: [Handle<UiNode>; 4],
+}
+
+/// One local player's crosshair, splitscreen has one for each player.
+#[derive(Debug, Clone, Copy)]
+pub struct Crosshair {
+    /// Middle of the player's view relative to the middle of the screen in pixels.
+    pub offset: Vector2<f32>,
+    /// The player recently damaged someone.
+    pub hit: bool,
+    /// Radius in pixels of the circle showing projectile spread.
+    pub spread: Option<f32>,
+}
+
+/// Max damage numbers on the screen at once, older ones are hidden.
+const DAMAGE_NUMBERS: usize = 16;
+
+/// Max players listed in the spectator panel.
+const SPECTATOR_ROWS: usize = 16;
+
+/// Colors of the markers over cycles and rows in the spectator panel.
+const PLAYER_COLORS: [Color; 8] = [RED, BLUE2, GREEN, YELLOW, MAGENTA, CYAN, ORANGE, WHITE];
+
+/// Color of things whose owner is gone, e.g. projectiles of a player who disconnected.
+const NEUTRAL_COLOR: Color = Color::opaque(128, 128, 128);
+
+/// Each player's color, stable for as long as they're connected.
+pub fn player_color(net_id: NetId) -> Color {
+    PLAYER_COLORS[net_id.0 as usize % PLAYER_COLORS.len()]
+}
+
+/// Like `player_color` but the player might no longer exist.
+pub fn owner_color(gs: &GameState, player_handle: Handle<Player>) -> Color {
+    gs.players
+        .try_borrow(player_handle)
+        .map_or(NEUTRAL_COLOR, |player| player_color(player.net_id))
+}
+
+/// Players in the order of the spectator panel, the number keys refer to this.
+///
+/// Ordered by ID, not score, so the numbers don't change during the match.
+pub fn spectator_players(gs: &GameState) -> Vec<Handle<Player>> {
+    let mut players: Vec<_> = gs.players.pair_iter().collect();
+    players.sort_by_key(|(_, player)| player.net_id);
+    players.into_iter().map(|(handle, _)| handle).collect()
+}
+
+fn v2(x: f32, y: f32) -> Vector2<f32> {
+    Vector2::new(x, y)
+}
+
+/// Convert a color cvar with components from 0 to 1.
+fn cvar_color(color: CVec3, alpha: u8) -> Color {
+    let color = Vec3::from(color) * 255.0;
+    Color::from_rgba(color.x as u8, color.y as u8, color.z as u8, alpha)
+}
+
+/// Order of `PlayerHud::damage`.
+const DAMAGE_FRONT: usize = 0;
+const DAMAGE_BACK: usize = 1;
+const DAMAGE_LEFT: usize = 2;
+const DAMAGE_RIGHT: usize = 3;
+
+impl PlayerHud {
+    fn new(ctx: &mut BuildContext) -> Self {
+        let boost = TextBuilder::new(
+            WidgetBuilder::new()
+                .with_horizontal_alignment(HorizontalAlignment::Center)
+                .with_vertical_alignment(VerticalAlignment::Bottom)
+                .with_margin(Thickness::bottom(20.0)),
+        )
+        .with_shadow(true)
+        .build(ctx);
+
+        let health = TextBuilder::new(
+            WidgetBuilder::new()
+                .with_horizontal_alignment(HorizontalAlignment::Right)
+                .with_vertical_alignment(VerticalAlignment::Bottom)
+                .with_margin(Thickness {
+                    left: 0.0,
+                    top: 0.0,
+                    right: 20.0,
+                    bottom: 45.0,
+                }),
+        )
+        .with_shadow(true)
+        .build(ctx);
+
+        let ammo = TextBuilder::new(
+            WidgetBuilder::new()
+                .with_horizontal_alignment(HorizontalAlignment::Right)
+                .with_vertical_alignment(VerticalAlignment::Bottom)
+                .with_margin(Thickness {
+                    left: 0.0,
+                    top: 0.0,
+                    right: 20.0,
+                    bottom: 20.0,
+                }),
+        )
+        .with_shadow(true)
+        .build(ctx);
+
+        let killcam = TextBuilder::new(
+            WidgetBuilder::new()
+                .with_horizontal_alignment(HorizontalAlignment::Center)
+                .with_vertical_alignment(VerticalAlignment::Bottom)
+                .with_margin(Thickness::bottom(120.0)),
+        )
+        .with_font_size(24.0)
+        .with_shadow(true)
+        .build(ctx);
+
+        let damage_texts = ["^", "v", "<", ">"];
+        let damage_margins = [
+            Thickness::bottom(120.0),
+            Thickness::top(120.0),
+            Thickness::right(120.0),
+            Thickness::left(120.0),
+        ];
+        let damage = [0, 1, 2, 3].map(|i| {
+            TextBuilder::new(
+                WidgetBuilder::new()
+                    .with_horizontal_alignment(HorizontalAlignment::Center)
+                    .with_vertical_alignment(VerticalAlignment::Center)
+                    .with_margin(damage_margins[i])
+                    .with_foreground(Brush::Solid(Color::RED))
+                    .with_visibility(false),
+            )
+            .with_text(damage_texts[i])
+            .with_font_size(32.0)
+            .with_shadow(true)
+            .build(ctx)
+        });
+
+        // Positioned over the viewport by `Hud::update_viewports`.
+        let root = BorderBuilder::new(
+            WidgetBuilder::new()
+                .with_horizontal_alignment(HorizontalAlignment::Left)
+                .with_vertical_alignment(VerticalAlignment::Top)
+                .with_background(Brush::Solid(Color::TRANSPARENT))
+                .with_hit_test_visibility(false)
+                .with_child(boost)
+                .with_child(health)
+                .with_child(ammo)
+                .with_child(killcam)
+                .with_children(damage),
+        )
+        .with_stroke_thickness(Thickness::uniform(0.0))
+        .build(ctx);
+
+        Self {
+            root,
+            boost,
+            health,
+            ammo,
+            killcam,
+            damage,
+        }
+    }
+}
+
+impl Hud {
+    pub fn new(ui: &mut UserInterface) -> Self {
+        let ctx = &mut ui.build_ctx();
+
+        let players = [PlayerHud::new(ctx), PlayerHud::new(ctx)];
+
+        let scoreboard = TextBuilder::new(
+            WidgetBuilder::new()
+                .with_horizontal_alignment(HorizontalAlignment::Center)
+                .with_vertical_alignment(VerticalAlignment::Center),
+        )
+        .with_shadow(true)
+        .build(ctx);
+
+        let vote = TextBuilder::new(
+            WidgetBuilder::new()
+                .with_horizontal_alignment(HorizontalAlignment::Center)
+                .with_vertical_alignment(VerticalAlignment::Top)
+                .with_margin(Thickness::top(20.0)),
+        )
+        .with_shadow(true)
+        .build(ctx);
+
+        let match_state = TextBuilder::new(
+            WidgetBuilder::new()
+                .with_horizontal_alignment(HorizontalAlignment::Center)
+                .with_vertical_alignment(VerticalAlignment::Top)
+                .with_margin(Thickness::top(80.0)),
+        )
+        .with_font_size(24.0)
+        .with_shadow(true)
+        .build(ctx);
+
+        let motd = TextBuilder::new(
+            WidgetBuilder::new()
+                .with_horizontal_alignment(HorizontalAlignment::Center)
+                .with_vertical_alignment(VerticalAlignment::Top)
+                .with_margin(Thickness::top(120.0)),
+        )
+        .with_horizontal_text_alignment(HorizontalAlignment::Center)
+        .with_font_size(20.0)
+        .with_shadow(true)
+        .build(ctx);
+
+        let spectator_rows: Vec<_> = (0..SPECTATOR_ROWS)
+            .map(|_| TextBuilder::new(WidgetBuilder::new()).with_shadow(true).build(ctx))
+            .collect();
+        let spectator_panel = StackPanelBuilder::new(
+            WidgetBuilder::new()
+                .with_horizontal_alignment(HorizontalAlignment::Left)
+                .with_vertical_alignment(VerticalAlignment::Center)
+                .with_margin(Thickness::left(20.0))
+                .with_children(spectator_rows.iter().copied()),
+        )
+        .build(ctx);
+
+        let damage_numbers = (0..DAMAGE_NUMBERS)
+            .map(|_| {
+                TextBuilder::new(
+                    WidgetBuilder::new().with_visibility(false).with_hit_test_visibility(false),
+                )
+                .with_font_size(20.0)
+                .with_shadow(true)
+                .build(ctx)
+            })
+            .collect();
+
+        let errors = TextBuilder::new(
+            WidgetBuilder::new()
+                .with_horizontal_alignment(HorizontalAlignment::Left)
+                .with_vertical_alignment(VerticalAlignment::Bottom)
+                .with_margin(Thickness {
+                    left: 20.0,
+                    top: 0.0,
+                    right: 0.0,
+                    bottom: 20.0,
+                })
+                .with_foreground(Brush::Solid(Color::RED)),
+        )
+        .with_shadow(true)
+        .build(ctx);
+
+        let root = BorderBuilder::new(
+            WidgetBuilder::new()
+                .with_background(Brush::Solid(Color::TRANSPARENT))
+                .with_hit_test_visibility(false)
+                .with_children(players.iter().map(|player| player.root))
+                .with_child(scoreboard)
+                .with_child(vote)
+                .with_child(match_state)
+                .with_child(motd)
+                .with_child(spectator_panel)
+                .with_child(errors),
+        )
+        .with_stroke_thickness(Thickness::uniform(0.0))
+        .build(ctx);
+
+        Self {
+            root,
+            players,
+            scoreboard,
+            vote,
+            match_state,
+            motd,
+            spectator_rows,
+            errors,
+            damage_numbers,
+            crosshair: VectorShapes::default(),
+        }
+    }
+
+    pub fn resized(&self, ui: &mut UserInterface, width: f32, height: f32) {
+        ui.send_message(WidgetMessage::width(self.root, MessageDirection::ToWidget, width));
+        ui.send_message(WidgetMessage::height(self.root, MessageDirection::ToWidget, height));
+    }
+
+    /// Place each local player's part of the HUD over their viewport, None hides it.
+    pub fn update_viewports(&self, ui: &mut UserInterface, viewports: [Option<Rect<f32>>; 2]) {
+        let screen_size = ui.screen_size();
+        for (player, viewport) in self.players.iter().zip(viewports) {
+            let root = player.root;
+            ui.send_message(WidgetMessage::visibility(
+                root,
+                MessageDirection::ToWidget,
+                viewport.is_some(),
+            ));
+            let Some(viewport) = viewport else {
+                continue;
+            };
+            let pos = viewport.position.component_mul(&screen_size);
+            let size = viewport.size.component_mul(&screen_size);
+            let margin = Thickness {
+                left: pos.x,
+                top: pos.y,
+                right: 0.0,
+                bottom: 0.0,
+            };
+            ui.send_message(WidgetMessage::margin(root, MessageDirection::ToWidget, margin));
+            ui.send_message(WidgetMessage::width(root, MessageDirection::ToWidget, size.x));
+            ui.send_message(WidgetMessage::height(root, MessageDirection::ToWidget, size.y));
+        }
+    }
+
+    /// `player` is the index of the local player,
+    /// `cycle` is None when they have no cycle.
+    pub fn update(
+        &self,
+        ui: &mut UserInterface,
+        cvars: &Cvars,
+        player: usize,
+        cycle: Option<&Cycle>,
+    ) {
+        let player = &self.players[player];
+        let text = match cycle {
+            Some(cycle) if cvars.hud_boost => {
+                let percent = cycle.boost_energy / cvars.g_boost_energy_max * 100.0;
+                format!("Boost {percent:.0}%")
+            }
+            _ => String::new(),
+        };
+        ui.send_message(TextMessage::text(player.boost, MessageDirection::ToWidget, text));
+
+        let text = match cycle {
+            Some(cycle) if cvars.hud_health => format!("Health {:.0}", cycle.health),
+            _ => String::new(),
+        };
+        ui.send_message(TextMessage::text(player.health, MessageDirection::ToWidget, text));
+
+        let text = match cycle {
+            Some(cycle) if cvars.hud_ammo => format!("Ammo {}", cycle.ammo),
+            _ => String::new(),
+        };
+        ui.send_message(TextMessage::text(player.ammo, MessageDirection::ToWidget, text));
+    }
+
+    /// Show kills and deaths of all players, best first.
+    pub fn update_scoreboard(&self, ui: &mut UserInterface, gs: &GameState, show: bool) {
+        let mut text = String::new();
+        if show {
+            if gs.match_state.is_over() {
+                text.push_str("Match over\n\n");
+            }
+            let mut players: Vec<_> = gs.players.iter().collect();
+            players.sort_by_key(|player| std::cmp::Reverse(player.kills));
+            for player in players {
+                // The ID is what `callvote kick` expects.
+                text.push_str(&format!(
+                    "Player {}    {} kills    {} deaths\n",
+                    player.net_id, player.kills, player.deaths
+                ));
+            }
+        }
+        ui.send_message(TextMessage::text(self.scoreboard, MessageDirection::ToWidget, text));
+    }
+
+    /// Show the vote in progress and the binds to vote, None when there's no vote.
+    pub fn update_vote(
+        &self,
+        ui: &mut UserInterface,
+        cvars: &Cvars,
+        game_time: f32,
+        vote: Option<&VoteStatus>,
+        voted: bool,
+    ) {
+        let text = match vote {
+            Some(vote) if cvars.hud_vote => {
+                let remaining = (vote.time_end - game_time).max(0.0);
+                let mut text = format!(
+                    "Vote: {} ({:.0}s)\nYes {}    No {}",
+                    vote.kind, remaining, vote.yes, vote.no
+                );
+                if !voted {
+                    text.push_str("\nF1 yes    F2 no");
+                }
+                text
+            }
+            _ => String::new(),
+        };
+        ui.send_message(TextMessage::text(self.vote, MessageDirection::ToWidget, text));
+    }
+
+    /// Say who killed the local player while watching them, None when not in a killcam.
+    pub fn update_killcam(&self, ui: &mut UserInterface, player: usize, killer: Option<NetId>) {
+        let text = match killer {
+            Some(killer) => format!("You were killed by Player {}", killer),
+            None => String::new(),
+        };
+        let killcam = self.players[player].killcam;
+        ui.send_message(TextMessage::text(killcam, MessageDirection::ToWidget, text));
+    }
+
+    /// Tell players the match hasn't started yet or is in overtime.
+    pub fn update_match_state(&self, ui: &mut UserInterface, gs: &GameState) {
+        let text = match gs.match_state {
+            MatchState::Warmup => "Warmup - waiting for players".to_owned(),
+            MatchState::Lobby => "Lobby - waiting for players to get ready (F3)".to_owned(),
+            MatchState::Countdown { start } => {
+                let remaining = (start - gs.game_time).max(0.0).ceil();
+                format!("Match starts in {remaining:.0}")
+            }
+            MatchState::Overtime { .. } => "Overtime - no respawns".to_owned(),
+            MatchState::Playing | MatchState::Intermission { .. } => String::new(),
+        };
+        ui.send_message(TextMessage::text(self.match_state, MessageDirection::ToWidget, text));
+    }
+
+    /// List all players with their health and score while the local player is observing.
+    ///
+    /// `local_state` is None when not connected.
+    pub fn update_spectator_panel(
+        &self,
+        ui: &mut UserInterface,
+        cvars: &Cvars,
+        gs: &GameState,
+        local_state: Option<PlayerState>,
+    ) {
+        let show = cvars.hud_spectator_panel
+            && matches!(local_state, Some(PlayerState::Observing | PlayerState::Spectating { .. }));
+        let players = if show {
+            spectator_players(gs)
+        } else {
+            Vec::new()
+        };
+        for (i, &row) in self.spectator_rows.iter().enumerate() {
+            let Some(&player_handle) = players.get(i) else {
+                ui.send_message(TextMessage::text(row, MessageDirection::ToWidget, String::new()));
+                continue;
+            };
+            let player = &gs.players[player_handle];
+            let status = match player.cycle_handle {
+                Some(cycle_handle) => format!("{:.0} hp", gs.cycles[cycle_handle].health),
+                None if player.state == PlayerState::Playing => "dead".to_owned(),
+                None => "observing".to_owned(),
+            };
+            // Keys 1-9 and then 0 jump to the player.
+            let key = if i < 10 {
+                format!("{}", (i + 1) % 10)
+            } else {
+                " ".to_owned()
+            };
+            let text = format!(
+                "{}  {}    {}    {} kills    {} deaths",
+                key, player.name, status, player.kills, player.deaths
+            );
+            ui.send_message(TextMessage::text(row, MessageDirection::ToWidget, text));
+            let brush = Brush::Solid(player_color(player.net_id));
+            ui.send_message(WidgetMessage::foreground(row, MessageDirection::ToWidget, brush));
+        }
+    }
+
+    /// Float numbers showing damage dealt by the local players up from where it happened.
+    ///
+    /// Each player's numbers are projected by their camera into their viewport.
+    pub fn update_damage_numbers(
+        &self,
+        ui: &mut UserInterface,
+        cvars: &Cvars,
+        game_time: f32,
+        views: &[(&Camera, &[DamageNumber])],
+    ) {
+        let screen_size = ui.screen_size();
+        let mut newest: Vec<_> = views
+            .iter()
+            .flat_map(|&(camera, numbers)| numbers.iter().map(move |number| (camera, number)))
+            .collect();
+        newest.sort_by(|(_, a), (_, b)| b.time.total_cmp(&a.time));
+        newest.truncate(DAMAGE_NUMBERS);
+        let mut widgets = self.damage_numbers.iter();
+        for (&widget, (camera, number)) in widgets.by_ref().zip(newest) {
+            let age = game_time - number.time;
+            let pos = number.pos + UP * (1.0 + age * cvars.hud_damage_numbers_speed);
+            let Some(screen_pos) = camera.project(pos, screen_size) else {
+                ui.send_message(WidgetMessage::visibility(
+                    widget,
+                    MessageDirection::ToWidget,
+                    false,
+                ));
+                continue;
+            };
+            let fade = 1.0 - age / cvars.hud_damage_numbers_duration;
+            let alpha = (fade.clamp(0.0, 1.0) * 255.0) as u8;
+            let brush = Brush::Solid(Color::from_rgba(255, 220, 0, alpha));
+            let text = format!("{:.0}", number.amount);
+            ui.send_message(TextMessage::text(widget, MessageDirection::ToWidget, text));
+            ui.send_message(WidgetMessage::foreground(widget, MessageDirection::ToWidget, brush));
+            ui.send_message(WidgetMessage::desired_position(
+                widget,
+                MessageDirection::ToWidget,
+                screen_pos,
+            ));
+            ui.send_message(WidgetMessage::visibility(widget, MessageDirection::ToWidget, true));
+        }
+        for &widget in widgets {
+            ui.send_message(WidgetMessage::visibility(widget, MessageDirection::ToWidget, false));
+        }
+    }
+
+    /// Draw a crosshair in the middle of each local player's view.
+    pub fn update_crosshair(
+        &mut self,
+        ui: &mut UserInterface,
+        cvars: &Cvars,
+        crosshairs: &[Crosshair],
+    ) {
+        let mut shapes = Vec::new();
+        if cvars.hud_crosshair {
+            for crosshair in crosshairs {
+                crosshair_shapes(cvars, crosshair, &mut shapes);
+            }
+        }
+        self.crosshair.update(ui, self.root, shapes);
+    }
+
+    /// Show the server's message of the day, None to hide it.
+    pub fn update_motd(&self, ui: &mut UserInterface, motd: Option<&str>) {
+        let text = motd.unwrap_or_default().to_owned();
+        ui.send_message(TextMessage::text(self.motd, MessageDirection::ToWidget, text));
+    }
+
+    /// Show how many soft asserts and unwraps failed so they don't go unnoticed in the log.
+    pub fn update_errors(&self, ui: &mut UserInterface, cvars: &Cvars) {
+        let (total, last_frame) = debug::soft_errors();
+        let text = if cvars.d_draw && cvars.d_draw_text && total > 0 {
+            format!("ERRORS: {total} ({last_frame} last frame)")
+        } else {
+            String::new()
+        };
+        ui.send_message(TextMessage::text(self.errors, MessageDirection::ToWidget, text));
+    }
+
+    /// Point towards where recent damage to the local player came from, fading out over time.
+    pub fn update_damage_indicators(
+        &self,
+        ui: &mut UserInterface,
+        cvars: &Cvars,
+        player: usize,
+        game_time: f32,
+        camera: &Camera,
+        indicators: &[DamageIndicator],
+    ) {
+        let camera_pos = camera.global_position();
+        let camera_rot = camera.global_rotation();
+        let mut intensities = [0.0f32; 4];
+        if cvars.hud_damage_indicators {
+            for indicator in indicators {
+                // In camera space, X is left and Z is forward.
+                let dir = camera_rot.inverse() * (indicator.source - camera_pos);
+                let i = if dir.z.abs() >= dir.x.abs() {
+                    if dir.z >= 0.0 {
+                        DAMAGE_FRONT
+                    } else {
+                        DAMAGE_BACK
+                    }
+                } else if dir.x >= 0.0 {
+                    DAMAGE_LEFT
+                } else {
+                    DAMAGE_RIGHT
+                };
+                let age = game_time - indicator.time;
+                let intensity = 1.0 - age / cvars.hud_damage_indicator_duration;
+                intensities[i] = intensities[i].max(intensity);
+            }
+        }
+
+        for (&widget, intensity) in self.players[player].damage.iter().zip(intensities) {
+            let visible = intensity > 0.0;
+            ui.send_message(WidgetMessage::visibility(widget, MessageDirection::ToWidget, visible));
+            if visible {
+                let alpha = (intensity.min(1.0) * 255.0) as u8;
+                let brush = Brush::Solid(Color::from_rgba(255, 0, 0, alpha));
+                ui.send_message(WidgetMessage::foreground(
+                    widget,
+                    MessageDirection::ToWidget,
+                    brush,
+                ));
+            }
+        }
+    }
+}
+
+/// The primitives of one local player's crosshair, see `Hud::update_crosshair`.
+fn crosshair_shapes(cvars: &Cvars, crosshair: &Crosshair, shapes: &mut Vec<Shape>) {
+    let Crosshair {
+        offset,
+        hit,
+        spread,
+    } = *crosshair;
+    let size = cvars.hud_crosshair_size;
+    let gap = cvars.hud_crosshair_gap;
+    let thickness = cvars.hud_crosshair_thickness;
+    let spread = spread.filter(|_| cvars.hud_crosshair_spread);
+    // Primitives are relative to the widget's top left corner.
+    let extent = (gap + size).max(spread.unwrap_or_default()) + thickness;
+    let c = Vector2::new(extent, extent);
+
+    let mut primitives = Vec::new();
+    match cvars.hud_crosshair_style {
+        CrosshairStyle::Dot => primitives.push(Primitive::Circle {
+            center: c,
+            radius: thickness,
+            segments: 12,
+        }),
+        CrosshairStyle::Cross => {
+            for dir in [v2(1.0, 0.0), v2(-1.0, 0.0), v2(0.0, 1.0), v2(0.0, -1.0)] {
+                primitives.push(Primitive::Line {
+                    begin: c + dir * gap,
+                    end: c + dir * (gap + size),
+                    thickness,
+                });
+            }
+        }
+        CrosshairStyle::Circle => primitives.push(Primitive::WireCircle {
+            center: c,
+            radius: size,
+            thickness,
+            segments: 32,
+        }),
+    }
+    let color = if hit {
+        // Diagonal lines make hits visible even with a color close to the normal one.
+        let diagonal = (gap + size) * 0.7;
+        for dir in [v2(1.0, 1.0), v2(-1.0, 1.0), v2(1.0, -1.0), v2(-1.0, -1.0)] {
+            let dir = dir.normalize();
+            primitives.push(Primitive::Line {
+                begin: c + dir * gap,
+                end: c + dir * diagonal,
+                thickness,
+            });
+        }
+        cvar_color(cvars.hud_crosshair_hit_color, 255)
+    } else {
+        cvar_color(cvars.hud_crosshair_color, 255)
+    };
+    shapes.push(Shape {
+        color,
+        primitives,
+        placement: Placement::Centered {
+            size: extent * 2.0,
+            offset,
+        },
+    });
+
+    if let Some(radius) = spread {
+        let circle = Primitive::WireCircle {
+            center: c,
+            radius,
+            thickness: 1.0,
+            segments: 32,
+        };
+        let color = cvar_color(cvars.hud_crosshair_color, 100);
+        shapes.push(Shape {
+            color,
+            primitives: vec![circle],
+            placement: Placement::Centered {
+                size: extent * 2.0,
+                offset,
+            },
+        });
+    }
+}
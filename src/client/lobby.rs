@@ -0,0 +1,197 @@
+//! Lobby - shown before the match while players pick teams and get ready, see `g_lobby`.
+
+use fyrox::gui::{
+    border::BorderBuilder,
+    button::{ButtonBuilder, ButtonMessage},
+    grid::{Column, GridBuilder, Row},
+    message::{MessageDirection, UiMessage},
+    stack_panel::StackPanelBuilder,
+    text::{TextBuilder, TextMessage},
+    widget::{WidgetBuilder, WidgetMessage},
+    BuildContext, HorizontalAlignment, Thickness, UiNode, UserInterface, VerticalAlignment,
+};
+
+use crate::prelude::*;
+
+/// The lobby screen.
+pub struct LobbyScreen {
+    root: Handle<UiNode>,
+    players: Handle<UiNode>,
+    red: Handle<UiNode>,
+    blue: Handle<UiNode>,
+    no_team: Handle<UiNode>,
+    ready: Handle<UiNode>,
+    /// The text inside the `ready` button.
+    ready_text: Handle<UiNode>,
+    /// Whether the local player is ready according to the server.
+    is_ready: bool,
+    open: bool,
+    /// The player closed the lobby to warm up, don't reopen it automatically.
+    pub dismissed: bool,
+}
+
+/// What the player chose in the lobby.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LobbyAction {
+    Team(Option<Team>),
+    Ready(bool),
+}
+
+impl LobbyScreen {
+    pub fn new(ui: &mut UserInterface) -> Self {
+        let ctx = &mut ui.build_ctx();
+
+        let title = TextBuilder::new(
+            WidgetBuilder::new()
+                .with_horizontal_alignment(HorizontalAlignment::Center)
+                .with_margin(Thickness::uniform(8.0)),
+        )
+        .with_text("Lobby")
+        .with_font_size(32.0)
+        .with_shadow(true)
+        .build(ctx);
+        let players =
+            TextBuilder::new(WidgetBuilder::new().with_margin(Thickness::uniform(4.0))).build(ctx);
+        let red = button(ctx, "Red", 0);
+        let blue = button(ctx, "Blue", 1);
+        let no_team = button(ctx, "No team", 2);
+        let teams = GridBuilder::new(
+            WidgetBuilder::new().with_child(red).with_child(blue).with_child(no_team),
+        )
+        .add_row(Row::auto())
+        .add_column(Column::stretch())
+        .add_column(Column::stretch())
+        .add_column(Column::stretch())
+        .build(ctx);
+        let ready_text = TextBuilder::new(WidgetBuilder::new())
+            .with_text("Ready")
+            .with_horizontal_text_alignment(HorizontalAlignment::Center)
+            .with_vertical_text_alignment(VerticalAlignment::Center)
+            .build(ctx);
+        let ready = ButtonBuilder::new(
+            WidgetBuilder::new().with_height(30.0).with_margin(Thickness::uniform(4.0)),
+        )
+        .with_content(ready_text)
+        .build(ctx);
+        let hint = TextBuilder::new(
+            WidgetBuilder::new()
+                .with_horizontal_alignment(HorizontalAlignment::Center)
+                .with_margin(Thickness::uniform(4.0)),
+        )
+        .with_text("ESC to warm up, F3 to come back")
+        .build(ctx);
+
+        let panel = StackPanelBuilder::new(
+            WidgetBuilder::new()
+                .with_child(title)
+                .with_child(players)
+                .with_child(teams)
+                .with_child(ready)
+                .with_child(hint),
+        )
+        .build(ctx);
+        let root = BorderBuilder::new(
+            WidgetBuilder::new()
+                .with_width(400.0)
+                .with_horizontal_alignment(HorizontalAlignment::Center)
+                .with_vertical_alignment(VerticalAlignment::Center)
+                .with_visibility(false)
+                .with_child(panel),
+        )
+        .build(ctx);
+
+        Self {
+            root,
+            players,
+            red,
+            blue,
+            no_team,
+            ready,
+            ready_text,
+            is_ready: false,
+            open: false,
+            dismissed: false,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn open(&mut self, ui: &mut UserInterface) {
+        self.open = true;
+        self.dismissed = false;
+        ui.send_message(WidgetMessage::visibility(self.root, MessageDirection::ToWidget, true));
+    }
+
+    pub fn close(&mut self, ui: &mut UserInterface) {
+        self.open = false;
+        ui.send_message(WidgetMessage::visibility(self.root, MessageDirection::ToWidget, false));
+    }
+
+    /// Show the players in the lobby and whether we're ready.
+    pub fn update(&mut self, ui: &mut UserInterface, lobby: &[LobbyPlayer], local_id: NetId) {
+        if !self.open {
+            return;
+        }
+
+        let mut text = String::new();
+        for player in lobby {
+            let team = match player.team {
+                Some(team) => team.to_string(),
+                None => "-".to_owned(),
+            };
+            let ready = if player.ready { "ready" } else { "not ready" };
+            let you = if player.player_id == local_id {
+                " (you)"
+            } else {
+                ""
+            };
+            text.push_str(&format!("{}{} - {} - {}\n", player.name, you, team, ready));
+        }
+        ui.send_message(TextMessage::text(self.players, MessageDirection::ToWidget, text));
+
+        self.is_ready = lobby.iter().any(|player| player.player_id == local_id && player.ready);
+        let label = if self.is_ready { "Not ready" } else { "Ready" };
+        ui.send_message(TextMessage::text(
+            self.ready_text,
+            MessageDirection::ToWidget,
+            label.to_owned(),
+        ));
+    }
+
+    /// Handle UI events.
+    ///
+    /// Returns what the player chose, if anything.
+    pub fn ui_message(&mut self, msg: &UiMessage) -> Option<LobbyAction> {
+        if !self.open
+            || msg.direction != MessageDirection::FromWidget
+            || msg.data() != Some(&ButtonMessage::Click)
+        {
+            return None;
+        }
+        let dest = msg.destination();
+        if dest == self.red {
+            Some(LobbyAction::Team(Some(Team::Red)))
+        } else if dest == self.blue {
+            Some(LobbyAction::Team(Some(Team::Blue)))
+        } else if dest == self.no_team {
+            Some(LobbyAction::Team(None))
+        } else if dest == self.ready {
+            Some(LobbyAction::Ready(!self.is_ready))
+        } else {
+            None
+        }
+    }
+}
+
+fn button(ctx: &mut BuildContext, text: &str, column: usize) -> Handle<UiNode> {
+    ButtonBuilder::new(
+        WidgetBuilder::new()
+            .with_height(30.0)
+            .with_margin(Thickness::uniform(4.0))
+            .on_column(column),
+    )
+    .with_text(text)
+    .build(ctx)
+}
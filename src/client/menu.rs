@@ -0,0 +1,149 @@
+//! Main menu - shown on startup and when pressing ESC in game.
+
+use fyrox::gui::{
+    border::BorderBuilder,
+    brush::Brush,
+    button::{ButtonBuilder, ButtonMessage},
+    message::{MessageDirection, UiMessage},
+    stack_panel::StackPanelBuilder,
+    text::{TextBuilder, TextMessage},
+    text_box::TextBoxBuilder,
+    widget::{WidgetBuilder, WidgetMessage},
+    BuildContext, HorizontalAlignment, Thickness, UiNode, UserInterface, VerticalAlignment,
+};
+
+use crate::prelude::*;
+
+/// The main menu screen.
+pub struct MainMenu {
+    root: Handle<UiNode>,
+    play_local: Handle<UiNode>,
+    addr_box: Handle<UiNode>,
+    connect: Handle<UiNode>,
+    settings: Handle<UiNode>,
+    quit: Handle<UiNode>,
+    /// The contents of `addr_box`.
+    addr: String,
+    open: bool,
+}
+
+/// What the player chose in the menu.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MenuAction {
+    PlayLocal,
+    Connect(String),
+    Settings,
+    Quit,
+}
+
+impl MainMenu {
+    pub fn new(ui: &mut UserInterface, cvars: &Cvars) -> Self {
+        let ctx = &mut ui.build_ctx();
+
+        let title = TextBuilder::new(
+            WidgetBuilder::new()
+                .with_horizontal_alignment(HorizontalAlignment::Center)
+                .with_margin(Thickness::uniform(8.0)),
+        )
+        .with_text("RustCycles")
+        .with_font_size(32.0)
+        .with_shadow(true)
+        .build(ctx);
+        let play_local = button(ctx, "Play Local");
+        let addr_box = TextBoxBuilder::new(
+            WidgetBuilder::new()
+                .with_height(25.0)
+                .with_margin(Thickness::uniform(4.0))
+                .with_background(Brush::Solid(Color::opaque(40, 40, 40))),
+        )
+        .with_text(&cvars.cl_connect_addr)
+        .with_vertical_text_alignment(VerticalAlignment::Center)
+        .build(ctx);
+        let connect = button(ctx, "Connect");
+        let settings = button(ctx, "Settings");
+        let quit = button(ctx, "Quit");
+
+        let panel = StackPanelBuilder::new(
+            WidgetBuilder::new()
+                .with_child(title)
+                .with_child(play_local)
+                .with_child(addr_box)
+                .with_child(connect)
+                .with_child(settings)
+                .with_child(quit),
+        )
+        .build(ctx);
+        let root = BorderBuilder::new(
+            WidgetBuilder::new()
+                .with_width(300.0)
+                .with_horizontal_alignment(HorizontalAlignment::Center)
+                .with_vertical_alignment(VerticalAlignment::Center)
+                .with_visibility(false)
+                .with_child(panel),
+        )
+        .build(ctx);
+
+        Self {
+            root,
+            play_local,
+            addr_box,
+            connect,
+            settings,
+            quit,
+            addr: cvars.cl_connect_addr.clone(),
+            open: false,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn open(&mut self, ui: &mut UserInterface) {
+        self.open = true;
+        ui.send_message(WidgetMessage::visibility(self.root, MessageDirection::ToWidget, true));
+    }
+
+    pub fn close(&mut self, ui: &mut UserInterface) {
+        self.open = false;
+        ui.send_message(WidgetMessage::visibility(self.root, MessageDirection::ToWidget, false));
+    }
+
+    /// Handle UI events.
+    ///
+    /// Returns what the player chose, if anything.
+    pub fn ui_message(&mut self, msg: &UiMessage) -> Option<MenuAction> {
+        if !self.open || msg.direction != MessageDirection::FromWidget {
+            return None;
+        }
+
+        if msg.destination() == self.addr_box {
+            if let Some(TextMessage::Text(text)) = msg.data() {
+                self.addr = text.clone();
+            }
+            return None;
+        }
+
+        if msg.data() != Some(&ButtonMessage::Click) {
+            return None;
+        }
+        let dest = msg.destination();
+        if dest == self.play_local {
+            Some(MenuAction::PlayLocal)
+        } else if dest == self.connect {
+            Some(MenuAction::Connect(self.addr.trim().to_owned()))
+        } else if dest == self.settings {
+            Some(MenuAction::Settings)
+        } else if dest == self.quit {
+            Some(MenuAction::Quit)
+        } else {
+            None
+        }
+    }
+}
+
+fn button(ctx: &mut BuildContext, text: &str) -> Handle<UiNode> {
+    ButtonBuilder::new(WidgetBuilder::new().with_height(30.0).with_margin(Thickness::uniform(4.0)))
+        .with_text(text)
+        .build(ctx)
+}
@@ -0,0 +1,172 @@
+//! Top-down view of cycles around the local player, see `hud_minimap`.
+//!
+//! Holding the map key shows a larger overview of the whole map instead.
+//! Other players only appear if they were visible recently, see `ClientGame::seen`.
+
+use fyrox::gui::{
+    border::BorderBuilder,
+    brush::Brush,
+    message::MessageDirection,
+    vector_image::Primitive,
+    widget::{WidgetBuilder, WidgetMessage},
+    Thickness, UiNode, UserInterface,
+};
+
+use crate::{
+    client::{
+        game::ClientGame,
+        hud,
+        vector_shapes::{Placement, Shape, VectorShapes},
+    },
+    prelude::*,
+};
+
+const MARGIN: f32 = 20.0;
+const MARKER_RADIUS: f32 = 4.0;
+
+pub struct Minimap {
+    root: Handle<UiNode>,
+    markers: VectorShapes,
+    window_size: Vector2<f32>,
+    visible: bool,
+}
+
+impl Minimap {
+    pub fn new(ui: &mut UserInterface) -> Self {
+        let root = BorderBuilder::new(
+            WidgetBuilder::new()
+                .with_visibility(false)
+                .with_background(Brush::Solid(Color::from_rgba(0, 0, 0, 120)))
+                .with_hit_test_visibility(false),
+        )
+        .with_stroke_thickness(Thickness::uniform(1.0))
+        .build(&mut ui.build_ctx());
+
+        Self {
+            root,
+            markers: VectorShapes::default(),
+            window_size: Vector2::zeros(),
+            visible: false,
+        }
+    }
+
+    pub fn resized(&mut self, width: f32, height: f32) {
+        self.window_size = Vector2::new(width, height);
+    }
+
+    /// `cg` is None when not connected.
+    pub fn update(
+        &mut self,
+        ui: &mut UserInterface,
+        cvars: &Cvars,
+        gs: &GameState,
+        scene: &Scene,
+        cg: Option<&ClientGame>,
+    ) {
+        let overview = cg.is_some_and(|cg| cg.input.map);
+        let visible = cg.is_some() && (cvars.hud_minimap || overview);
+        if visible != self.visible {
+            self.visible = visible;
+            ui.send_message(WidgetMessage::visibility(
+                self.root,
+                MessageDirection::ToWidget,
+                visible,
+            ));
+        }
+        let Some(cg) = cg.filter(|_| visible) else {
+            self.markers.clear(ui);
+            return;
+        };
+
+        // The minimap follows the camera, the overview shows the map around its center.
+        let camera_pos = **scene.graph[cg.camera_handle].local_transform().position();
+        let (size, range, center, pos) = if overview {
+            let size = cvars.hud_minimap_overview_size;
+            let pos = (self.window_size - Vector2::new(size, size)) / 2.0;
+            (size, cvars.hud_minimap_overview_range, Vec3::zeros(), pos)
+        } else {
+            let size = cvars.hud_minimap_size;
+            (size, cvars.hud_minimap_range, camera_pos, Vector2::new(MARGIN, MARGIN))
+        };
+        ui.send_message(WidgetMessage::width(self.root, MessageDirection::ToWidget, size));
+        ui.send_message(WidgetMessage::height(self.root, MessageDirection::ToWidget, size));
+        ui.send_message(WidgetMessage::desired_position(
+            self.root,
+            MessageDirection::ToWidget,
+            pos,
+        ));
+
+        // LEFT is +X so it has to be flipped, FORWARD (+Z) is up on the screen.
+        let scale = size / 2.0 / range;
+        let to_screen = |world: Vec3| {
+            let rel = world - center;
+            Vector2::new(size / 2.0 - rel.x * scale, size / 2.0 - rel.z * scale)
+        };
+        let on_map = |point: Vector2<f32>| {
+            (0.0..=size).contains(&point.x) && (0.0..=size).contains(&point.y)
+        };
+
+        // Each vector image has only one color.
+        let mut groups: Vec<(Color, Vec<Primitive>)> = Vec::new();
+        let mut push =
+            |color: Color, primitive: Primitive| match groups.iter_mut().find(|(c, _)| *c == color)
+            {
+                Some((_, group)) => group.push(primitive),
+                None => groups.push((color, vec![primitive])),
+            };
+        let local_cycle_handle = gs.players[cg.player_handle].cycle_handle;
+        for (cycle_handle, cycle) in gs.cycles.pair_iter() {
+            let world = **scene.graph[cycle.body_handle].local_transform().position();
+            let point = to_screen(world);
+            if !on_map(point) {
+                continue;
+            }
+            if Some(cycle_handle) == local_cycle_handle {
+                // An arrow pointing where the player is looking.
+                let dir = cg.input.yaw_rotation() * FORWARD;
+                let forward = Vector2::new(-dir.x, -dir.z).normalize() * MARKER_RADIUS * 2.0;
+                let side = Vector2::new(-forward.y, forward.x) / 2.0;
+                let points = [
+                    point + forward,
+                    point - forward + side,
+                    point - forward - side,
+                ];
+                push(WHITE, Primitive::Triangle { points });
+                continue;
+            }
+            let seen = cg
+                .seen
+                .get(&cycle.net_id)
+                .is_some_and(|&time| gs.game_time - time <= cvars.hud_minimap_seen_duration);
+            if !seen {
+                continue;
+            }
+            let color = hud::player_color(gs.players[cycle.player_handle].net_id);
+            let circle = Primitive::Circle {
+                center: point,
+                radius: MARKER_RADIUS,
+                segments: 8,
+            };
+            push(color, circle);
+        }
+        if let Some(radius) = gs.overtime_radius() {
+            let circle = Primitive::WireCircle {
+                center: to_screen(Vec3::zeros()),
+                radius: radius * scale,
+                thickness: 1.0,
+                segments: 64,
+            };
+            push(RED, circle);
+        }
+
+        let shapes = groups
+            .into_iter()
+            .map(|(color, primitives)| Shape {
+                color,
+                primitives,
+                placement: Placement::Fill,
+            })
+            .collect();
+        self.markers.update(ui, self.root, shapes);
+    }
+}
@@ -4,48 +4,137 @@
 //! When connected to a remote server, contains a game client.
 //! When playing locally, contains both a client and a server.
 
-use std::sync::mpsc;
+use std::{path::Path, sync::mpsc, time::SystemTime};
 
-use cvars_console_fyrox::FyroxConsole;
 use fyrox::{
-    core::instant::Instant,
-    dpi::PhysicalSize,
-    engine::GraphicsContext,
-    event::{ElementState, KeyEvent, MouseButton, MouseScrollDelta, TouchPhase},
+    asset::untyped::UntypedResource,
+    core::{
+        futures::{executor, future},
+        instant::Instant,
+        math::Rect,
+    },
+    dpi::{PhysicalPosition, PhysicalSize},
+    engine::{Engine, GraphicsContext},
+    event::{ElementState, KeyEvent, MouseButton, MouseScrollDelta, Touch, TouchPhase},
     event_loop::EventLoopWindowTarget,
     gui::{
         brush::Brush,
         formatted_text::WrapMode,
         message::{MessageDirection, UiMessage},
-        text::TextBuilder,
+        text::{TextBuilder, TextMessage},
         widget::{WidgetBuilder, WidgetMessage},
-        UiNode,
+        HorizontalAlignment, UiNode, VerticalAlignment,
     },
     keyboard::{KeyCode, PhysicalKey},
     renderer::QualitySettings,
-    window::CursorGrabMode,
+    scene::camera::Projection,
+    window::{CursorGrabMode, Fullscreen},
 };
 
 use crate::{
-    client::game::ClientGame,
-    common::net::{self, LocalConnection, LocalListener},
-    debug,
+    client::{
+        browser::ServerBrowser,
+        console::{Console, ConsoleCommand},
+        demo_player::DemoConnection,
+        frame_stats::FrameStats,
+        game::{ClientFrameCtx, ClientGame},
+        gamepad::Gamepads,
+        graphs::{GraphSample, PerfGraphs},
+        hud::{Crosshair, Hud},
+        lobby::{LobbyAction, LobbyScreen},
+        menu::{MainMenu, MenuAction},
+        minimap::Minimap,
+        render_scale::RenderScale,
+        settings::SettingsMenu,
+        touch::TouchControls,
+    },
+    common::{
+        demo,
+        entities::PlayerState,
+        maps,
+        net::{self, Connection, Listener, LocalConnection, LocalListener},
+        savestate, trace, CYCLE_MODEL_PATH,
+    },
+    debug, engine_loop,
     prelude::*,
-    server::game::ServerGame,
+    server::game::{ServerFrameCtx, ServerGame},
 };
 
+#[cfg(not(target_arch = "wasm32"))]
+use std::net::SocketAddr;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{client::hot_reload::AssetWatcher, common::net::PendingTcpConnection};
+
 /// The process that runs a player's game client.
 pub struct ClientProcess {
     pub cvars: Cvars,
     clock: Instant,
+    /// Used instead of `clock` when set, tests use it to control time exactly.
+    pub manual_time: Option<f32>,
+    /// Real time during the previous update.
+    real_time_prev: f32,
+    /// Game time which gamelogic should catch up to.
+    ///
+    /// Follows real time except when slowed down or paused in local games.
+    game_time_target: f32,
+    /// Run one gamelogic frame during the next update when `d_singlestep` is on.
+    step_requested: bool,
     mouse_grabbed: bool,
     shift_pressed: bool,
+    alt_pressed: bool,
+    /// The window has focus, decides which FPS cap applies.
+    focused: bool,
     pub engine: Engine,
-    r_quality: i32,
-    console: FyroxConsole,
+    /// Kept alive so they're not unloaded, see `preload_resources`.
+    _preloaded: Vec<UntypedResource>,
+    /// Some while `d_hot_reload_assets` is on.
+    #[cfg(not(target_arch = "wasm32"))]
+    asset_watcher: Option<AssetWatcher>,
+    /// Watching failed, don't try again every frame until the cvar is toggled.
+    #[cfg(not(target_arch = "wasm32"))]
+    asset_watcher_failed: bool,
+    /// The vsync setting the graphics context was created with.
+    vsync: bool,
+    /// The window mode last applied to the window.
+    ///
+    /// None until graphics are initialized.
+    window_mode: Option<WindowMode>,
+    /// The quality settings last applied to the renderer.
+    ///
+    /// None until graphics are initialized.
+    quality: Option<QualitySettings>,
+    render_scale: RenderScale,
+    console: Console,
+    hud: Hud,
+    graphs: PerfGraphs,
+    minimap: Minimap,
+    touch_controls: TouchControls,
+    gamepads: Gamepads,
+    frame_stats: FrameStats,
+    /// Frames rendered so far, used by `d_benchmark_frames`.
+    frames: usize,
+    browser: ServerBrowser,
+    lobby: LobbyScreen,
+    menu: MainMenu,
+    settings: SettingsMenu,
+    /// The last seen value of `cl_connect_addr` to detect changes.
+    connect_addr: String,
     debug_text: Handle<UiNode>,
-    gs: GameState,
-    cg: ClientGame,
+    /// Shows connection progress and errors.
+    status_text: Handle<UiNode>,
+    /// The server's message of the day and the real time it arrived, see `hud_motd_duration`.
+    motd: Option<(String, f32)>,
+    pub gs: GameState,
+    /// None while connecting or if connecting failed.
+    pub cg: Option<ClientGame>,
+    connecting: Option<Connecting>,
+    /// The second local player when playing splitscreen, see `cl_splitscreen`.
+    ///
+    /// None until its init arrives.
+    pub cg2: Option<ClientGame>,
+    /// The second player's connection to the local server while waiting for init.
+    connecting2: Option<Box<dyn Connection<ServerMessage>>>,
     /// Optional server-side game data when playing in local mode (with shared or LATER separate game state).
     sg: Option<ServerGame>,
     // LATER Optional server-side game state when playing in local mode with separate game states.
@@ -54,10 +143,68 @@ pub struct ClientProcess {
     pub exit: bool,
 }
 
+/// What the client does after starting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientStart {
+    /// Show the main menu and let the player choose.
+    Menu,
+    /// Play a local game with the server in the same process.
+    Local,
+    /// Connect to `cl_connect_addr`.
+    Connect,
+    /// Play a local game for `d_benchmark_frames` frames, then print frame statistics and exit.
+    Benchmark,
+    /// Watch the server demo at this path, see `DemoConnection`.
+    Replay(String),
+}
+
+/// Progress of connecting to a server.
+enum Connecting {
+    /// Waiting for the TCP connection (and encryption handshake).
+    #[cfg(not(target_arch = "wasm32"))]
+    Tcp {
+        addr: SocketAddr,
+        pending: PendingTcpConnection,
+    },
+    /// Waiting for the browser to open the WebSocket.
+    #[cfg(target_arch = "wasm32")]
+    WebSocket {
+        conn: net::WebSocketConnection,
+        /// Real time when we started connecting.
+        start: f32,
+    },
+    /// Connected, waiting for the server to send init data.
+    Init {
+        conn: Box<dyn Connection<ServerMessage>>,
+        /// Real time when we started waiting.
+        start: f32,
+    },
+    /// Got init but we don't have the map, downloading it from the server.
+    Download {
+        conn: Box<dyn Connection<ServerMessage>>,
+        init: Init,
+        /// The map file received so far.
+        data: Vec<u8>,
+        /// Real time when we last received a chunk.
+        last_chunk: f32,
+    },
+}
+
 impl ClientProcess {
-    pub async fn new(cvars: Cvars, mut engine: Engine, local_game: bool) -> Self {
+    pub async fn new(mut cvars: Cvars, mut engine: Engine, start: ClientStart) -> Self {
         let clock = Instant::now();
 
+        if cvars.cl_guid.is_empty() {
+            cvars.cl_guid = load_or_create_guid(GUID_PATH);
+        }
+
+        // Everything logged from now on also shows up in the console.
+        debug::set_log_capture(true);
+        debug::logger::update(&cvars);
+
+        // Has to be first so the scene is drawn below all other UI.
+        let render_scale = RenderScale::new(&mut engine.user_interface);
+
         let debug_text =
             TextBuilder::new(WidgetBuilder::new().with_foreground(Brush::Solid(Color::RED)))
                 // LATER react to changes at runtime
@@ -71,72 +218,134 @@ impl ClientProcess {
                 .with_wrap(WrapMode::Letter)
                 .build(&mut engine.user_interface.build_ctx());
 
+        let status_text = TextBuilder::new(
+            WidgetBuilder::new()
+                .with_horizontal_alignment(HorizontalAlignment::Center)
+                .with_vertical_alignment(VerticalAlignment::Center),
+        )
+        .with_shadow(true)
+        .build(&mut engine.user_interface.build_ctx());
+
+        let hud = Hud::new(&mut engine.user_interface);
+
+        let graphs = PerfGraphs::new(&mut engine.user_interface);
+
+        let minimap = Minimap::new(&mut engine.user_interface);
+
+        let touch_controls = TouchControls::new(&mut engine.user_interface);
+
+        let browser = ServerBrowser::new(&mut engine.user_interface);
+
+        let lobby = LobbyScreen::new(&mut engine.user_interface);
+
+        let menu = MainMenu::new(&mut engine.user_interface, &cvars);
+        let settings = SettingsMenu::new(&mut engine.user_interface);
+
         // Z index doesn't work, console has to be created after debug_text (and any other UI):
         // https://github.com/FyroxEngine/Fyrox/issues/356
-        let console = FyroxConsole::new(&mut engine.user_interface);
+        let console = Console::new(&mut engine.user_interface);
+
+        // Natively, loading is fast enough to do on demand.
+        #[cfg(target_arch = "wasm32")]
+        let preloaded = preload_resources(&engine).await;
+        #[cfg(not(target_arch = "wasm32"))]
+        let preloaded = Vec::new();
 
-        let gs_type = if local_game {
+        let local = start == ClientStart::Local || start == ClientStart::Benchmark;
+        let gs_type = if local {
             GameStateType::Shared
         } else {
             GameStateType::Client
         };
-        let mut gs = GameState::new(&cvars, &mut engine, gs_type).await;
-
-        let (sg, cg) = if local_game {
-            // LATER Multithreading would be sweet but we can't use threads in WASM.
-
-            let (tx1, rx1) = mpsc::channel();
-            let (tx2, rx2) = mpsc::channel();
-            let conn1 = LocalConnection::new(tx1, rx2);
-            let conn2 = LocalConnection::new(tx2, rx1);
-
-            // Init server first, otherwise the client has nothing to connect to.
-            let listener = LocalListener::new(conn1);
-            let mut sg = ServerGame::new(Box::new(listener)).await;
-
-            // Make the server accept the local connection
-            // and send init data into it so the client can read it during creation.
-            // Otherwise the client would remain stuck.
-            // Yes, this is really ugly.
-            let mut ctx = ServerFrameCtx {
-                cvars: &cvars,
-                scene: &mut engine.scenes[gs.scene_handle],
-                gs: &mut gs,
-                sg: &mut sg,
-            };
-            ctx.accept_new_connections();
-
-            let cg =
-                ClientGame::new(&cvars, &mut engine, debug_text, Box::new(conn2), &mut gs).await;
-
-            (Some(sg), cg)
+        let mut gs = GameState::new(
+            &cvars,
+            &engine.resource_manager,
+            &mut engine.scenes,
+            gs_type,
+            &cvars.g_map,
+        )
+        .await;
+
+        let (sg, connecting, connecting2) = if local {
+            let (sg, connecting, connecting2) =
+                start_local_server(&cvars, &mut engine, &mut gs).await;
+            (Some(sg), Some(connecting), connecting2)
         } else {
-            let conn = net::tcp_connect_blocking(&cvars, "127.0.0.1:26000");
-            let cg =
-                ClientGame::new(&cvars, &mut engine, debug_text, Box::new(conn), &mut gs).await;
-
-            (None, cg)
+            (None, None, None)
         };
 
+        let connect_addr = cvars.cl_connect_addr.clone();
+
         let exit = cvars.d_exit_after_one_frame;
+        let vsync = cvars.cl_vsync;
 
         let elapsed = clock.elapsed();
         dbg_logf!("ClientProcess::new() took {} ms", elapsed.as_millis());
 
-        Self {
+        let mut client = Self {
             cvars,
             clock,
+            manual_time: None,
+            real_time_prev: 0.0,
+            game_time_target: 0.0,
+            step_requested: false,
             mouse_grabbed: false,
             shift_pressed: false,
+            alt_pressed: false,
+            focused: true,
+            vsync,
+            window_mode: None, // Monitors are only known after graphics_context is initialized
+
             engine,
-            r_quality: -1, // Initialize this on the first frame, after graphics_context
+            _preloaded: preloaded,
+            #[cfg(not(target_arch = "wasm32"))]
+            asset_watcher: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            asset_watcher_failed: false,
+            quality: None, // Initialize this on the first frame, after graphics_context
+            render_scale,
             console,
+            hud,
+            graphs,
+            minimap,
+            touch_controls,
+            gamepads: Gamepads::new(),
+            frame_stats: FrameStats::default(),
+            frames: 0,
+            browser,
+            lobby,
+            menu,
+            settings,
+            connect_addr,
             debug_text,
+            status_text,
+            motd: None,
             gs,
-            cg,
+            cg: None,
+            connecting,
+            cg2: None,
+            connecting2,
             sg,
             exit,
+        };
+
+        match start {
+            ClientStart::Menu => client.menu.open(&mut client.engine.user_interface),
+            ClientStart::Local | ClientStart::Benchmark => {}
+            ClientStart::Connect => {
+                let addr = client.cvars.cl_connect_addr.clone();
+                client.connect_to(&addr);
+            }
+            ClientStart::Replay(path) => match demo::load(Path::new(&path)) {
+                Ok(entries) => match DemoConnection::new(entries) {
+                    Ok(conn) => client.connected(Box::new(conn)),
+                    Err(e) => client.connect_failed(&format!("Can't replay {path}: {e}")),
+                },
+                Err(e) => client.connect_failed(&format!("Failed to load demo {path}: {e}")),
+            },
         }
+
+        client
     }
 
     pub fn resized(&mut self, size: PhysicalSize<u32>) {
@@ -160,6 +369,19 @@ impl ClientProcess {
             size.width as f32,
         ));
 
+        self.hud
+            .resized(&mut self.engine.user_interface, size.width as f32, size.height as f32);
+
+        self.graphs
+            .resized(&mut self.engine.user_interface, size.width as f32, size.height as f32);
+
+        self.minimap.resized(size.width as f32, size.height as f32);
+
+        self.touch_controls.resized(size.width as f32, size.height as f32);
+
+        self.render_scale
+            .resized(&mut self.engine.user_interface, size.width, size.height);
+
         self.console.resized(
             &mut self.engine.user_interface,
             size.width as f32,
@@ -167,6 +389,16 @@ impl ClientProcess {
         );
     }
 
+    pub fn moved(&mut self, pos: PhysicalPosition<i32>) {
+        // Fullscreen windows also move when switching monitors.
+        if self.cvars.cl_fullscreen {
+            return;
+        }
+        self.cvars.cl_window_x = pos.x;
+        self.cvars.cl_window_y = pos.y;
+        self.cvars.cl_window_pos_auto = false;
+    }
+
     pub fn focused(&mut self, focus: bool) {
         if self.cvars.d_events && self.cvars.d_events_focused {
             dbg_logf!("{} focused: {:?}", self.real_time(), focus);
@@ -178,13 +410,19 @@ impl ClientProcess {
         // the game can get stuck in a loop (bugs like this are most common on startup)
         // and it would never ungrab.
         if focus {
-            if self.cvars.cl_mouse_grab_on_focus && !self.console.is_open() {
+            if self.cvars.cl_mouse_grab_on_focus && !self.is_menu_open() {
                 self.set_mouse_grab(true);
             }
         } else {
             self.set_mouse_grab(false);
+            // Keys released while another window has focus don't generate events here,
+            // after alt+tabbing back, Enter would still toggle fullscreen.
+            self.shift_pressed = false;
+            self.alt_pressed = false;
         }
 
+        self.focused = focus;
+
         // LATER pause/unpause
     }
 
@@ -197,11 +435,20 @@ impl ClientProcess {
         }
 
         self.client_input(event);
-        if !self.console.is_open() {
+        if !self.is_menu_open() {
             self.game_input(event);
         }
     }
 
+    /// Whether any UI which needs the mouse and keyboard is open.
+    fn is_menu_open(&self) -> bool {
+        self.console.is_open()
+            || self.browser.is_open()
+            || self.lobby.is_open()
+            || self.menu.is_open()
+            || self.settings.is_open()
+    }
+
     /// Input that is handled regardless of whether we're in menu/console/game.
     fn client_input(&mut self, event: &KeyEvent) {
         use KeyCode::*;
@@ -219,9 +466,22 @@ impl ClientProcess {
                     // This shortcut should not be configurable so it works for all players
                     // no matter how much they break their config.
                     self.open_console();
+                } else if self.browser.is_open() {
+                    self.browser.close(&mut self.engine.user_interface);
+                } else if self.lobby.is_open() {
+                    self.lobby.close(&mut self.engine.user_interface);
+                    self.lobby.dismissed = true;
+                    self.set_mouse_grab(true);
+                } else if self.settings.is_open() {
+                    self.close_settings();
+                } else if self.menu.is_open() {
+                    // There's nothing to go back to before starting a game.
+                    if self.cg.is_some() || self.connecting.is_some() {
+                        self.menu.close(&mut self.engine.user_interface);
+                        self.set_mouse_grab(true);
+                    }
                 } else {
-                    // ESC anywhere else just ungrabs the mouse.
-                    self.set_mouse_grab(false);
+                    self.open_menu();
                 }
             }
             Code(Backquote) if pressed => {
@@ -230,17 +490,101 @@ impl ClientProcess {
                     self.open_console();
                 }
             }
+            Code(F2) if pressed => {
+                // LATER Configurable bind.
+                let can_vote = self.cg.as_ref().is_some_and(|cg| cg.vote.is_some() && !cg.voted);
+                if can_vote && !self.browser.is_open() {
+                    // F2 votes no while there's a vote, otherwise it's the server browser.
+                    self.cg.as_mut().unwrap().vote(false);
+                } else if self.browser.is_open() {
+                    self.browser.close(&mut self.engine.user_interface);
+                } else {
+                    let real_time = self.real_time();
+                    self.browser.open(&mut self.engine.user_interface, &self.cvars, real_time);
+                    self.release_all_keys();
+                    self.set_mouse_grab(false);
+                }
+            }
+            Code(F3) if pressed => {
+                // LATER Configurable bind.
+                let in_lobby = self.cg.as_ref().is_some_and(|cg| !cg.lobby.is_empty());
+                if self.lobby.is_open() {
+                    self.lobby.close(&mut self.engine.user_interface);
+                    self.lobby.dismissed = true;
+                    self.set_mouse_grab(true);
+                } else if in_lobby && !self.is_menu_open() {
+                    self.open_lobby();
+                }
+            }
+            Code(F10) if pressed => self.step_requested = true,
             Code(ShiftLeft) => self.shift_pressed = pressed,
+            Code(AltLeft | AltRight) => self.alt_pressed = pressed,
+            Code(Enter | NumpadEnter) if pressed && self.alt_pressed => {
+                self.cvars.cl_fullscreen = !self.cvars.cl_fullscreen;
+            }
             _ => (),
         }
     }
 
+    fn open_menu(&mut self) {
+        self.menu.open(&mut self.engine.user_interface);
+        self.release_all_keys();
+        self.set_mouse_grab(false);
+    }
+
+    fn open_lobby(&mut self) {
+        self.lobby.open(&mut self.engine.user_interface);
+        self.release_all_keys();
+        self.set_mouse_grab(false);
+    }
+
+    /// Open the lobby when the server starts one and close it when the match starts.
+    fn update_lobby(&mut self) {
+        let Some(cg) = &self.cg else {
+            if self.lobby.is_open() {
+                self.lobby.close(&mut self.engine.user_interface);
+            }
+            return;
+        };
+        if cg.lobby.is_empty() {
+            self.lobby.dismissed = false;
+            if self.lobby.is_open() {
+                self.lobby.close(&mut self.engine.user_interface);
+                if !self.is_menu_open() {
+                    self.set_mouse_grab(true);
+                }
+            }
+            return;
+        }
+
+        let local_id = self.gs.players[cg.player_handle].net_id;
+        if !self.lobby.is_open() && !self.lobby.dismissed && !self.is_menu_open() {
+            self.open_lobby();
+        }
+        let cg = self.cg.as_ref().unwrap();
+        self.lobby.update(&mut self.engine.user_interface, &cg.lobby, local_id);
+    }
+
+    /// Go back from settings to the main menu.
+    fn close_settings(&mut self) {
+        self.settings.close(&mut self.engine.user_interface, &self.cvars);
+        self.menu.open(&mut self.engine.user_interface);
+    }
+
     fn open_console(&mut self) {
         self.console.open(&mut self.engine.user_interface, self.mouse_grabbed);
-        self.cg.input.release_all_keys();
+        self.release_all_keys();
         self.set_mouse_grab(false);
     }
 
+    fn release_all_keys(&mut self) {
+        if let Some(cg) = &mut self.cg {
+            cg.input.release_all_keys();
+            cg.observer_fast = false;
+            cg.observer_slow = false;
+        }
+    }
+
     fn close_console(&mut self) {
         let grab = self.console.close(&mut self.engine.user_interface);
         self.set_mouse_grab(grab);
@@ -252,31 +596,48 @@ impl ClientProcess {
         use PhysicalKey::*;
 
         let pressed = event.state == ElementState::Pressed;
+        let Some(cg) = &mut self.cg else {
+            return;
+        };
 
         match event.physical_key {
-            Code(KeyW) => self.cg.input.forward = pressed,
-            Code(KeyA) => self.cg.input.left = pressed,
-            Code(KeyS) => self.cg.input.backward = pressed,
-            Code(KeyD) => self.cg.input.right = pressed,
-            Code(Space) => self.cg.input.up = pressed,
-            Code(ShiftLeft) => self.cg.input.down = pressed,
-            Code(KeyQ) => self.cg.input.prev_weapon = pressed,
-            Code(KeyE) => self.cg.input.next_weapon = pressed,
-            Code(KeyR) => self.cg.input.reload = pressed,
-            Code(KeyF) => self.cg.input.flag = pressed,
-            Code(KeyG) => self.cg.input.grenade = pressed,
-            Code(KeyK) => self.cg.input.kill = pressed,
-            Code(KeyM) => self.cg.input.map = pressed,
-            Code(Tab) => self.cg.input.score = pressed,
-            Code(Enter) => self.cg.input.chat = pressed,
-            Code(Pause) => self.cg.input.pause = pressed,
-            Code(F12) => self.cg.input.screenshot = pressed,
+            Code(KeyW) => cg.input.forward = pressed,
+            Code(KeyA) => cg.input.left = pressed,
+            Code(KeyS) => cg.input.backward = pressed,
+            Code(KeyD) => cg.input.right = pressed,
+            Code(Space) => cg.input.up = pressed,
+            Code(KeyC) => cg.input.down = pressed,
+            Code(ShiftLeft) => cg.observer_fast = pressed,
+            Code(KeyQ) => cg.input.prev_weapon = pressed,
+            Code(KeyE) => cg.input.next_weapon = pressed,
+            Code(KeyR) => cg.input.reload = pressed,
+            Code(KeyF) => cg.input.flag = pressed,
+            Code(KeyG) => cg.input.grenade = pressed,
+            Code(KeyK) => cg.input.kill = pressed,
+            Code(KeyM) => cg.input.map = pressed,
+            Code(Tab) => cg.input.score = pressed,
+            Code(Enter) => cg.input.chat = pressed,
+            Code(Pause) => cg.input.pause = pressed,
+            Code(F12) => cg.input.screenshot = pressed,
+            Code(ControlLeft) => {
+                cg.input.boost = pressed;
+                cg.observer_slow = pressed;
+            }
+            Code(F1) if pressed && cg.vote.is_some() && !cg.voted => cg.vote(true),
+            Code(Digit1) if pressed => cg.observer_jump = Some(0),
+            Code(Digit2) if pressed => cg.observer_jump = Some(1),
+            Code(Digit3) if pressed => cg.observer_jump = Some(2),
+            Code(Digit4) if pressed => cg.observer_jump = Some(3),
+            Code(Digit5) if pressed => cg.observer_jump = Some(4),
+            Code(Digit6) if pressed => cg.observer_jump = Some(5),
+            Code(Digit7) if pressed => cg.observer_jump = Some(6),
+            Code(Digit8) if pressed => cg.observer_jump = Some(7),
+            Code(Digit9) if pressed => cg.observer_jump = Some(8),
+            Code(Digit0) if pressed => cg.observer_jump = Some(9),
             _ => (),
         }
 
-        self.cg.input.real_time = self.real_time();
-        self.cg.input.game_time = self.gs.game_time;
-        self.cg.send_input();
+        cg.latch_buttons();
     }
 
     pub fn mouse_wheel(&self, delta: MouseScrollDelta, phase: TouchPhase) {
@@ -301,25 +662,61 @@ impl ClientProcess {
             dbg_logf!("{} mouse_input: {:?} {:?}", self.real_time(), state, button);
         }
 
-        if !self.console.is_open() {
-            self.set_mouse_grab(true);
+        if self.is_menu_open() || self.cg.is_none() {
+            return;
+        }
+
+        self.set_mouse_grab(true);
 
-            let pressed = state == ElementState::Pressed;
-            match button {
-                MouseButton::Left => self.cg.input.fire1 = pressed,
-                MouseButton::Right => self.cg.input.fire2 = pressed,
-                MouseButton::Middle => self.cg.input.zoom = pressed,
-                MouseButton::Back => self.cg.input.marker2 = pressed,
-                MouseButton::Forward => self.cg.input.marker1 = pressed,
-                MouseButton::Other(8) => self.cg.input.marker1 = pressed,
-                MouseButton::Other(9) => self.cg.input.marker2 = pressed,
-                MouseButton::Other(_) => {}
+        let cg = self.cg.as_mut().unwrap();
+
+        let pressed = state == ElementState::Pressed;
+        match button {
+            MouseButton::Left => cg.input.fire1 = pressed,
+            MouseButton::Right => {
+                cg.input.fire2 = pressed;
+                cg.observer_teleport |= pressed;
             }
+            MouseButton::Middle => cg.input.zoom = pressed,
+            MouseButton::Back => cg.input.marker2 = pressed,
+            MouseButton::Forward => cg.input.marker1 = pressed,
+            MouseButton::Other(8) => cg.input.marker1 = pressed,
+            MouseButton::Other(9) => cg.input.marker2 = pressed,
+            MouseButton::Other(_) => {}
+        }
 
-            self.cg.input.real_time = self.real_time();
-            self.cg.input.game_time = self.gs.game_time;
-            self.cg.send_input();
+        cg.latch_buttons();
+    }
+
+    pub fn touch(&mut self, touch: &Touch) {
+        if self.cvars.d_events && self.cvars.d_events_touch {
+            dbg_logf!(
+                "{} touch: {} {:?} {:?}",
+                self.real_time(),
+                touch.id,
+                touch.phase,
+                touch.location
+            );
         }
+
+        if self.cvars.cl_touch_auto && !self.cvars.cl_touch {
+            dbg_logf!("Touch detected, enabling touch controls");
+            self.cvars.cl_touch = true;
+        }
+
+        if !self.cvars.cl_touch || self.is_menu_open() || self.cg.is_none() {
+            self.touch_controls.reset();
+            return;
+        }
+
+        let cg = self.cg.as_mut().unwrap();
+        let (changed, look) = self.touch_controls.touch(&self.cvars, touch, &mut cg.input);
+        if changed {
+            cg.latch_buttons();
+        }
+
+        let sens = self.cvars.cl_touch_sensitivity;
+        cg.look(&self.cvars, (look.x as f64, look.y as f64), sens, sens);
     }
 
     /// Either grab mouse and hide cursor
@@ -372,7 +769,7 @@ impl ClientProcess {
             dbg_logf!("{} mouse_motion: {:?}", self.real_time(), delta);
         }
 
-        if self.console.is_open() {
+        if self.is_menu_open() {
             return;
         }
 
@@ -388,26 +785,477 @@ impl ClientProcess {
         // based on real time from last event. Instead, save the cumulative delta
         // and update angles/speeds once per frame.
 
-        let zoom_factor = if self.cg.input.zoom {
-            self.cvars.cl_zoom_factor
-        } else {
-            1.0
+        let Some(cg) = &mut self.cg else {
+            return;
         };
-
         let sens_h = self.cvars.m_sensitivity * self.cvars.m_sensitivity_horizontal;
         let sens_v = self.cvars.m_sensitivity * self.cvars.m_sensitivity_vertical;
-        // Subtract, don't add the delta - nalgebra rotations are counterclockwise.
-        let delta_yaw = -delta.0 as f32 * sens_h / zoom_factor;
-        let delta_pitch = delta.1 as f32 * sens_v / zoom_factor;
+        cg.look(&self.cvars, delta, sens_h, sens_v);
+    }
 
-        self.cg.delta_yaw += delta_yaw;
-        self.cg.delta_pitch += delta_pitch;
+    /// Apply gamepad input to the splitscreen player, or the first player if there's only one.
+    ///
+    /// Unlike mouse motion, sticks report a position, not a delta,
+    /// so turning depends on how long the frame took.
+    fn gamepad_input(&mut self, frame_time: f32) {
+        let menu_open = self.is_menu_open();
+        let cg = match (&mut self.cg, &mut self.cg2) {
+            _ if menu_open => None,
+            (_, Some(cg2)) => Some(cg2),
+            (cg, None) => cg.as_mut(),
+        };
+        let Some(cg) = cg else {
+            self.gamepads.update(&self.cvars, None, frame_time);
+            return;
+        };
+        let (changed, look) = self.gamepads.update(&self.cvars, Some(&mut cg.input), frame_time);
+        if changed {
+            cg.latch_buttons();
+        }
+
+        let sens = self.cvars.cl_gamepad_sensitivity;
+        cg.look(&self.cvars, (look.x as f64, look.y as f64), sens, sens);
     }
 
     pub fn ui_message(&mut self, msg: &UiMessage) {
         self.ui_message_logging(msg);
 
-        self.console.ui_message(&mut self.engine.user_interface, &mut self.cvars, msg);
+        let command =
+            self.console.ui_message(&mut self.engine.user_interface, &mut self.cvars, msg);
+        match command {
+            Some(ConsoleCommand::CallVote(kind)) => match &mut self.cg {
+                Some(cg) => cg.call_vote(kind),
+                None => dbg_logf!("can't call a vote when not in a game"),
+            },
+            Some(ConsoleCommand::FrameStatsReset) => self.frame_stats.reset(),
+            Some(ConsoleCommand::Load(name)) => self.load_savestate(&name),
+            Some(ConsoleCommand::Map(map)) => self.change_map(map),
+            Some(ConsoleCommand::Save(name)) => self.save_savestate(&name),
+            Some(ConsoleCommand::Stats(player)) => match &mut self.cg {
+                Some(cg) => cg.request_stats(player),
+                None => dbg_logf!("can't request stats when not in a game"),
+            },
+            Some(ConsoleCommand::Step) => {
+                if self.sg.is_some() && self.cvars.d_singlestep {
+                    self.step_requested = true;
+                } else {
+                    dbg_logf!("step only works in local games with d_singlestep on");
+                }
+            }
+            None => {}
+        }
+
+        let real_time = self.real_time();
+        let addr =
+            self.browser
+                .ui_message(&mut self.engine.user_interface, &self.cvars, real_time, msg);
+        if let Some(addr) = addr {
+            self.connect_to(&addr.to_string());
+        }
+
+        let back = self.settings.ui_message(&mut self.engine.user_interface, &mut self.cvars, msg);
+        if back {
+            self.close_settings();
+        }
+
+        if let Some(action) = self.lobby.ui_message(msg) {
+            // Both splitscreen players share the screen so they get ready together.
+            for cg in self.cg.iter_mut().chain(self.cg2.iter_mut()) {
+                match action {
+                    LobbyAction::Team(team) => cg.set_team(team),
+                    LobbyAction::Ready(ready) => cg.set_ready(ready),
+                }
+            }
+        }
+
+        match self.menu.ui_message(msg) {
+            Some(MenuAction::PlayLocal) => self.play_local(),
+            Some(MenuAction::Connect(addr)) => self.connect_to(&addr),
+            Some(MenuAction::Settings) => {
+                self.menu.close(&mut self.engine.user_interface);
+                self.settings.open(&mut self.engine.user_interface, &self.cvars);
+            }
+            Some(MenuAction::Quit) => self.exit = true,
+            None => {}
+        }
+    }
+
+    /// Resolve `addr` and connect to it, see `connect`.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn connect_to(&mut self, addr: &str) {
+        match net::resolve(addr, self.cvars.net_prefer_ipv6) {
+            Ok(addr) => self.connect(addr),
+            Err(e) => self.connect_failed(&format!("Invalid address {addr}: {e}")),
+        }
+    }
+
+    /// Browsers can't resolve names or open TCP connections, the WebSocket does both.
+    #[cfg(target_arch = "wasm32")]
+    fn connect_to(&mut self, addr: &str) {
+        self.leave_game(addr);
+        match net::WebSocketConnection::connect(addr, self.cvars.cl_net_webrtc) {
+            Ok(conn) => {
+                let start = self.real_time();
+                self.connecting = Some(Connecting::WebSocket { conn, start });
+                self.set_status(&format!("Connecting to {addr}..."));
+            }
+            Err(e) => self.connect_failed(&format!("Failed to connect to {addr}: {e}")),
+        }
+    }
+
+    /// Leave the current game (local or remote) and start connecting to the server at `addr`.
+    ///
+    /// Progress is checked every frame in `poll_connecting`.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn connect(&mut self, addr: SocketAddr) {
+        self.leave_game(&addr.to_string());
+
+        let pending = net::tcp_connect(&self.cvars, addr);
+        self.connecting = Some(Connecting::Tcp { addr, pending });
+        self.set_status(&format!("Connecting to {addr}..."));
+    }
+
+    /// Leave the current game (local or remote) before connecting to `addr`.
+    fn leave_game(&mut self, addr: &str) {
+        dbg_logf!("{} connecting to {}", self.real_time(), addr);
+
+        self.browser.close(&mut self.engine.user_interface);
+        self.menu.close(&mut self.engine.user_interface);
+
+        self.cvars.cl_connect_addr = addr.to_owned();
+        self.connect_addr = self.cvars.cl_connect_addr.clone();
+
+        if self.cg.is_some() || self.sg.is_some() {
+            // The server tells us its map in init, this is just a placeholder until then.
+            self.new_game_state(GameStateType::Client);
+        }
+    }
+
+    /// Leave the current game (local or remote) and start a local one.
+    fn play_local(&mut self) {
+        dbg_logf!("{} starting local game", self.real_time());
+
+        self.browser.close(&mut self.engine.user_interface);
+        self.menu.close(&mut self.engine.user_interface);
+        self.set_mouse_grab(true);
+
+        self.connecting = None;
+        self.new_game_state(GameStateType::Shared);
+        let (sg, connecting, connecting2) =
+            executor::block_on(start_local_server(&self.cvars, &mut self.engine, &mut self.gs));
+        self.sg = Some(sg);
+        self.connecting = Some(connecting);
+        self.connecting2 = connecting2;
+        self.set_status("");
+    }
+
+    /// Throw away the current game and load `g_map` into a fresh game state.
+    fn new_game_state(&mut self, gs_type: GameStateType) {
+        self.cg = None;
+        self.cg2 = None;
+        self.connecting2 = None;
+        self.sg = None;
+        self.engine.scenes.remove(self.gs.scene_handle);
+        self.gs = executor::block_on(GameState::new(
+            &self.cvars,
+            &self.engine.resource_manager,
+            &mut self.engine.scenes,
+            gs_type,
+            &self.cvars.g_map,
+        ));
+
+        // The new game starts at time 0, without this the game loop would try to catch up.
+        self.clock = Instant::now();
+        self.manual_time = self.manual_time.map(|_| 0.0);
+        self.real_time_prev = 0.0;
+        self.game_time_target = 0.0;
+    }
+
+    /// Introduce ourselves to the server and wait for init.
+    ///
+    /// The connection has already sent our version.
+    fn connected(&mut self, mut conn: Box<dyn Connection<ServerMessage>>) {
+        let name = net::serialize(ClientMessage::Name(self.cvars.cl_name.clone()));
+        let guid = net::serialize(ClientMessage::Guid(self.cvars.cl_guid.clone()));
+        let res = conn.send(&name).and_then(|()| conn.send(&guid));
+        if let Err(e) = res {
+            self.connect_failed(&format!("Failed to send player name: {e}"));
+            return;
+        }
+        self.connecting = Some(Connecting::Init {
+            conn,
+            start: self.real_time(),
+        });
+    }
+
+    fn poll_connecting(&mut self) {
+        let Some(connecting) = self.connecting.take() else {
+            return;
+        };
+
+        match connecting {
+            #[cfg(not(target_arch = "wasm32"))]
+            Connecting::Tcp { addr, mut pending } => match pending.poll() {
+                None => self.connecting = Some(Connecting::Tcp { addr, pending }),
+                Some(Ok(conn)) => self.connected(Box::new(conn)),
+                Some(Err(e)) => self.connect_failed(&format!("Failed to connect to {addr}: {e}")),
+            },
+            #[cfg(target_arch = "wasm32")]
+            Connecting::WebSocket { mut conn, start } => match conn.poll_open() {
+                None if self.real_time() - start > self.cvars.cl_net_connect_timeout => {
+                    self.connect_failed("Timed out connecting to server");
+                }
+                None => self.connecting = Some(Connecting::WebSocket { conn, start }),
+                Some(Ok(())) => self.connected(Box::new(conn)),
+                Some(Err(e)) => self.connect_failed(&format!("Failed to connect: {e}")),
+            },
+            Connecting::Init { mut conn, start } => {
+                let (msg, closed) = conn.receive_one(self.cvars.cl_net_max_message_len);
+                match msg {
+                    Some(ServerMessage::Version(version, _)) => {
+                        dbg_logf!("client version: {}", Version::current());
+                        dbg_logf!("server version: {}", version);
+                        if version.game != Version::current().game {
+                            self.connect_failed(&format!("Not a RustCycles server: {version}"));
+                            return;
+                        }
+                        self.connecting = Some(Connecting::Init { conn, start });
+                    }
+                    Some(ServerMessage::Init(init)) => {
+                        let missing = self.gs.gs_type == GameStateType::Client
+                            && maps::path(&init.map).is_none();
+                        if !missing {
+                            self.start_game(conn, init);
+                        } else if !self.cvars.cl_map_download {
+                            self.connect_failed(&format!(
+                                "The server uses unknown map {}",
+                                init.map
+                            ));
+                        } else {
+                            dbg_logf!("downloading map {}", init.map);
+                            self.set_status(&format!("Downloading map {}", init.map));
+                            let msg = net::serialize(ClientMessage::RequestMap(init.map.clone()));
+                            if let Err(e) = conn.send(&msg) {
+                                self.connect_failed(&format!("Failed to request map: {e}"));
+                                return;
+                            }
+                            self.connecting = Some(Connecting::Download {
+                                conn,
+                                init,
+                                data: Vec::new(),
+                                last_chunk: self.real_time(),
+                            });
+                        }
+                    }
+                    Some(ServerMessage::ChangeMap { .. }) => {
+                        // The map changed before we got to the old init,
+                        // the new one follows.
+                        self.connecting = Some(Connecting::Init { conn, start });
+                    }
+                    Some(_) => self.connect_failed("The first message from the server wasn't init"),
+                    None if closed => self.connect_failed("Connection closed before init"),
+                    None if self.real_time() - start > self.cvars.cl_net_init_timeout => {
+                        self.connect_failed("The server didn't send init data");
+                    }
+                    None => self.connecting = Some(Connecting::Init { conn, start }),
+                }
+            }
+            Connecting::Download {
+                mut conn,
+                init,
+                mut data,
+                mut last_chunk,
+            } => loop {
+                let (msg, closed) = conn.receive_one(self.cvars.cl_net_max_message_len);
+                match msg {
+                    Some(ServerMessage::MapChunk(chunk)) => {
+                        if chunk.map != init.map || chunk.offset != data.len() as u64 {
+                            self.connect_failed("Received wrong part of the map");
+                            return;
+                        }
+                        data.extend_from_slice(&chunk.data);
+                        last_chunk = self.real_time();
+                        let percent = data.len() as f32 / chunk.total_len.max(1) as f32 * 100.0;
+                        self.set_status(&format!("Downloading map {} {:.0}%", init.map, percent));
+                        if data.len() as u64 >= chunk.total_len {
+                            if maps::hash(&data) != chunk.hash {
+                                self.connect_failed("The downloaded map is corrupted");
+                                return;
+                            }
+                            if let Err(e) = save_map(&init.map, &data) {
+                                self.connect_failed(&format!("Failed to save map: {e}"));
+                                return;
+                            }
+                            dbg_logf!("downloaded map {} ({} bytes)", init.map, data.len());
+                            self.start_game(conn, init);
+                            // We missed everything the server sent while downloading.
+                            self.cg.as_mut().unwrap().request_full_sync();
+                            return;
+                        }
+                    }
+                    Some(ServerMessage::ChangeMap { .. }) => {
+                        // Don't need this map anymore, the new init follows.
+                        self.connecting = Some(Connecting::Init {
+                            conn,
+                            start: self.real_time(),
+                        });
+                        return;
+                    }
+                    // Game state messages are useless without the map,
+                    // we get a full sync after downloading.
+                    Some(_) => {}
+                    None if closed => {
+                        self.connect_failed("Connection closed while downloading the map");
+                        return;
+                    }
+                    None if self.real_time() - last_chunk > self.cvars.cl_net_init_timeout => {
+                        self.connect_failed("The server stopped sending the map");
+                        return;
+                    }
+                    None => {
+                        self.connecting = Some(Connecting::Download {
+                            conn,
+                            init,
+                            data,
+                            last_chunk,
+                        });
+                        return;
+                    }
+                }
+            },
+        }
+    }
+
+    fn start_game(&mut self, conn: Box<dyn Connection<ServerMessage>>, mut init: Init) {
+        let motd = std::mem::take(&mut init.motd);
+        if !motd.is_empty() {
+            dbg_logf!("Message of the day:");
+            for line in motd.lines() {
+                dbg_logf!("    {}", line);
+            }
+            self.motd = Some((motd, self.real_time()));
+        }
+
+        // In local games, the server has already loaded the map into the shared state.
+        // Remote clients might have a different map or a game state from before a map change.
+        let stale = init.map != self.gs.map_name || self.gs.players.alive_count() > 0;
+        if self.gs.gs_type == GameStateType::Client && stale {
+            let map = init.map.clone();
+            self.reload_game_state(&map);
+        }
+        // A fresh game state starts in warmup or playing depending on local cvars.
+        self.gs.match_start = init.match_start;
+        self.gs.match_state = init.match_state;
+        let res = executor::block_on(ClientGame::new(
+            &self.cvars,
+            &mut self.engine,
+            self.debug_text,
+            conn,
+            &mut self.gs,
+            init,
+            false,
+        ));
+        match res {
+            Ok(cg) => {
+                self.cg = Some(cg);
+                self.set_status("");
+            }
+            Err(e) => self.connect_failed(&format!("Invalid init from server: {e}")),
+        }
+    }
+
+    /// Throw away the client game but keep the connection and wait for a new init,
+    /// e.g. after the server changed the map.
+    fn reinit(&mut self) {
+        if let Some(cg2) = self.cg2.take() {
+            self.connecting2 = Some(cg2.into_conn());
+        }
+        let Some(cg) = self.cg.take() else {
+            return;
+        };
+        self.connecting = Some(Connecting::Init {
+            conn: cg.into_conn(),
+            start: self.real_time(),
+        });
+    }
+
+    /// Create the splitscreen player's game once its init arrives.
+    ///
+    /// It only plays on the local server so the map is always loaded already.
+    fn poll_connecting2(&mut self) {
+        let Some(mut conn) = self.connecting2.take() else {
+            return;
+        };
+        loop {
+            let (msg, closed) = conn.receive_one(self.cvars.cl_net_max_message_len);
+            match msg {
+                Some(ServerMessage::Init(init)) => {
+                    let res = executor::block_on(ClientGame::new(
+                        &self.cvars,
+                        &mut self.engine,
+                        self.debug_text,
+                        conn,
+                        &mut self.gs,
+                        init,
+                        true,
+                    ));
+                    match res {
+                        Ok(cg2) => self.cg2 = Some(cg2),
+                        Err(e) => dbg_logf!("splitscreen player's init is invalid: {e}"),
+                    }
+                    return;
+                }
+                // The version comes before init, after a map change the new init follows.
+                Some(_) => {}
+                None if closed => {
+                    dbg_logf!("splitscreen player's connection closed before init");
+                    return;
+                }
+                None => {
+                    self.connecting2 = Some(conn);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Replace the client's game state with an empty one using the server's map.
+    ///
+    /// Time continues from the old game state.
+    fn reload_game_state(&mut self, map: &str) {
+        if maps::path(map).is_none() {
+            panic!("map {map} should have been downloaded before loading it");
+        }
+        self.engine.scenes.remove(self.gs.scene_handle);
+        let mut gs = executor::block_on(GameState::new(
+            &self.cvars,
+            &self.engine.resource_manager,
+            &mut self.engine.scenes,
+            GameStateType::Client,
+            map,
+        ));
+        gs.frame_num = self.gs.frame_num;
+        gs.game_time = self.gs.game_time;
+        gs.game_time_prev = self.gs.game_time_prev;
+        self.gs = gs;
+    }
+
+    fn connect_failed(&mut self, msg: &str) {
+        dbg_logf!("{} {}", self.real_time(), msg);
+        self.connecting = None;
+        self.set_status(&format!("{msg}\nPress F2 to open the server browser."));
+        // Let the player try again or do something else.
+        if !self.cvars.cl_headless && self.cg.is_none() {
+            self.open_menu();
+        }
+    }
+
+    fn set_status(&mut self, status: &str) {
+        self.engine.user_interface.send_message(TextMessage::text(
+            self.status_text,
+            MessageDirection::ToWidget,
+            status.to_owned(),
+        ));
     }
 
     fn ui_message_logging(&mut self, msg: &UiMessage) {
@@ -438,47 +1286,124 @@ impl ClientProcess {
         }
     }
 
-    pub fn update(&mut self, window_target: &EventLoopWindowTarget<()>) {
+    /// `window_target` is None when running without an event loop, e.g. in tests.
+    pub fn update(&mut self, window_target: Option<&EventLoopWindowTarget<()>>) {
         // LATER read these (again), verify what works best in practise:
         // https://gafferongames.com/post/fix_your_timestep/
         // https://medium.com/@tglaiel/how-to-make-your-game-run-at-60fps-24c61210fe75
 
-        let game_time_target = self.real_time();
+        debug::config_watch::update(&mut self.cvars);
+        debug::logger::update(&self.cvars);
+        debug::crash::update(&self.cvars);
+        let update_start = Instant::now();
+        debug::profiler::frame(&mut self.cvars);
+        net::stats_frame(&self.cvars);
+        trace::stats_frame(&self.cvars);
+        profile_scope!("update");
+
+        #[cfg(not(target_arch = "wasm32"))]
+        self.hot_reload_assets();
+
+        if let Some(sg) = &mut self.sg {
+            let scene_prev = self.gs.scene_handle;
+            if let Some(map) = sg.next_map.take() {
+                // Intermission is over.
+                self.cvars.g_map = map;
+                sg.change_map(&mut self.cvars, &mut self.engine, &mut self.gs, true);
+            } else if self.cvars.g_map != self.gs.map_name {
+                sg.change_map(&mut self.cvars, &mut self.engine, &mut self.gs, false);
+            }
+            // The server's scene is shared with the client
+            // so the client has to start over before it touches the old one.
+            if self.gs.scene_handle != scene_prev {
+                self.reinit();
+            }
+        }
+        self.poll_connecting();
+        self.poll_connecting2();
+
+        let real_time = self.real_time();
+        if self.sg.is_some() && self.cvars.d_singlestep {
+            if self.step_requested {
+                self.step_requested = false;
+                // Halfway between the next frame and the one after
+                // so float errors don't cause running zero or two frames.
+                self.game_time_target = self.gs.game_time + 1.5 * self.gs.dt;
+            }
+        } else if self.sg.is_some() {
+            self.step_requested = false;
+            let speed = if self.cvars.d_pause {
+                0.0
+            } else {
+                self.cvars.d_speed
+            };
+            self.game_time_target += (real_time - self.real_time_prev) * speed;
+        } else {
+            // The server decides how fast time runs.
+            self.game_time_target = real_time;
+        }
+        let frame_time = real_time - self.real_time_prev;
+        self.real_time_prev = real_time;
+        self.gamepad_input(frame_time);
+        for cg in self.cg.iter_mut().chain(self.cg2.iter_mut()) {
+            cg.input.real_time = real_time;
+        }
+        let game_time_target = self.game_time_target;
 
         let dt_update = game_time_target - self.gs.game_time;
         if dt_update > 5.0 {
             dbg_logf!("large dt_update: {dt_update}");
         }
 
-        // LATER Abstract game loop logic and merge with server?
-        let dt = 1.0 / 60.0;
-        while self.gs.game_time + dt < game_time_target {
-            self.gs.frame_num += 1;
-            self.gs.game_time_prev = self.gs.game_time;
-            self.gs.game_time += dt;
-            debug::set_game_time(self.gs.game_time);
+        // See engine_loop for why this isn't a fyrox plugin.
+        let dt = self.gs.dt;
+        let ticks_start = Instant::now();
+        let mut physics_ms = 0.0;
+        while engine_loop::next_tick(&mut self.gs, game_time_target) {
+            profile_scope!("tick");
 
             // LATER Check order of cl and sv stuff for minimum latency.
             // LATER change endpoint name for parts to locl/losv?
 
-            self.cl_ctx().tick_begin_frame();
+            self.cl_ctx().map(|mut ctx| ctx.tick_begin_frame());
+            self.cl_ctx2().map(|mut ctx| ctx.tick_begin_frame());
+            if self.cg.as_ref().is_some_and(|cg| cg.map_change) {
+                // The rest of the messages are for the new map.
+                self.reinit();
+                break;
+            }
             self.sv_ctx().map(|mut ctx| ctx.tick_begin_frame());
 
+            let snapshot = (self.sg.is_some() && self.cvars.d_determinism_check)
+                .then(|| self.ctx().determinism_snapshot());
             self.ctx().tick_before_physics(dt);
 
-            self.cl_ctx().tick_before_physics(dt);
+            self.cl_ctx().map(|mut ctx| ctx.tick_before_physics(dt));
+            self.cl_ctx2().map(|mut ctx| ctx.tick_before_physics(dt));
+
+            let physics_start = Instant::now();
+            {
+                profile_scope!("physics");
+                engine_loop::update_scene(
+                    &mut self.engine,
+                    self.gs.scene_handle,
+                    dt,
+                    window_target,
+                );
+            }
+            physics_ms += physics_start.elapsed().as_secs_f32() * 1000.0;
 
-            // Update animations, transformations, physics, ...
-            // Dummy lag since we don't use fyrox plugins.
-            let mut lag = 0.0;
-            self.engine.pre_update(dt, window_target, &mut lag, FxHashMap::default());
-            // Sanity check - if the engine starts doing something with this, we'll know.
-            assert_eq!(lag, 0.0);
+            self.ctx().tick_after_physics();
+            if let Some(snapshot) = snapshot {
+                self.ctx().check_determinism(snapshot, dt);
+            }
+            self.sv_ctx().map(|mut ctx| ctx.tick_after_physics());
 
             // `tick_after_physics` tells the engine to draw debug shapes and text.
             // Any debug calls after it will show up next frame.
             self.ctx().debug_engine_updates(v!(-5 3 3));
-            self.cl_ctx().tick_after_physics(dt);
+            self.cl_ctx().map(|mut ctx| ctx.tick_after_physics(dt));
+            self.cl_ctx2().map(|mut ctx| ctx.tick_after_physics(dt));
             self.ctx().debug_engine_updates(v!(-6 3 3));
 
             // `sys_send_update` sends debug shapes and text to client.
@@ -487,11 +1412,403 @@ impl ClientProcess {
             self.sv_ctx().map(|mut ctx| ctx.sys_send_update());
             self.ctx().debug_engine_updates(v!(-6 5 3));
 
-            // Update UI
-            self.engine.post_update(dt);
+            engine_loop::update_ui(&mut self.engine, dt);
+        }
+        let ticks_ms = ticks_start.elapsed().as_secs_f32() * 1000.0;
+
+        let alpha = if self.sg.is_some() && self.cvars.d_singlestep {
+            // Show exactly the frame that was just simulated, not a blend with the previous one.
+            1.0
+        } else {
+            ((game_time_target - self.gs.game_time) / dt).clamp(0.0, 1.0)
+        };
+        self.cl_ctx().map(|mut ctx| ctx.interpolate(alpha));
+        self.cl_ctx2().map(|mut ctx| ctx.interpolate(alpha));
+        self.update_viewports();
+
+        let viewports = self.viewports();
+        let local_players = [&self.cg, &self.cg2];
+        self.hud.update_viewports(
+            &mut self.engine.user_interface,
+            [0, 1].map(|i| local_players[i].as_ref().map(|_| viewports[i])),
+        );
+        let graph = &self.engine.scenes[self.gs.scene_handle].graph;
+        let mut damage_numbers = Vec::new();
+        for (i, cg) in local_players.into_iter().enumerate() {
+            let Some(cg) = cg else {
+                continue;
+            };
+            let cycle = self
+                .gs
+                .players
+                .try_borrow(cg.player_handle)
+                .and_then(|player| player.cycle_handle)
+                .map(|cycle_handle| &self.gs.cycles[cycle_handle]);
+            self.hud.update(&mut self.engine.user_interface, &self.cvars, i, cycle);
+
+            let killer = cg
+                .killcam
+                .and_then(|killcam| self.gs.players.try_borrow(killcam.killer_handle))
+                .map(|killer| killer.net_id);
+            self.hud.update_killcam(&mut self.engine.user_interface, i, killer);
+
+            let camera = graph[cg.camera_handle].as_camera();
+            self.hud.update_damage_indicators(
+                &mut self.engine.user_interface,
+                &self.cvars,
+                i,
+                self.gs.game_time,
+                camera,
+                &cg.damage_indicators,
+            );
+            damage_numbers.push((camera, cg.damage_numbers.as_slice()));
         }
+        self.hud.update_damage_numbers(
+            &mut self.engine.user_interface,
+            &self.cvars,
+            self.gs.game_time,
+            &damage_numbers,
+        );
+        let show_scores =
+            self.gs.match_state.is_over() || self.cg.as_ref().is_some_and(|cg| cg.input.score);
+        self.hud
+            .update_scoreboard(&mut self.engine.user_interface, &self.gs, show_scores);
+        let crosshairs: Vec<_> = local_players
+            .into_iter()
+            .zip(viewports)
+            .filter_map(|(cg, viewport)| self.crosshair(cg.as_ref()?, viewport))
+            .collect();
+        self.hud.update_errors(&mut self.engine.user_interface, &self.cvars);
+        self.hud
+            .update_crosshair(&mut self.engine.user_interface, &self.cvars, &crosshairs);
+        self.hud.update_match_state(&mut self.engine.user_interface, &self.gs);
+        let local_state = self
+            .cg
+            .as_ref()
+            .and_then(|cg| self.gs.players.try_borrow(cg.player_handle))
+            .map(|player| player.state);
+        self.hud.update_spectator_panel(
+            &mut self.engine.user_interface,
+            &self.cvars,
+            &self.gs,
+            local_state,
+        );
+        if let Some((_, time)) = self.motd {
+            if real_time - time > self.cvars.hud_motd_duration {
+                self.motd = None;
+            }
+        }
+        self.minimap.update(
+            &mut self.engine.user_interface,
+            &self.cvars,
+            &self.gs,
+            &self.engine.scenes[self.gs.scene_handle],
+            self.cg.as_ref(),
+        );
+        self.update_lobby();
+        let touch_visible = self.cvars.cl_touch && self.cg.is_some() && !self.is_menu_open();
+        self.touch_controls
+            .update(&mut self.engine.user_interface, &self.cvars, touch_visible);
+        let motd = self.motd.as_ref().map(|(motd, _)| motd.as_str());
+        self.hud.update_motd(&mut self.engine.user_interface, motd);
+        let (vote, voted) = match &self.cg {
+            Some(cg) => (cg.vote.as_ref(), cg.voted),
+            None => (None, false),
+        };
+        self.hud.update_vote(
+            &mut self.engine.user_interface,
+            &self.cvars,
+            self.gs.game_time,
+            vote,
+            voted,
+        );
+
+        let net_bytes = self.cg.as_mut().map_or(0, |cg| std::mem::take(&mut cg.net_bytes));
+        let sample = GraphSample {
+            frame_ms: frame_time * 1000.0,
+            tick_ms: ticks_ms - physics_ms,
+            physics_ms,
+            net_bytes,
+        };
+        self.graphs.update(&mut self.engine.user_interface, &self.cvars, sample);
+
+        self.browser.update(&mut self.engine.user_interface, &self.cvars, real_time);
+        self.console.update(&mut self.engine.user_interface, &self.cvars);
+
+        if self.cvars.cl_connect_addr != self.connect_addr {
+            self.connect_addr = self.cvars.cl_connect_addr.clone();
+            let addr = self.connect_addr.clone();
+            self.connect_to(&addr);
+        }
+
+        if let Some(window_target) = window_target {
+            self.update_vsync(window_target);
+        }
+        self.update_window();
+        self.render_scale.update(
+            &mut self.engine.user_interface,
+            &mut self.engine.scenes[self.gs.scene_handle],
+            &self.cvars,
+            real_time,
+            frame_time,
+        );
 
         self.update_graphics();
+
+        // Cheap enough that there's no need to check whether it changed.
+        self.engine.sound_engine.state().set_master_gain(self.cvars.snd_volume);
+
+        let update_ms = update_start.elapsed().as_secs_f32() * 1000.0;
+        self.frame_stats
+            .add(self.cvars.d_frame_stats_samples, frame_time * 1000.0, update_ms);
+        if let Some(cg) = &mut self.cg {
+            cg.frame_stats = if self.cvars.d_frame_stats {
+                self.frame_stats.text()
+            } else {
+                String::new()
+            };
+        }
+
+        self.frames += 1;
+        if self.cvars.d_benchmark_frames > 0 && self.frames >= self.cvars.d_benchmark_frames {
+            dbg_logf!("benchmark finished:\n{}", self.frame_stats.text());
+            self.exit = true;
+        }
+    }
+
+    /// The first and second player's part of the screen, see `cl_splitscreen`.
+    fn viewports(&self) -> [Rect<f32>; 2] {
+        if self.cg2.is_some() {
+            [Rect::new(0.0, 0.0, 1.0, 0.5), Rect::new(0.0, 0.5, 1.0, 0.5)]
+        } else {
+            [Rect::new(0.0, 0.0, 1.0, 1.0), Rect::new(0.0, 0.0, 1.0, 1.0)]
+        }
+    }
+
+    /// Give each local player's camera its part of the screen.
+    ///
+    /// Both cameras draw into the same target, the window or `RenderScale`'s texture.
+    fn update_viewports(&mut self) {
+        let viewports = self.viewports();
+        let graph = &mut self.engine.scenes[self.gs.scene_handle].graph;
+        for (cg, viewport) in [&self.cg, &self.cg2].into_iter().zip(viewports) {
+            if let Some(cg) = cg {
+                graph[cg.camera_handle].as_camera_mut().set_viewport(viewport);
+            }
+        }
+    }
+
+    /// The crosshair in the middle of the player's viewport, None if they're not driving.
+    fn crosshair(&self, cg: &ClientGame, viewport: Rect<f32>) -> Option<Crosshair> {
+        let player = &self.gs.players[cg.player_handle];
+        if player.state != PlayerState::Playing || player.cycle_handle.is_none() {
+            return None;
+        }
+        let hit = cg
+            .time_hit_confirmed
+            .is_some_and(|time| self.gs.game_time - time <= self.cvars.hud_crosshair_hit_duration);
+        let screen_size = self.engine.user_interface.screen_size();
+        let camera = &self.engine.scenes[self.gs.scene_handle].graph[cg.camera_handle];
+        // One standard deviation of the spread, projected onto the screen.
+        let spread = match camera.as_camera().projection() {
+            Projection::Perspective(perspective) => {
+                let tan = self.cvars.g_projectile_spread / self.cvars.g_projectile_speed;
+                let view_height = screen_size.y * viewport.h();
+                Some(tan / (perspective.fov / 2.0).tan() * view_height / 2.0)
+            }
+            Projection::Orthographic(_) => None,
+        };
+        let center = (viewport.position + viewport.size / 2.0).component_mul(&screen_size);
+        Some(Crosshair {
+            offset: center - screen_size / 2.0,
+            hit,
+            spread,
+        })
+    }
+
+    /// Switch a local game to another map, remote servers have to vote on it.
+    fn change_map(&mut self, map: String) {
+        if self.sg.is_some() {
+            if maps::path(&map).is_none() {
+                dbg_logf!("unknown map {}", map);
+                return;
+            }
+            // Loaded at the start of the next update.
+            self.cvars.g_map = map;
+        } else if let Some(cg) = &mut self.cg {
+            cg.call_vote(VoteKind::Map(map));
+        } else {
+            dbg_logf!("can't change the map when not in a game");
+        }
+    }
+
+    /// Start or stop watching assets according to `d_hot_reload_assets`
+    /// and reload the ones which changed.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn hot_reload_assets(&mut self) {
+        if !self.cvars.d_hot_reload_assets {
+            if self.asset_watcher.take().is_some() {
+                dbg_logf!("stopped watching data/");
+            }
+            self.asset_watcher_failed = false;
+            return;
+        }
+        if self.asset_watcher.is_none() && !self.asset_watcher_failed {
+            match AssetWatcher::new() {
+                Ok(watcher) => self.asset_watcher = Some(watcher),
+                Err(e) => {
+                    dbg_logf!("failed to watch data/: {}", e);
+                    self.asset_watcher_failed = true;
+                }
+            }
+        }
+        let Some(watcher) = &mut self.asset_watcher else {
+            return;
+        };
+
+        let reloaded = watcher.update(&self.engine.resource_manager);
+        // The map is instantiated when loading it so it has to be loaded again.
+        // Remote servers have their own copy, only local games can restart it.
+        if let Some(sg) = &mut self.sg {
+            let map_path = maps::path(&self.gs.map_name);
+            if reloaded.iter().any(|path| Some(path) == map_path.as_ref()) {
+                dbg_logf!("restarting {} to apply changes", self.gs.map_name);
+                sg.next_map = Some(self.gs.map_name.clone());
+            }
+        }
+    }
+
+    /// Savestates need both the server and client state, remote servers only share the latter.
+    fn savestates_available(&self) -> bool {
+        let local = self.sg.is_some() && self.cg.is_some();
+        if !local {
+            dbg_logf!("savestates only work in local games");
+        }
+        local
+    }
+
+    /// Save the current game state under `name`, see the `save` console command.
+    fn save_savestate(&mut self, name: &str) {
+        if !self.savestates_available() {
+            return;
+        }
+        let res = savestate::path(&self.cvars.d_savestate_dir, name).and_then(|path| {
+            savestate::save(&path, &self.ctx().full_sync())?;
+            Ok(path)
+        });
+        match res {
+            Ok(path) => dbg_logf!("saved frame {} to {}", self.gs.frame_num, path.display()),
+            Err(e) => dbg_logf!("failed to save {name}: {e}"),
+        }
+    }
+
+    /// Restore the game state saved under `name`, see the `load` console command.
+    fn load_savestate(&mut self, name: &str) {
+        if !self.savestates_available() {
+            return;
+        }
+        let res = savestate::path(&self.cvars.d_savestate_dir, name)
+            .and_then(|path| savestate::load(&path));
+        let sync = match res {
+            Ok(sync) => sync,
+            Err(e) => {
+                dbg_logf!("failed to load {name}: {e}");
+                return;
+            }
+        };
+
+        // Players are matched up by network ID.
+        let cg = self.cg.as_ref().unwrap();
+        let local_player_id = self.gs.players[cg.player_handle].net_id;
+        if !sync.players.iter().any(|sp| sp.player_id == local_player_id) {
+            // LATER Let the local player take over one of the savestate's players.
+            dbg_logf!("failed to load {name}: local player {local_player_id} is not in it");
+            return;
+        }
+        let client_players = self.sg.as_ref().unwrap().client_players(&self.gs);
+
+        self.gs.frame_num = sync.frame_num as usize;
+        self.gs.game_time = sync.game_time;
+        self.gs.game_time_prev = sync.game_time_prev;
+        self.game_time_target = sync.game_time;
+        debug::set_frame(self.gs.frame_num, self.gs.game_time);
+        self.ctx().apply_full_sync(sync);
+
+        self.sg.as_mut().unwrap().relink_players(&self.gs, client_players);
+        let player_handle = self.gs.player_ids.get(local_player_id).unwrap();
+        self.cg.as_mut().unwrap().player_handle = player_handle;
+
+        dbg_logf!("loaded frame {} from {name}", self.gs.frame_num);
+    }
+
+    /// Recreate the graphics context if `cl_vsync` changed - it can only be set when creating it.
+    fn update_vsync(&mut self, window_target: &EventLoopWindowTarget<()>) {
+        if self.cvars.cl_vsync == self.vsync || self.cvars.cl_headless {
+            return;
+        }
+        self.vsync = self.cvars.cl_vsync;
+        dbg_logf!("{} setting vsync to {}", self.real_time(), self.vsync);
+
+        let initialized = matches!(self.engine.graphics_context, GraphicsContext::Initialized(_));
+        if initialized {
+            self.engine.destroy_graphics_context().unwrap();
+        }
+        if let GraphicsContext::Uninitialized(params) = &mut self.engine.graphics_context {
+            params.vsync = self.vsync;
+        }
+        if initialized {
+            self.engine.initialize_graphics_context(window_target).unwrap();
+            // The new renderer and window start with default settings.
+            self.quality = None;
+            self.window_mode = None;
+        }
+    }
+
+    /// Switch between fullscreen and windowed mode, monitors or window sizes
+    /// when the `cl_fullscreen*`, `cl_monitor` or `cl_window_*` cvars change.
+    fn update_window(&mut self) {
+        let window = match &self.engine.graphics_context {
+            GraphicsContext::Initialized(ctx) => &ctx.window,
+            _ => return,
+        };
+
+        let mode = WindowMode {
+            fullscreen: self.cvars.cl_fullscreen,
+            exclusive: self.cvars.cl_fullscreen_exclusive,
+            monitor: self.cvars.cl_monitor,
+            width: self.cvars.cl_window_width,
+            height: self.cvars.cl_window_height,
+        };
+        if self.window_mode.as_ref() == Some(&mode) {
+            return;
+        }
+
+        if mode.fullscreen {
+            let monitor = window.available_monitors().nth(mode.monitor).or_else(|| {
+                dbg_logf!("monitor {} not found, using the current one", mode.monitor);
+                window.current_monitor()
+            });
+            let video_mode = monitor.as_ref().and_then(|monitor| {
+                monitor.video_modes().max_by_key(|video_mode| {
+                    let size = video_mode.size();
+                    (size.width * size.height, video_mode.refresh_rate_millihertz())
+                })
+            });
+            let fullscreen = match video_mode {
+                Some(video_mode) if mode.exclusive => Fullscreen::Exclusive(video_mode),
+                _ => Fullscreen::Borderless(monitor),
+            };
+            window.set_fullscreen(Some(fullscreen));
+        } else {
+            window.set_fullscreen(None);
+            let _ = window.request_inner_size(PhysicalSize::new(mode.width, mode.height));
+            if !self.cvars.cl_window_pos_auto {
+                let pos = PhysicalPosition::new(self.cvars.cl_window_x, self.cvars.cl_window_y);
+                window.set_outer_position(pos);
+            }
+        }
+
+        self.window_mode = Some(mode);
     }
 
     fn update_graphics(&mut self) {
@@ -500,19 +1817,24 @@ impl ClientProcess {
             _ => return,
         };
 
-        if self.cvars.r_quality != self.r_quality {
-            self.r_quality = self.cvars.r_quality;
-
-            let quality = match self.cvars.r_quality {
-                0 => QualitySettings::low(),
-                1 => QualitySettings::medium(),
-                2 => QualitySettings::high(),
-                _ => {
-                    dbg_logf!("Invalid r_quality value: {}", self.cvars.r_quality);
-                    QualitySettings::low()
-                }
-            };
-            ctx.renderer.set_quality_settings(&quality).unwrap();
+        let quality = match self.cvars.r_quality {
+            0 => QualitySettings::low(),
+            1 => QualitySettings::medium(),
+            2 => QualitySettings::high(),
+            3 => custom_quality(&self.cvars),
+            _ => QualitySettings::low(),
+        };
+        // Comparing the result means any of the custom cvars changing is also detected.
+        if self.quality != Some(quality) {
+            if !(0..=3).contains(&self.cvars.r_quality) {
+                dbg_logf!("Invalid r_quality value: {}", self.cvars.r_quality);
+            }
+            // The values come from cvars so they can be anything, e.g. too much for the GPU.
+            // Keeping the old `self.quality` means it's tried again until the cvars are fixed.
+            match ctx.renderer.set_quality_settings(&quality) {
+                Ok(()) => self.quality = Some(quality),
+                Err(e) => dbg_logf_every!(300, "failed to apply quality settings: {:?}", e),
+            }
         }
 
         ctx.window.request_redraw();
@@ -527,20 +1849,30 @@ impl ClientProcess {
         })
     }
 
-    fn cl_ctx(&mut self) -> ClientFrameCtx {
+    fn cl_ctx(&mut self) -> Option<ClientFrameCtx> {
+        self.cl_ctx_for(false)
+    }
+
+    /// Context of the splitscreen player.
+    fn cl_ctx2(&mut self) -> Option<ClientFrameCtx> {
+        self.cl_ctx_for(true)
+    }
+
+    fn cl_ctx_for(&mut self, second: bool) -> Option<ClientFrameCtx> {
         let renderer = match &mut self.engine.graphics_context {
             GraphicsContext::Initialized(ctx) => Some(&mut ctx.renderer),
             _ => None,
         };
 
-        ClientFrameCtx {
+        let cg = if second { &mut self.cg2 } else { &mut self.cg };
+        cg.as_mut().map(|cg| ClientFrameCtx {
             cvars: &self.cvars,
             scene: &mut self.engine.scenes[self.gs.scene_handle],
             gs: &mut self.gs,
-            cg: &mut self.cg,
+            cg,
             renderer,
             ui: &mut self.engine.user_interface,
-        }
+        })
     }
 
     fn ctx(&mut self) -> FrameCtx {
@@ -555,11 +1887,204 @@ impl ClientProcess {
         dbg_logf!("{} bye", self.real_time());
     }
 
+    /// Real time in seconds until the next frame may start according to the FPS cap,
+    /// zero if there's no cap or it's already late.
+    ///
+    /// See `cl_fps_max` and `cl_fps_max_unfocused`.
+    pub fn time_until_next_frame(&self) -> f32 {
+        let fps_max = if self.focused {
+            self.cvars.cl_fps_max
+        } else {
+            self.cvars.cl_fps_max_unfocused
+        };
+        if fps_max <= 0.0 {
+            return 0.0;
+        }
+        (self.real_time_prev + 1.0 / fps_max - self.real_time()).max(0.0)
+    }
+
+    /// Real time in seconds until the next tick should run, zero if it's already late.
+    pub fn time_until_next_tick(&self) -> f32 {
+        (self.gs.game_time + self.gs.dt - self.game_time_target).max(0.0)
+    }
+
     pub fn real_time(&self) -> f32 {
         // LATER How to handle time in logging code? Real or frame time?
         // Should be OK to create one instant as 0 and clone it to a global/client/server.
         // Elapsed is guaranteed to be monotonic even across instances
         // because it uses Instant::now() internally.
-        self.clock.elapsed().as_secs_f32()
+        self.manual_time.unwrap_or_else(|| self.clock.elapsed().as_secs_f32())
+    }
+}
+
+/// How the window is shown, see `ClientProcess::update_window`.
+#[derive(Debug, PartialEq)]
+struct WindowMode {
+    fullscreen: bool,
+    exclusive: bool,
+    monitor: usize,
+    width: i32,
+    height: i32,
+}
+
+/// Limits of `r_shadow_map_size`.
+const SHADOW_MAP_SIZE_MIN: usize = 16;
+const SHADOW_MAP_SIZE_MAX: usize = 4096;
+
+/// Quality settings for `r_quality 3` composed from the individual `r_*` cvars.
+fn custom_quality(cvars: &Cvars) -> QualitySettings {
+    let mut quality = QualitySettings::high();
+
+    quality.point_shadows_enabled = cvars.r_shadows;
+    quality.spot_shadows_enabled = cvars.r_shadows;
+    quality.point_soft_shadows = cvars.r_soft_shadows;
+    quality.spot_soft_shadows = cvars.r_soft_shadows;
+    // Zero sized textures fail to create and huge ones can run out of GPU memory.
+    let shadow_map_size = cvars.r_shadow_map_size.clamp(SHADOW_MAP_SIZE_MIN, SHADOW_MAP_SIZE_MAX);
+    quality.point_shadow_map_size = shadow_map_size;
+    quality.spot_shadow_map_size = shadow_map_size;
+    quality.point_shadows_distance = cvars.r_shadows_distance;
+    quality.spot_shadows_distance = cvars.r_shadows_distance;
+    quality.csm_settings.enabled = cvars.r_shadows;
+    quality.csm_settings.size = shadow_map_size;
+    quality.csm_settings.pcf = cvars.r_soft_shadows;
+
+    quality.use_ssao = cvars.r_ssao;
+    quality.ssao_radius = cvars.r_ssao_radius;
+    quality.light_scatter_enabled = cvars.r_light_scatter;
+    quality.fxaa = cvars.r_fxaa;
+    quality.use_parallax_mapping = cvars.r_parallax;
+    quality.use_bloom = cvars.r_bloom;
+    quality.use_occlusion_culling = cvars.r_occlusion_culling;
+
+    quality
+}
+
+/// Create a server in this process and connect to it,
+/// the client should be created after receiving init from the returned connection.
+///
+/// With `cl_splitscreen`, the second player's connection is also returned.
+async fn start_local_server(
+    cvars: &Cvars,
+    engine: &mut Engine,
+    gs: &mut GameState,
+) -> (ServerGame, Connecting, Option<Box<dyn Connection<ServerMessage>>>) {
+    // LATER Multithreading would be sweet but we can't use threads in WASM.
+
+    let mut listeners: Vec<Box<dyn Listener<ClientMessage>>> = Vec::new();
+    let (listener, conn) = local_connection(&cvars.cl_name, &cvars.cl_guid);
+    listeners.push(Box::new(listener));
+    let conn2 = if cvars.cl_splitscreen {
+        // A different GUID so the server keeps separate stats.
+        let guid = format!("{}2", cvars.cl_guid);
+        let (listener, conn) = local_connection(&cvars.cl_splitscreen_name, &guid);
+        listeners.push(Box::new(listener));
+        Some(conn)
+    } else {
+        None
+    };
+
+    // Init server first, otherwise the client has nothing to connect to.
+    let mut sg = ServerGame::new(listeners).await;
+
+    // Make the server accept the local connection
+    // and send init data into it so the client can read it on the first frame.
+    let mut ctx = ServerFrameCtx {
+        cvars,
+        scene: &mut engine.scenes[gs.scene_handle],
+        gs,
+        sg: &mut sg,
+    };
+    ctx.accept_new_connections();
+
+    let connecting = Connecting::Init { conn, start: 0.0 };
+    (sg, connecting, conn2)
+}
+
+/// A connection to the local server and the listener the server accepts it from.
+fn local_connection(name: &str, guid: &str) -> (LocalListener, Box<dyn Connection<ServerMessage>>) {
+    let (tx1, rx1) = mpsc::channel();
+    let (tx2, rx2) = mpsc::channel();
+    let conn1 = LocalConnection::new(tx1, rx2);
+    let mut conn2: Box<dyn Connection<ServerMessage>> = Box::new(LocalConnection::new(tx2, rx1));
+
+    // The server reads it on its first frame, remote clients send it after connecting.
+    let name = net::serialize(ClientMessage::Name(name.to_owned()));
+    let guid = net::serialize(ClientMessage::Guid(guid.to_owned()));
+    if let Err(e) = conn2.send(&name).and_then(|()| conn2.send(&guid)) {
+        dbg_logf!("failed to send player name to local server: {}", e);
+    }
+
+    (LocalListener::new(conn1), conn2)
+}
+
+/// Load everything the client and a local server might need later.
+///
+/// In the browser, resources are fetched asynchronously so waiting for them
+/// with `block_on` (e.g. when starting a local game from the menu or changing maps)
+/// would never finish. Once loaded, requesting them again resolves immediately
+/// as long as the returned handles keep them alive.
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+async fn preload_resources(engine: &Engine) -> Vec<UntypedResource> {
+    let mut paths = vec![CYCLE_MODEL_PATH.to_owned()];
+    for map in maps::MAPS {
+        paths.push(map.path.to_owned());
+        for side in maps::SKYBOX_SIDES {
+            paths.push(format!("{}/{}.png", map.skybox, side));
+        }
+    }
+    paths.sort();
+    paths.dedup();
+
+    let clock = Instant::now();
+    let requests = paths.iter().map(|path| engine.resource_manager.request_untyped(path));
+    let mut resources = Vec::new();
+    for (path, res) in paths.iter().zip(future::join_all(requests).await) {
+        match res {
+            Ok(resource) => resources.push(resource),
+            Err(e) => dbg_logf!("failed to preload {}: {:?}", path, e),
+        }
     }
+    dbg_logf!("preloaded {} resources in {} ms", resources.len(), clock.elapsed().as_millis());
+    resources
+}
+
+/// Where the player's `cl_guid` is saved.
+pub const GUID_PATH: &str = "guid.txt";
+
+/// Load the GUID or generate a new one and save it for next time.
+///
+/// Failing to save it is not fatal, stats just won't carry over to the next session.
+fn load_or_create_guid(path: &str) -> String {
+    match std::fs::read_to_string(path) {
+        Ok(guid) if !guid.trim().is_empty() => return guid.trim().to_owned(),
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => dbg_logf!("WARNING failed to read GUID from {path}: {e}"),
+    }
+
+    // Not for anything security related, it only needs to be unlikely to collide.
+    let nanos = debug::logger::system_time_now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_nanos() as u64);
+    // There are no processes in the browser.
+    #[cfg(target_arch = "wasm32")]
+    let pid = 0;
+    #[cfg(not(target_arch = "wasm32"))]
+    let pid = std::process::id();
+    let mut rng = Xoshiro256PlusPlus::seed_from_u64(nanos ^ u64::from(pid));
+    let guid = format!("{:016x}{:016x}", rng.gen::<u64>(), rng.gen::<u64>());
+    if let Err(e) = std::fs::write(path, &guid) {
+        dbg_logf!("WARNING failed to save GUID to {path}: {e}");
+    }
+    guid
+}
+
+/// Store a map downloaded from the server so `maps::path` finds it.
+fn save_map(map: &str, data: &[u8]) -> std::io::Result<()> {
+    let path = maps::download_path(map).ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("invalid map name {map}"))
+    })?;
+    std::fs::create_dir_all(maps::DOWNLOADS_DIR)?;
+    std::fs::write(path, data)
 }
@@ -0,0 +1,134 @@
+//! Rendering the 3D scene at a lower resolution than the window and upscaling it.
+//!
+//! The scene is rendered into a texture which is shown by an image
+//! stretched over the whole window, below all other UI.
+
+use fyrox::{
+    gui::{
+        image::{ImageBuilder, ImageMessage},
+        message::MessageDirection,
+        widget::{WidgetBuilder, WidgetMessage},
+        UiNode, UserInterface,
+    },
+    resource::texture::{TextureResource, TextureResourceExtension},
+};
+
+use crate::prelude::*;
+
+/// How much the dynamic scale changes at once.
+const STEP: f32 = 0.05;
+
+/// Don't change the dynamic scale more often than this, in seconds,
+/// to give the frame time average a chance to react.
+const ADJUST_INTERVAL: f32 = 0.5;
+
+pub struct RenderScale {
+    /// Shows the scene texture, hidden when rendering at full resolution.
+    image: Handle<UiNode>,
+    /// None when rendering directly to the window.
+    target: Option<TextureResource>,
+    target_size: (u32, u32),
+    window_size: (u32, u32),
+    /// The current scale, lower than `r_render_scale` when the dynamic mode reduced it.
+    scale: f32,
+    /// Moving average of frame times in ms.
+    frame_time_avg: f32,
+    /// Real time when the dynamic mode last changed the scale.
+    time_adjusted: f32,
+}
+
+impl RenderScale {
+    /// Must be called before creating any other UI so the scene is drawn below it.
+    pub fn new(ui: &mut UserInterface) -> Self {
+        let image = ImageBuilder::new(
+            WidgetBuilder::new().with_visibility(false).with_hit_test_visibility(false),
+        )
+        // Render targets are upside down.
+        .with_flip(true)
+        .build(&mut ui.build_ctx());
+
+        Self {
+            image,
+            target: None,
+            target_size: (0, 0),
+            window_size: (1, 1),
+            scale: 1.0,
+            frame_time_avg: 0.0,
+            time_adjusted: 0.0,
+        }
+    }
+
+    pub fn resized(&mut self, ui: &mut UserInterface, width: u32, height: u32) {
+        self.window_size = (width.max(1), height.max(1));
+        ui.send_message(WidgetMessage::width(self.image, MessageDirection::ToWidget, width as f32));
+        ui.send_message(WidgetMessage::height(
+            self.image,
+            MessageDirection::ToWidget,
+            height as f32,
+        ));
+    }
+
+    /// Pick the scale for this frame and point the scene's rendering at the right target.
+    ///
+    /// `frame_time` is the real time since the last frame in seconds.
+    pub fn update(
+        &mut self,
+        ui: &mut UserInterface,
+        scene: &mut Scene,
+        cvars: &Cvars,
+        real_time: f32,
+        frame_time: f32,
+    ) {
+        self.frame_time_avg = self.frame_time_avg * 0.9 + frame_time * 1000.0 * 0.1;
+
+        let max = cvars.r_render_scale.clamp(0.1, 1.0);
+        if cvars.r_render_scale_dynamic {
+            if real_time - self.time_adjusted > ADJUST_INTERVAL {
+                if self.frame_time_avg > cvars.r_render_scale_target_ms {
+                    self.scale -= STEP;
+                    self.time_adjusted = real_time;
+                } else if self.frame_time_avg < cvars.r_render_scale_target_ms * 0.8 {
+                    self.scale += STEP;
+                    self.time_adjusted = real_time;
+                }
+            }
+            let min = cvars.r_render_scale_min.clamp(0.1, max);
+            self.scale = self.scale.clamp(min, max);
+        } else {
+            self.scale = max;
+        }
+
+        if self.scale >= 1.0 {
+            if self.target.take().is_some() {
+                ui.send_message(WidgetMessage::visibility(
+                    self.image,
+                    MessageDirection::ToWidget,
+                    false,
+                ));
+            }
+        } else {
+            let size = (
+                ((self.window_size.0 as f32 * self.scale) as u32).max(1),
+                ((self.window_size.1 as f32 * self.scale) as u32).max(1),
+            );
+            if self.target.is_none() || size != self.target_size {
+                let target = TextureResource::new_render_target(size.0, size.1);
+                ui.send_message(ImageMessage::texture(
+                    self.image,
+                    MessageDirection::ToWidget,
+                    Some(target.clone().into()),
+                ));
+                ui.send_message(WidgetMessage::visibility(
+                    self.image,
+                    MessageDirection::ToWidget,
+                    true,
+                ));
+                self.target = Some(target);
+                self.target_size = size;
+            }
+        }
+
+        // Set every frame because map changes create a new scene.
+        scene.rendering_options.render_target = self.target.clone();
+    }
+}
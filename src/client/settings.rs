@@ -0,0 +1,324 @@
+//! Settings menu - widgets generated from `cvars::SETTINGS`.
+
+use fyrox::gui::{
+    border::BorderBuilder,
+    button::{ButtonBuilder, ButtonMessage},
+    check_box::{CheckBoxBuilder, CheckBoxMessage},
+    dropdown_list::{DropdownListBuilder, DropdownListMessage},
+    message::{MessageDirection, UiMessage},
+    scroll_bar::{ScrollBarBuilder, ScrollBarMessage},
+    stack_panel::StackPanelBuilder,
+    text::{TextBuilder, TextMessage},
+    widget::{WidgetBuilder, WidgetMessage},
+    BuildContext, HorizontalAlignment, Orientation, Thickness, UiNode, UserInterface,
+    VerticalAlignment,
+};
+
+use crate::{
+    cvars::{Setting, SettingKind, SettingsTab, CONFIG_PATH, SETTINGS},
+    prelude::*,
+};
+
+/// LATER Generate this from bind cvars once binds are configurable.
+const BINDS: &[(&str, &str)] = &[
+    ("W A S D", "Move"),
+    ("Space / C", "Up / down while observing"),
+    ("Shift / Ctrl", "Faster / slower while observing"),
+    ("1 - 0", "Go to player while observing"),
+    ("Ctrl", "Boost"),
+    ("Left mouse", "Fire / join"),
+    ("Right mouse", "Fire 2 / observe / go to player"),
+    ("Middle mouse", "Zoom"),
+    ("Tab", "Scoreboard"),
+    ("M", "Map overview"),
+    ("F1", "Vote yes"),
+    ("F2", "Vote no, server browser when there's no vote"),
+    ("ESC", "Menu"),
+    ("Shift+ESC or `", "Console"),
+    ("Alt+Enter", "Toggle fullscreen"),
+];
+
+/// The settings screen.
+pub struct SettingsMenu {
+    root: Handle<UiNode>,
+    /// Buttons to switch tabs, in the same order as `SettingsTab::ALL`.
+    tab_buttons: Vec<Handle<UiNode>>,
+    /// Contents of each tab, in the same order as `SettingsTab::ALL`.
+    tab_panels: Vec<Handle<UiNode>>,
+    back: Handle<UiNode>,
+    /// One for each item in `SETTINGS`.
+    rows: Vec<SettingRow>,
+    open: bool,
+}
+
+struct SettingRow {
+    setting: &'static Setting,
+    /// Check box, scroll bar or dropdown list depending on the setting's kind.
+    widget: Handle<UiNode>,
+    /// Shows the current value of ranges.
+    value_text: Handle<UiNode>,
+}
+
+impl SettingsMenu {
+    pub fn new(ui: &mut UserInterface) -> Self {
+        let ctx = &mut ui.build_ctx();
+
+        let tab_buttons: Vec<_> =
+            SettingsTab::ALL.iter().map(|tab| button(ctx, &format!("{tab:?}"))).collect();
+        let tab_row =
+            StackPanelBuilder::new(WidgetBuilder::new().with_children(tab_buttons.clone()))
+                .with_orientation(Orientation::Horizontal)
+                .build(ctx);
+
+        let mut rows = Vec::new();
+        let mut tab_panels = Vec::new();
+        for (i, &tab) in SettingsTab::ALL.iter().enumerate() {
+            let mut children = Vec::new();
+            for setting in SETTINGS.iter().filter(|setting| setting.tab == tab) {
+                let row = SettingRow::new(ctx, setting);
+                children.push(row_panel(ctx, setting.label, row.widget, row.value_text));
+                rows.push(row);
+            }
+            if tab == SettingsTab::Binds {
+                for (keys, action) in BINDS {
+                    let text = text(ctx, keys);
+                    children.push(row_panel(ctx, action, text, Handle::NONE));
+                }
+            }
+            let panel = StackPanelBuilder::new(
+                WidgetBuilder::new()
+                    .with_margin(Thickness::uniform(4.0))
+                    .with_visibility(i == 0)
+                    .with_children(children),
+            )
+            .build(ctx);
+            tab_panels.push(panel);
+        }
+
+        let back = button(ctx, "Back");
+
+        let panel = StackPanelBuilder::new(
+            WidgetBuilder::new()
+                .with_child(tab_row)
+                .with_children(tab_panels.clone())
+                .with_child(back),
+        )
+        .build(ctx);
+        let root = BorderBuilder::new(
+            WidgetBuilder::new()
+                .with_width(600.0)
+                .with_horizontal_alignment(HorizontalAlignment::Center)
+                .with_vertical_alignment(VerticalAlignment::Center)
+                .with_visibility(false)
+                .with_child(panel),
+        )
+        .build(ctx);
+
+        Self {
+            root,
+            tab_buttons,
+            tab_panels,
+            back,
+            rows,
+            open: false,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Show the menu with the current values of cvars,
+    /// they might have been changed in the console since last time.
+    pub fn open(&mut self, ui: &mut UserInterface, cvars: &Cvars) {
+        self.open = true;
+        ui.send_message(WidgetMessage::visibility(self.root, MessageDirection::ToWidget, true));
+        for row in &self.rows {
+            row.update(ui, cvars);
+        }
+    }
+
+    /// Hide the menu and save the settings.
+    pub fn close(&mut self, ui: &mut UserInterface, cvars: &Cvars) {
+        self.open = false;
+        ui.send_message(WidgetMessage::visibility(self.root, MessageDirection::ToWidget, false));
+        match cvars.save_config(CONFIG_PATH) {
+            Ok(()) => dbg_logf!("saved settings to {}", CONFIG_PATH),
+            Err(e) => dbg_logf!("failed to save settings to {}: {}", CONFIG_PATH, e),
+        }
+    }
+
+    /// Handle UI events, changed settings are written to `cvars` immediately.
+    ///
+    /// Returns true if the player wants to go back.
+    pub fn ui_message(
+        &mut self,
+        ui: &mut UserInterface,
+        cvars: &mut Cvars,
+        msg: &UiMessage,
+    ) -> bool {
+        if !self.open || msg.direction != MessageDirection::FromWidget {
+            return false;
+        }
+
+        let dest = msg.destination();
+        if msg.data() == Some(&ButtonMessage::Click) {
+            if dest == self.back {
+                return true;
+            }
+            if let Some(index) = self.tab_buttons.iter().position(|&button| button == dest) {
+                for (i, &panel) in self.tab_panels.iter().enumerate() {
+                    ui.send_message(WidgetMessage::visibility(
+                        panel,
+                        MessageDirection::ToWidget,
+                        i == index,
+                    ));
+                }
+            }
+            return false;
+        }
+
+        let Some(row) = self.rows.iter().find(|row| row.widget == dest) else {
+            return false;
+        };
+        let value = match row.setting.kind {
+            SettingKind::Bool => match msg.data() {
+                Some(CheckBoxMessage::Check(Some(checked))) => checked.to_string(),
+                _ => return false,
+            },
+            SettingKind::Range { step, .. } => match msg.data() {
+                Some(ScrollBarMessage::Value(value)) => {
+                    // Round so the config doesn't end up with values like 0.15000001.
+                    let value = (value / step).round() * step;
+                    format_range(value, step)
+                }
+                _ => return false,
+            },
+            SettingKind::Enum(options) => match msg.data() {
+                Some(DropdownListMessage::SelectionChanged(Some(i))) => options[*i].0.to_owned(),
+                _ => return false,
+            },
+        };
+        if cvars.get_string(row.setting.cvar).as_deref() == Ok(value.as_str()) {
+            return false;
+        }
+        match cvars.set_str(row.setting.cvar, &value) {
+            Ok(()) => dbg_logf!("{} = {}", row.setting.cvar, value),
+            Err(e) => dbg_logf!("failed to set {} to {}: {}", row.setting.cvar, value, e),
+        }
+        row.update(ui, cvars);
+        false
+    }
+}
+
+impl SettingRow {
+    fn new(ctx: &mut BuildContext, setting: &'static Setting) -> Self {
+        let widget_builder = || {
+            WidgetBuilder::new()
+                .with_width(200.0)
+                .with_height(25.0)
+                .with_margin(Thickness::uniform(2.0))
+        };
+        let widget = match setting.kind {
+            SettingKind::Bool => CheckBoxBuilder::new(widget_builder().with_width(25.0)).build(ctx),
+            SettingKind::Range { min, max, step } => ScrollBarBuilder::new(widget_builder())
+                .with_orientation(Orientation::Horizontal)
+                .with_min(min)
+                .with_max(max)
+                .with_step(step)
+                .build(ctx),
+            SettingKind::Enum(options) => {
+                let items = options.iter().map(|(_, label)| text(ctx, label)).collect();
+                DropdownListBuilder::new(widget_builder()).with_items(items).build(ctx)
+            }
+        };
+        let value_text = match setting.kind {
+            SettingKind::Range { .. } => text(ctx, ""),
+            _ => Handle::NONE,
+        };
+        Self {
+            setting,
+            widget,
+            value_text,
+        }
+    }
+
+    /// Make the widget show the cvar's current value.
+    fn update(&self, ui: &mut UserInterface, cvars: &Cvars) {
+        let Ok(value) = cvars.get_string(self.setting.cvar) else {
+            soft_unreachable!("unknown cvar {} in settings", self.setting.cvar);
+        };
+        let dir = MessageDirection::ToWidget;
+        match self.setting.kind {
+            SettingKind::Bool => {
+                let checked = value == "true";
+                ui.send_message(CheckBoxMessage::checked(self.widget, dir, Some(checked)));
+            }
+            SettingKind::Range { step, .. } => {
+                let value: f32 = value.parse().unwrap_or_default();
+                ui.send_message(ScrollBarMessage::value(self.widget, dir, value));
+                let text = format_range(value, step);
+                ui.send_message(TextMessage::text(self.value_text, dir, text));
+            }
+            SettingKind::Enum(options) => {
+                let selected = options.iter().position(|(option, _)| *option == value);
+                ui.send_message(DropdownListMessage::selection(self.widget, dir, selected));
+            }
+        }
+    }
+}
+
+/// Show as many decimal places as the step needs.
+fn format_range(value: f32, step: f32) -> String {
+    let decimals = if step >= 1.0 {
+        0
+    } else {
+        (-step.log10()).ceil() as usize
+    };
+    format!("{value:.decimals$}")
+}
+
+/// Label on the left, widget and optionally its value on the right.
+fn row_panel(
+    ctx: &mut BuildContext,
+    label: &str,
+    widget: Handle<UiNode>,
+    value_text: Handle<UiNode>,
+) -> Handle<UiNode> {
+    let label = TextBuilder::new(
+        WidgetBuilder::new()
+            .with_width(300.0)
+            .with_vertical_alignment(VerticalAlignment::Center)
+            .with_margin(Thickness::uniform(2.0)),
+    )
+    .with_text(label)
+    .build(ctx);
+    let mut builder = WidgetBuilder::new().with_child(label).with_child(widget);
+    if value_text.is_some() {
+        builder = builder.with_child(value_text);
+    }
+    StackPanelBuilder::new(builder)
+        .with_orientation(Orientation::Horizontal)
+        .build(ctx)
+}
+
+fn button(ctx: &mut BuildContext, text: &str) -> Handle<UiNode> {
+    ButtonBuilder::new(
+        WidgetBuilder::new()
+            .with_width(100.0)
+            .with_height(25.0)
+            .with_margin(Thickness::uniform(4.0)),
+    )
+    .with_text(text)
+    .build(ctx)
+}
+
+fn text(ctx: &mut BuildContext, text: &str) -> Handle<UiNode> {
+    TextBuilder::new(
+        WidgetBuilder::new()
+            .with_vertical_alignment(VerticalAlignment::Center)
+            .with_margin(Thickness::uniform(2.0)),
+    )
+    .with_text(text)
+    .build(ctx)
+}
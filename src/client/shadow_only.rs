@@ -0,0 +1,93 @@
+//! Drawing nodes only into shadow maps,
+//! e.g. the local cycle in first person which would block the view but should still cast a shadow.
+//!
+//! Fyrox doesn't render shadows of invisible meshes
+//! but it skips surfaces whose shader doesn't have the render pass being drawn.
+//! Their materials are replaced by ones using only the shadow passes of the standard shader.
+
+use fyrox::{
+    asset::untyped::ResourceKind,
+    material::{
+        shader::{Shader, ShaderResource, STANDARD_SHADER_SRC},
+        Material, MaterialResource,
+    },
+    scene::{graph::Graph, mesh::Mesh},
+};
+
+use crate::prelude::*;
+
+/// A node whose meshes are only drawn into shadow maps.
+#[derive(Debug, Default)]
+pub struct ShadowOnly {
+    /// Created on first use.
+    shader: Option<ShaderResource>,
+    /// `Handle::NONE` when everything is drawn normally.
+    root: Handle<Node>,
+    /// Each mesh under `root` and the materials of its surfaces before they were replaced.
+    originals: Vec<(Handle<Node>, Vec<MaterialResource>)>,
+}
+
+impl ShadowOnly {
+    /// Draw `root` and its descendants only into shadow maps
+    /// and restore the node which was drawn like that before.
+    ///
+    /// `root` is `Handle::NONE` to draw everything normally.
+    pub fn update(&mut self, graph: &mut Graph, root: Handle<Node>) {
+        if root == self.root {
+            return;
+        }
+        self.restore(graph);
+        if root.is_none() {
+            return;
+        }
+
+        let shader = self.shader.get_or_insert_with(shadow_shader).clone();
+        let handles: Vec<_> = graph.traverse_handle_iter(root).collect();
+        for handle in handles {
+            let Some(mesh) = graph[handle].cast_mut::<Mesh>() else {
+                continue;
+            };
+            let mut materials = Vec::new();
+            for surface in mesh.surfaces_mut() {
+                let original = surface.material().clone();
+                let material = shadow_material(&shader, &original.data_ref());
+                surface.set_material(MaterialResource::new_ok(ResourceKind::Embedded, material));
+                materials.push(original);
+            }
+            self.originals.push((handle, materials));
+        }
+        self.root = root;
+    }
+
+    fn restore(&mut self, graph: &mut Graph) {
+        for (handle, materials) in self.originals.drain(..) {
+            // The node is gone when e.g. the cycle was destroyed.
+            let mesh = graph.try_get_mut(handle).and_then(|node| node.cast_mut::<Mesh>());
+            let Some(mesh) = mesh else {
+                continue;
+            };
+            for (surface, material) in mesh.surfaces_mut().iter_mut().zip(materials) {
+                surface.set_material(material);
+            }
+        }
+        self.root = Handle::NONE;
+    }
+}
+
+/// The standard shader without the passes which draw to the screen.
+fn shadow_shader() -> ShaderResource {
+    let mut shader = Shader::from_string(STANDARD_SHADER_SRC).unwrap();
+    // DirectionalShadow, PointShadow and SpotShadow.
+    shader.definition.passes.retain(|pass| pass.name.ends_with("Shadow"));
+    ShaderResource::new_ok(ResourceKind::Embedded, shader)
+}
+
+/// Keeps the properties of `original`, the shadow passes use e.g. the diffuse texture for alpha testing.
+fn shadow_material(shader: &ShaderResource, original: &Material) -> Material {
+    let mut material = Material::from_shader(shader.clone(), None);
+    for (name, value) in original.properties() {
+        // The model might use a custom shader with properties the standard one doesn't have.
+        let _ = material.set_property(name, value.clone());
+    }
+    material
+}
@@ -0,0 +1,294 @@
+//! On-screen controls for touch screens, see `cl_touch`.
+//!
+//! A virtual joystick in the bottom left accelerates and brakes, buttons in the bottom right
+//! fire, boost and jump, and dragging anywhere else looks around.
+//! They set the same `Input` fields as the keyboard and mouse.
+
+use fyrox::{
+    event::{Touch, TouchPhase},
+    gui::{
+        message::MessageDirection,
+        text::TextBuilder,
+        vector_image::Primitive,
+        widget::{WidgetBuilder, WidgetMessage},
+        HorizontalAlignment, UiNode, UserInterface, VerticalAlignment,
+    },
+};
+
+use crate::{
+    client::vector_shapes::{Placement, Shape, VectorShapes},
+    prelude::*,
+};
+
+/// Touches which start this many radii from the joystick's center grab it.
+const JOYSTICK_GRAB: f32 = 2.0;
+
+const IDLE: Color = Color::from_rgba(255, 255, 255, 60);
+const ACTIVE: Color = Color::from_rgba(255, 255, 255, 140);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TouchButton {
+    Fire,
+    Boost,
+    Jump,
+}
+
+impl TouchButton {
+    const ALL: [TouchButton; 3] = [TouchButton::Fire, TouchButton::Boost, TouchButton::Jump];
+
+    fn label(self) -> &'static str {
+        match self {
+            TouchButton::Fire => "FIRE",
+            TouchButton::Boost => "BOOST",
+            TouchButton::Jump => "JUMP",
+        }
+    }
+
+    fn input(self, input: &mut Input) -> &mut bool {
+        match self {
+            TouchButton::Fire => &mut input.fire1,
+            TouchButton::Boost => &mut input.boost,
+            TouchButton::Jump => &mut input.up,
+        }
+    }
+}
+
+/// What a finger on the screen controls, decided when it first touches.
+#[derive(Debug, Clone, Copy)]
+enum TouchRole {
+    Joystick,
+    Button(TouchButton),
+    Look { last: Vector2<f32> },
+}
+
+/// Positions in pixels, computed from the cvars and window size.
+struct Layout {
+    joystick: Vector2<f32>,
+    joystick_radius: f32,
+    buttons: [(TouchButton, Vector2<f32>); 3],
+    button_radius: f32,
+}
+
+pub struct TouchControls {
+    shapes: VectorShapes,
+    /// Top-level texts so they can be centered on the buttons.
+    labels: [Handle<UiNode>; 3],
+    window_size: Vector2<f32>,
+    visible: bool,
+    /// Fingers currently on the screen by their ID.
+    touches: FxHashMap<u64, TouchRole>,
+    /// Position of the joystick's knob relative to its center.
+    knob: Vector2<f32>,
+}
+
+impl TouchControls {
+    pub fn new(ui: &mut UserInterface) -> Self {
+        let labels = TouchButton::ALL.map(|button| {
+            TextBuilder::new(
+                WidgetBuilder::new().with_visibility(false).with_hit_test_visibility(false),
+            )
+            .with_text(button.label())
+            .with_horizontal_text_alignment(HorizontalAlignment::Center)
+            .with_vertical_text_alignment(VerticalAlignment::Center)
+            .with_shadow(true)
+            .build(&mut ui.build_ctx())
+        });
+
+        Self {
+            shapes: VectorShapes::default(),
+            labels,
+            window_size: Vector2::zeros(),
+            visible: false,
+            touches: FxHashMap::default(),
+            knob: Vector2::zeros(),
+        }
+    }
+
+    pub fn resized(&mut self, width: f32, height: f32) {
+        self.window_size = Vector2::new(width, height);
+    }
+
+    fn layout(&self, cvars: &Cvars) -> Layout {
+        // Everything scales with the height so the controls
+        // stay the same physical size in both orientations.
+        let (w, h) = (self.window_size.x, self.window_size.y);
+        let joystick =
+            Vector2::new(cvars.cl_touch_joystick_x * h, h - cvars.cl_touch_joystick_y * h);
+        let button_radius = cvars.cl_touch_button_radius * h;
+        let fire = Vector2::new(w - cvars.cl_touch_buttons_x * h, h - cvars.cl_touch_buttons_y * h);
+        let spacing = button_radius * 2.5;
+        Layout {
+            joystick,
+            joystick_radius: cvars.cl_touch_joystick_radius * h,
+            buttons: [
+                (TouchButton::Fire, fire),
+                (TouchButton::Boost, fire - Vector2::new(spacing, 0.0)),
+                (TouchButton::Jump, fire - Vector2::new(0.0, spacing)),
+            ],
+            button_radius,
+        }
+    }
+
+    /// Forget all fingers, e.g. when the menu opens.
+    pub fn reset(&mut self) {
+        self.touches.clear();
+        self.knob = Vector2::zeros();
+    }
+
+    /// Update `input` according to the touch.
+    ///
+    /// Returns whether any input buttons changed
+    /// and how far the finger moved in pixels if it's looking around.
+    pub fn touch(
+        &mut self,
+        cvars: &Cvars,
+        touch: &Touch,
+        input: &mut Input,
+    ) -> (bool, Vector2<f32>) {
+        let pos = Vector2::new(touch.location.x as f32, touch.location.y as f32);
+        let layout = self.layout(cvars);
+
+        let role = match touch.phase {
+            TouchPhase::Started => {
+                let button = layout
+                    .buttons
+                    .iter()
+                    .find(|(_, center)| (pos - center).norm() <= layout.button_radius);
+                let role = if let Some(&(button, _)) = button {
+                    TouchRole::Button(button)
+                } else if (pos - layout.joystick).norm() <= layout.joystick_radius * JOYSTICK_GRAB
+                    && !self.touches.values().any(|role| matches!(role, TouchRole::Joystick))
+                {
+                    TouchRole::Joystick
+                } else {
+                    TouchRole::Look { last: pos }
+                };
+                self.touches.insert(touch.id, role);
+                role
+            }
+            TouchPhase::Moved => match self.touches.get(&touch.id) {
+                Some(&role) => role,
+                None => return (false, Vector2::zeros()),
+            },
+            TouchPhase::Ended | TouchPhase::Cancelled => match self.touches.remove(&touch.id) {
+                Some(role) => role,
+                None => return (false, Vector2::zeros()),
+            },
+        };
+        let released = matches!(touch.phase, TouchPhase::Ended | TouchPhase::Cancelled);
+
+        match role {
+            TouchRole::Joystick => {
+                let offset = pos - layout.joystick;
+                self.knob = if released {
+                    Vector2::zeros()
+                } else if offset.norm() > layout.joystick_radius {
+                    offset.normalize() * layout.joystick_radius
+                } else {
+                    offset
+                };
+
+                // The screen's Y axis points down.
+                // Cycles steer where the player looks so X is unused.
+                let dir = self.knob / layout.joystick_radius;
+                let deadzone = cvars.cl_touch_joystick_deadzone;
+                let prev = (input.forward, input.backward);
+                input.forward = dir.y < -deadzone;
+                input.backward = dir.y > deadzone;
+                let changed = prev != (input.forward, input.backward);
+                (changed, Vector2::zeros())
+            }
+            TouchRole::Button(button) => {
+                // Another finger might still be holding the same button.
+                let held = self
+                    .touches
+                    .values()
+                    .any(|role| matches!(role, TouchRole::Button(other) if *other == button));
+                let pressed = button.input(input);
+                let changed = *pressed != held;
+                *pressed = held;
+                (changed, Vector2::zeros())
+            }
+            TouchRole::Look { last } => {
+                if !released {
+                    self.touches.insert(touch.id, TouchRole::Look { last: pos });
+                }
+                (false, pos - last)
+            }
+        }
+    }
+
+    pub fn update(&mut self, ui: &mut UserInterface, cvars: &Cvars, visible: bool) {
+        if visible != self.visible {
+            self.visible = visible;
+            for label in self.labels {
+                ui.send_message(WidgetMessage::visibility(
+                    label,
+                    MessageDirection::ToWidget,
+                    visible,
+                ));
+            }
+        }
+        if !visible {
+            self.shapes.clear(ui);
+            return;
+        }
+
+        let layout = self.layout(cvars);
+        let joystick_active = self.touches.values().any(|role| matches!(role, TouchRole::Joystick));
+
+        // Each vector image has only one color.
+        let mut idle = Vec::new();
+        let mut active = Vec::new();
+        let joystick_group = if joystick_active {
+            &mut active
+        } else {
+            &mut idle
+        };
+        joystick_group.push(Primitive::WireCircle {
+            center: layout.joystick,
+            radius: layout.joystick_radius,
+            thickness: 2.0,
+            segments: 48,
+        });
+        joystick_group.push(Primitive::Circle {
+            center: layout.joystick + self.knob,
+            radius: layout.joystick_radius / 3.0,
+            segments: 24,
+        });
+        for (i, &(button, center)) in layout.buttons.iter().enumerate() {
+            let pressed = self
+                .touches
+                .values()
+                .any(|role| matches!(role, TouchRole::Button(other) if *other == button));
+            let group = if pressed { &mut active } else { &mut idle };
+            group.push(Primitive::Circle {
+                center,
+                radius: layout.button_radius,
+                segments: 32,
+            });
+
+            let size = layout.button_radius * 2.0;
+            let label = self.labels[i];
+            ui.send_message(WidgetMessage::width(label, MessageDirection::ToWidget, size));
+            ui.send_message(WidgetMessage::height(label, MessageDirection::ToWidget, size));
+            ui.send_message(WidgetMessage::desired_position(
+                label,
+                MessageDirection::ToWidget,
+                center - Vector2::new(size, size) / 2.0,
+            ));
+        }
+
+        let shapes = [(IDLE, idle), (ACTIVE, active)]
+            .into_iter()
+            .filter(|(_, primitives)| !primitives.is_empty())
+            .map(|(color, primitives)| Shape {
+                color,
+                primitives,
+                placement: Placement::Fill,
+            })
+            .collect();
+        // Top-level so primitives are relative to the window.
+        self.shapes.update(ui, Handle::NONE, shapes);
+    }
+}
@@ -0,0 +1,327 @@
+//! Trails behind moving objects.
+//!
+//! `TrailWalls` are glowing walls behind cycles built from their trail points.
+//! Each cycle's wall is split into chunks of `CHUNK_SEGMENTS` segments.
+//! Only the newest chunk is rebuilt when the trail grows, full chunks keep their mesh
+//! until they expire so the amount of geometry uploaded to the GPU each frame stays bounded.
+//! Chunks fade out as they get close to expiring, see `r_trail_wall_fade`.
+//!
+//! `Ribbons` are purely visual, short streaks behind projectiles and cycles
+//! to make fast objects easier to follow, see `r_trails`.
+
+use fyrox::{
+    asset::untyped::ResourceKind,
+    core::{math::TriangleDefinition, sstorage::ImmutableString},
+    material::{Material, MaterialResource, PropertyValue},
+    scene::{
+        graph::Graph,
+        mesh::{
+            buffer::{TriangleBuffer, VertexBuffer},
+            surface::{SurfaceBuilder, SurfaceData, SurfaceResource},
+            vertex::StaticVertex,
+            MeshBuilder, RenderPath,
+        },
+    },
+};
+
+use crate::{
+    client::hud,
+    common::entities::{Projectile, TrailPoint},
+    prelude::*,
+};
+
+/// Number of segments after which a chunk is full and its mesh no longer changes.
+const CHUNK_SEGMENTS: usize = 32;
+
+#[derive(Debug, Default)]
+pub struct TrailWalls {
+    /// Chunks of each cycle's wall, oldest first.
+    ///
+    /// Walls of destroyed cycles stay until they expire.
+    chunks: FxHashMap<NetId, Vec<Chunk>>,
+}
+
+#[derive(Debug)]
+struct Chunk {
+    node_handle: Handle<Node>,
+    color: Color,
+    /// Game time of the oldest point in the chunk.
+    time_first: f32,
+    /// Game time of the newest point in the chunk.
+    time_last: f32,
+    /// Full chunks are never rebuilt.
+    sealed: bool,
+}
+
+impl TrailWalls {
+    pub fn update(&mut self, cvars: &Cvars, gs: &GameState, scene: &mut Scene) {
+        if !cvars.r_trail_walls {
+            self.clear(scene);
+            return;
+        }
+
+        for cycle in &gs.cycles {
+            let color = hud::player_color(gs.players[cycle.player_handle].net_id);
+            let chunks = self.chunks.entry(cycle.net_id).or_default();
+
+            // The newest chunk starts where the last full one ended so there's no gap.
+            let start = chunks.iter().rev().find(|chunk| chunk.sealed).map(|chunk| chunk.time_last);
+            let points: Vec<_> = cycle
+                .trail
+                .iter()
+                .filter(|point| start.map_or(true, |start| point.time >= start))
+                .copied()
+                .collect();
+
+            let head = chunks.last().filter(|chunk| !chunk.sealed);
+            let changed = match (head, points.first(), points.last()) {
+                (Some(head), Some(first), Some(last)) => {
+                    head.time_first != first.time || head.time_last != last.time
+                }
+                _ => true,
+            };
+            if !changed {
+                continue;
+            }
+            if head.is_some() {
+                let head = chunks.pop().unwrap();
+                scene.graph.remove_node(head.node_handle);
+            }
+            if points.len() < 2 {
+                continue;
+            }
+            let node_handle = build_wall(&mut scene.graph, cvars, &points, color);
+            chunks.push(Chunk {
+                node_handle,
+                color,
+                time_first: points[0].time,
+                time_last: points[points.len() - 1].time,
+                sealed: points.len() > CHUNK_SEGMENTS,
+            });
+        }
+
+        for chunks in self.chunks.values_mut() {
+            chunks.retain(|chunk| {
+                let remaining = cvars.g_trail_duration - (gs.game_time - chunk.time_last);
+                if remaining <= 0.0 {
+                    scene.graph.remove_node(chunk.node_handle);
+                    return false;
+                }
+                if remaining < cvars.r_trail_wall_fade {
+                    let fade = remaining / cvars.r_trail_wall_fade;
+                    let mesh = scene.graph[chunk.node_handle].as_mesh_mut();
+                    let mut material = mesh.surfaces_mut()[0].material().data_ref();
+                    let opacity = cvars.r_trail_wall_opacity * fade;
+                    set_color(&mut material, chunk.color, opacity, cvars.r_trail_wall_glow * fade);
+                }
+                true
+            });
+        }
+        self.chunks.retain(|_, chunks| !chunks.is_empty());
+    }
+
+    /// Remove all walls from the scene.
+    pub fn clear(&mut self, scene: &mut Scene) {
+        for (_, chunks) in self.chunks.drain() {
+            for chunk in chunks {
+                scene.graph.remove_node(chunk.node_handle);
+            }
+        }
+    }
+}
+
+/// Short streaks behind projectiles and cycles.
+#[derive(Debug, Default)]
+pub struct Ribbons {
+    /// Recent positions of each followed object and when it was there, oldest first.
+    ///
+    /// Kept after the object is gone until the positions expire so the ribbon doesn't pop.
+    histories: FxHashMap<RibbonSource, Ribbon>,
+    /// Mesh nodes reused every frame instead of creating new ones.
+    pool: Vec<Handle<Node>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum RibbonSource {
+    Cycle(NetId),
+    Projectile(Handle<Projectile>),
+}
+
+#[derive(Debug)]
+struct Ribbon {
+    color: Color,
+    points: VecDeque<(Vec3, f32)>,
+}
+
+impl Ribbons {
+    /// Record where things are now and rebuild the ribbons' meshes.
+    ///
+    /// Ribbons are flat strips turned to face the camera.
+    pub fn update(&mut self, cvars: &Cvars, gs: &GameState, scene: &mut Scene, camera_pos: Vec3) {
+        if !cvars.r_trails {
+            self.histories.clear();
+        } else {
+            let time = gs.game_time;
+            for cycle in &gs.cycles {
+                let pos = **scene.graph[cycle.body_handle].local_transform().position();
+                let color = hud::player_color(gs.players[cycle.player_handle].net_id);
+                self.record(RibbonSource::Cycle(cycle.net_id), color, pos, time);
+            }
+            let projs = &gs.projectiles;
+            for (proj_handle, slot) in projs.pair_iter() {
+                // The shooter might have disconnected since firing.
+                let color = hud::owner_color(gs, projs.player_handles[slot]);
+                let pos = projs.positions[slot];
+                self.record(RibbonSource::Projectile(proj_handle), color, pos, time);
+            }
+            for (source, ribbon) in &mut self.histories {
+                let duration = match source {
+                    RibbonSource::Cycle(_) => cvars.r_trails_cycle_duration,
+                    RibbonSource::Projectile(_) => cvars.r_trails_projectile_duration,
+                };
+                while ribbon.points.front().is_some_and(|&(_, t)| time - t > duration) {
+                    ribbon.points.pop_front();
+                }
+            }
+            self.histories.retain(|_, ribbon| !ribbon.points.is_empty());
+        }
+
+        let mut used = 0;
+        for (source, ribbon) in &self.histories {
+            if ribbon.points.len() < 2 {
+                continue;
+            }
+            let width = match source {
+                RibbonSource::Cycle(_) => cvars.r_trails_cycle_width,
+                RibbonSource::Projectile(_) => cvars.r_trails_projectile_width,
+            };
+            let data = ribbon_data(&ribbon.points, width, camera_pos);
+            if used == self.pool.len() {
+                let material = Material::standard_two_sides();
+                let node_handle = new_mesh(&mut scene.graph, data, material);
+                self.pool.push(node_handle);
+            } else {
+                let mesh = scene.graph[self.pool[used]].as_mesh_mut();
+                mesh.surfaces_mut()[0]
+                    .set_data(SurfaceResource::new_ok(ResourceKind::Embedded, data));
+            }
+            let node = &mut scene.graph[self.pool[used]];
+            node.set_visibility(true);
+            let mut material = node.as_mesh_mut().surfaces_mut()[0].material().data_ref();
+            set_color(&mut material, ribbon.color, cvars.r_trails_opacity, cvars.r_trails_glow);
+            used += 1;
+        }
+        for &node_handle in &self.pool[used..] {
+            scene.graph[node_handle].set_visibility(false);
+        }
+    }
+
+    fn record(&mut self, source: RibbonSource, color: Color, pos: Vec3, time: f32) {
+        let ribbon = self.histories.entry(source).or_insert_with(|| Ribbon {
+            color,
+            points: VecDeque::new(),
+        });
+        ribbon.points.push_back((pos, time));
+    }
+}
+
+/// A strip along the points which narrows towards the oldest one.
+fn ribbon_data(points: &VecDeque<(Vec3, f32)>, width: f32, camera_pos: Vec3) -> SurfaceData {
+    let mut vertices = Vec::with_capacity(points.len() * 2);
+    for i in 0..points.len() {
+        let (pos, _) = points[i];
+        let dir = match points.get(i + 1) {
+            Some(&(next, _)) => next - pos,
+            None => pos - points[i - 1].0,
+        };
+        let to_camera = camera_pos - pos;
+        let normal = to_camera.try_normalize(f32::EPSILON).unwrap_or(UP);
+        let side = dir.cross(&to_camera).try_normalize(f32::EPSILON).unwrap_or(LEFT);
+        let half_width = width / 2.0 * i as f32 / (points.len() - 1) as f32;
+        let u = i as f32;
+        vertices.push(StaticVertex::from_pos_uv_normal(
+            pos + side * half_width,
+            Vector2::new(u, 0.0),
+            normal,
+        ));
+        vertices.push(StaticVertex::from_pos_uv_normal(
+            pos - side * half_width,
+            Vector2::new(u, 1.0),
+            normal,
+        ));
+    }
+    let mut triangles = Vec::with_capacity((points.len() - 1) * 2);
+    for i in 0..points.len() as u32 - 1 {
+        let a = i * 2;
+        triangles.push(TriangleDefinition([a, a + 1, a + 2]));
+        triangles.push(TriangleDefinition([a + 1, a + 3, a + 2]));
+    }
+    surface_data(vertices, triangles)
+}
+
+/// A mesh with a quad between each pair of consecutive points.
+fn build_wall(
+    graph: &mut Graph,
+    cvars: &Cvars,
+    points: &[TrailPoint],
+    color: Color,
+) -> Handle<Node> {
+    let mut vertices = Vec::with_capacity(points.len() * 2);
+    for (i, point) in points.iter().enumerate() {
+        let dir = match points.get(i + 1) {
+            Some(next) => next.pos - point.pos,
+            None => point.pos - points[i - 1].pos,
+        };
+        let normal = dir.cross(&point.up).try_normalize(f32::EPSILON).unwrap_or(LEFT);
+        // The body is at the wheel's center, the wall should start at the ground.
+        let bottom = point.pos - point.up * cvars.g_wheel_ground_distance;
+        let top = bottom + point.up * cvars.r_trail_wall_height;
+        let u = i as f32;
+        vertices.push(StaticVertex::from_pos_uv_normal(bottom, Vector2::new(u, 0.0), normal));
+        vertices.push(StaticVertex::from_pos_uv_normal(top, Vector2::new(u, 1.0), normal));
+    }
+
+    let mut triangles = Vec::with_capacity((points.len() - 1) * 2);
+    for i in 0..points.len() as u32 - 1 {
+        let bottom = i * 2;
+        triangles.push(TriangleDefinition([bottom, bottom + 1, bottom + 2]));
+        triangles.push(TriangleDefinition([bottom + 1, bottom + 3, bottom + 2]));
+    }
+
+    let mut material = Material::standard_two_sides();
+    set_color(&mut material, color, cvars.r_trail_wall_opacity, cvars.r_trail_wall_glow);
+    new_mesh(graph, surface_data(vertices, triangles), material)
+}
+
+fn surface_data(vertices: Vec<StaticVertex>, triangles: Vec<TriangleDefinition>) -> SurfaceData {
+    let vertex_count = vertices.len();
+    SurfaceData::new(
+        VertexBuffer::new(vertex_count, vertices).unwrap(),
+        TriangleBuffer::new(triangles),
+    )
+}
+
+fn new_mesh(graph: &mut Graph, data: SurfaceData, material: Material) -> Handle<Node> {
+    let surface = SurfaceBuilder::new(SurfaceResource::new_ok(ResourceKind::Embedded, data))
+        .with_material(MaterialResource::new_ok(ResourceKind::Embedded, material))
+        .build();
+
+    MeshBuilder::new(BaseBuilder::new())
+        .with_surfaces(vec![surface])
+        // Forward rendering so trails can be transparent.
+        .with_render_path(RenderPath::Forward)
+        .build(graph)
+}
+
+/// `opacity` is from 0 to 1, `glow` is the emission strength.
+fn set_color(material: &mut Material, color: Color, opacity: f32, glow: f32) {
+    let alpha = (opacity.clamp(0.0, 1.0) * 255.0) as u8;
+    let diffuse = Color::from_rgba(color.r, color.g, color.b, alpha);
+    material
+        .set_property(&ImmutableString::new("diffuseColor"), PropertyValue::Color(diffuse))
+        .soft_unwrap();
+    let emission = v!(color.r as f32, color.g as f32, color.b as f32) / 255.0 * glow;
+    material
+        .set_property(&ImmutableString::new("emissionStrength"), PropertyValue::Vector3(emission))
+        .soft_unwrap();
+}
@@ -0,0 +1,155 @@
+//! Vector images which are only rebuilt when their shapes change.
+//!
+//! There's no message to replace the primitives of a vector image
+//! so changing them means removing the widgets and building new ones.
+//! Many shapes (e.g. the crosshair) stay the same most frames so comparing first is much cheaper.
+
+use fyrox::gui::{
+    brush::Brush,
+    message::MessageDirection,
+    vector_image::{Primitive, VectorImageBuilder},
+    widget::{WidgetBuilder, WidgetMessage},
+    HorizontalAlignment, Thickness, UiNode, UserInterface, VerticalAlignment,
+};
+
+use crate::prelude::*;
+
+/// One vector image, each can only have one color.
+#[derive(Debug, Clone)]
+pub struct Shape {
+    pub color: Color,
+    pub primitives: Vec<Primitive>,
+    pub placement: Placement,
+}
+
+/// Where the image is within its parent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Placement {
+    /// Primitives are relative to the parent's top left corner.
+    Fill,
+    /// A square with sides `size` pixels long centered in the parent and moved by `offset`.
+    ///
+    /// Primitives are relative to the square's top left corner.
+    Centered { size: f32, offset: Vector2<f32> },
+}
+
+/// The widgets showing a list of `Shape`s.
+#[derive(Debug, Default)]
+pub struct VectorShapes {
+    shapes: Vec<Shape>,
+    widgets: Vec<Handle<UiNode>>,
+}
+
+impl VectorShapes {
+    /// Show these shapes, widgets are only rebuilt if they differ from the last call.
+    ///
+    /// `parent` is `Handle::NONE` for top-level widgets.
+    pub fn update(&mut self, ui: &mut UserInterface, parent: Handle<UiNode>, shapes: Vec<Shape>) {
+        let same = self.shapes.len() == shapes.len()
+            && self.shapes.iter().zip(&shapes).all(|(old, new)| shapes_eq(old, new));
+        if same {
+            return;
+        }
+
+        self.clear(ui);
+        for shape in &shapes {
+            let widget = build(ui, shape);
+            if parent.is_some() {
+                ui.send_message(WidgetMessage::link(widget, MessageDirection::ToWidget, parent));
+            }
+            self.widgets.push(widget);
+        }
+        self.shapes = shapes;
+    }
+
+    /// Remove all the widgets.
+    pub fn clear(&mut self, ui: &mut UserInterface) {
+        for widget in self.widgets.drain(..) {
+            ui.send_message(WidgetMessage::remove(widget, MessageDirection::ToWidget));
+        }
+        self.shapes.clear();
+    }
+}
+
+fn build(ui: &mut UserInterface, shape: &Shape) -> Handle<UiNode> {
+    let mut widget = WidgetBuilder::new()
+        .with_foreground(Brush::Solid(shape.color))
+        .with_hit_test_visibility(false);
+    if let Placement::Centered { size, offset } = shape.placement {
+        // A centered widget moves by half the difference between opposite margins.
+        let margin = Thickness {
+            left: (offset.x * 2.0).max(0.0),
+            top: (offset.y * 2.0).max(0.0),
+            right: (-offset.x * 2.0).max(0.0),
+            bottom: (-offset.y * 2.0).max(0.0),
+        };
+        widget = widget
+            .with_width(size)
+            .with_height(size)
+            .with_margin(margin)
+            .with_horizontal_alignment(HorizontalAlignment::Center)
+            .with_vertical_alignment(VerticalAlignment::Center);
+    }
+    VectorImageBuilder::new(widget)
+        .with_primitives(shape.primitives.clone())
+        .build(&mut ui.build_ctx())
+}
+
+fn shapes_eq(a: &Shape, b: &Shape) -> bool {
+    a.color == b.color
+        && a.placement == b.placement
+        && a.primitives.len() == b.primitives.len()
+        && a.primitives.iter().zip(&b.primitives).all(|(a, b)| primitives_eq(a, b))
+}
+
+/// Fyrox doesn't implement `PartialEq` for primitives, this handles the ones we use.
+fn primitives_eq(a: &Primitive, b: &Primitive) -> bool {
+    match (a, b) {
+        (Primitive::Triangle { points: a }, Primitive::Triangle { points: b }) => a == b,
+        (
+            Primitive::Line {
+                begin: begin_a,
+                end: end_a,
+                thickness: thickness_a,
+            },
+            Primitive::Line {
+                begin: begin_b,
+                end: end_b,
+                thickness: thickness_b,
+            },
+        ) => begin_a == begin_b && end_a == end_b && thickness_a == thickness_b,
+        (
+            Primitive::Circle {
+                center: center_a,
+                radius: radius_a,
+                segments: segments_a,
+            },
+            Primitive::Circle {
+                center: center_b,
+                radius: radius_b,
+                segments: segments_b,
+            },
+        ) => center_a == center_b && radius_a == radius_b && segments_a == segments_b,
+        (
+            Primitive::WireCircle {
+                center: center_a,
+                radius: radius_a,
+                thickness: thickness_a,
+                segments: segments_a,
+            },
+            Primitive::WireCircle {
+                center: center_b,
+                radius: radius_b,
+                thickness: thickness_b,
+                segments: segments_b,
+            },
+        ) => {
+            center_a == center_b
+                && radius_a == radius_b
+                && thickness_a == thickness_b
+                && segments_a == segments_b
+        }
+        // Anything else is rebuilt every time to be safe.
+        _ => false,
+    }
+}
@@ -0,0 +1,68 @@
+//! Stepping the engine one tick at a time from our own game loop.
+//!
+//! We don't use Fyrox's plugin and script framework.
+//! Its executor owns the event loop and decides when to update the engine
+//! but we need to:
+//! - run gamelogic at a fixed tick rate which can be sped up, paused or singlestepped
+//! - run gamelogic both before and after physics within the same tick
+//! - run a client and a server in one process (local mode) sharing the same scene
+//! - run headless without an event loop or window, e.g. in tests with manual time
+//!
+//! Scripts would also tie gamelogic to scene nodes while ours lives in `GameState`.
+//!
+//! All calls into the engine's update functions go through here
+//! so engine upgrades which change them only need to touch this file.
+
+use fyrox::{engine::Engine, event_loop::EventLoopWindowTarget};
+
+use crate::{debug, prelude::*};
+
+/// Advance game time by one tick if another one fits before `game_time_target`.
+///
+/// Returns false when the loop should stop and wait for the next frame.
+pub fn next_tick(gs: &mut GameState, game_time_target: f32) -> bool {
+    if gs.game_time + gs.dt >= game_time_target {
+        return false;
+    }
+    gs.frame_num += 1;
+    gs.game_time_prev = gs.game_time;
+    gs.game_time += gs.dt;
+    debug::set_frame(gs.frame_num, gs.game_time);
+    true
+}
+
+/// Update animations, transformations, physics, ...
+///
+/// `window_target` is None when running without an event loop.
+/// The UI is updated separately by `update_ui` so gamelogic after physics
+/// can still change it during the same tick.
+pub fn update_scene(
+    engine: &mut Engine,
+    scene_handle: Handle<Scene>,
+    dt: f32,
+    window_target: Option<&EventLoopWindowTarget<()>>,
+) {
+    match window_target {
+        Some(window_target) => {
+            // Dummy lag since we don't use the plugin executor's fixed timestep.
+            let mut lag = 0.0;
+            engine.pre_update(dt, window_target, &mut lag, FxHashMap::default());
+            // Sanity check - if the engine starts doing something with this, we'll know.
+            assert_eq!(lag, 0.0);
+        }
+        None => {
+            // Engine::pre_update needs an event loop,
+            // update just the scene ourselves.
+            // The frame size only matters for rendering.
+            let scene = &mut engine.scenes[scene_handle];
+            scene.update(Vector2::new(1.0, 1.0), dt, Default::default());
+        }
+    }
+}
+
+/// Update the UI, call once per tick after all gamelogic.
+///
+/// Only clients have a UI.
+pub fn update_ui(engine: &mut Engine, dt: f32) {
+    engine.post_update(dt);
+}
@@ -0,0 +1,257 @@
+//! Running a server and headless clients in one process for integration tests.
+//!
+//! Time is advanced manually one tick at a time so tests don't depend on how fast they run.
+//! Messages still go through real TCP connections on localhost.
+
+use std::{sync::Once, thread, time::Duration};
+
+use fyrox::{core::futures::executor, engine::Engine};
+
+use crate::{
+    client::process::{ClientProcess, ClientStart},
+    debug,
+    prelude::*,
+    server::process::ServerProcess,
+};
+
+pub struct TestGame {
+    pub server: ServerProcess,
+    pub clients: Vec<ClientProcess>,
+    /// Ticks run since the start.
+    ticks: usize,
+}
+
+impl TestGame {
+    /// Start a server and `clients` headless clients which connect to it.
+    ///
+    /// `setup` can change the cvars used by all of them.
+    pub fn new(clients: usize, setup: impl Fn(&mut Cvars)) -> Self {
+        // The logger is global, tests run in parallel.
+        static LOGGER: Once = Once::new();
+        LOGGER.call_once(debug::logger::init);
+        // Client and server run in the same thread like in local mode.
+        debug::set_endpoint("lo");
+
+        let mut cvars = Cvars::default();
+        // Let the OS choose a free port so tests can run in parallel.
+        cvars.sv_net_listen_addr = "127.0.0.1:0".to_owned();
+        cvars.sv_headless = true;
+        cvars.cl_headless = true;
+        setup(&mut cvars);
+
+        let engine = crate::init_engine_server();
+        let mut server = executor::block_on(ServerProcess::new(cvars.clone(), engine));
+        server.manual_time = Some(0.0);
+
+        cvars.cl_connect_addr = server.addr.to_string();
+        let clients = (0..clients)
+            .map(|i| {
+                let mut cvars = cvars.clone();
+                // Don't touch the GUID file in the working directory.
+                cvars.cl_guid = format!("{i:032x}");
+                let engine = crate::init_engine_client(&cvars);
+                let start = ClientStart::Connect;
+                let mut client = executor::block_on(ClientProcess::new(cvars, engine, start));
+                client.manual_time = Some(0.0);
+                client
+            })
+            .collect();
+
+        Self {
+            server,
+            clients,
+            ticks: 0,
+        }
+    }
+
+    /// Run one tick on the server, then on all clients.
+    pub fn tick(&mut self) {
+        self.ticks += 1;
+        // Halfway between ticks so float errors don't cause running zero or two ticks.
+        let time = (self.ticks as f32 + 0.5) * self.server.gs.dt;
+
+        self.server.manual_time = Some(time);
+        self.server.update(None);
+        for client in &mut self.clients {
+            client.manual_time = Some(time);
+            client.update(None);
+        }
+    }
+
+    pub fn run(&mut self, ticks: usize) {
+        for _ in 0..ticks {
+            self.tick();
+        }
+    }
+
+    /// Tick until all clients have received init.
+    ///
+    /// Connecting happens on other threads so this waits in real time too.
+    /// Panics if it takes too long.
+    pub fn connect(&mut self) {
+        for _ in 0..5000 {
+            if self.clients.iter().all(|client| client.cg.is_some()) {
+                return;
+            }
+            self.tick();
+            thread::sleep(Duration::from_millis(1));
+        }
+        panic!("clients failed to connect");
+    }
+}
+
+/// Positions of all cycles' bodies by network ID.
+pub fn cycle_positions(engine: &Engine, gs: &GameState) -> Vec<(NetId, Vec3)> {
+    let scene = &engine.scenes[gs.scene_handle];
+    gs.cycles
+        .iter()
+        .map(|cycle| (cycle.net_id, scene.graph[cycle.body_handle].global_position()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    use crate::{
+        common::{demo, entities::PlayerState},
+        server::replay,
+    };
+
+    #[test]
+    fn clients_connect_and_sync() {
+        let mut game = TestGame::new(2, |_| {});
+        game.connect();
+        game.run(60);
+
+        assert_eq!(game.server.gs.players.alive_count(), 2);
+        assert_eq!(game.server.gs.cycles.alive_count(), 2);
+
+        let server_positions = cycle_positions(&game.server.engine, &game.server.gs);
+        for client in &game.clients {
+            assert_eq!(client.gs.players.alive_count(), 2);
+            let client_positions = cycle_positions(&client.engine, &client.gs);
+            assert_eq!(client_positions.len(), server_positions.len());
+            for (id, server_pos) in &server_positions {
+                let (_, client_pos) = client_positions
+                    .iter()
+                    .find(|(client_id, _)| client_id == id)
+                    .unwrap_or_else(|| panic!("cycle {id} missing on client"));
+                // Clients can be a tick behind.
+                let diff = (client_pos - server_pos).norm();
+                assert!(diff < 0.5, "cycle {id} is {diff} m off");
+            }
+        }
+    }
+
+    #[test]
+    fn warmup_ends_when_enough_players_join() {
+        let mut game = TestGame::new(2, |cvars| {
+            cvars.g_warmup = true;
+            cvars.g_players_min = 2;
+            cvars.g_warmup_countdown = 0.5;
+        });
+        game.connect();
+        assert!(game.server.gs.match_state.is_warmup());
+
+        game.run(60);
+        assert_eq!(game.server.gs.match_state, MatchState::Playing);
+        for client in &game.clients {
+            assert_eq!(client.gs.match_state, MatchState::Playing);
+            for player in &client.gs.players {
+                assert_eq!(player.state, PlayerState::Playing);
+            }
+        }
+    }
+
+    /// Play 2 players for a couple of seconds and return the path of the recorded demo.
+    fn record_demo(dir: &Path) -> PathBuf {
+        let dir_str = dir.to_str().unwrap().to_owned();
+        let mut game = TestGame::new(2, |cvars| {
+            cvars.sv_record_demos = true;
+            cvars.sv_record_demos_dir = dir_str.clone();
+        });
+        game.connect();
+        let cg = game.clients[0].cg.as_mut().unwrap();
+        cg.input.forward = true;
+        cg.input.fire1 = true;
+        let cg = game.clients[1].cg.as_mut().unwrap();
+        cg.input.left = true;
+        cg.input.boost = true;
+        game.run(120);
+        game.server.cvars.sv_record_demos = false;
+        game.tick();
+
+        fs::read_dir(dir).unwrap().next().unwrap().unwrap().path()
+    }
+
+    #[test]
+    fn recorded_demo_replays_identically() {
+        let dir = std::env::temp_dir().join(format!("rustcycles-demo-{}", std::process::id()));
+        let path = record_demo(&dir);
+        let entries = demo::load(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let engine = crate::init_engine_server();
+        let report = replay::verify(Cvars::default(), engine, entries).unwrap();
+        assert!(report.mismatch.is_none(), "{:?}", report.mismatch);
+        assert!(report.frames > 120, "only {} frames compared", report.frames);
+    }
+
+    /// Demos saved in `tests/demos` must keep re-simulating to the same state,
+    /// see `server::replay`.
+    #[test]
+    fn stored_demos_replay_identically() {
+        let mut count = 0;
+        for entry in fs::read_dir("tests/demos").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("demo") {
+                continue;
+            }
+            let entries = demo::load(&path).unwrap();
+            let engine = crate::init_engine_server();
+            let report = replay::verify(Cvars::default(), engine, entries).unwrap();
+            assert!(report.mismatch.is_none(), "{}: {:?}", path.display(), report.mismatch);
+            assert!(report.frames > 0, "{}: no frames compared", path.display());
+            count += 1;
+        }
+        // Otherwise the test would pass without checking anything.
+        assert!(count > 0, "no demos in tests/demos, see tests/demos/README.md");
+    }
+
+    /// Replace `tests/demos/basic.demo` with a new recording.
+    ///
+    /// Only needed when the demo format changes, see `tests/demos/README.md`.
+    #[test]
+    #[ignore]
+    fn record_stored_demo() {
+        let dir = std::env::temp_dir().join(format!("rustcycles-stored-{}", std::process::id()));
+        let path = record_demo(&dir);
+        fs::create_dir_all("tests/demos").unwrap();
+        fs::copy(&path, "tests/demos/basic.demo").unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn distant_cycles_are_not_replicated() {
+        let mut game = TestGame::new(2, |cvars| {
+            // Each client only gets its own cycle.
+            cvars.sv_interest_radius = 0.001;
+            cvars.sv_interest_margin = 0.0;
+        });
+        game.connect();
+        game.run(10);
+
+        for client in &game.clients {
+            assert_eq!(client.gs.players.alive_count(), 2);
+            assert_eq!(client.gs.cycles.alive_count(), 1);
+            let cycle = client.gs.cycles.iter().next().unwrap();
+            assert_eq!(cycle.player_handle, client.cg.as_ref().unwrap().player_handle);
+        }
+    }
+}
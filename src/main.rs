@@ -1,36 +1,59 @@
 //! This file contains entry points and event loops for the native client and server.
+//! The browser client's entry point is in wasm.rs.
 //! If you're looking for the main _game_ loops, it's in ClientProcess and ServerProcess.
 
-#![allow(clippy::option_map_unit_fn)] // Map is sometimes more readable.
+// Map is sometimes more readable.
+#![allow(clippy::option_map_unit_fn)]
 
 // Keep this first so the macros are available everywhere without having to import them.
 #[macro_use]
-pub mod debug;
+extern crate rustcycles_common;
 
 mod client;
-mod common;
-mod cvars;
-mod prelude;
+mod engine_loop;
+#[cfg(test)]
+mod harness;
 mod server;
+#[cfg(target_arch = "wasm32")]
+mod wasm;
 
-use std::{env, error::Error, panic, process::Command, sync::Arc};
+// Re-export the shared modules so the rest of the game can keep using paths like `crate::common`.
+use rustcycles_common::{common, cvars, debug, prelude};
+
+use std::{panic, sync::Arc};
 
 use fyrox::{
     asset::manager::ResourceManager,
     core::{
-        futures::executor,
         log::{Log, MessageKind},
         task::TaskPool,
     },
-    dpi::{LogicalSize, PhysicalSize},
-    engine::{EngineInitParams, GraphicsContextParams, SerializationContext},
+    dpi::{PhysicalPosition, PhysicalSize},
+    engine::{Engine, EngineInitParams, GraphicsContextParams, SerializationContext},
     event::{DeviceEvent, Event, WindowEvent},
-    event_loop::{ControlFlow, EventLoop},
+    event_loop::{ControlFlow, EventLoopWindowTarget},
     utils::translate_event,
     window::{Fullscreen, WindowBuilder},
 };
 
-use crate::{client::process::ClientProcess, prelude::*, server::process::ServerProcess};
+use crate::{client::process::ClientProcess, prelude::*};
+
+// The browser only runs the client, the other entry points are native only.
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+    env,
+    error::Error,
+    path::Path,
+    process::Command,
+    thread,
+    time::{Duration, Instant},
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use fyrox::{core::futures::executor, dpi::LogicalSize, event_loop::EventLoop};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{client::process::ClientStart, server::process::ServerProcess};
 
 // Master TODO list:
 // v0.1 - MVP:
@@ -41,12 +64,12 @@ use crate::{client::process::ClientProcess, prelude::*, server::process::ServerP
 //  - [x] Primitive networking to force client/server split
 //  - [ ] Driving and collisions
 //  - [ ] Trails
-//  - [ ] WASM local client for testing / showcases
-//      - [ ] Client and server in one process - local gameplay
+//  - [x] WASM client for testing / showcases
+//      - [x] Client and server in one process - local gameplay
 // yak-shaving:
 //  - [ ] What is happening when FPS drops to single digits
 //        (e.g. when using physics.draw twice in a frame)
-//      - [ ] Custom counter for FPS and durations - avg, max
+//      - [x] Custom counter for FPS and durations - avg, max
 // v0.2:
 //  - [x] Readme
 //  - [x] GH social preview (screenshot)
@@ -54,9 +77,10 @@ use crate::{client::process::ClientProcess, prelude::*, server::process::ServerP
 //      - [x] Check all paths are lowercase (or we might have issues on windows)
 //  - [ ] CI artifacts - allow downloading from GH
 //  - [ ] Trimesh colliders
-//      - [ ] Poles - there's many - check perf
-//      - [ ] Everything - is it possible to tunnel through at high speeds?
-//          - Yes - try CCD?
+//      - [x] Poles - there's many - check perf
+//          - Each has its own convex collider now, see g_physics_pole_colliders
+//      - [x] Everything - is it possible to tunnel through at high speeds?
+//          - Yes - cycles use CCD, see g_physics_ccd and g_physics_max_ccd_substeps
 //  - [ ] Use proper lights instead of just ambient light
 //  - [ ] Texture the whole arena
 //  - [ ] Finish RustCycle model
@@ -75,16 +99,56 @@ use crate::{client::process::ClientProcess, prelude::*, server::process::ServerP
 //  - [ ] If possible, lint against unwrap, print, println, dbg,
 //          todo, panic, unreachable, unimplemented, ... See debug.rs for alternatives.
 
+#[cfg(not(target_arch = "wasm32"))]
 #[derive(Debug)]
 enum Endpoint {
+    /// Run a local game with separate client and server processes
+    Launcher,
     /// Run a local game (client and server in one process)
     Local,
     /// Run only the game client
     Client,
     /// Run only the game server
     Server,
+    /// Watch a server demo from the view of the first player who connected
+    Replay(String),
+    /// Re-simulate a server demo and compare the results
+    Verify(String),
+    /// Run a local game for a fixed number of frames and print frame statistics
+    Benchmark,
 }
 
+/// Command line options which apply to multiple commands.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Default)]
+struct Options {
+    connect: Option<String>,
+    map: Option<String>,
+    name: Option<String>,
+    /// Loaded instead of `CONFIG_PATH`.
+    config: Option<String>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Options {
+    /// Options which are just shorthands for cvars.
+    fn apply(&self, mut cvars: Cvars) -> Cvars {
+        if let Some(map) = &self.map {
+            cvars.g_map = map.clone();
+        }
+        if let Some(name) = &self.name {
+            cvars.cl_name = name.clone();
+        }
+        cvars
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn main() {
+    wasm::main();
+}
+
+#[cfg(not(target_arch = "wasm32"))]
 fn main() -> Result<(), Box<dyn Error>> {
     // Sometimes people clone the repo and try `cargo run` without reading the instructions.
     // It has already happened back when using LFS, now it's the same with submodules.
@@ -105,10 +169,12 @@ fn main() -> Result<(), Box<dyn Error>> {
     // If hand parsing gets too complex, might wanna consider one of the libs here:
     // https://github.com/rosetta-rs/argparse-rosetta-rs
     let mut args = env::args().skip(1).peekable(); // Skip path to self
+    let mut connect_addr = None;
+    let mut benchmark_frames = None;
     let endpoint = match args.peek().map(String::as_str) {
         Some("launcher") => {
             args.next();
-            None
+            Some(Endpoint::Launcher)
         }
         Some("local") => {
             args.next();
@@ -116,21 +182,57 @@ fn main() -> Result<(), Box<dyn Error>> {
         }
         Some("client") => {
             args.next();
+            // Cvar names never contain a colon so this is unambiguous.
+            if args.peek().is_some_and(|arg| arg.contains(':')) {
+                connect_addr = args.next();
+            }
             Some(Endpoint::Client)
         }
         Some("server") => {
             args.next();
             Some(Endpoint::Server)
         }
+        Some("replay") => {
+            args.next();
+            let demo = args.next().ok_or("missing demo file for `replay`")?;
+            Some(Endpoint::Replay(demo))
+        }
+        Some("verify") => {
+            args.next();
+            let demo = args.next().ok_or("missing demo file for `verify`")?;
+            Some(Endpoint::Verify(demo))
+        }
+        Some("benchmark") => {
+            args.next();
+            // Cvar names never start with a digit so this is unambiguous.
+            if let Some(frames) = args.peek().and_then(|arg| arg.parse().ok()) {
+                args.next();
+                benchmark_frames = Some(frames);
+            }
+            Some(Endpoint::Benchmark)
+        }
         #[rustfmt::skip]
         Some("--help") => {
-            println!("Usage: rustcycles [launcher|local|client|server] [cvar1 value1 cvar2 value2 ...]");
+            println!("Usage: rustcycles [command] [options] [cvar1 value1 cvar2 value2 ...]");
             println!();
             println!("Commands (optional):");
-            println!("    launcher   Run a local game with separate client and server processes (default)");
-            println!("    local      Run a local game with client and server in one process (experimental)");
-            println!("    client     Run only the game client");
-            println!("    server     Run only the dedicated game server");
+            println!("    (none)               Run the game client and show the main menu (default)");
+            println!("    launcher             Run a local game with separate client and server processes");
+            println!("    local                Run a local game with client and server in one process (experimental)");
+            println!("    client [host:port]   Run only the game client, optionally connect to the given address");
+            println!("    server               Run only the dedicated game server");
+            println!("    replay <demo>        Watch a server demo from the view of the first player who connected");
+            println!("    verify <demo>        Re-simulate a server demo and check the game state matches the recording");
+            println!("    benchmark [frames]   Run a local game for the given number of frames (default 1000),");
+            println!("                         then print frame statistics and exit");
+            println!();
+            println!("Options (optional, after the command):");
+            println!("    --connect <host:port>   Connect to a server (client only)");
+            println!("    --map <name>            Map to play or host");
+            println!("    --name <player>         Player name");
+            println!("    --config <file>         Load cvars from this file instead of {}", crate::cvars::CONFIG_PATH);
+            println!("    --help                  Print this help (must be the first argument)");
+            println!("    --version               Print the version (must be the first argument)");
             println!();
             println!("Cvars (optional):");
             println!("    You can specify cvars in key value pairs separated by space.");
@@ -143,19 +245,33 @@ fn main() -> Result<(), Box<dyn Error>> {
             return Ok(());
         }
         Some("--version") => {
-            // LATER Would be nice to print git hash and dirty status here.
-            // Find a way to do that without increasing compile times or only do that in release builds.
-            // Note that it's especially annoying when dirty status changes and forces a rebuild.
-            // Maybe also include time of build.
-            // https://doc.rust-lang.org/cargo/reference/environment-variables.html#environment-variables-cargo-sets-for-crates
-            println!("RustCycles {}", env!("CARGO_PKG_VERSION"));
+            // Git hash, dirty status and build time are only available in release builds,
+            // see common/build.rs.
+            println!("{}", Version::current());
             return Ok(());
         }
-        Some(arg) if arg.starts_with('-') => {
-            panic!("Unknown option: {}", arg);
-        }
         _ => None,
     };
+
+    let mut options = Options::default();
+    while let Some(option) = args.next_if(|arg| arg.starts_with('-')) {
+        let mut value =
+            || args.next().ok_or_else(|| format!("missing value for option `{option}`"));
+        match option.as_str() {
+            "--connect" => options.connect = Some(value()?),
+            "--map" => options.map = Some(value()?),
+            "--name" => options.name = Some(value()?),
+            "--config" => options.config = Some(value()?),
+            _ => return Err(format!("unknown option `{option}`, see --help").into()),
+        }
+    }
+    if options.connect.is_some() {
+        if !matches!(endpoint, None | Some(Endpoint::Client)) {
+            return Err("--connect only works when running just the client".into());
+        }
+        connect_addr = options.connect.clone();
+    }
+
     // Anything else, we assume it's a cvar.
     // Some games require cvars/commands to be prefixed by `+` which allows more specific error messages
     // because they know it's meant to be a cvar/command and not a malformed command line option.
@@ -163,26 +279,66 @@ fn main() -> Result<(), Box<dyn Error>> {
     let cvar_args = args.collect();
 
     match endpoint {
-        // LATER None should launch client and offer choice in menu
         None => {
+            init_global_state("cl");
+            let mut cvars = client_cvars(&options, &cvar_args)?;
+            let start = match connect_addr {
+                Some(addr) => {
+                    cvars.cl_connect_addr = addr;
+                    ClientStart::Connect
+                }
+                None => ClientStart::Menu,
+            };
+            client_main(cvars, start);
+        }
+        Some(Endpoint::Launcher) => {
             init_global_state("launcher");
-            client_server_main(cvar_args);
+            client_server_main(&options, cvar_args);
         }
         Some(Endpoint::Local) => {
             init_global_state("lo");
-            let cvars = args_to_cvars(&cvar_args)?;
-            client_main(cvars, true);
+            let cvars = client_cvars(&options, &cvar_args)?;
+            client_main(cvars, ClientStart::Local);
         }
         Some(Endpoint::Client) => {
             init_global_state("cl");
-            let cvars = args_to_cvars(&cvar_args)?;
-            client_main(cvars, false);
+            let mut cvars = client_cvars(&options, &cvar_args)?;
+            if let Some(addr) = connect_addr {
+                cvars.cl_connect_addr = addr;
+            }
+            client_main(cvars, ClientStart::Connect);
         }
         Some(Endpoint::Server) => {
             init_global_state("sv");
-            let cvars = args_to_cvars(&cvar_args)?;
+            let mut cvars = Cvars::default();
+            if let Some(config) = &options.config {
+                cvars.load_config(config);
+            }
+            let cvars = args_to_cvars(options.apply(cvars), &cvar_args)?;
             server_main(cvars);
         }
+        Some(Endpoint::Replay(demo)) => {
+            init_global_state("cl");
+            let cvars = client_cvars(&options, &cvar_args)?;
+            client_main(cvars, ClientStart::Replay(demo));
+        }
+        Some(Endpoint::Verify(demo)) => {
+            init_global_state("sv");
+            let mut cvars = Cvars::default();
+            if let Some(config) = &options.config {
+                cvars.load_config(config);
+            }
+            let cvars = args_to_cvars(options.apply(cvars), &cvar_args)?;
+            verify_main(cvars, &demo)?;
+        }
+        Some(Endpoint::Benchmark) => {
+            init_global_state("lo");
+            let mut cvars = client_cvars(&options, &cvar_args)?;
+            let frames = benchmark_frames.unwrap_or(1000);
+            cvars.d_benchmark_frames = frames;
+            cvars.d_frame_stats_samples = frames;
+            client_main(cvars, ClientStart::Benchmark);
+        }
     }
 
     Ok(())
@@ -190,6 +346,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 
 fn init_global_state(endpoint_name: &'static str) {
     debug::set_endpoint(endpoint_name);
+    debug::logger::init();
 
     // LATER Switch fyrox to a more standard logger
     // or at least add a level below INFO so load times can remain as INFO
@@ -201,12 +358,22 @@ fn init_global_state(endpoint_name: &'static str) {
     panic::set_hook(Box::new(move |panic_info| {
         dbg_logf!("panicking"); // No need to print panic_info here, it'll be printed later anyway.
         prev_hook(panic_info);
+        debug::crash::report(panic_info);
     }));
 }
 
-fn args_to_cvars(cvar_args: &[String]) -> Result<Cvars, String> {
+/// Cvars for the client - saved settings overridden by the command line.
+///
+/// Explicit cvars override options.
+#[cfg(not(target_arch = "wasm32"))]
+fn client_cvars(options: &Options, cvar_args: &[String]) -> Result<Cvars, String> {
     let mut cvars = Cvars::default();
+    let config = options.config.as_deref().unwrap_or(crate::cvars::CONFIG_PATH);
+    cvars.load_config(config);
+    args_to_cvars(options.apply(cvars), cvar_args)
+}
 
+fn args_to_cvars(mut cvars: Cvars, cvar_args: &[String]) -> Result<Cvars, String> {
     let mut cvars_iter = cvar_args.iter();
     while let Some(cvar_name) = cvars_iter.next() {
         // Cvar names can optionally be prefixed by '+'.
@@ -246,7 +413,8 @@ fn args_to_cvars(cvar_args: &[String]) -> Result<Cvars, String> {
 ///
 /// LATER It should do that explicitly, right now it only kills the server
 /// because client quits without a server anyway.
-fn client_server_main(cvar_args: Vec<String>) {
+#[cfg(not(target_arch = "wasm32"))]
+fn client_server_main(options: &Options, cvar_args: Vec<String>) {
     let path = env::args().next().unwrap();
 
     let mut server_cmd = Command::new(&path);
@@ -255,11 +423,33 @@ fn client_server_main(cvar_args: Vec<String>) {
     server_cmd.arg("server");
     client_cmd.arg("client");
 
+    // Options have to come before cvars.
+    if let Some(map) = &options.map {
+        server_cmd.arg("--map").arg(map);
+    }
+    if let Some(name) = &options.name {
+        client_cmd.arg("--name").arg(name);
+    }
+    if let Some(config) = &options.config {
+        client_cmd.arg("--config").arg(config);
+    }
+
     for arg in &cvar_args {
         server_cmd.arg(arg);
         client_cmd.arg(arg);
     }
 
+    // Make the client connect wherever the server listens unless told otherwise.
+    let cvar_names: Vec<_> =
+        cvar_args.iter().step_by(2).map(|name| name.trim_start_matches('+')).collect();
+    if !cvar_names.contains(&"cl_connect_addr") {
+        if let Some(i) = cvar_names.iter().position(|&name| name == "sv_net_listen_addr") {
+            if let Some(addr) = cvar_args.get(i * 2 + 1) {
+                client_cmd.arg("cl_connect_addr").arg(addr);
+            }
+        }
+    }
+
     let mut server = server_cmd.spawn().unwrap();
     // Sleep so the client window appears later and gets focus.
     std::thread::sleep(std::time::Duration::from_millis(50));
@@ -277,9 +467,10 @@ fn client_server_main(cvar_args: Vec<String>) {
 
 /// LATER Do we want a shared game state or just running both
 /// client and server in one thread? Update docs on Endpoint or wherever.
-fn client_main(cvars: Cvars, local_game: bool) {
+#[cfg(not(target_arch = "wasm32"))]
+fn client_main(cvars: Cvars, start: ClientStart) {
     let engine = init_engine_client(&cvars);
-    let mut client = executor::block_on(ClientProcess::new(cvars, engine, local_game));
+    let mut client = executor::block_on(ClientProcess::new(cvars, engine, start));
 
     let event_loop = EventLoop::new().unwrap();
     // We have to use Poll instead of the default Wait because we need the main "loop" (i.e. this event handler)
@@ -292,92 +483,148 @@ fn client_main(cvars: Cvars, local_game: bool) {
     // With it, we can use AboutToWait to run updates as soon as needed.
     // The downside is we occupy a full CPU core (or 2 when there's also a server process).
     // LATER Offload gamelogic and rendering to another thread so input can be received at any time and sent to server immediately.
-    // LATER Is there a way to not waste CPU cycles so much? WaitUntil and calculate how much time till next frame?
+    //
+    // Processes which don't need to react to input or render as fast as possible
+    // (the dedicated server and headless clients) can instead use WaitUntil
+    // with the time of the next tick, see `sv_idle_sleep` and `cl_headless_idle_sleep`.
     //
     // This comment also applies to server_main.
     event_loop.set_control_flow(ControlFlow::Poll);
     event_loop
-        .run(move |event, window_target| {
-            // Exhaustively match all variants so we notice if the enum changes.
-            #[allow(clippy::single_match)]
-            match event {
-                Event::NewEvents(_) => {}
-                Event::WindowEvent { event, .. } => {
-                    if let Some(os_event) = translate_event(&event) {
-                        client.engine.user_interface.process_os_event(&os_event);
-                    }
+        .run(move |event, window_target| client_event(&mut client, event, window_target))
+        .unwrap();
+}
 
-                    match event {
-                        WindowEvent::Resized(size) => {
-                            client.resized(size);
-                        }
-                        WindowEvent::CloseRequested => {
-                            window_target.exit();
-                        }
-                        WindowEvent::Focused(focus) => {
-                            client.focused(focus);
-                        }
-                        WindowEvent::KeyboardInput { event, .. } => {
-                            client.keyboard_input(&event);
-                        }
-                        WindowEvent::MouseWheel { delta, phase, .. } => {
-                            client.mouse_wheel(delta, phase);
-                        }
-                        WindowEvent::MouseInput { state, button, .. } => {
-                            client.mouse_input(state, button);
-                        }
-                        WindowEvent::RedrawRequested => {
-                            // This event never happens in headless mode.
-                            // So don't put anything here except rendering (duh).
+/// Handle one event of the client's event loop, shared by the native and browser clients.
+fn client_event(
+    client: &mut ClientProcess,
+    event: Event<()>,
+    window_target: &EventLoopWindowTarget<()>,
+) {
+    // Exhaustively match all variants so we notice if the enum changes.
+    #[allow(clippy::single_match)]
+    match event {
+        Event::NewEvents(_) => {}
+        Event::WindowEvent { event, .. } => {
+            if let Some(os_event) = translate_event(&event) {
+                client.engine.user_interface.process_os_event(&os_event);
+            }
 
-                            client.engine.render().unwrap();
-                        }
-                        _ => {}
-                    }
+            match event {
+                WindowEvent::Resized(size) => {
+                    client.resized(size);
                 }
-                // Using device event for mouse motion because
-                // - it reports delta, not position
-                // - it doesn't care whether we're at the edge of the screen
-                Event::DeviceEvent { event, .. } => match event {
-                    DeviceEvent::MouseMotion { delta } => {
-                        client.mouse_motion(delta);
-                    }
-                    _ => {}
-                },
-                Event::UserEvent(_) => {}
-                // LATER test suspend/resume
-                Event::Suspended => {
-                    if !client.cvars.cl_headless {
-                        client.engine.destroy_graphics_context().unwrap();
-                    }
+                WindowEvent::CloseRequested => {
+                    window_target.exit();
                 }
-                Event::Resumed => {
-                    if !client.cvars.cl_headless {
-                        client.engine.initialize_graphics_context(window_target).unwrap();
-                    }
+                WindowEvent::Moved(pos) => {
+                    client.moved(pos);
                 }
-                Event::AboutToWait => {
-                    while let Some(msg) = client.engine.user_interface.poll_message() {
-                        client.ui_message(&msg);
-                    }
-                    client.update(window_target);
-                    if client.exit {
-                        window_target.exit();
-                    }
+                WindowEvent::Focused(focus) => {
+                    client.focused(focus);
                 }
-                Event::LoopExiting => {
-                    client.loop_exiting();
+                WindowEvent::KeyboardInput { event, .. } => {
+                    client.keyboard_input(&event);
                 }
-                Event::MemoryWarning => {}
+                WindowEvent::MouseWheel { delta, phase, .. } => {
+                    client.mouse_wheel(delta, phase);
+                }
+                WindowEvent::MouseInput { state, button, .. } => {
+                    client.mouse_input(state, button);
+                }
+                WindowEvent::Touch(touch) => {
+                    client.touch(&touch);
+                }
+                WindowEvent::RedrawRequested => {
+                    // This event never happens in headless mode.
+                    // So don't put anything here except rendering (duh).
+
+                    profile_scope!("render");
+                    client.engine.render().unwrap();
+                }
+                _ => {}
             }
-        })
-        .unwrap();
+        }
+        // Using device event for mouse motion because
+        // - it reports delta, not position
+        // - it doesn't care whether we're at the edge of the screen
+        Event::DeviceEvent { event, .. } => match event {
+            DeviceEvent::MouseMotion { delta } => {
+                client.mouse_motion(delta);
+            }
+            _ => {}
+        },
+        Event::UserEvent(_) => {}
+        // LATER test suspend/resume
+        Event::Suspended => {
+            if !client.cvars.cl_headless {
+                client.engine.destroy_graphics_context().unwrap();
+            }
+        }
+        Event::Resumed => {
+            if !client.cvars.cl_headless {
+                client.engine.initialize_graphics_context(window_target).unwrap();
+            }
+        }
+        Event::AboutToWait => {
+            while let Some(msg) = client.engine.user_interface.poll_message() {
+                client.ui_message(&msg);
+            }
+            // Events can wake us up before the FPS cap allows another frame.
+            if client.time_until_next_frame() == 0.0 {
+                client.update(Some(window_target));
+                if client.exit {
+                    window_target.exit();
+                }
+            }
+
+            let frame_sleep = client.time_until_next_frame();
+            if client.cvars.cl_headless && client.cvars.cl_headless_idle_sleep {
+                let sleep = client.time_until_next_tick();
+                window_target.set_control_flow(wait_until(sleep));
+            } else if frame_sleep > 0.0 {
+                window_target.set_control_flow(wait_until(frame_sleep));
+            } else {
+                window_target.set_control_flow(ControlFlow::Poll);
+            }
+        }
+        Event::LoopExiting => {
+            client.loop_exiting();
+        }
+        Event::MemoryWarning => {}
+    }
+}
+
+/// Re-simulate the demo and fail if the result differs, useful in CI.
+#[cfg(not(target_arch = "wasm32"))]
+fn verify_main(cvars: Cvars, demo: &str) -> Result<(), Box<dyn Error>> {
+    let entries = common::demo::load(Path::new(demo))?;
+    let engine = init_engine_server();
+    let report = server::replay::verify(cvars, engine, entries)?;
+    match report.mismatch {
+        None => {
+            let checksum = report.final_checksum.unwrap_or_default();
+            println!("{demo}: {} frames match, final checksum {checksum:016x}", report.frames);
+            Ok(())
+        }
+        Some(mismatch) => Err(format!(
+            "{demo}: state differs at frame {}: checksum {:016x}, recorded {:016x}",
+            mismatch.frame_num, mismatch.actual, mismatch.expected
+        )
+        .into()),
+    }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 fn server_main(cvars: Cvars) {
     let engine = init_engine_server();
     let mut server = executor::block_on(ServerProcess::new(cvars, engine));
 
+    if server.cvars.sv_headless {
+        server_main_headless(server);
+        return;
+    }
+
     let event_loop = EventLoop::new().unwrap();
     // See client_main for explanation.
     event_loop.set_control_flow(ControlFlow::Poll);
@@ -413,15 +660,51 @@ fn server_main(cvars: Cvars) {
                 }
                 Event::AboutToWait => {
                     while let Some(_msg) = server.engine.user_interface.poll_message() {}
-                    server.update(window_target);
+                    server.update(Some(window_target));
+
+                    if server.cvars.sv_idle_sleep {
+                        let sleep = server.time_until_next_tick();
+                        window_target.set_control_flow(wait_until(sleep));
+                    } else {
+                        window_target.set_control_flow(ControlFlow::Poll);
+                    }
                 }
-                Event::LoopExiting => dbg_logf!("bye"),
+                Event::LoopExiting => server.loop_exiting(),
                 Event::MemoryWarning => {}
             }
         })
         .unwrap();
 }
 
+/// Run the dedicated server with its own fixed-rate loop,
+/// without winit's event loop so no window or graphics are created.
+///
+/// LATER Handle SIGTERM/SIGINT so the server can shut down gracefully (e.g. tell the master).
+#[cfg(not(target_arch = "wasm32"))]
+fn server_main_headless(mut server: ServerProcess) {
+    loop {
+        server.update(None);
+
+        if server.cvars.sv_idle_sleep {
+            let sleep = server.time_until_next_tick();
+            thread::sleep(Duration::from_secs_f32(sleep));
+        }
+    }
+}
+
+/// Wake up the event loop after `sleep` seconds (or sooner if an event arrives).
+#[cfg(not(target_arch = "wasm32"))]
+fn wait_until(sleep: f32) -> ControlFlow {
+    ControlFlow::WaitUntil(Instant::now() + Duration::from_secs_f32(sleep))
+}
+
+/// Winit uses its own `Instant` in the browser, std's would panic there.
+/// The browser limits how often the page can run anyway so just keep polling.
+#[cfg(target_arch = "wasm32")]
+fn wait_until(_sleep: f32) -> ControlFlow {
+    ControlFlow::Poll
+}
+
 fn init_engine_client(cvars: &Cvars) -> Engine {
     let mut window_builder = WindowBuilder::new().with_title("RustCycles");
     if cvars.cl_fullscreen {
@@ -433,6 +716,17 @@ fn init_engine_client(cvars: &Cvars) -> Engine {
         // Using PhysicalSize seems more ... logical, if we let users configure it in pixels.
         window_builder = window_builder.with_inner_size(PhysicalSize::new(width, height));
     }
+    if !cvars.cl_window_pos_auto {
+        let pos = PhysicalPosition::new(cvars.cl_window_x, cvars.cl_window_y);
+        window_builder = window_builder.with_position(pos);
+    }
+    // The monitor and exclusive mode are applied after the window is created,
+    // see `ClientProcess::update_window`.
+    #[cfg(target_arch = "wasm32")]
+    {
+        use fyrox::platform::web::WindowBuilderExtWebSys;
+        window_builder = window_builder.with_append(true);
+    }
 
     // LATER no vsync
     let task_pool = Arc::new(TaskPool::new());
@@ -448,6 +742,9 @@ fn init_engine_client(cvars: &Cvars) -> Engine {
     .unwrap()
 }
 
+/// The graphics context is only created when `Event::Resumed` arrives
+/// so this is also usable for the headless server.
+#[cfg(not(target_arch = "wasm32"))]
 fn init_engine_server() -> Engine {
     let window_builder = WindowBuilder::new()
         .with_title("RustCycles server")
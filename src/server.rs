@@ -1,4 +1,13 @@
 //! The authoritative server in a client-server multiplayer game architecture.
 
+pub mod demo;
 pub mod game;
+// The browser only runs a server for local games, inside the client process.
+#[cfg(not(target_arch = "wasm32"))]
+pub mod master;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod process;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod replay;
+pub mod results;
+pub mod stats;
@@ -0,0 +1,69 @@
+//! Server-side demo recording, see `sv_record_demos` and `common::demo` for the format.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, ErrorKind, Write},
+    path::PathBuf,
+};
+
+use crate::{
+    common::demo::{DemoEntry, DemoEntryRef, DemoHeader},
+    prelude::*,
+};
+
+/// A demo file being written.
+pub struct DemoRecorder {
+    pub path: PathBuf,
+    /// The map being recorded, a new demo starts when it changes.
+    pub map: String,
+    writer: BufWriter<File>,
+    /// Writing failed, the recording should be stopped.
+    pub failed: bool,
+}
+
+impl DemoRecorder {
+    /// Create a new demo in `dir` named after the map and time and write its header.
+    pub fn new(dir: &str, header: DemoHeader) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        // Colons aren't allowed in file names on windows.
+        let time = header.recorded_at.replace(' ', "_").replace(':', "-");
+        let path = PathBuf::from(dir).join(format!("{}-{}.demo", time, header.map));
+        let writer = BufWriter::new(File::create(&path)?);
+        let mut recorder = Self {
+            path,
+            map: header.map.clone(),
+            writer,
+            failed: false,
+        };
+        recorder.write_entry(&DemoEntry::Header(header))?;
+        Ok(recorder)
+    }
+
+    /// Errors are logged and set `failed` so callers don't have to handle them
+    /// in the middle of gamelogic.
+    pub fn write(&mut self, entry: &DemoEntryRef<'_>) {
+        if self.failed {
+            return;
+        }
+        if let Err(e) = self.write_entry(entry) {
+            dbg_logf!("failed to write demo {}: {}", self.path.display(), e);
+            self.failed = true;
+        }
+    }
+
+    fn write_entry(&mut self, entry: &DemoEntryRef<'_>) -> io::Result<()> {
+        bincode::serialize_into(&mut self.writer, entry)
+            .map_err(|e| io::Error::new(ErrorKind::Other, e))
+    }
+
+    /// Called once per frame so a crash loses at most one frame.
+    pub fn flush(&mut self) {
+        if self.failed {
+            return;
+        }
+        if let Err(e) = self.writer.flush() {
+            dbg_logf!("failed to write demo {}: {}", self.path.display(), e);
+            self.failed = true;
+        }
+    }
+}
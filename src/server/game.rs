@@ -2,22 +2,61 @@
 
 use std::io::ErrorKind;
 
+use fyrox::{core::futures::executor, engine::Engine};
+
 use crate::{
     common::{
-        entities::{Player, PlayerState},
-        net::{self, Connection, Listener},
+        collisions::CollisionEvent,
+        demo::{DemoEntry, DemoEntryRef, DemoHeader, DEMO_FORMAT},
+        entities::{Cycle, Player, PlayerState},
+        maps,
+        net::{self, Connection, Listener, NetworkMessage},
+        Input,
+    },
+    debug::{
+        logger::{system_time_now, utc_timestamp},
+        DEBUG_SHAPES, DEBUG_TEXTS, DEBUG_TEXTS_WORLD,
     },
-    debug::{DEBUG_SHAPES, DEBUG_TEXTS, DEBUG_TEXTS_WORLD},
     prelude::*,
+    server::{
+        demo::DemoRecorder,
+        results::{self, Kill, MatchResult},
+        stats::{self, MatchStats, StatsWorker},
+    },
 };
 
 /// A game server. Could be a dedicated or a listen server.
 ///
 /// Lets clients connect to play.
 pub struct ServerGame {
-    // LATER Connections and the listener should probably be persistent across matches.
-    listener: Box<dyn Listener<ClientMessage>>,
+    // LATER Connections and the listeners should probably be persistent across matches.
+    /// TCP and optionally WebSocket for remote games, mpsc for local games.
+    listeners: Vec<Box<dyn Listener<ClientMessage>>>,
     clients: Pool<RemoteClient>,
+    /// Reused for serializing every message so sending doesn't allocate.
+    send_buf: NetworkMessage,
+    /// Scratch space for `ServerFrameCtx::for_each_client` so it doesn't allocate every frame.
+    client_handles: Vec<Handle<RemoteClient>>,
+    /// Set when intermission is over, the process then switches to this map.
+    pub next_map: Option<String>,
+    /// The last `g_map_rotation` which was checked for unknown maps
+    /// so they're only reported when it changes.
+    map_rotation_checked: String,
+    vote: Option<Vote>,
+    /// Cycles currently going faster than `sv_anticheat_speed_max`
+    /// so they're only logged once each time.
+    speeding: FxHashSet<NetId>,
+    /// Started when first needed, see `sv_stats_db`.
+    stats: Option<StatsWorker>,
+    /// Kills in the current match for `MatchResult`.
+    kill_log: Vec<Kill>,
+    /// The last `ServerMessage::Lobby` sent to clients so it's only sent when it changes.
+    lobby_sent: Vec<LobbyPlayer>,
+    /// See `sv_record_demos`.
+    demo: Option<DemoRecorder>,
+    /// Creating or writing the demo file failed, don't try again every frame
+    /// until `sv_record_demos` is toggled.
+    demo_failed: bool,
 }
 
 /// All data necessary to run a frame of server-side gamelogic in one convenient package.
@@ -37,10 +76,162 @@ pub struct ServerFrameCtx<'a> {
 }
 
 impl ServerGame {
-    pub async fn new(listener: Box<dyn Listener<ClientMessage>>) -> Self {
+    pub async fn new(listeners: Vec<Box<dyn Listener<ClientMessage>>>) -> Self {
         Self {
-            listener,
+            listeners,
             clients: Pool::new(),
+            send_buf: NetworkMessage::default(),
+            client_handles: Vec::new(),
+            next_map: None,
+            map_rotation_checked: String::new(),
+            vote: None,
+            speeding: FxHashSet::default(),
+            stats: None,
+            kill_log: Vec::new(),
+            lobby_sent: Vec::new(),
+            demo: None,
+            demo_failed: false,
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn add_listener(&mut self, listener: Box<dyn Listener<ClientMessage>>) {
+        self.listeners.push(listener);
+    }
+
+    /// Load the map in `g_map` and move everyone to it, starting a new match.
+    ///
+    /// Players keep their scores unless `reset_scores` is set,
+    /// everyone who was playing gets a new cycle.
+    /// Clients are told to throw away their game state and get a new init.
+    pub fn change_map(
+        &mut self,
+        cvars: &mut Cvars,
+        engine: &mut Engine,
+        gs: &mut GameState,
+        reset_scores: bool,
+    ) {
+        if maps::path(&cvars.g_map).is_none() {
+            dbg_logf!("unknown map {}", cvars.g_map);
+            // Don't try again every frame.
+            cvars.g_map = gs.map_name.clone();
+            // After intermission, the match has to restart somewhere
+            // or the server would stay in intermission forever.
+            if !gs.match_state.is_over() {
+                return;
+            }
+        }
+        dbg_logf!("changing map from {} to {}", gs.map_name, cvars.g_map);
+        let cvars = &*cvars;
+
+        // Players get new handles and clients get a new init without the vote anyway.
+        if let Some(vote) = self.vote.take() {
+            dbg_logf!("vote {} cancelled by map change", vote.kind);
+        }
+        // Clients will ask for the new map if they need it.
+        // Everyone has to get ready again for the next match.
+        for client in &mut self.clients {
+            client.map_download = None;
+            client.ready = false;
+        }
+        // Clients throw away their lobby with the old game state.
+        self.lobby_sent.clear();
+        self.kill_log.clear();
+
+        let client_players = self.client_players(gs);
+        let old_ctx = FrameCtx {
+            cvars,
+            scene: &mut engine.scenes[gs.scene_handle],
+            gs,
+        };
+        let mut sync = old_ctx.full_sync();
+        // Only players carry over, everything else belongs to the old map.
+        sync.cycles.clear();
+        sync.projectiles.clear();
+        sync.pickups.clear();
+        sync.match_start = gs.game_time;
+        sync.match_state = if cvars.g_lobby {
+            MatchState::Lobby
+        } else if cvars.g_warmup {
+            MatchState::Warmup
+        } else {
+            MatchState::Playing
+        };
+        if reset_scores {
+            for player in &mut sync.players {
+                player.kills = 0;
+                player.deaths = 0;
+            }
+        }
+
+        let mut new_gs = executor::block_on(GameState::new(
+            cvars,
+            &engine.resource_manager,
+            &mut engine.scenes,
+            gs.gs_type,
+            &cvars.g_map,
+        ));
+        // Time keeps going so the game loop and clients don't notice a jump.
+        new_gs.frame_num = gs.frame_num;
+        new_gs.game_time = gs.game_time;
+        new_gs.game_time_prev = gs.game_time_prev;
+
+        let mut ctx = FrameCtx {
+            cvars,
+            scene: &mut engine.scenes[new_gs.scene_handle],
+            gs: &mut new_gs,
+        };
+        ctx.set_dt(gs.dt);
+        ctx.apply_full_sync(sync);
+        let playing: Vec<_> = ctx
+            .gs
+            .players
+            .pair_iter()
+            .filter(|(_, player)| player.state == PlayerState::Playing)
+            .map(|(player_handle, _)| player_handle)
+            .collect();
+        for player_handle in playing {
+            ctx.spawn_cycle(player_handle, None);
+        }
+
+        engine.scenes.remove(gs.scene_handle);
+        *gs = new_gs;
+        self.relink_players(gs, client_players);
+
+        let mut sv_ctx = ServerFrameCtx {
+            cvars,
+            scene: &mut engine.scenes[gs.scene_handle],
+            gs,
+            sg: self,
+        };
+        // Start recording the new map before telling clients about it.
+        sv_ctx.update_demo();
+        let msg = ServerMessage::ChangeMap {
+            map: sv_ctx.gs.map_name.clone(),
+        };
+        sv_ctx.network_send(msg, SendDest::All);
+        // Sending ChangeMap might have disconnected some clients, they're skipped.
+        sv_ctx.for_each_client(|sv_ctx, client_handle| sv_ctx.send_init(client_handle));
+    }
+
+    /// The network ID of each client's player.
+    ///
+    /// Get it before `FrameCtx::apply_full_sync` and pass it to `relink_players` after.
+    pub fn client_players(&self, gs: &GameState) -> Vec<(Handle<RemoteClient>, NetId)> {
+        self.clients
+            .pair_iter()
+            .map(|(client_handle, client)| (client_handle, gs.players[client.player_handle].net_id))
+            .collect()
+    }
+
+    /// Look up player handles again by network ID after `FrameCtx::apply_full_sync`.
+    pub fn relink_players(
+        &mut self,
+        gs: &GameState,
+        client_players: Vec<(Handle<RemoteClient>, NetId)>,
+    ) {
+        for (client_handle, player_id) in client_players {
+            self.clients[client_handle].player_handle = gs.player_ids.get(player_id).unwrap();
         }
     }
 }
@@ -54,61 +245,106 @@ impl ServerFrameCtx<'_> {
         }
     }
 
+    /// Call `f` for every client, it gets the whole context so it can send messages etc.
+    ///
+    /// Clients disconnected by `f` (e.g. because sending failed) are skipped.
+    /// Clients which connect during the loop are not visited.
+    fn for_each_client(&mut self, mut f: impl FnMut(&mut Self, Handle<RemoteClient>)) {
+        // Taken out so `f` can borrow self, a nested call simply allocates its own.
+        let mut client_handles = std::mem::take(&mut self.sg.client_handles);
+        self.sg.clients.collect_handles_into(&mut client_handles);
+        for &client_handle in &client_handles {
+            if self.sg.clients.is_valid_handle(client_handle) {
+                f(self, client_handle);
+            }
+        }
+        self.sg.client_handles = client_handles;
+    }
+
     pub fn tick_begin_frame(&mut self) {
+        self.sys_demo();
         self.accept_new_connections();
         self.connect_bots();
         self.sys_receive();
+        self.sys_vote();
+        self.sys_map_downloads();
+        self.sys_apply_inputs();
+        self.sys_map_rotation();
+        self.sys_match_state();
+        self.sys_lobby();
+        self.sys_suicide();
+        self.sys_out_of_bounds();
+        self.sys_respawn();
+    }
+
+    pub fn tick_after_physics(&mut self) {
+        self.sys_ramming();
+        self.sys_pickups();
+        self.sys_speed_check();
+        self.sys_stats();
+        self.sys_stats_replies();
     }
 
     pub fn accept_new_connections(&mut self) {
-        loop {
-            match self.sg.listener.accept_conn() {
-                Ok(conn) => {
-                    dbg_logf!("connection accepted {}", conn.addr());
-
-                    // TODO(bug) If sending fails, clien is disconnected but this function continues - will likely crash.
-
-                    // TODO Do what RecWars does - client and player created together,
-                    // send init to new player, AddPlayer to everyone except him.
-                    // Spawning and sending it a separate event that happens many times during a game.
-
-                    // Add player
-                    // This is sent to all clients except the new one.
-                    let player = Player::new(None);
-                    let player_handle = self.gs.players.spawn(player);
-                    let add_player = AddPlayer {
-                        name: "Player".to_owned(), // LATER from client
-                        player_index: player_handle.index(),
-                    };
-                    let msg = ServerMessage::AddPlayer(add_player);
-                    self.network_send(msg, SendDest::All);
-
-                    // Create client
-                    // This is after adding the player so that we can send the new client
-                    // its own player index.
-                    let client = RemoteClient::new(conn, player_handle);
-                    let client_handle = self.sg.clients.spawn(client);
-                    self.send_init(client_handle);
-
-                    // Spawn cycle
-                    let cycle_handle = self.ctx().spawn_cycle(player_handle, None);
-
-                    // Tell all players
-                    let player_cycle = PlayerCycle {
-                        player_index: player_handle.index(),
-                        cycle_index: cycle_handle.index(),
-                    };
-                    let msg = ServerMessage::SpawnCycle(player_cycle);
-                    self.network_send(msg, SendDest::All);
+        let mut accepted = Vec::new();
+        for listener in &mut self.sg.listeners {
+            loop {
+                match listener.accept_conn() {
+                    Ok(conn) => accepted.push(conn),
+                    Err(err) => match err.kind() {
+                        ErrorKind::WouldBlock => {
+                            break;
+                        }
+                        _ => panic!("network error (accept): {}", err),
+                    },
                 }
-                Err(err) => match err.kind() {
-                    ErrorKind::WouldBlock => {
-                        break;
-                    }
-                    _ => panic!("network error (accept): {}", err),
-                },
             }
         }
+        for conn in accepted {
+            self.accept_connection(conn);
+        }
+    }
+
+    fn accept_connection(&mut self, conn: Box<dyn Connection<ClientMessage>>) {
+        dbg_logf!("connection accepted {}", conn.addr());
+
+        // TODO(bug) If sending fails, clien is disconnected but this function continues - will likely crash.
+
+        // TODO Do what RecWars does - client and player created together,
+        // send init to new player, Spawn to everyone except him.
+        // Spawning and sending it a separate event that happens many times during a game.
+
+        // Add player
+        // This is sent to all clients except the new one.
+        let player_id = self.gs.alloc_net_id();
+        let player = Player::new(player_id, None);
+        let player_handle = self.ctx().spawn_player(player);
+        dbg_logf!("client {} joined as player {}", conn.addr(), player_id);
+        self.record_demo(&DemoEntry::Connect { player_id });
+        let msg = ServerMessage::Spawn(self.player_spawn(player_handle));
+        self.network_send(msg, SendDest::All);
+
+        // Create client
+        // This is after adding the player so that we can send the new client
+        // its own player ID.
+        let client = RemoteClient::new(conn, player_handle);
+        let client_handle = self.sg.clients.spawn(client);
+        let msg = ServerMessage::Version(Version::current(), None);
+        self.network_send(msg, SendDest::One(client_handle));
+        if self.sg.clients.is_valid_handle(client_handle) {
+            self.send_init(client_handle);
+        }
+
+        // Spawn cycle
+        // Clients are told in `sys_replicate`.
+        self.ctx().spawn_cycle(player_handle, None);
+
+        // Nobody wants to wait in the menu during warmup.
+        if self.gs.match_state.is_warmup() {
+            self.gs.players[player_handle].state = PlayerState::Playing;
+            let msg = ServerMessage::Join { player_id };
+            self.network_send(msg, SendDest::All);
+        }
     }
 
     fn connect_bots(&mut self) {
@@ -119,35 +355,143 @@ impl ServerFrameCtx<'_> {
     fn sys_receive(&mut self) {
         let mut disconnected = Vec::new();
         let mut msgs_to_all = Vec::new();
+        let mut resync = Vec::new();
+        let mut vote_calls = Vec::new();
+        let mut votes = Vec::new();
+        let mut map_requests = Vec::new();
+        let mut stats_requests = Vec::new();
         for (client_handle, client) in self.sg.clients.pair_iter_mut() {
-            let (msgs, closed) = client.conn.receive();
+            let (msgs, closed) = client.conn.receive(self.cvars.sv_net_max_message_len);
+            if self.cvars.net_encryption && client.conn.is_plaintext() {
+                dbg_logf!("client {} is not encrypted, disconnecting", client.conn.addr());
+                disconnected.push(client_handle);
+                continue;
+            }
             // We might have received valid messages before the stream was closed - handle them
             // even though for some, such as player input, it doesn't affect anything.
             for msg in msgs {
+                if let Some(demo) = &mut self.sg.demo {
+                    let from = self.gs.players[client.player_handle].net_id;
+                    demo.write(&DemoEntry::Received { from, msg: &msg });
+                }
                 match msg {
-                    ClientMessage::Version(_) => todo!(),
-                    ClientMessage::Input(input) => {
-                        // LATER (server reconciliation) handle more inputs arriving in one frame
-                        self.gs.players[client.player_handle].input = input;
+                    ClientMessage::Version(version, _) => {
+                        // Escaped because the game name comes from the client.
+                        dbg_logf!(
+                            "client {} version: {:?}",
+                            client.conn.addr(),
+                            version.to_string()
+                        );
+                        if version.game != Version::current().game {
+                            dbg_logf!("client {} is not a RustCycles client", client.conn.addr());
+                            disconnected.push(client_handle);
+                        }
+                    }
+                    ClientMessage::Input { seq, input } => {
+                        if let Some(prev) = client.input_seq {
+                            if seq != prev.wrapping_add(1) {
+                                dbg_logf!(
+                                    "client {} input seq jumped from {} to {}",
+                                    client.conn.addr(),
+                                    prev,
+                                    seq
+                                );
+                            }
+                        }
+                        client.input_seq = Some(seq);
+
+                        let mut input: Input = input.into();
+                        let player_id = self.gs.players[client.player_handle].net_id;
+                        if let Err(err) = check_input_time(self.cvars, self.gs.game_time, &input) {
+                            if self.cvars.sv_anticheat_log {
+                                dbg_logf!("anticheat: player {} sent {}", player_id, err);
+                            }
+                            continue;
+                        }
+                        if clamp_input(self.cvars, &mut input) && self.cvars.sv_anticheat_log {
+                            dbg_logf!("anticheat: player {} sent angles out of range", player_id);
+                        }
+                        client.inputs.push_back(input);
+                        // Don't let latency grow indefinitely after a lag spike.
+                        while client.inputs.len() > self.cvars.sv_input_buffer_max.max(1) {
+                            client.inputs.pop_front();
+                        }
                     }
                     ClientMessage::Chat(chat) => {
+                        if let Some(args) = chat.strip_prefix("callvote ") {
+                            match VoteKind::parse(args) {
+                                Ok(kind) => vote_calls.push((client.player_handle, kind)),
+                                Err(err) => dbg_logf!("client {}: {}", client.conn.addr(), err),
+                            }
+                            continue;
+                        }
                         // LATER Show chat in-game
-                        dbg_logd!(chat);
+                        let player_id = self.gs.players[client.player_handle].net_id;
+                        // Escaped so players can't forge log lines with newlines or terminal escapes.
+                        dbg_logf!("player {} says: {:?}", player_id, chat);
                     }
                     ClientMessage::Join => {
-                        self.gs.players[client.player_handle].state = PlayerState::Playing;
-                        let player_index = client.player_handle.index();
-                        dbg_logf!("player {} is now playing", player_index);
-                        let msg = ServerMessage::Join { player_index };
+                        let player = &mut self.gs.players[client.player_handle];
+                        player.state = PlayerState::Playing;
+                        let player_id = player.net_id;
+                        dbg_logf!("player {} is now playing", player_id);
+                        let msg = ServerMessage::Join { player_id };
                         msgs_to_all.push(msg);
                     }
                     ClientMessage::Observe => {
-                        self.gs.players[client.player_handle].state = PlayerState::Observing;
-                        let player_index = client.player_handle.index();
-                        dbg_logf!("player {} is now observing", player_index);
-                        let msg = ServerMessage::Observe { player_index };
+                        let player = &mut self.gs.players[client.player_handle];
+                        player.state = PlayerState::Observing;
+                        let player_id = player.net_id;
+                        dbg_logf!("player {} is now observing", player_id);
+                        let msg = ServerMessage::Observe { player_id };
                         msgs_to_all.push(msg);
                     }
+                    ClientMessage::RequestFullSync => {
+                        dbg_logf!("client {} requested full sync", client.conn.addr());
+                        resync.push(client_handle);
+                    }
+                    ClientMessage::CallVote(kind) => {
+                        vote_calls.push((client.player_handle, kind));
+                    }
+                    ClientMessage::Vote(yes) => {
+                        votes.push((client.player_handle, yes));
+                    }
+                    ClientMessage::Name(name) => {
+                        // LATER Tell other clients.
+                        let name: String = name.trim().chars().take(32).collect();
+                        if name.is_empty() {
+                            continue;
+                        }
+                        let player = &mut self.gs.players[client.player_handle];
+                        dbg_logf!("player {} is now known as {:?}", player.net_id, name);
+                        player.name = name;
+                    }
+                    ClientMessage::Guid(guid) => {
+                        // Short GUIDs could be guessed.
+                        let is_valid = (32..=64).contains(&guid.len())
+                            && guid.chars().all(|c| c.is_ascii_alphanumeric());
+                        if is_valid {
+                            client.guid = Some(stats::guid_key(&guid));
+                        } else {
+                            dbg_logf!("client {} sent invalid GUID {:?}", client.conn.addr(), guid);
+                        }
+                    }
+                    ClientMessage::RequestStats(player) => {
+                        stats_requests.push((client_handle, player));
+                    }
+                    ClientMessage::RequestMap(map) => {
+                        map_requests.push((client_handle, map));
+                    }
+                    ClientMessage::Ready(ready) => {
+                        let player_id = self.gs.players[client.player_handle].net_id;
+                        dbg_logf!("player {} ready: {}", player_id, ready);
+                        client.ready = ready;
+                    }
+                    ClientMessage::Team(team) => {
+                        let player_id = self.gs.players[client.player_handle].net_id;
+                        dbg_logf!("player {} team: {:?}", player_id, team);
+                        client.team = team;
+                    }
                 }
             }
             if closed {
@@ -160,63 +504,1124 @@ impl ServerFrameCtx<'_> {
         for msg in msgs_to_all {
             self.network_send(msg, SendDest::All);
         }
+        for client_handle in resync {
+            self.send_full_sync(client_handle);
+        }
+        for (player_handle, kind) in vote_calls {
+            self.call_vote(player_handle, kind);
+        }
+        for (player_handle, yes) in votes {
+            self.cast_vote(player_handle, yes);
+        }
+        for (client_handle, map) in map_requests {
+            self.start_map_download(client_handle, map);
+        }
+        for (client_handle, player) in stats_requests {
+            self.send_stats(client_handle, player);
+        }
+    }
+
+    /// Reply to `ClientMessage::RequestStats`.
+    fn send_stats(&mut self, client_handle: Handle<RemoteClient>, query: String) {
+        if !self.sg.clients.is_valid_handle(client_handle) {
+            return;
+        }
+        // Connected players can be looked up by ID or their current name,
+        // the DB only knows the name they had when their stats were last saved.
+        let connected =
+            query.parse().ok().and_then(|id| self.player_client(NetId(id))).or_else(|| {
+                self.sg
+                    .clients
+                    .pair_iter()
+                    .find(|(_, client)| self.gs.players[client.player_handle].name == query)
+                    .map(|(handle, _)| handle)
+            });
+        let key = connected
+            .and_then(|handle| self.sg.clients[handle].guid.clone())
+            .unwrap_or_else(|| query.clone());
+
+        let player_id = self.gs.players[self.sg.clients[client_handle].player_handle].net_id;
+        if let Some(worker) = self.stats_worker() {
+            // Answered in `sys_stats_replies`.
+            worker.get(player_id, query, key);
+        } else {
+            let msg = ServerMessage::Stats { query, stats: None };
+            self.network_send(msg, SendDest::One(client_handle));
+        }
+    }
+
+    /// Send the stats looked up by the worker to the players who asked.
+    fn sys_stats_replies(&mut self) {
+        let mut replies = Vec::new();
+        if let Some(worker) = &self.sg.stats {
+            while let Some(reply) = worker.poll() {
+                replies.push(reply);
+            }
+        }
+        for reply in replies {
+            // The player might have disconnected in the meantime.
+            let Some(client_handle) = self.player_client(reply.player_id) else {
+                continue;
+            };
+            let msg = ServerMessage::Stats {
+                query: reply.query,
+                stats: reply.stats,
+            };
+            self.network_send(msg, SendDest::One(client_handle));
+        }
+    }
+
+    /// The worker for the DB at `sv_stats_db`, None if it's disabled or can't be started.
+    fn stats_worker(&mut self) -> Option<&StatsWorker> {
+        let cvars = self.cvars;
+        let path = &cvars.sv_stats_db;
+        if path.is_empty() {
+            self.sg.stats = None;
+            return None;
+        }
+        if self.sg.stats.as_ref().map(|worker| &worker.path) != Some(path) {
+            // Dropping the old worker waits for its writes so the two don't fight over the file.
+            self.sg.stats = None;
+            self.sg.stats = match StatsWorker::start(path) {
+                Ok(worker) => Some(worker),
+                Err(e) => {
+                    dbg_logf!("failed to start stats worker for {}: {}", path, e);
+                    None
+                }
+            };
+        }
+        self.sg.stats.as_ref()
+    }
+
+    /// Add what the client's player did since the last save to their totals.
+    ///
+    /// Clients which didn't send a GUID don't get stats.
+    fn save_stats(&mut self, client_handle: Handle<RemoteClient>, finished: bool) {
+        let client = &mut self.sg.clients[client_handle];
+        let stats = std::mem::take(&mut client.stats);
+        let Some(key) = client.guid.clone() else {
+            return;
+        };
+        let name = self.gs.players[client.player_handle].name.clone();
+        if let Some(worker) = self.stats_worker() {
+            worker.add(key, name, stats, finished);
+        }
+    }
+
+    /// Stats of the client controlling the player.
+    ///
+    /// None outside of the match itself so warmup and intermission don't count.
+    fn match_stats(&mut self, player_handle: Handle<Player>) -> Option<&mut MatchStats> {
+        if !self.gs.match_state.is_running() {
+            return None;
+        }
+        self.sg
+            .clients
+            .iter_mut()
+            .find(|client| client.player_handle == player_handle)
+            .map(|client| &mut client.stats)
+    }
+
+    /// Count time played and distance driven, see `sv_stats_db`.
+    fn sys_stats(&mut self) {
+        if !self.gs.match_state.is_running() {
+            return;
+        }
+        for client in &mut self.sg.clients {
+            let player = &self.gs.players[client.player_handle];
+            if player.state != PlayerState::Playing {
+                continue;
+            }
+            client.stats.time_played += self.gs.dt;
+            if let Some(cycle_handle) = player.cycle_handle {
+                let body_handle = self.gs.cycles[cycle_handle].body_handle;
+                let vel = self.scene.graph[body_handle].as_rigid_body().lin_vel();
+                client.stats.distance += v!(vel.x, 0, vel.z).norm() * self.gs.dt;
+            }
+        }
+    }
+
+    fn start_map_download(&mut self, client_handle: Handle<RemoteClient>, map: String) {
+        let Some(client) = self.sg.clients.try_borrow_mut(client_handle) else {
+            return;
+        };
+        // Only send the current map, clients have no business downloading anything else.
+        let path = maps::path(&map).filter(|_| map == self.gs.map_name);
+        let Some(path) = path else {
+            dbg_logf!("client {} requested unavailable map {}", client.conn.addr(), map);
+            return;
+        };
+        let data = match std::fs::read(&path) {
+            Ok(data) => data,
+            Err(e) => {
+                dbg_logf!("failed to read map {} from {}: {}", map, path.display(), e);
+                return;
+            }
+        };
+        dbg_logf!("sending map {} ({} bytes) to client {}", map, data.len(), client.conn.addr());
+        client.map_download = Some(MapDownload {
+            hash: maps::hash(&data),
+            map,
+            data,
+            offset: 0,
+        });
+    }
+
+    /// Send the next chunk of the map to every client downloading it.
+    ///
+    /// One chunk per tick so downloads don't delay updates for everyone.
+    fn sys_map_downloads(&mut self) {
+        let chunk_size = self.cvars.sv_map_download_chunk_size.max(1);
+        let mut chunks = Vec::new();
+        for (client_handle, client) in self.sg.clients.pair_iter_mut() {
+            let Some(download) = &mut client.map_download else {
+                continue;
+            };
+            let end = (download.offset + chunk_size).min(download.data.len());
+            let chunk = MapChunk {
+                map: download.map.clone(),
+                offset: download.offset as u64,
+                total_len: download.data.len() as u64,
+                hash: download.hash,
+                data: download.data[download.offset..end].to_vec(),
+            };
+            chunks.push((client_handle, chunk));
+            download.offset = end;
+            if end == download.data.len() {
+                client.map_download = None;
+            }
+        }
+        for (client_handle, chunk) in chunks {
+            // A previous send might have disconnected the client.
+            if self.sg.clients.is_valid_handle(client_handle) {
+                self.network_send(ServerMessage::MapChunk(chunk), SendDest::One(client_handle));
+            }
+        }
+    }
+
+    /// Start a vote if none is in progress, the caller votes yes.
+    fn call_vote(&mut self, caller: Handle<Player>, kind: VoteKind) {
+        // The caller might have disconnected after sending it.
+        let Some(caller_id) = self.gs.players.try_borrow(caller).map(|player| player.net_id) else {
+            return;
+        };
+        if !self.cvars.sv_votes {
+            dbg_logf!("player {} called vote {:?} but voting is disabled", caller_id, kind);
+            return;
+        }
+        if let Some(vote) = &self.sg.vote {
+            dbg_logf!(
+                "player {} called vote {:?} but vote {} is in progress",
+                caller_id,
+                kind,
+                vote.kind
+            );
+            return;
+        }
+        let valid = match &kind {
+            VoteKind::Map(map) => maps::path(map).is_some(),
+            VoteKind::Kick { player_id } => self.player_client(*player_id).is_some(),
+        };
+        if !valid {
+            dbg_logf!("player {} called invalid vote {:?}", caller_id, kind);
+            return;
+        }
+
+        dbg_logf!("player {} called vote {}", caller_id, kind);
+        let mut voters = FxHashMap::default();
+        voters.insert(caller, true);
+        self.sg.vote = Some(Vote {
+            kind,
+            voters,
+            time_end: self.gs.game_time + self.cvars.sv_vote_duration,
+            changed: true,
+        });
+    }
+
+    /// Record a player's vote, they can change their mind until the vote ends.
+    fn cast_vote(&mut self, player_handle: Handle<Player>, yes: bool) {
+        let Some(vote) = &mut self.sg.vote else {
+            return;
+        };
+        if self.gs.players.is_valid_handle(player_handle)
+            && vote.voters.insert(player_handle, yes) != Some(yes)
+        {
+            vote.changed = true;
+        }
+    }
+
+    /// Tell clients how the vote is going and resolve it.
+    ///
+    /// A vote passes once more than half of all connected players voted yes
+    /// and fails once that's no longer possible or when it times out.
+    fn sys_vote(&mut self) {
+        let Some(vote) = &mut self.sg.vote else {
+            return;
+        };
+
+        let players = &self.gs.players;
+        let voters_before = vote.voters.len();
+        vote.voters.retain(|&player_handle, _| players.is_valid_handle(player_handle));
+        if vote.voters.len() != voters_before {
+            vote.changed = true;
+        }
+
+        let status = vote.status();
+        let total = self.sg.clients.alive_count();
+        let passed = status.yes * 2 > total;
+        let failed = status.no * 2 >= total || vote.time_end <= self.gs.game_time;
+        if !passed && !failed {
+            if vote.changed {
+                vote.changed = false;
+                self.network_send(ServerMessage::VoteStatus(Some(status)), SendDest::All);
+            }
+            return;
+        }
+
+        let vote = self.sg.vote.take().unwrap();
+        self.network_send(ServerMessage::VoteStatus(None), SendDest::All);
+        if !passed {
+            dbg_logf!("vote {} failed ({} yes, {} no)", vote.kind, status.yes, status.no);
+            return;
+        }
+
+        dbg_logf!("vote {} passed ({} yes, {} no)", vote.kind, status.yes, status.no);
+        match vote.kind {
+            // The process changes the map and starts a new match.
+            VoteKind::Map(map) => self.sg.next_map = Some(map),
+            VoteKind::Kick { player_id } => {
+                if let Some(client_handle) = self.player_client(player_id) {
+                    self.disconnect(client_handle);
+                }
+            }
+        }
+    }
+
+    /// The client controlling the player with the given network ID, if any.
+    fn player_client(&self, player_id: NetId) -> Option<Handle<RemoteClient>> {
+        let player_handle = self.gs.player_ids.get(player_id)?;
+        self.sg
+            .clients
+            .pair_iter()
+            .find(|(_, client)| client.player_handle == player_handle)
+            .map(|(client_handle, _)| client_handle)
+    }
+
+    /// Use one buffered input per client each tick
+    /// so inputs which arrived in the same server frame aren't lost.
+    ///
+    /// If there's none, the player keeps the previous input.
+    fn sys_apply_inputs(&mut self) {
+        for client in &mut self.sg.clients {
+            if let Some(input) = client.inputs.pop_front() {
+                self.gs.players[client.player_handle].input = input;
+            }
+        }
+    }
+
+    /// Mark the start of the frame in the demo, if recording, see `sv_record_demos`.
+    fn sys_demo(&mut self) {
+        self.update_demo();
+        let entry = DemoEntry::Frame {
+            frame_num: self.gs.frame_num,
+            game_time: self.gs.game_time,
+        };
+        self.record_demo(&entry);
+        if let Some(demo) = &mut self.sg.demo {
+            demo.flush();
+        }
+    }
+
+    /// Start or stop recording when `sv_record_demos` or the map changes.
+    fn update_demo(&mut self) {
+        let map_changed = self.sg.demo.as_ref().is_some_and(|demo| demo.map != self.gs.map_name);
+        if self.sg.demo.as_ref().is_some_and(|demo| demo.failed) {
+            self.sg.demo = None;
+            self.sg.demo_failed = true;
+        }
+        if !self.cvars.sv_record_demos || map_changed {
+            if let Some(demo) = self.sg.demo.take() {
+                dbg_logf!("stopped recording demo {}", demo.path.display());
+            }
+        }
+        if !self.cvars.sv_record_demos {
+            self.sg.demo_failed = false;
+            return;
+        }
+        if self.sg.demo.is_some() || self.sg.demo_failed {
+            return;
+        }
+
+        let header = DemoHeader {
+            format: DEMO_FORMAT,
+            version: Version::current(),
+            map: self.gs.map_name.clone(),
+            gameplay_cvars: self.cvars.gameplay_cvars(),
+            recorded_at: utc_timestamp(system_time_now()),
+            sync: self.ctx().full_sync(),
+        };
+        match DemoRecorder::new(&self.cvars.sv_record_demos_dir, header) {
+            Ok(demo) => {
+                dbg_logf!("recording demo {}", demo.path.display());
+                self.sg.demo = Some(demo);
+            }
+            Err(e) => {
+                dbg_logf!("failed to start recording demo: {}", e);
+                self.sg.demo_failed = true;
+            }
+        }
+    }
+
+    fn record_demo(&mut self, entry: &DemoEntryRef<'_>) {
+        if let Some(demo) = &mut self.sg.demo {
+            demo.write(entry);
+        }
+    }
+
+    /// Move the match through warmup, countdown, playing and intermission, see `MatchState`.
+    fn sys_match_state(&mut self) {
+        let enough_players = if self.cvars.g_lobby {
+            self.lobby_ready()
+        } else {
+            self.sg.clients.alive_count() >= self.cvars.g_players_min
+        };
+        match self.gs.match_state {
+            MatchState::Warmup | MatchState::Lobby => {
+                if enough_players {
+                    let start = self.gs.game_time + self.cvars.g_warmup_countdown;
+                    self.set_match_state(MatchState::Countdown { start });
+                }
+            }
+            MatchState::Countdown { start } => {
+                if !enough_players {
+                    let state = if self.cvars.g_lobby {
+                        MatchState::Lobby
+                    } else {
+                        MatchState::Warmup
+                    };
+                    self.set_match_state(state);
+                } else if start <= self.gs.game_time {
+                    self.start_match();
+                }
+            }
+            MatchState::Playing => {
+                let fraglimit_reached = self.cvars.g_fraglimit > 0
+                    && self.gs.players.iter().any(|player| player.kills >= self.cvars.g_fraglimit);
+                let timelimit_reached = self.cvars.g_timelimit > 0.0
+                    && self.gs.match_start + self.cvars.g_timelimit <= self.gs.game_time;
+                if timelimit_reached && self.cvars.g_overtime && self.leaders_tied() {
+                    let state = MatchState::Overtime {
+                        start: self.gs.game_time,
+                        radius: self.cvars.g_overtime_radius,
+                        duration: self.cvars.g_overtime_shrink_duration,
+                    };
+                    self.set_match_state(state);
+                } else if fraglimit_reached || timelimit_reached {
+                    self.end_match();
+                }
+            }
+            MatchState::Overtime { .. } => {
+                // Everyone left might have died at the same time, there's no point continuing.
+                if !self.leaders_tied() || self.gs.cycles.alive_count() <= 1 {
+                    self.end_match();
+                }
+            }
+            MatchState::Intermission { end } => {
+                if self.sg.next_map.is_none()
+                    && end + self.cvars.g_intermission_duration <= self.gs.game_time
+                {
+                    let map = next_map(&self.cvars.g_map_rotation, &self.gs.map_name, |map| {
+                        maps::path(map).is_some()
+                    });
+                    self.sg.next_map = Some(map);
+                }
+            }
+        }
+    }
+
+    /// Report unknown maps in `g_map_rotation` when it changes, they're skipped when rotating.
+    fn sys_map_rotation(&mut self) {
+        if self.cvars.g_map_rotation == self.sg.map_rotation_checked {
+            return;
+        }
+        self.sg.map_rotation_checked = self.cvars.g_map_rotation.clone();
+        for map in self.cvars.g_map_rotation.split_whitespace() {
+            if maps::path(map).is_none() {
+                dbg_logf!("WARNING unknown map {} in g_map_rotation", map);
+            }
+        }
+    }
+
+    /// Enough players are ready to start the match, see `g_lobby_min_ready`.
+    fn lobby_ready(&self) -> bool {
+        let total = self.sg.clients.alive_count();
+        let ready = self.sg.clients.iter().filter(|client| client.ready).count();
+        if self.cvars.g_lobby_min_ready == 0 {
+            total > 0 && ready == total
+        } else {
+            ready >= self.cvars.g_lobby_min_ready as usize
+        }
+    }
+
+    /// Tell clients who's in the lobby whenever it changes.
+    ///
+    /// The list is empty once the match starts so clients know to close the lobby.
+    fn sys_lobby(&mut self) {
+        let mut lobby = Vec::new();
+        if self.cvars.g_lobby && self.gs.match_state.is_warmup() {
+            for client in &self.sg.clients {
+                let player = &self.gs.players[client.player_handle];
+                lobby.push(LobbyPlayer {
+                    player_id: player.net_id,
+                    name: player.name.clone(),
+                    team: client.team,
+                    ready: client.ready,
+                });
+            }
+        }
+        if lobby != self.sg.lobby_sent {
+            self.sg.lobby_sent = lobby.clone();
+            self.network_send(ServerMessage::Lobby(lobby), SendDest::All);
+        }
+    }
+
+    /// More than one player has the most kills.
+    fn leaders_tied(&self) -> bool {
+        let Some(max) = self.gs.players.iter().map(|player| player.kills).max() else {
+            return false;
+        };
+        self.gs.players.iter().filter(|player| player.kills == max).count() > 1
+    }
+
+    fn end_match(&mut self) {
+        self.for_each_client(|sv_ctx, client_handle| sv_ctx.save_stats(client_handle, true));
+        let kills = std::mem::take(&mut self.sg.kill_log);
+        results::export(self.cvars, &MatchResult::new(self.gs, kills));
+        let end = self.gs.game_time;
+        self.set_match_state(MatchState::Intermission { end });
+    }
+
+    fn set_match_state(&mut self, state: MatchState) {
+        dbg_logf!("match state: {:?}", state);
+        self.gs.match_state = state;
+        self.network_send(ServerMessage::MatchState(state), SendDest::All);
+    }
+
+    /// End warmup, reset scores and respawn everyone so the match starts fair.
+    fn start_match(&mut self) {
+        self.ctx().start_match();
+        self.sg.kill_log.clear();
+        // What happened during warmup doesn't count.
+        for client in &mut self.sg.clients {
+            client.stats = MatchStats::default();
+        }
+        // Clients are told about the new cycles in `sys_replicate`.
+        for player_handle in self.gs.players.collect_handles() {
+            if let Some(cycle_handle) = self.gs.players[player_handle].cycle_handle {
+                self.ctx().despawn_cycle(cycle_handle);
+            }
+            self.ctx().spawn_cycle(player_handle, None);
+        }
+        self.set_match_state(MatchState::Playing);
+    }
+
+    /// The kill button destroys the player's own cycle.
+    ///
+    /// The cooldown is counted from spawning so holding the button
+    /// doesn't turn into an endless cycle of deaths and respawns.
+    fn sys_suicide(&mut self) {
+        if self.gs.match_state.is_over() {
+            return;
+        }
+
+        let mut to_kill = Vec::new();
+        for (player_handle, player) in self.gs.players.pair_iter() {
+            if player.state != PlayerState::Playing || !player.input.kill {
+                continue;
+            }
+            let Some(cycle_handle) = player.cycle_handle else {
+                continue;
+            };
+            let cycle = &self.gs.cycles[cycle_handle];
+            if cycle.time_spawned + self.cvars.g_kill_cooldown < self.gs.game_time {
+                to_kill.push(player_handle);
+            }
+        }
+        for player_handle in to_kill {
+            self.kill_player(player_handle, None);
+        }
+    }
+
+    /// Kill cycles which fell off the arena, entered a kill volume
+    /// or are outside the shrinking area in overtime.
+    fn sys_out_of_bounds(&mut self) {
+        let overtime_radius = self.gs.overtime_radius();
+        let mut positions = Vec::new();
+        for cycle in &self.gs.cycles {
+            let body = &self.scene.graph[cycle.body_handle];
+            positions.push((cycle.player_handle, **body.local_transform().position()));
+        }
+        for (player_handle, pos) in positions {
+            let outside_overtime =
+                overtime_radius.is_some_and(|radius| v!(pos.x, 0, pos.z).norm() > radius);
+            if self.ctx().is_out_of_bounds(pos) || outside_overtime {
+                self.kill_player(player_handle, None);
+            }
+        }
+    }
+
+    fn sys_respawn(&mut self) {
+        if self.gs.match_state.is_over() {
+            return;
+        }
+        // In overtime, dying is final so the shrinking area eventually forces a result.
+        if let MatchState::Overtime { .. } = self.gs.match_state {
+            return;
+        }
+
+        let delay = if self.gs.match_state.is_warmup() {
+            0.0
+        } else {
+            self.cvars.g_respawn_delay
+        };
+        let mut to_respawn = Vec::new();
+        for (player_handle, player) in self.gs.players.pair_iter() {
+            if player.cycle_handle.is_none() && player.time_died + delay <= self.gs.game_time {
+                to_respawn.push(player_handle);
+            }
+        }
+        for player_handle in to_respawn {
+            // Clients are told in `sys_replicate`.
+            self.ctx().spawn_cycle(player_handle, None);
+        }
+    }
+
+    /// Damage cycles which crashed into each other.
+    ///
+    /// Both cycles take the same damage based on how fast they were approaching each other,
+    /// the faster one gets credited with the kill.
+    fn sys_ramming(&mut self) {
+        let mut damages = Vec::new();
+        for event in &self.gs.collision_events {
+            let &CollisionEvent::CycleCycle {
+                cycle1,
+                cycle2,
+                normal,
+            } = event
+            else {
+                continue;
+            };
+            let vel1 = self.gs.cycles[cycle1].vel_prev;
+            let vel2 = self.gs.cycles[cycle2].vel_prev;
+            let closing_speed = (vel1 - vel2).dot(&normal);
+            if closing_speed <= self.cvars.g_ram_threshold {
+                continue;
+            }
+            let damage = (closing_speed - self.cvars.g_ram_threshold) * self.cvars.g_ram_damage;
+            let (faster, slower) = if vel1.norm() >= vel2.norm() {
+                (cycle1, cycle2)
+            } else {
+                (cycle2, cycle1)
+            };
+            let damage_to_slower = damage * self.damage_factor(faster);
+            let damage_to_faster = damage * self.damage_factor(slower);
+            damages.push((slower, damage_to_slower, self.attacker(faster), true));
+            damages.push((faster, damage_to_faster, self.attacker(slower), false));
+        }
+
+        for (cycle_handle, damage, attacker, credit_kill) in damages {
+            self.damage_cycle(cycle_handle, damage, attacker, credit_kill);
+        }
+    }
+
+    /// Who to credit for damage dealt by the cycle and where it came from.
+    ///
+    /// Looked up before any damage is dealt because the cycle
+    /// might die from an earlier collision in the same tick.
+    fn attacker(&self, cycle_handle: Handle<Cycle>) -> (Handle<Player>, Vec3) {
+        let cycle = &self.gs.cycles[cycle_handle];
+        let pos = self.scene.graph[cycle.body_handle].global_position();
+        (cycle.player_handle, pos)
+    }
+
+    /// Deal damage and count it towards the attacker's stats.
+    ///
+    /// All damage goes through here so clients are told about it and stats include all of it.
+    /// `credit_kill` means the attacker gets the kill if the cycle dies.
+    fn damage_cycle(
+        &mut self,
+        cycle_handle: Handle<Cycle>,
+        damage: f32,
+        (attacker, source): (Handle<Player>, Vec3),
+        credit_kill: bool,
+    ) {
+        // Might have died from an earlier collision this tick.
+        let Some(cycle) = self.gs.cycles.try_borrow(cycle_handle) else {
+            return;
+        };
+        let msg = ServerMessage::Damage {
+            cycle_id: cycle.net_id,
+            damage,
+            source: source.into(),
+        };
+        self.network_send(msg, SendDest::All);
+
+        let cycle = &self.gs.cycles[cycle_handle];
+        let attacker_id = self.gs.players[attacker].net_id;
+        let victim = self.gs.players[cycle.player_handle].net_id;
+        let pos = self.scene.graph[cycle.body_handle].global_position();
+        if let Some(client_handle) = self.player_client(attacker_id) {
+            let msg = ServerMessage::DamageDealt {
+                attacker: attacker_id,
+                victim,
+                amount: damage,
+                pos: pos.into(),
+            };
+            self.network_send(msg, SendDest::One(client_handle));
+        }
+        if let Some(stats) = self.match_stats(attacker) {
+            stats.damage += damage;
+        }
+
+        let cycle = &mut self.gs.cycles[cycle_handle];
+        cycle.health -= damage;
+        if cycle.health <= 0.0 {
+            let player_handle = cycle.player_handle;
+            self.kill_player(player_handle, credit_kill.then_some(attacker));
+        }
+    }
+
+    /// Damage dealt by the cycle is multiplied by this.
+    fn damage_factor(&self, cycle_handle: Handle<Cycle>) -> f32 {
+        if self.gs.cycles[cycle_handle].damage_boost_until > self.gs.game_time {
+            self.cvars.g_pickup_damage_factor
+        } else {
+            1.0
+        }
+    }
+
+    /// Cycles take pickups they drive through.
+    ///
+    /// Clients apply the effect when they're told, they don't check overlaps themselves.
+    fn sys_pickups(&mut self) {
+        if self.gs.match_state.is_over() {
+            return;
+        }
+
+        let mut taken = Vec::new();
+        for (pickup_handle, pickup) in self.gs.pickups.pair_iter() {
+            if !pickup.is_available(self.cvars, self.gs.game_time) {
+                continue;
+            }
+            for (cycle_handle, cycle) in self.gs.cycles.pair_iter() {
+                let body_pos = **self.scene.graph[cycle.body_handle].local_transform().position();
+                if (body_pos - pickup.pos).norm() <= self.cvars.g_pickup_radius {
+                    taken.push((pickup_handle, cycle_handle));
+                    break;
+                }
+            }
+        }
+
+        for (pickup_handle, cycle_handle) in taken {
+            self.ctx().apply_pickup(pickup_handle, cycle_handle);
+            let msg = ServerMessage::PickupTaken {
+                pickup_index: pickup_handle.index(),
+                cycle_id: self.gs.cycles[cycle_handle].net_id,
+            };
+            self.network_send(msg, SendDest::All);
+        }
+    }
+
+    /// Flag cycles moving faster than should be possible, see `sv_anticheat_speed_max`.
+    ///
+    /// The server simulates movement itself so this shouldn't happen with any input
+    /// but it can catch physics exploits.
+    fn sys_speed_check(&mut self) {
+        let mut speeding = FxHashSet::default();
+        for cycle in &self.gs.cycles {
+            let vel = self.scene.graph[cycle.body_handle].as_rigid_body().lin_vel();
+            let speed = v!(vel.x, 0, vel.z).norm();
+            if speed <= self.cvars.sv_anticheat_speed_max {
+                continue;
+            }
+            speeding.insert(cycle.net_id);
+            if !self.sg.speeding.contains(&cycle.net_id) && self.cvars.sv_anticheat_log {
+                let player_id = self.gs.players[cycle.player_handle].net_id;
+                dbg_logf!("anticheat: player {} is going {:.1} m/s", player_id, speed);
+            }
+        }
+        self.sg.speeding = speeding;
+    }
+
+    /// Destroy the player's cycle and count a death.
+    ///
+    /// `killer` is None for suicides and accidents.
+    /// The player respawns after `g_respawn_delay`, see `sys_respawn`.
+    pub fn kill_player(&mut self, player_handle: Handle<Player>, killer: Option<Handle<Player>>) {
+        let Some(cycle_handle) = self.gs.players[player_handle].cycle_handle else {
+            return;
+        };
+        // Clients are told in `sys_replicate`.
+        self.ctx().despawn_cycle(cycle_handle);
+
+        let player = &mut self.gs.players[player_handle];
+        player.deaths += 1;
+        player.time_died = self.gs.game_time;
+        let player_id = player.net_id;
+        let mut killer_id = None;
+        if let Some(killer) = killer {
+            let killer = &mut self.gs.players[killer];
+            killer.kills += 1;
+            killer_id = Some(killer.net_id);
+            dbg_logf!("player {} killed player {}", killer.net_id, player_id);
+        } else {
+            dbg_logf!("player {} died", player_id);
+        }
+        if let Some(stats) = self.match_stats(player_handle) {
+            stats.deaths += 1;
+        }
+        if let Some(stats) = killer.and_then(|killer| self.match_stats(killer)) {
+            stats.kills += 1;
+        }
+        if self.gs.match_state.is_running() {
+            self.sg.kill_log.push(Kill {
+                time: self.gs.game_time - self.gs.match_start,
+                killer: killer_id,
+                victim: player_id,
+            });
+        }
+
+        let msg = ServerMessage::Death {
+            player_id,
+            killer_id,
+        };
+        self.network_send(msg, SendDest::All);
     }
 
     fn disconnect(&mut self, client_handle: Handle<RemoteClient>) {
+        self.save_stats(client_handle, false);
         let client = self.sg.clients.free(client_handle);
+        let player = &self.gs.players[client.player_handle];
+        let player_id = player.net_id;
+        dbg_logf!("client {} (player {}) disconnected", client.conn.addr(), player_id);
+        self.record_demo(&DemoEntry::Disconnect { player_id });
+        // Clients remove the cycle along with the player.
+        if let Some(cycle_handle) = player.cycle_handle {
+            let cycle_id = self.gs.cycles[cycle_handle].net_id;
+            for client in &mut self.sg.clients {
+                client.relevant.remove(&cycle_id);
+            }
+        }
         self.ctx().free_player(client.player_handle);
-        let msg = ServerMessage::RemovePlayer {
-            player_index: client.player_handle.index(),
+        let msg = ServerMessage::Despawn {
+            kind: EntityKind::Player,
+            net_id: player_id,
         };
         self.network_send(msg, SendDest::All);
     }
 
-    fn send_init(&mut self, client_handle: Handle<RemoteClient>) {
-        let mut player_indices = Vec::new();
-        for (player_handle, _) in self.gs.players.pair_iter() {
-            player_indices.push(player_handle.index());
+    /// Network IDs of cycles the client should know about, see `sv_interest_radius`.
+    fn relevant_cycles(&self, client_handle: Handle<RemoteClient>) -> FxHashSet<NetId> {
+        let client = &self.sg.clients[client_handle];
+        let cycle_pos =
+            |cycle: &Cycle| **self.scene.graph[cycle.body_handle].local_transform().position();
+        let center = self.gs.players[client.player_handle]
+            .cycle_handle
+            .map(|cycle_handle| cycle_pos(&self.gs.cycles[cycle_handle]));
+        let radius = self.cvars.sv_interest_radius;
+
+        self.gs
+            .cycles
+            .iter()
+            .filter(|cycle| {
+                let Some(center) = center else {
+                    return true;
+                };
+                if radius <= 0.0 {
+                    return true;
+                }
+                let max_dist = if client.relevant.contains(&cycle.net_id) {
+                    radius + self.cvars.sv_interest_margin
+                } else {
+                    radius
+                };
+                (cycle_pos(cycle) - center).norm() <= max_dist
+            })
+            .map(|cycle| cycle.net_id)
+            .collect()
+    }
+
+    /// Network IDs of relevant cycles whose updates should be withheld from the client
+    /// because its cycle hasn't seen them recently, see `sv_visibility_culling`.
+    fn occluded_cycles(&mut self, client_handle: Handle<RemoteClient>) -> FxHashSet<NetId> {
+        let client = &self.sg.clients[client_handle];
+        let Some(own_handle) = self.gs.players[client.player_handle].cycle_handle else {
+            // Observers can fly anywhere.
+            return FxHashSet::default();
+        };
+        let own_body = self.scene.graph[self.gs.cycles[own_handle].body_handle].as_rigid_body();
+        let eye = **own_body.local_transform().position()
+            + UP * self.cvars.sv_visibility_eye_height
+            + own_body.lin_vel() * self.cvars.sv_visibility_lookahead;
+
+        let mut occluded = FxHashSet::default();
+        let mut seen = Vec::new();
+        for &net_id in &client.relevant {
+            let cycle_handle = self.gs.cycle_ids.get(net_id).unwrap();
+            if cycle_handle == own_handle || self.cycle_visible_from(eye, cycle_handle) {
+                seen.push(net_id);
+                continue;
+            }
+            let last_seen = client.last_seen.get(&net_id).copied();
+            if last_seen
+                .map_or(true, |time| self.gs.game_time - time > self.cvars.sv_visibility_grace)
+            {
+                occluded.insert(net_id);
+            }
+        }
+
+        let client = &mut self.sg.clients[client_handle];
+        for net_id in seen {
+            client.last_seen.insert(net_id, self.gs.game_time);
+        }
+        occluded
+    }
+
+    /// Whether there's a line of sight from `eye` to any part of the cycle.
+    ///
+    /// Only the map blocks visibility, other cycles don't.
+    fn cycle_visible_from(&self, eye: Vec3, cycle_handle: Handle<Cycle>) -> bool {
+        let body = &self.scene.graph[self.gs.cycles[cycle_handle].body_handle];
+        let center = **body.local_transform().position();
+        // The center and points just outside the collider in each direction
+        // so cycles peeking around a corner count as visible.
+        let offsets = [Vec3::zeros(), UP, -UP, LEFT, -LEFT, FORWARD, -FORWARD];
+        offsets.iter().any(|offset| {
+            let target = center + offset * 0.3;
+            let opts = TraceOptions::filter(CollisionLayer::MAP_ONLY);
+            trace_line(self.cvars, &*self.scene, eye, target - eye, opts).is_empty()
+        })
+    }
+
+    /// Spawn cycles on clients when they become relevant to them
+    /// and despawn them when they stop being relevant or are removed on the server.
+    fn sys_replicate(&mut self) {
+        self.for_each_client(|sv_ctx, client_handle| {
+            let relevant = sv_ctx.relevant_cycles(client_handle);
+            let client = &mut sv_ctx.sg.clients[client_handle];
+            // Sort so the order of messages doesn't depend on the hash set.
+            let mut entered: Vec<_> = relevant.difference(&client.relevant).copied().collect();
+            entered.sort();
+            let mut left: Vec<_> = client.relevant.difference(&relevant).copied().collect();
+            left.sort();
+            client.last_seen.retain(|net_id, _| relevant.contains(net_id));
+            client.relevant = relevant;
+
+            let mut msgs = Vec::new();
+            for net_id in left {
+                msgs.push(ServerMessage::Despawn {
+                    kind: EntityKind::Cycle,
+                    net_id,
+                });
+            }
+            for net_id in entered {
+                let cycle_handle = sv_ctx.gs.cycle_ids.get(net_id).unwrap();
+                msgs.push(ServerMessage::Spawn(sv_ctx.cycle_spawn(cycle_handle)));
+                let trail = &sv_ctx.gs.cycles[cycle_handle].trail;
+                if !trail.is_empty() {
+                    msgs.push(ServerMessage::Trail {
+                        cycle_id: net_id,
+                        removed: 0,
+                        added: trail.iter().map(|&point| point.into()).collect(),
+                    });
+                }
+            }
+            for msg in msgs {
+                // Sending might have disconnected the client.
+                if !sv_ctx.sg.clients.is_valid_handle(client_handle) {
+                    break;
+                }
+                sv_ctx.network_send(msg, SendDest::One(client_handle));
+            }
+        });
+    }
+
+    /// Tell clients how the trails of cycles they have changed this tick.
+    ///
+    /// Unlike updates, this ignores `sv_visibility_culling`.
+    /// Walls are meant to be seen from afar and holes in them would be confusing.
+    fn sys_send_trails(&mut self) {
+        let mut trails = Vec::new();
+        for cycle in &self.gs.cycles {
+            if cycle.trail_removed == 0 && cycle.trail_added == 0 {
+                continue;
+            }
+            let skip = cycle.trail.len().saturating_sub(cycle.trail_added as usize);
+            let added: Vec<_> = cycle.trail.iter().skip(skip).map(|&point| point.into()).collect();
+            trails.push((cycle.net_id, cycle.trail_removed, added));
+        }
+        if trails.is_empty() {
+            return;
         }
-        let local_player_index = self.sg.clients[client_handle].player_handle.index();
 
-        let mut player_cycles = Vec::new();
+        self.for_each_client(|sv_ctx, client_handle| {
+            for (cycle_id, removed, added) in &trails {
+                // Sending might have disconnected the client.
+                if !sv_ctx.sg.clients.is_valid_handle(client_handle) {
+                    break;
+                }
+                if !sv_ctx.sg.clients[client_handle].relevant.contains(cycle_id) {
+                    continue;
+                }
+                let msg = ServerMessage::Trail {
+                    cycle_id: *cycle_id,
+                    removed: *removed,
+                    added: added.clone(),
+                };
+                sv_ctx.network_send(msg, SendDest::One(client_handle));
+            }
+        });
+    }
+
+    /// What clients need to create a copy of the player.
+    fn player_spawn(&self, player_handle: Handle<Player>) -> Spawn {
+        Spawn {
+            kind: EntityKind::Player,
+            net_id: self.gs.players[player_handle].net_id,
+            owner_id: None,
+            name: Some(self.gs.players[player_handle].name.clone()),
+        }
+    }
+
+    /// What clients need to create a copy of the cycle.
+    fn cycle_spawn(&self, cycle_handle: Handle<Cycle>) -> Spawn {
+        let cycle = &self.gs.cycles[cycle_handle];
+        Spawn {
+            kind: EntityKind::Cycle,
+            net_id: cycle.net_id,
+            owner_id: Some(self.gs.players[cycle.player_handle].net_id),
+            name: None,
+        }
+    }
+
+    fn send_init(&mut self, client_handle: Handle<RemoteClient>) {
+        let local_player_handle = self.sg.clients[client_handle].player_handle;
+        let local_player_id = self.gs.players[local_player_handle].net_id;
+
+        // The client throws away its old state, including what was replicated to it.
+        self.sg.clients[client_handle].relevant.clear();
+        let relevant = self.relevant_cycles(client_handle);
+
+        // Players first because cycles refer to them.
+        // All players are always replicated, they're needed for the scoreboard.
+        let mut entities = Vec::new();
+        for player_handle in self.gs.players.collect_handles() {
+            entities.push(self.player_spawn(player_handle));
+        }
         for (cycle_handle, cycle) in self.gs.cycles.pair_iter() {
-            let init_player = PlayerCycle {
-                player_index: cycle.player_handle.index(),
-                cycle_index: cycle_handle.index(),
-            };
-            player_cycles.push(init_player);
+            if relevant.contains(&cycle.net_id) {
+                entities.push(self.cycle_spawn(cycle_handle));
+            }
         }
+        self.sg.clients[client_handle].relevant = relevant;
+
+        // Don't show it again after every map change.
+        let motd = if self.sg.clients[client_handle].motd_sent {
+            String::new()
+        } else {
+            self.sg.clients[client_handle].motd_sent = true;
+            self.motd()
+        };
 
         let init = Init {
-            player_indices,
-            local_player_index,
-            player_cycles,
-            player_projectiles: Vec::new(), // LATER
+            map: self.gs.map_name.clone(),
+            dt: self.gs.dt,
+            local_player_id,
+            match_start: self.gs.match_start,
+            match_state: self.gs.match_state,
+            entities,
+            motd,
         };
         let msg = ServerMessage::Init(init);
         self.network_send(msg, SendDest::One(client_handle));
+
+        // Init only creates the entities, this gets their exact state
+        // including things like the RNG which would be hard to sync otherwise.
+        self.send_full_sync(client_handle);
+
+        if let Some(vote) = &self.sg.vote {
+            if self.sg.clients.is_valid_handle(client_handle) {
+                let msg = ServerMessage::VoteStatus(Some(vote.status()));
+                self.network_send(msg, SendDest::One(client_handle));
+            }
+        }
+    }
+
+    /// The message of the day from `sv_motd_file` or `sv_motd`.
+    fn motd(&self) -> String {
+        let path = &self.cvars.sv_motd_file;
+        if path.is_empty() {
+            return self.cvars.sv_motd.replace("\\n", "\n");
+        }
+        match std::fs::read_to_string(path) {
+            Ok(text) => text.trim_end().to_owned(),
+            Err(e) => {
+                dbg_logf!("failed to read MOTD from {}: {}", path, e);
+                String::new()
+            }
+        }
     }
 
+    fn send_full_sync(&mut self, client_handle: Handle<RemoteClient>) {
+        // The client might have been disconnected by a previous send.
+        if !self.sg.clients.is_valid_handle(client_handle) {
+            return;
+        }
+        let mut sync = self.ctx().full_sync();
+        let relevant = &self.sg.clients[client_handle].relevant;
+        sync.cycles.retain(|sc| relevant.contains(&sc.cycle_id));
+        let msg = ServerMessage::FullSync(sync);
+        self.network_send(msg, SendDest::One(client_handle));
+    }
+
+    /// Updates are sent unreliably because each one contains the whole state
+    /// of the entities it mentions so a lost one is replaced by the next.
     pub fn sys_send_update(&mut self) {
-        let mut player_inputs = Vec::new();
-        for (player_handle, player) in self.gs.players.pair_iter() {
-            let pi = PlayerInput {
-                player_index: player_handle.index(),
-                input: player.input,
-            };
-            player_inputs.push(pi);
+        if self.sg.demo.is_some() {
+            let checksum = self.ctx().checksum();
+            self.record_demo(&DemoEntry::Checksum(checksum));
         }
 
-        let mut cycle_physics = Vec::new();
-        for (cycle_handle, cycle) in self.gs.cycles.pair_iter() {
+        // Before `sys_replicate` so cycles it spawns on clients this frame
+        // get their trail only once, with the spawn.
+        self.sys_send_trails();
+        self.sys_replicate();
+
+        // Each update is paired with the cycle it reveals the position of (if any)
+        // so it can be withheld when the cycle is not relevant or occluded.
+        let mut entities = Vec::new();
+        for player in &self.gs.players {
+            let cycle_id = player.cycle_handle.map(|handle| self.gs.cycles[handle].net_id);
+            let update = EntityUpdate {
+                net_id: player.net_id,
+                state: EntityState::Player(player.input.into()),
+            };
+            entities.push((cycle_id, update));
+        }
+        for cycle in &self.gs.cycles {
             let body = self.scene.graph[cycle.body_handle].as_rigid_body();
-            let cp = CyclePhysics {
-                cycle_index: cycle_handle.index(),
-                translation: **body.local_transform().position(),
-                rotation: **body.local_transform().rotation(),
-                velocity: body.lin_vel(),
+            let physics = CyclePhysics {
+                translation: QuantizedPos::encode(**body.local_transform().position()),
+                rotation: QuantizedRot::encode(**body.local_transform().rotation()),
+                velocity: QuantizedVel::encode(body.lin_vel()),
+                boost_energy: cycle.boost_energy,
+                health: cycle.health,
             };
-            cycle_physics.push(cp);
+            let update = EntityUpdate {
+                net_id: cycle.net_id,
+                state: EntityState::Cycle(physics),
+            };
+            entities.push((Some(cycle.net_id), update));
         }
 
         // Send debug items, then clear everything on the server (not just expired)
@@ -225,54 +1630,215 @@ impl ServerFrameCtx<'_> {
         let debug_texts_world = DEBUG_TEXTS_WORLD.take();
         let debug_shapes = DEBUG_SHAPES.take();
 
-        let msg = ServerMessage::Update(Update {
-            player_inputs,
-            cycle_physics,
-            debug_texts,
-            debug_texts_world,
-            debug_shapes,
+        if self.cvars.sv_interest_radius <= 0.0 && !self.cvars.sv_visibility_culling {
+            let msg = ServerMessage::Update(Update {
+                frame_num: self.gs.frame_num as u64,
+                checksum: Some(self.ctx().net_checksum()),
+                entities: entities.into_iter().map(|(_, update)| update).collect(),
+                debug_texts,
+                debug_texts_world,
+                debug_shapes,
+            });
+            self.network_send_unreliable(msg, SendDest::All);
+            return;
+        }
+
+        // Each client gets only what's relevant to it.
+        self.for_each_client(|sv_ctx, client_handle| {
+            let occluded = if sv_ctx.cvars.sv_visibility_culling {
+                sv_ctx.occluded_cycles(client_handle)
+            } else {
+                FxHashSet::default()
+            };
+            let relevant = &sv_ctx.sg.clients[client_handle].relevant;
+            let entities = entities
+                .iter()
+                .filter(|(cycle_id, update)| {
+                    let Some(cycle_id) = cycle_id else {
+                        return true;
+                    };
+                    // Players without a relevant cycle are harmless, the client can't simulate them.
+                    let is_player = matches!(update.state, EntityState::Player(_));
+                    (is_player || relevant.contains(cycle_id)) && !occluded.contains(cycle_id)
+                })
+                .map(|(_, update)| update.clone())
+                .collect();
+            let msg = ServerMessage::Update(Update {
+                frame_num: sv_ctx.gs.frame_num as u64,
+                checksum: None,
+                entities,
+                debug_texts: debug_texts.clone(),
+                debug_texts_world: debug_texts_world.clone(),
+                debug_shapes: debug_shapes.clone(),
+            });
+            sv_ctx.network_send_unreliable(msg, SendDest::One(client_handle));
         });
-        self.network_send(msg, SendDest::All);
     }
 
     // LATER This only needs Engine for self.disconnect,
     // but forces all callers to also take Engine.
     fn network_send(&mut self, msg: ServerMessage, dest: SendDest) {
+        self.network_send_with(msg, dest, true);
+    }
+
+    /// Like `network_send` but the message may be lost, see `Connection::send_unreliable`.
+    fn network_send_unreliable(&mut self, msg: ServerMessage, dest: SendDest) {
+        self.network_send_with(msg, dest, false);
+    }
+
+    fn network_send_with(&mut self, msg: ServerMessage, dest: SendDest, reliable: bool) {
         // LATER This is incredibly ugly, plus creating the Vec is inafficient.
         //          - Save all streams in a Vec?
         //          - Inline this fn and remove SendDest?
         let mut disconnected = Vec::new();
-        let network_msg = net::serialize(msg);
+        if self.sg.demo.is_some() {
+            let to = match dest {
+                SendDest::One(handle) => {
+                    Some(self.gs.players[self.sg.clients[handle].player_handle].net_id)
+                }
+                SendDest::All => None,
+            };
+            self.record_demo(&DemoEntry::Message { to, msg: &msg });
+        }
+        // Taken out so disconnecting (which sends messages) can use it.
+        let mut network_msg = std::mem::take(&mut self.sg.send_buf);
+        net::serialize_into(&msg, &mut network_msg);
+        let send = |conn: &mut Box<dyn Connection<ClientMessage>>| {
+            if reliable {
+                conn.send(&network_msg)
+            } else {
+                conn.send_unreliable(&network_msg)
+            }
+        };
         match dest {
             SendDest::One(handle) => {
-                if let Err(e) = self.sg.clients[handle].conn.send(&network_msg) {
+                if let Err(e) = send(&mut self.sg.clients[handle].conn) {
                     dbg_logf!("Error in network_send One - index {}: {:?}", handle.index(), e);
                     disconnected.push(handle);
                 }
             }
             SendDest::All => {
                 for (handle, client) in self.sg.clients.pair_iter_mut() {
-                    if let Err(e) = client.conn.send(&network_msg) {
+                    if let Err(e) = send(&mut client.conn) {
                         dbg_logf!("Error in network_send All - index {}: {:?}", handle.index(), e);
                         disconnected.push(handle);
                     }
                 }
             }
         };
+        self.sg.send_buf = network_msg;
         for client_handle in disconnected {
             self.disconnect(client_handle);
         }
     }
 }
 
+/// Inputs with times which are not even close to the server's can't be trusted.
+fn check_input_time(cvars: &Cvars, game_time: f32, input: &Input) -> Result<(), String> {
+    if !input.real_time.is_finite() || !input.game_time.is_finite() {
+        return Err(format!(
+            "non-finite time (real {}, game {})",
+            input.real_time, input.game_time
+        ));
+    }
+    let diff = (input.game_time - game_time).abs();
+    if diff > cvars.sv_input_time_diff_max {
+        return Err(format!("game time {} which is {:.2} s off", input.game_time, diff));
+    }
+    Ok(())
+}
+
+/// Clamp angles to what a legitimate client can send.
+///
+/// Returns whether anything had to be clamped.
+fn clamp_input(cvars: &Cvars, input: &mut Input) -> bool {
+    let pitch_max = cvars.sv_input_pitch_max;
+    let speed_max = cvars.sv_input_angle_speed_max;
+    let before = (input.yaw.0, input.yaw_speed.0, input.pitch.0, input.pitch_speed.0);
+
+    input.yaw = input.yaw.normalized();
+    input.yaw_speed.0 = input.yaw_speed.0.clamp(-speed_max, speed_max);
+    input.pitch.0 = input.pitch.0.clamp(-pitch_max, pitch_max);
+    input.pitch_speed.0 = input.pitch_speed.0.clamp(-speed_max, speed_max);
+
+    before != (input.yaw.0, input.yaw_speed.0, input.pitch.0, input.pitch_speed.0)
+}
+
+/// The map after `current` in the space separated `rotation`.
+///
+/// Maps for which `known` returns false are skipped.
+/// Starts from the beginning if `current` is not in the rotation
+/// and stays on `current` if the rotation has no known maps.
+fn next_map(rotation: &str, current: &str, known: impl Fn(&str) -> bool) -> String {
+    let maps: Vec<_> = rotation.split_whitespace().filter(|&map| known(map)).collect();
+    let next = match maps.iter().position(|&map| map == current) {
+        Some(i) => maps[(i + 1) % maps.len()],
+        None => maps.first().copied().unwrap_or(current),
+    };
+    next.to_owned()
+}
+
+struct Vote {
+    kind: VoteKind,
+    /// Who voted and whether yes or no.
+    voters: FxHashMap<Handle<Player>, bool>,
+    /// Game time when the vote fails.
+    time_end: f32,
+    /// Clients haven't been told about the latest votes yet.
+    changed: bool,
+}
+
+impl Vote {
+    fn status(&self) -> VoteStatus {
+        let yes = self.voters.values().filter(|&&yes| yes).count() as u32;
+        VoteStatus {
+            kind: self.kind.clone(),
+            yes,
+            no: self.voters.len() as u32 - yes,
+            time_end: self.time_end,
+        }
+    }
+}
+
 enum SendDest {
     One(Handle<RemoteClient>),
     All,
 }
 
-struct RemoteClient {
+pub struct RemoteClient {
     conn: Box<dyn Connection<ClientMessage>>,
     player_handle: Handle<Player>,
+    /// Cycles currently replicated to this client, see `sv_interest_radius`.
+    relevant: FxHashSet<NetId>,
+    /// Game time when the client's cycle last saw each relevant cycle,
+    /// see `sv_visibility_culling`.
+    last_seen: FxHashMap<NetId, f32>,
+    /// Received inputs which haven't been used by gamelogic yet, oldest first.
+    inputs: VecDeque<Input>,
+    /// Sequence number of the last received input.
+    input_seq: Option<u32>,
+    /// The map the client is downloading from us, if any.
+    map_download: Option<MapDownload>,
+    /// The client already got the message of the day.
+    motd_sent: bool,
+    /// Hash of the `ClientMessage::Guid`, None if it hasn't been sent (yet).
+    ///
+    /// See `stats::guid_key`.
+    guid: Option<String>,
+    /// What the player did since their stats were last saved, see `sv_stats_db`.
+    stats: MatchStats,
+    /// See `ClientMessage::Ready`, reset on map change.
+    ready: bool,
+    /// See `ClientMessage::Team`.
+    team: Option<Team>,
+}
+
+struct MapDownload {
+    map: String,
+    data: Vec<u8>,
+    hash: u64,
+    /// How many bytes were already sent.
+    offset: usize,
 }
 
 impl RemoteClient {
@@ -280,6 +1846,61 @@ impl RemoteClient {
         Self {
             conn,
             player_handle,
+            relevant: FxHashSet::default(),
+            last_seen: FxHashMap::default(),
+            inputs: VecDeque::new(),
+            input_seq: None,
+            map_download: None,
+            motd_sent: false,
+            guid: None,
+            stats: MatchStats::default(),
+            ready: false,
+            team: None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_map_rotation() {
+        let all = |_: &str| true;
+        assert_eq!(next_map("", "arena", all), "arena");
+        assert_eq!(next_map("arena", "arena", all), "arena");
+        assert_eq!(next_map("a b c", "a", all), "b");
+        assert_eq!(next_map("a b c", "c", all), "a");
+        assert_eq!(next_map(" a  b ", "x", all), "a");
+
+        let known = |map: &str| map != "typo";
+        assert_eq!(next_map("a typo b", "a", known), "b");
+        assert_eq!(next_map("typo", "a", known), "a");
+    }
+
+    #[test]
+    fn input_validation() {
+        let cvars = Cvars::default();
+
+        let mut input = Input {
+            game_time: 10.0,
+            yaw: Deg(-90.0),
+            pitch: Deg(30.0),
+            ..Default::default()
+        };
+        assert!(check_input_time(&cvars, 10.1, &input).is_ok());
+        assert!(check_input_time(&cvars, 100.0, &input).is_err());
+        assert!(clamp_input(&cvars, &mut input));
+        assert_eq!(input.yaw.0, 270.0);
+        assert!(!clamp_input(&cvars, &mut input));
+
+        input.pitch = Deg(120.0);
+        input.yaw_speed = Deg(-1e6);
+        assert!(clamp_input(&cvars, &mut input));
+        assert_eq!(input.pitch.0, cvars.sv_input_pitch_max);
+        assert_eq!(input.yaw_speed.0, -cvars.sv_input_angle_speed_max);
+
+        input.game_time = f32::NAN;
+        assert!(check_input_time(&cvars, 10.0, &input).is_err());
+    }
+}
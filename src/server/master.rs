@@ -0,0 +1,143 @@
+//! Making the server discoverable - announcing it to the master server
+//! and responding to direct info requests from clients.
+//!
+//! See `common::master` for the protocol.
+
+use std::{
+    io::{self, ErrorKind},
+    net::{SocketAddr, UdpSocket},
+};
+
+use crate::{
+    common::master::{
+        self, InfoRequest, InfoResponse, MasterRequest, ServerInfo, INFO_REQUEST_LEN,
+        MAX_DATAGRAM_LEN,
+    },
+    prelude::*,
+};
+
+/// Periodically tells the master server about this game server.
+///
+/// Failures are only logged, the game should keep running
+/// even if the master is unreachable.
+pub struct MasterClient {
+    socket: UdpSocket,
+    master_addr: SocketAddr,
+    /// The port game clients should connect to.
+    game_port: u16,
+    /// Real time of the last announcement.
+    last_announce: Option<f32>,
+}
+
+impl MasterClient {
+    pub fn new(master_addr: SocketAddr, game_port: u16) -> io::Result<Self> {
+        let socket = master::udp_socket_for(master_addr)?;
+        Ok(Self {
+            socket,
+            master_addr,
+            game_port,
+            last_announce: None,
+        })
+    }
+
+    /// Announce the server if at least `interval` seconds passed since the last announcement.
+    ///
+    /// `info` is only called when announcing, `port` is filled in automatically.
+    pub fn tick(&mut self, real_time: f32, interval: f32, info: impl FnOnce() -> ServerInfo) {
+        if let Some(last) = self.last_announce {
+            if real_time - last < interval {
+                return;
+            }
+        }
+        self.last_announce = Some(real_time);
+
+        let mut info = info();
+        info.port = self.game_port;
+        self.send(&MasterRequest::Announce(info));
+    }
+
+    /// Ask the master to stop listing this server.
+    pub fn remove(&self) {
+        self.send(&MasterRequest::Remove {
+            port: self.game_port,
+        });
+    }
+
+    fn send(&self, request: &MasterRequest) {
+        let bytes = bincode::serialize(request).unwrap();
+        if let Err(e) = self.socket.send_to(&bytes, self.master_addr) {
+            dbg_logf!("failed to send to master {}: {e}", self.master_addr);
+        }
+    }
+}
+
+/// Answers `InfoRequest`s sent by clients to the game port over UDP.
+///
+/// Anyone can send requests with a spoofed source address
+/// so responses are never larger than requests, see `INFO_REQUEST_LEN`.
+pub struct InfoResponder {
+    socket: UdpSocket,
+}
+
+impl InfoResponder {
+    /// Bind to the same address and port as the game's TCP listener.
+    ///
+    /// LAN discovery only reaches servers listening on all interfaces,
+    /// which is also the only way LAN clients can connect.
+    pub fn new(addr: SocketAddr) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self { socket })
+    }
+
+    /// Respond to all pending requests.
+    ///
+    /// `info` is only called if there are any requests, `port` is filled in automatically.
+    pub fn respond(&self, info: impl FnOnce() -> ServerInfo) {
+        let mut info = Some(info);
+        let mut server_info = None;
+        let mut buf = vec![0; MAX_DATAGRAM_LEN];
+        loop {
+            let (len, src) = match self.socket.recv_from(&mut buf) {
+                Ok(res) => res,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return,
+                Err(e) => {
+                    dbg_logf!("failed to receive info request: {e}");
+                    return;
+                }
+            };
+            if len < INFO_REQUEST_LEN {
+                dbg_logf_once!("ignoring info request from {src} without padding ({len} bytes)");
+                continue;
+            }
+            let request: InfoRequest = match bincode::deserialize(&buf[..len]) {
+                Ok(request) => request,
+                Err(e) => {
+                    dbg_logf!("malformed info request from {src}: {e}");
+                    continue;
+                }
+            };
+
+            let server_info = server_info.get_or_insert_with(|| {
+                let mut server_info = info.take().unwrap()();
+                server_info.port = self.socket.local_addr().unwrap().port();
+                server_info
+            });
+            let response = InfoResponse {
+                time: request.time,
+                info: server_info.clone(),
+            };
+            let bytes = bincode::serialize(&response).unwrap();
+            if bytes.len() > len {
+                dbg_logf_once!(
+                    "info response ({} bytes) is longer than the request, is sv_name too long?",
+                    bytes.len()
+                );
+                continue;
+            }
+            if let Err(e) = self.socket.send_to(&bytes, src) {
+                dbg_logf!("failed to send info response to {src}: {e}");
+            }
+        }
+    }
+}
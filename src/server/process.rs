@@ -1,30 +1,92 @@
 //! The process that runs a dedicated server.
 
-use std::net::TcpListener;
-
-use fyrox::{core::instant::Instant, event_loop::EventLoopWindowTarget};
-
-use crate::{debug, prelude::*, server::game::ServerGame};
+use std::net::{SocketAddr, TcpListener};
+
+use fyrox::{core::instant::Instant, engine::Engine, event_loop::EventLoopWindowTarget};
+
+use crate::{
+    common::{
+        master::ServerInfo,
+        messages::Version,
+        net::{self, Listener, WebSocketListener},
+        trace,
+    },
+    debug, engine_loop,
+    prelude::*,
+    server::{
+        game::{ServerFrameCtx, ServerGame},
+        master::{InfoResponder, MasterClient},
+    },
+};
 
 /// The process that runs a dedicated server.
 pub struct ServerProcess {
     pub cvars: Cvars,
     pub clock: Instant,
+    /// Used instead of `clock` when set, tests use it to control time exactly.
+    pub manual_time: Option<f32>,
     pub engine: Engine,
-    gs: GameState,
+    /// The address clients connect to, useful when the OS chose the port.
+    pub addr: SocketAddr,
+    pub gs: GameState,
     sg: ServerGame,
+    master: Option<MasterClient>,
+    info_responder: Option<InfoResponder>,
 }
 
 impl ServerProcess {
     pub async fn new(cvars: Cvars, mut engine: Engine) -> Self {
         let clock = Instant::now();
 
-        let listener = TcpListener::bind(&cvars.sv_net_listen_addr).unwrap();
+        debug::logger::update(&cvars);
+        debug::logger::update_file(&cvars.sv_log_file, cvars.sv_log_rotate_mb);
+
+        let listener = TcpListener::bind(&cvars.sv_net_listen_addr)
+            .unwrap_or_else(|e| panic!("failed to listen on {}: {}", cvars.sv_net_listen_addr, e));
         listener.set_nonblocking(true).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let info_responder = match InfoResponder::new(addr) {
+            Ok(responder) => Some(responder),
+            Err(e) => {
+                dbg_logf!("failed to set up responding to info requests: {e}");
+                None
+            }
+        };
+        let master = if cvars.sv_master_announce {
+            let port = addr.port();
+            let res = net::resolve(&cvars.net_master_addr, cvars.net_prefer_ipv6)
+                .and_then(|addr| MasterClient::new(addr, port));
+            match res {
+                Ok(master) => Some(master),
+                Err(e) => {
+                    dbg_logf!("failed to set up master server announcements: {e}");
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let mut listeners: Vec<Box<dyn Listener<ClientMessage>>> = vec![Box::new(listener)];
+        let ws_addr = &cvars.sv_net_listen_addr_websocket;
+        if !ws_addr.is_empty() {
+            let listener = WebSocketListener::bind(ws_addr, cvars.sv_net_webrtc)
+                .unwrap_or_else(|e| panic!("failed to listen on {}: {}", ws_addr, e));
+            dbg_logf!("listening for WebSocket clients on {}", listener.local_addr().unwrap());
+            listeners.push(Box::new(listener));
+        }
 
         let gs_type = GameStateType::Server;
-        let gs = GameState::new(&cvars, &mut engine, gs_type).await;
-        let sg = ServerGame::new(Box::new(listener)).await;
+        let gs = GameState::new(
+            &cvars,
+            &engine.resource_manager,
+            &mut engine.scenes,
+            gs_type,
+            &cvars.g_map,
+        )
+        .await;
+        let sg = ServerGame::new(listeners).await;
 
         let elapsed = clock.elapsed();
         dbg_logf!("ServerProcess::new() took {} ms", elapsed.as_millis());
@@ -32,15 +94,38 @@ impl ServerProcess {
         Self {
             cvars,
             clock,
+            manual_time: None,
             engine,
+            addr,
             gs,
             sg,
+            master,
+            info_responder,
         }
     }
 
     /// This is similar to Client::update,
     /// see that for more information.
-    pub fn update(&mut self, window_target: &EventLoopWindowTarget<()>) {
+    ///
+    /// `window_target` is None when running headless without an event loop.
+    pub fn update(&mut self, window_target: Option<&EventLoopWindowTarget<()>>) {
+        debug::config_watch::update(&mut self.cvars);
+        debug::logger::update(&self.cvars);
+        debug::logger::update_file(&self.cvars.sv_log_file, self.cvars.sv_log_rotate_mb);
+        debug::crash::update(&self.cvars);
+        debug::profiler::frame(&mut self.cvars);
+        net::stats_frame(&self.cvars);
+        trace::stats_frame(&self.cvars);
+        profile_scope!("update");
+
+        if let Some(map) = self.sg.next_map.take() {
+            // Intermission is over.
+            self.cvars.g_map = map;
+            self.sg.change_map(&mut self.cvars, &mut self.engine, &mut self.gs, true);
+        } else if self.cvars.g_map != self.gs.map_name {
+            self.sg.change_map(&mut self.cvars, &mut self.engine, &mut self.gs, false);
+        }
+
         let game_time_target = self.real_time();
 
         let dt_update = game_time_target - self.gs.game_time;
@@ -48,23 +133,31 @@ impl ServerProcess {
             dbg_logf!("large dt_update: {dt_update}");
         }
 
-        let dt = 1.0 / 60.0;
-        while self.gs.game_time + dt < game_time_target {
-            self.gs.frame_num += 1;
-            self.gs.game_time_prev = self.gs.game_time;
-            self.gs.game_time += dt;
-            debug::set_game_time(self.gs.game_time);
+        let dt = self.gs.dt;
+        while engine_loop::next_tick(&mut self.gs, game_time_target) {
+            profile_scope!("tick");
 
             self.sv_ctx().tick_begin_frame();
 
+            let snapshot =
+                self.cvars.d_determinism_check.then(|| self.ctx().determinism_snapshot());
             self.ctx().tick_before_physics(dt);
 
-            // There's currently no need to split this into pre_ and post_update like on the client.
-            // Dummy lag since we don't use fyrox plugins.
-            let mut lag = 0.0;
-            self.engine.update(dt, window_target, &mut lag, FxHashMap::default());
-            // Sanity check - if the engine starts doing something with this, we'll know.
-            assert_eq!(lag, 0.0);
+            {
+                profile_scope!("physics");
+                engine_loop::update_scene(
+                    &mut self.engine,
+                    self.gs.scene_handle,
+                    dt,
+                    window_target,
+                );
+            }
+
+            self.ctx().tick_after_physics();
+            if let Some(snapshot) = snapshot {
+                self.ctx().check_determinism(snapshot, dt);
+            }
+            self.sv_ctx().tick_after_physics();
 
             // `sys_send_update` sends debug shapes and text to client.
             // Any debug calls after it will show up next frame.
@@ -72,6 +165,28 @@ impl ServerProcess {
             self.sv_ctx().sys_send_update();
             self.ctx().debug_engine_updates(v!(-6 5 3));
         }
+
+        self.announce();
+    }
+
+    /// Tell the master and any clients who ask about this server.
+    fn announce(&mut self) {
+        let real_time = self.real_time();
+        if let Some(master) = &mut self.master {
+            master.tick(real_time, self.cvars.sv_master_announce_interval, || {
+                server_info(&self.cvars, &self.gs)
+            });
+        }
+        if let Some(responder) = &self.info_responder {
+            responder.respond(|| server_info(&self.cvars, &self.gs));
+        }
+    }
+
+    pub fn loop_exiting(&self) {
+        if let Some(master) = &self.master {
+            master.remove();
+        }
+        dbg_logf!("bye");
     }
 
     fn sv_ctx(&mut self) -> ServerFrameCtx {
@@ -83,7 +198,7 @@ impl ServerProcess {
         }
     }
 
-    fn ctx(&mut self) -> FrameCtx {
+    pub fn ctx(&mut self) -> FrameCtx {
         FrameCtx {
             cvars: &self.cvars,
             scene: &mut self.engine.scenes[self.gs.scene_handle],
@@ -91,7 +206,28 @@ impl ServerProcess {
         }
     }
 
+    /// Accept clients from another source than the listeners created from cvars.
+    pub fn add_listener(&mut self, listener: Box<dyn Listener<ClientMessage>>) {
+        self.sg.add_listener(listener);
+    }
+
+    /// Real time in seconds until the next tick should run, zero if it's already late.
+    pub fn time_until_next_tick(&self) -> f32 {
+        (self.gs.game_time + self.gs.dt - self.real_time()).max(0.0)
+    }
+
     pub fn real_time(&self) -> f32 {
-        self.clock.elapsed().as_secs_f32()
+        self.manual_time.unwrap_or_else(|| self.clock.elapsed().as_secs_f32())
+    }
+}
+
+fn server_info(cvars: &Cvars, gs: &GameState) -> ServerInfo {
+    ServerInfo {
+        name: cvars.sv_name.clone(),
+        map: gs.map_name.clone(),
+        players: gs.players.alive_count(),
+        // Filled in by the sender.
+        port: 0,
+        version: Version::current(),
     }
 }
@@ -0,0 +1,195 @@
+//! Re-simulating server demos to check gamelogic changes didn't change the outcome,
+//! see `sv_record_demos` and the `verify` command.
+//!
+//! Everything clients sent is fed to a new server at the same frames as when recording
+//! and its checksum is compared with the recorded one after every frame.
+//! Since the whole server runs again, this also covers what `FrameCtx::check_determinism` can't,
+//! e.g. spawning, respawning and the match state.
+//!
+//! Only demos which start with an empty server can be re-simulated,
+//! the state of connected clients isn't part of the demo.
+//! Gameplay cvars are stored in the demo header and set before re-simulating.
+//!
+//! LATER Disconnects caused by the server (e.g. a failed send) are replayed
+//! at the start of the frame so they might happen earlier in the frame than when recording.
+
+use std::{
+    io::{self, ErrorKind},
+    sync::mpsc::{self, Receiver, Sender},
+};
+
+use fyrox::{core::futures::executor, engine::Engine};
+
+use crate::{
+    common::{
+        demo::DemoEntry,
+        net::{self, Connection, Listener, LocalConnection, NetworkMessage},
+    },
+    prelude::*,
+    server::process::ServerProcess,
+};
+
+#[derive(Debug, Clone)]
+pub struct ReplayReport {
+    /// How many frames had their checksum compared.
+    pub frames: usize,
+    /// The first frame which ended with a different state than when recording.
+    pub mismatch: Option<Mismatch>,
+    /// The checksum after the last compared frame.
+    pub final_checksum: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Mismatch {
+    pub frame_num: usize,
+    pub expected: u64,
+    pub actual: u64,
+}
+
+/// The client side of a connection from the demo.
+struct ReplayClient {
+    /// None after the client disconnected so the server sees the connection closed.
+    to_server: Option<Sender<NetworkMessage>>,
+    /// Kept open so the server can keep sending, its messages are thrown away.
+    from_server: Receiver<NetworkMessage>,
+}
+
+/// Gives the server connections in the order clients connected in the demo.
+struct ReplayListener {
+    pending: Receiver<LocalConnection>,
+}
+
+impl Listener<ClientMessage> for ReplayListener {
+    fn accept_conn(&mut self) -> io::Result<Box<dyn Connection<ClientMessage>>> {
+        match self.pending.try_recv() {
+            Ok(conn) => Ok(Box::new(conn)),
+            Err(_) => Err(io::Error::new(ErrorKind::WouldBlock, "no new connections")),
+        }
+    }
+}
+
+/// Run a new server with the inputs recorded in `entries` and compare its state with the demo.
+///
+/// The map and gameplay cvars are taken from the demo, everything else from `cvars`.
+/// Stops at the first mismatch since everything after it is likely to differ too.
+pub fn verify(
+    mut cvars: Cvars,
+    engine: Engine,
+    entries: Vec<DemoEntry>,
+) -> Result<ReplayReport, String> {
+    let mut entries = entries.into_iter();
+    let Some(DemoEntry::Header(header)) = entries.next() else {
+        return Err("demo doesn't start with a header".to_owned());
+    };
+    if header.version.game != Version::current().game {
+        return Err(format!("not a RustCycles demo: {}", header.version));
+    }
+    if !header.sync.players.is_empty() {
+        return Err(format!(
+            "the demo starts with {} players already connected, \
+            only demos recorded from the start of a server can be re-simulated",
+            header.sync.players.len()
+        ));
+    }
+    dbg_logf!("verifying demo of {} recorded at {}", header.map, header.recorded_at);
+
+    cvars.g_map = header.map.clone();
+    for (name, value) in &header.gameplay_cvars {
+        cvars
+            .set_str(name, value)
+            .map_err(|err| format!("failed to set {name} from the demo: {err}"))?;
+    }
+    cvars.sv_record_demos = false;
+    cvars.sv_headless = true;
+    cvars.sv_master_announce = false;
+    // Let the OS choose a free port so it doesn't clash with a running server.
+    cvars.sv_net_listen_addr = "127.0.0.1:0".to_owned();
+
+    let mut server = executor::block_on(ServerProcess::new(cvars, engine));
+    let (conn_sender, conn_receiver) = mpsc::channel();
+    server.add_listener(Box::new(ReplayListener {
+        pending: conn_receiver,
+    }));
+
+    let mut clients: FxHashMap<NetId, ReplayClient> = FxHashMap::default();
+    let mut frame_num = server.gs.frame_num;
+    let mut report = ReplayReport {
+        frames: 0,
+        mismatch: None,
+        final_checksum: None,
+    };
+    for entry in entries {
+        match entry {
+            DemoEntry::Header(_) => {
+                return Err("unexpected header in the middle of the demo".into())
+            }
+            DemoEntry::Frame {
+                frame_num: next, ..
+            } => {
+                // Frames where nothing was received still have to run.
+                run_to(&mut server, next.saturating_sub(1));
+                frame_num = next;
+            }
+            DemoEntry::Connect { player_id } => {
+                let (to_server, server_receiver) = mpsc::channel();
+                let (server_sender, from_server) = mpsc::channel();
+                let conn = LocalConnection::new(server_sender, server_receiver);
+                conn_sender.send(conn).unwrap();
+                let client = ReplayClient {
+                    to_server: Some(to_server),
+                    from_server,
+                };
+                clients.insert(player_id, client);
+            }
+            DemoEntry::Disconnect { player_id } => {
+                if let Some(client) = clients.get_mut(&player_id) {
+                    client.to_server = None;
+                }
+            }
+            DemoEntry::Received { from, msg } => {
+                let client = clients
+                    .get(&from)
+                    .ok_or_else(|| format!("message from unknown player {from}"))?;
+                if let Some(to_server) = &client.to_server {
+                    to_server.send(net::serialize(msg)).unwrap();
+                }
+            }
+            // What the server sent is only interesting to people watching the demo.
+            DemoEntry::Message { .. } => {}
+            DemoEntry::Checksum(expected) => {
+                run_to(&mut server, frame_num);
+                for client in clients.values() {
+                    while client.from_server.try_recv().is_ok() {}
+                }
+                // The demo ends when the map changes but the server might have
+                // changed it a few frames sooner because it runs one frame per update.
+                if server.gs.map_name != header.map {
+                    break;
+                }
+
+                let actual = server.ctx().checksum();
+                report.frames += 1;
+                report.final_checksum = Some(actual);
+                if actual != expected {
+                    report.mismatch = Some(Mismatch {
+                        frame_num,
+                        expected,
+                        actual,
+                    });
+                    break;
+                }
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Run ticks until the server finishes `frame_num`, same as the test harness.
+fn run_to(server: &mut ServerProcess, frame_num: usize) {
+    while server.gs.frame_num < frame_num {
+        let target = server.gs.frame_num + 1;
+        // Halfway between ticks so float errors don't cause running zero or two ticks.
+        server.manual_time = Some((target as f32 + 0.5) * server.gs.dt);
+        server.update(None);
+    }
+}
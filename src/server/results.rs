@@ -0,0 +1,95 @@
+//! JSON summaries of finished matches for leaderboards and other community tools,
+//! see `sv_match_results_dir` and `sv_match_results_url`.
+
+use std::{fs, thread, time::SystemTime};
+
+use crate::{
+    common::net,
+    debug::logger::{system_time_now, utc_timestamp},
+    prelude::*,
+};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MatchResult {
+    pub map: String,
+    /// When the match ended as `YYYY-MM-DD hh:mm:ss` in UTC.
+    pub ended_at: String,
+    /// Length of the match in seconds.
+    pub duration: f32,
+    /// Sorted by score, best first.
+    pub players: Vec<PlayerResult>,
+    pub kills: Vec<Kill>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlayerResult {
+    pub id: NetId,
+    pub name: String,
+    pub kills: u32,
+    pub deaths: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Kill {
+    /// Seconds since the start of the match.
+    pub time: f32,
+    /// None for suicides and accidents.
+    pub killer: Option<NetId>,
+    pub victim: NetId,
+}
+
+impl MatchResult {
+    pub fn new(gs: &GameState, kills: Vec<Kill>) -> Self {
+        let mut players: Vec<_> = gs
+            .players
+            .iter()
+            .map(|player| PlayerResult {
+                id: player.net_id,
+                name: player.name.clone(),
+                kills: player.kills,
+                deaths: player.deaths,
+            })
+            .collect();
+        // Most kills first like on the scoreboard, fewer deaths break ties.
+        players.sort_by_key(|player| (std::cmp::Reverse(player.kills), player.deaths, player.id));
+
+        Self {
+            map: gs.map_name.clone(),
+            ended_at: utc_timestamp(system_time_now()),
+            duration: gs.game_time - gs.match_start,
+            players,
+            kills,
+        }
+    }
+}
+
+/// Save the result into `sv_match_results_dir` and send it to `sv_match_results_url`
+/// if they're set.
+///
+/// Sending happens on another thread so a slow webhook doesn't stall the game.
+pub fn export(cvars: &Cvars, result: &MatchResult) {
+    if cvars.sv_match_results_dir.is_empty() && cvars.sv_match_results_url.is_empty() {
+        return;
+    }
+    let json = serde_json::to_string_pretty(result).unwrap();
+
+    if !cvars.sv_match_results_dir.is_empty() {
+        let secs = system_time_now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        let dir = &cvars.sv_match_results_dir;
+        let path = format!("{}/match-{}-{}.json", dir, secs, result.map);
+        match fs::create_dir_all(dir).and_then(|()| fs::write(&path, &json)) {
+            Ok(()) => dbg_logf!("match result saved to {}", path),
+            Err(e) => dbg_logf!("failed to save match result to {}: {}", path, e),
+        }
+    }
+
+    if !cvars.sv_match_results_url.is_empty() {
+        let url = cvars.sv_match_results_url.clone();
+        thread::spawn(move || match net::http_post(&url, "application/json", &json) {
+            Ok(()) => dbg_logf!("match result sent to {}", url),
+            Err(e) => dbg_logf!("failed to send match result to {}: {}", url, e),
+        });
+    }
+}
@@ -0,0 +1,387 @@
+//! Player stats saved across matches in SQLite, see `sv_stats_db`.
+//!
+//! The server accumulates `MatchStats` for each client during a match
+//! and adds them to the player's totals when the match ends or the client disconnects.
+//! The DB is only accessed from `StatsWorker`'s thread so a slow disk doesn't stall the game.
+//!
+//! Players are identified by a secret `cl_guid`. The server only stores its hash, see `guid_key`.
+//!
+//! SQLite doesn't build for the browser so there opening the DB always fails.
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::SystemTime;
+use std::{
+    sync::mpsc::{self, Receiver, Sender},
+    thread::{self, JoinHandle},
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use rusqlite::{params, Connection, OptionalExtension};
+use snow::{
+    params::HashChoice,
+    resolvers::{CryptoResolver, DefaultResolver},
+};
+
+use crate::prelude::*;
+
+/// The key the player's stats are saved under.
+///
+/// Clients send a random secret instead of a public ID
+/// and only its hash is ever stored or compared.
+/// Knowing the key, e.g. from the DB, isn't enough to write to someone else's stats,
+/// that requires the secret which only the player and the servers they connected to know.
+pub fn guid_key(guid: &str) -> String {
+    let mut hasher = DefaultResolver
+        .resolve_hash(&HashChoice::Blake2s)
+        .expect("snow always has BLAKE2s");
+    hasher.input(guid.as_bytes());
+    let mut hash = [0; 32];
+    hasher.result(&mut hash);
+    hash.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// What a player did since their stats were last saved.
+#[derive(Debug, Clone, Default)]
+pub struct MatchStats {
+    pub kills: u32,
+    pub deaths: u32,
+    pub damage: f32,
+    pub distance: f32,
+    pub time_played: f32,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub struct StatsDb {
+    conn: Connection,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl StatsDb {
+    /// Open the DB, creating it if it doesn't exist.
+    pub fn open(path: &str) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS players (
+                guid TEXT PRIMARY KEY NOT NULL,
+                name TEXT NOT NULL,
+                matches INTEGER NOT NULL,
+                kills INTEGER NOT NULL,
+                deaths INTEGER NOT NULL,
+                damage REAL NOT NULL,
+                distance REAL NOT NULL,
+                time_played REAL NOT NULL,
+                last_seen INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS players_name ON players (name);",
+        )?;
+        Ok(Self { conn })
+    }
+
+    /// Add the stats to the player's totals.
+    ///
+    /// `finished` means the player was there when the match ended
+    /// as opposed to disconnecting in the middle.
+    pub fn add(
+        &self,
+        guid: &str,
+        name: &str,
+        stats: &MatchStats,
+        finished: bool,
+    ) -> rusqlite::Result<()> {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs() as i64);
+        self.conn.execute(
+            "INSERT INTO players
+                (guid, name, matches, kills, deaths, damage, distance, time_played, last_seen)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+            ON CONFLICT (guid) DO UPDATE SET
+                name = excluded.name,
+                matches = matches + excluded.matches,
+                kills = kills + excluded.kills,
+                deaths = deaths + excluded.deaths,
+                damage = damage + excluded.damage,
+                distance = distance + excluded.distance,
+                time_played = time_played + excluded.time_played,
+                last_seen = excluded.last_seen",
+            params![
+                guid,
+                name,
+                finished as u32,
+                stats.kills,
+                stats.deaths,
+                stats.damage,
+                stats.distance,
+                stats.time_played,
+                now,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Totals of the player with the given GUID or name.
+    ///
+    /// If multiple players used the name, returns the one seen most recently.
+    pub fn get(&self, player: &str) -> rusqlite::Result<Option<PlayerStats>> {
+        self.conn
+            .query_row(
+                "SELECT name, matches, kills, deaths, damage, distance, time_played
+                FROM players
+                WHERE guid = ?1 OR name = ?1
+                ORDER BY guid = ?1 DESC, last_seen DESC
+                LIMIT 1",
+                params![player],
+                |row| {
+                    Ok(PlayerStats {
+                        name: row.get(0)?,
+                        matches: row.get(1)?,
+                        kills: row.get(2)?,
+                        deaths: row.get(3)?,
+                        damage: row.get(4)?,
+                        distance: row.get(5)?,
+                        time_played: row.get(6)?,
+                    })
+                },
+            )
+            .optional()
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub struct StatsDb;
+
+#[cfg(target_arch = "wasm32")]
+impl StatsDb {
+    pub fn open(_path: &str) -> Result<Self, &'static str> {
+        Err("SQLite isn't available in the browser")
+    }
+
+    pub fn add(
+        &self,
+        _guid: &str,
+        _name: &str,
+        _stats: &MatchStats,
+        _finished: bool,
+    ) -> Result<(), &'static str> {
+        unreachable!("the DB can't be opened")
+    }
+
+    pub fn get(&self, _player: &str) -> Result<Option<PlayerStats>, &'static str> {
+        unreachable!("the DB can't be opened")
+    }
+}
+
+enum Request {
+    Add {
+        key: String,
+        name: String,
+        stats: MatchStats,
+        finished: bool,
+    },
+    Get {
+        player_id: NetId,
+        query: String,
+        key: String,
+    },
+}
+
+/// The answer to `StatsWorker::get`.
+pub struct StatsReply {
+    /// The player who asked.
+    pub player_id: NetId,
+    pub query: String,
+    pub stats: Option<PlayerStats>,
+}
+
+/// Owns the `StatsDb` on a background thread.
+///
+/// Dropping the worker waits until all queued writes are finished.
+pub struct StatsWorker {
+    /// Where the DB is so a new worker can be started when `sv_stats_db` changes.
+    pub path: String,
+    requests: Option<Sender<Request>>,
+    replies: Receiver<StatsReply>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl StatsWorker {
+    /// Start the thread, it opens (or creates) the DB at `path`.
+    ///
+    /// Fails where threads aren't supported, e.g. in the browser.
+    pub fn start(path: &str) -> std::io::Result<Self> {
+        let (requests, requests_rx) = mpsc::channel();
+        let (replies_tx, replies) = mpsc::channel();
+        let db_path = path.to_owned();
+        let thread = thread::Builder::new()
+            .name("stats".to_owned())
+            .spawn(move || run(&db_path, requests_rx, replies_tx))?;
+        Ok(Self {
+            path: path.to_owned(),
+            requests: Some(requests),
+            replies,
+            thread: Some(thread),
+        })
+    }
+
+    /// Queue adding the stats to the totals of the player with the key, see `StatsDb::add`.
+    pub fn add(&self, key: String, name: String, stats: MatchStats, finished: bool) {
+        self.send(Request::Add {
+            key,
+            name,
+            stats,
+            finished,
+        });
+    }
+
+    /// Queue looking up totals, see `StatsDb::get`.
+    ///
+    /// The reply comes from `poll` in a later frame.
+    pub fn get(&self, player_id: NetId, query: String, key: String) {
+        self.send(Request::Get {
+            player_id,
+            query,
+            key,
+        });
+    }
+
+    /// The next finished lookup, if any.
+    pub fn poll(&self) -> Option<StatsReply> {
+        self.replies.try_recv().ok()
+    }
+
+    fn send(&self, request: Request) {
+        let requests = self.requests.as_ref().unwrap();
+        if requests.send(request).is_err() {
+            dbg_logf!("stats thread for {} is no longer running", self.path);
+        }
+    }
+}
+
+impl Drop for StatsWorker {
+    fn drop(&mut self) {
+        // Closing the channel makes the thread exit after the remaining requests.
+        self.requests = None;
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                dbg_logf!("stats thread for {} panicked", self.path);
+            }
+        }
+    }
+}
+
+/// The worker thread's main loop, runs until the `StatsWorker` is dropped.
+///
+/// If the DB can't be opened, lookups are still answered so clients aren't left waiting.
+fn run(path: &str, requests: Receiver<Request>, replies: Sender<StatsReply>) {
+    let db = match StatsDb::open(path) {
+        Ok(db) => Some(db),
+        Err(e) => {
+            dbg_logf!("failed to open stats DB {}: {}", path, e);
+            None
+        }
+    };
+    for request in requests {
+        match request {
+            Request::Add {
+                key,
+                name,
+                stats,
+                finished,
+            } => {
+                let Some(db) = &db else {
+                    continue;
+                };
+                if let Err(e) = db.add(&key, &name, &stats, finished) {
+                    dbg_logf!("failed to save stats of {:?}: {}", name, e);
+                }
+            }
+            Request::Get {
+                player_id,
+                query,
+                key,
+            } => {
+                let stats = db.as_ref().and_then(|db| {
+                    db.get(&key).unwrap_or_else(|e| {
+                        dbg_logf!("failed to read stats of {:?}: {}", key, e);
+                        None
+                    })
+                });
+                let reply = StatsReply {
+                    player_id,
+                    query,
+                    stats,
+                };
+                // The server is shutting down, nobody is waiting for the reply.
+                let _ = replies.send(reply);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_and_get() {
+        let db = StatsDb::open(":memory:").unwrap();
+        assert_eq!(db.get("abc").unwrap(), None);
+
+        let stats = MatchStats {
+            kills: 3,
+            deaths: 1,
+            damage: 250.0,
+            distance: 1000.0,
+            time_played: 60.0,
+        };
+        db.add("abc", "Alice", &stats, true).unwrap();
+        db.add("abc", "Alice2", &stats, false).unwrap();
+        db.add("def", "Bob", &stats, true).unwrap();
+
+        let alice = db.get("abc").unwrap().unwrap();
+        assert_eq!(alice.name, "Alice2");
+        assert_eq!(alice.matches, 1);
+        assert_eq!(alice.kills, 6);
+        assert_eq!(alice.deaths, 2);
+        assert_eq!(alice.distance, 2000.0);
+        assert_eq!(db.get("Alice2").unwrap(), Some(alice));
+        assert_eq!(db.get("Alice").unwrap(), None);
+        assert_eq!(db.get("Bob").unwrap().unwrap().kills, 3);
+    }
+
+    #[test]
+    fn guid_key_is_not_the_guid() {
+        let key = guid_key("abc");
+        assert_eq!(key.len(), 64);
+        assert_ne!(key, "abc");
+        assert_eq!(key, guid_key("abc"));
+        assert_ne!(key, guid_key("abd"));
+    }
+
+    #[test]
+    fn worker_saves_and_replies() {
+        let path = std::env::temp_dir().join(format!("rustcycles-stats-{}.db", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+
+        let stats = MatchStats {
+            kills: 2,
+            ..Default::default()
+        };
+        let worker = StatsWorker::start(path).unwrap();
+        worker.add("abc".to_owned(), "Alice".to_owned(), stats, true);
+        worker.get(NetId(7), "Alice".to_owned(), "abc".to_owned());
+        let reply = loop {
+            if let Some(reply) = worker.poll() {
+                break reply;
+            }
+            thread::yield_now();
+        };
+        assert_eq!(reply.player_id, NetId(7));
+        assert_eq!(reply.query, "Alice");
+        assert_eq!(reply.stats.unwrap().kills, 2);
+        drop(worker);
+
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,134 @@
+//! Entry point for the browser.
+//!
+//! The client draws into a canvas appended to the page
+//! and connects to servers over WebSockets, see `sv_net_listen_addr_websocket`.
+//! Cvars are set in the URL query, e.g. `?cl_name=Player&cl_connect_addr=localhost:26001`.
+//! With `?local` the client plays a local game with the server in the same page,
+//! otherwise without `cl_connect_addr` the main menu is shown.
+//!
+//! Build with `cargo build --target wasm32-unknown-unknown`
+//! and generate the JS bindings with `wasm-bindgen --target web`,
+//! `web/index.html` is the page that loads them.
+
+use std::{cell::RefCell, panic, rc::Rc};
+
+use fyrox::{
+    engine::GraphicsContext,
+    event::Event,
+    event_loop::{ControlFlow, EventLoop},
+    platform::web::EventLoopExtWebSys,
+};
+
+use crate::{
+    args_to_cvars,
+    client::process::{ClientProcess, ClientStart},
+    client_event, init_engine_client, init_global_state,
+    prelude::*,
+};
+
+pub fn main() {
+    init_global_state("cl");
+
+    // The default hook prints to stderr which goes nowhere in the browser.
+    let prev_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |panic_info| {
+        web_sys::console::error_1(&panic_info.to_string().into());
+        prev_hook(panic_info);
+    }));
+
+    let mut cvar_args = query_args();
+    let local = match cvar_args.iter().step_by(2).position(|name| name == "local") {
+        Some(i) => {
+            cvar_args.drain(i * 2..i * 2 + 2);
+            true
+        }
+        None => false,
+    };
+
+    let mut defaults = Cvars::default();
+    if is_touch_device() {
+        touch_defaults(&mut defaults);
+    }
+    let cvars = match args_to_cvars(defaults.clone(), &cvar_args) {
+        Ok(cvars) => cvars,
+        Err(e) => {
+            dbg_logf!("ERROR {}, using default cvars", e);
+            defaults
+        }
+    };
+    let start = if local {
+        ClientStart::Local
+    } else if cvar_args.iter().step_by(2).any(|name| name == "cl_connect_addr") {
+        ClientStart::Connect
+    } else {
+        ClientStart::Menu
+    };
+
+    let engine = init_engine_client(&cvars);
+
+    // Loading resources in the browser is asynchronous and blocking would freeze the page
+    // so the client is created in the background while the event loop is already running.
+    let client = Rc::new(RefCell::new(None));
+    let client_clone = Rc::clone(&client);
+    wasm_bindgen_futures::spawn_local(async move {
+        let process = ClientProcess::new(cvars, engine, start).await;
+        *client_clone.borrow_mut() = Some(process);
+    });
+
+    let event_loop = EventLoop::new().unwrap();
+    // See client_main in main.rs.
+    event_loop.set_control_flow(ControlFlow::Poll);
+    let mut resumed = false;
+    event_loop.spawn(move |event, window_target| {
+        let mut client = client.borrow_mut();
+        let Some(client) = client.as_mut() else {
+            if let Event::Resumed = event {
+                resumed = true;
+            }
+            return;
+        };
+
+        // `Resumed` might have arrived while the client was still loading.
+        let uninitialized =
+            matches!(client.engine.graphics_context, GraphicsContext::Uninitialized(_));
+        if resumed && uninitialized {
+            client.engine.initialize_graphics_context(window_target).unwrap();
+        }
+
+        client_event(client, event, window_target);
+    });
+}
+
+fn is_touch_device() -> bool {
+    web_sys::window().unwrap().navigator().max_touch_points() > 0
+}
+
+/// Phones and tablets have no mouse to grab, small screens and weak GPUs.
+///
+/// Applied before the URL query so it can override them.
+fn touch_defaults(cvars: &mut Cvars) {
+    cvars.cl_mouse_grab_on_focus = false;
+    cvars.cl_touch = true;
+    cvars.hud_minimap = false;
+    cvars.r_render_scale_dynamic = true;
+}
+
+/// Cvar names and values from the page's URL query in the same format as on the command line.
+fn query_args() -> Vec<String> {
+    let search = web_sys::window().unwrap().location().search().unwrap_or_default();
+    let mut args = Vec::new();
+    for pair in search.trim_start_matches('?').split('&') {
+        if pair.is_empty() {
+            continue;
+        }
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        for part in [name, value] {
+            let decoded = js_sys::decode_uri_component(&part.replace('+', " "))
+                .ok()
+                .and_then(|decoded| decoded.as_string())
+                .unwrap_or_else(|| part.to_owned());
+            args.push(decoded);
+        }
+    }
+    args
+}